@@ -0,0 +1,344 @@
+/* Automatic hinting for fonts that come with little or no hinting of their
+own (and for CFF outlines, which the bytecode interpreter can't run).
+Loosely follows FreeType's autofit:
+https://freetype.org/autohinting/hinter.html
+
+The outline is analysed along each axis for edges (runs where the outline is
+flat, or has an extremum, along that axis). Edges on opposite sides of the ink
+that face each other are paired into stems. Along y, edges near the font's
+blue zones (baseline, x-height, cap height, ascender, descender) are snapped
+to the grid position of the zone, and overshoots are flattened at sizes where
+they would be less than half a pixel. Stem widths are rounded to whole pixels
+and stems not held by a blue zone are centred on the grid. Every coordinate
+is then moved by the piecewise-linear warp between the fitted edges, so
+curves keep their shape and contours stay closed. */
+
+use ttf_parser::{Face, GlyphId, Rect};
+
+use crate::curve::{Contour, Element};
+use crate::outline::extract_glyph_outline;
+use crate::variation::Variation;
+
+// Characters that define each blue zone, with flat ones (the zone itself)
+// first and round ones (the overshoot) after the '|'. Top zones match edges
+// with ink below them, bottom zones edges with ink above.
+const TOP_BLUES: [&str; 3] = ["xzvw|oe", "HEZT|OC", "bdhkl|"];
+const BOTTOM_BLUES: [&str; 2] = ["xzHE|oO", "pq|"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlueZone {
+    top: bool,
+    // Font units
+    reference: f32,
+    overshoot: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    // Position along the axis in font units
+    position: f32,
+    // Extent along the other axis, for checking that two edges face each other
+    range: (f32, f32),
+    // Whether the ink is on the far (higher) side of the edge
+    low: bool,
+    // Length of the outline making up the edge
+    weight: f32,
+}
+
+pub fn autohint_outline(face: &Face, contours: Vec<Contour>, bbox: Rect, pixels_per_em: f32, blues: &[BlueZone]) -> (Vec<Contour>, Rect) {
+    /* Grid-fits an outline (in font units) for the given size and returns it
+    with its new bounding box, still in font units. The blue zones are the
+    face's, from blue_zones. */
+    let units_per_em = face.units_per_em() as f32;
+    let scale = pixels_per_em / units_per_em;
+    let clockwise = signed_area(&contours) <= 0f32;
+
+    let y_edges = find_edges(&contours, false, clockwise, units_per_em);
+    let x_edges = find_edges(&contours, true, clockwise, units_per_em);
+    let y_anchors = fit_edges(&y_edges, blues, scale, units_per_em);
+    let x_anchors = fit_edges(&x_edges, &[], scale, units_per_em);
+
+    let mut contours = contours;
+    for contour in contours.iter_mut() {
+        contour.map_points(|(x, y)| (warp(x, &x_anchors), warp(y, &y_anchors)));
+    }
+    // The warp is monotonic, so the box moves with the outline
+    let bbox = Rect {
+        x_min: warp(bbox.x_min as f32, &x_anchors).floor() as i16,
+        y_min: warp(bbox.y_min as f32, &y_anchors).floor() as i16,
+        x_max: warp(bbox.x_max as f32, &x_anchors).ceil() as i16,
+        y_max: warp(bbox.y_max as f32, &y_anchors).ceil() as i16,
+    };
+    return (contours, bbox);
+}
+
+pub fn standard_stem_widths(face: &Face, variations: &[Variation]) -> (f32, f32) {
+    /* Typical vertical and horizontal stem widths in font units, measured on
+    'o' like autofit does (the sides and the top and bottom of the bowl). */
+    let units_per_em = face.units_per_em() as f32;
+    let fallback = units_per_em / 12f32;
+    let glyph_id = match face.glyph_index('o') {
+        Some(g) => g,
+        None => return (fallback, fallback),
+    };
+    let (contours, _) = extract_glyph_outline(face, glyph_id, variations);
+    let clockwise = signed_area(&contours) <= 0f32;
+    let thinnest = |x_axis: bool| -> f32 {
+        let edges = find_edges(&contours, x_axis, clockwise, units_per_em);
+        let mut widths = Vec::<f32>::new();
+        for low in edges.iter().filter(|e| e.low) {
+            // Nearest edge facing this one
+            let width = edges.iter().filter(|e| !e.low && e.position > low.position).map(|e| e.position - low.position).fold(f32::MAX, f32::min);
+            if width < units_per_em / 4f32 {
+                widths.push(width);
+            }
+        }
+        return widths.into_iter().reduce(f32::min).unwrap_or(fallback);
+    };
+    return (thinnest(true), thinnest(false));
+}
+
+pub fn blue_zones(face: &Face, variations: &[Variation]) -> Vec<BlueZone> {
+    // Measures the blue zones from the font's own reference characters (a dozen glyph outlines, so callers keep them per face)
+    let mut zones = Vec::<BlueZone>::new();
+    let zone_specs = TOP_BLUES.iter().map(|s| (true, s)).chain(BOTTOM_BLUES.iter().map(|s| (false, s)));
+    for (top, spec) in zone_specs {
+        let (flat, round) = spec.split_once('|').unwrap();
+        let measure = |characters: &str| -> Option<f32> {
+            let values: Vec<f32> = characters
+                .chars()
+                .filter_map(|c| face.glyph_index(c))
+                .filter_map(|glyph_id: GlyphId| extract_glyph_outline(face, glyph_id, variations).1)
+                .map(|b| if top { b.y_max as f32 } else { b.y_min as f32 })
+                .collect();
+            if values.is_empty() {
+                return None;
+            }
+            return Some(values.iter().sum::<f32>() / values.len() as f32);
+        };
+        if let Some(reference) = measure(flat) {
+            let overshoot = measure(round).unwrap_or(reference);
+            zones.push(BlueZone { top, reference, overshoot });
+        }
+    }
+    return zones;
+}
+
+fn signed_area(contours: &[Contour]) -> f32 {
+    // Positive for counter-clockwise outlines
+    return contours.iter().map(|contour| contour.signed_area()).sum();
+}
+
+fn find_edges(contours: &[Contour], x_axis: bool, clockwise: bool, units_per_em: f32) -> Vec<Edge> {
+    /* Finds the edges along an axis: straight runs that are nearly
+    perpendicular to it and curve ends where the tangent is. Candidates close
+    to each other with the same orientation are merged into one edge. */
+    // Coordinates as (along the axis, across it)
+    let point = |p: (f32, f32)| if x_axis { p } else { (p.1, p.0) };
+    // For clockwise outlines the ink is above edges running towards -x and right of edges running towards +y
+    let is_low = |across_direction: f32| (if x_axis { across_direction > 0f32 } else { across_direction < 0f32 }) == clockwise;
+    // Roughly 5 degrees
+    let is_flat = |along: f32, across: f32| across.abs() > 0f32 && along.abs() * 12f32 <= across.abs();
+
+    let mut candidates = Vec::<Edge>::new();
+    for curve in contours.iter().flat_map(|contour| contour.curves.iter()) {
+        let (start, first_control, last_control, end) = match *curve {
+            Element::Line(line) => {
+                let (start, end) = (point(line.from), point(line.to));
+                let (along, across) = (end.0 - start.0, end.1 - start.1);
+                if is_flat(along, across) {
+                    candidates.push(Edge { position: (start.0 + end.0) / 2f32, range: (start.1.min(end.1), start.1.max(end.1)), low: is_low(across), weight: across.abs() });
+                }
+                continue;
+            },
+            Element::Quad(quad) => (point(quad.from), point(quad.control), point(quad.control), point(quad.to)),
+            Element::Cubic(cubic) => (point(cubic.from), point(cubic.control1), point(cubic.control2), point(cubic.to)),
+        };
+        // A curve that leaves or arrives flat has an extremum at that end
+        let (along, across) = (first_control.0 - start.0, first_control.1 - start.1);
+        if is_flat(along, across) {
+            candidates.push(Edge { position: start.0, range: (start.1.min(first_control.1), start.1.max(first_control.1)), low: is_low(across), weight: across.abs() });
+        }
+        let (along, across) = (end.0 - last_control.0, end.1 - last_control.1);
+        if is_flat(along, across) {
+            candidates.push(Edge { position: end.0, range: (end.1.min(last_control.1), end.1.max(last_control.1)), low: is_low(across), weight: across.abs() });
+        }
+    }
+
+    candidates.sort_by(|a, b| a.position.total_cmp(&b.position));
+    let tolerance = units_per_em / 200f32;
+    let mut edges = Vec::<Edge>::new();
+    for candidate in candidates {
+        let merged = edges.iter_mut().rev().take_while(|e| candidate.position - e.position <= tolerance).find(|e| e.low == candidate.low);
+        match merged {
+            Some(edge) => {
+                let weight = edge.weight + candidate.weight;
+                edge.position = (edge.position * edge.weight + candidate.position * candidate.weight) / weight;
+                edge.range = (edge.range.0.min(candidate.range.0), edge.range.1.max(candidate.range.1));
+                edge.weight = weight;
+            },
+            None => edges.push(candidate),
+        }
+    }
+    return edges;
+}
+
+fn fit_edges(edges: &[Edge], blues: &[BlueZone], scale: f32, units_per_em: f32) -> Vec<(f32, f32)> {
+    /* Works out where the blue zone and stem edges go on the grid. Returns
+    (original, fitted) positions in font units, sorted and never crossing. */
+    let mut fitted: Vec<Option<f32>> = vec![None; edges.len()];
+
+    // Blue zone edges snap to the zone, or its overshoot when that is at least half a pixel away
+    let fuzz = units_per_em / 64f32;
+    for (i, edge) in edges.iter().enumerate() {
+        let zone = blues
+            .iter()
+            .filter(|z| z.top != edge.low)
+            .filter(|z| edge.position >= z.reference.min(z.overshoot) - fuzz && edge.position <= z.reference.max(z.overshoot) + fuzz)
+            .min_by(|a, b| (a.reference - edge.position).abs().total_cmp(&(b.reference - edge.position).abs()));
+        if let Some(zone) = zone {
+            let reference = (zone.reference * scale).round();
+            let overshoot = ((zone.overshoot - zone.reference) * scale).round();
+            let near_overshoot = (edge.position - zone.overshoot).abs() < (edge.position - zone.reference).abs();
+            fitted[i] = Some(if near_overshoot { reference + overshoot } else { reference });
+        }
+    }
+
+    // Pair each low edge with the nearest high edge facing it, thinnest stems first
+    let max_stem = units_per_em / 4f32;
+    let mut pairs = Vec::<(usize, usize)>::new();
+    for (i, low) in edges.iter().enumerate().filter(|(_, e)| e.low) {
+        for (j, high) in edges.iter().enumerate().filter(|(_, e)| !e.low) {
+            let width = high.position - low.position;
+            let overlaps = low.range.0 <= high.range.1 && high.range.0 <= low.range.1;
+            if width > 0f32 && width <= max_stem && overlaps {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| (edges[a.1].position - edges[a.0].position).total_cmp(&(edges[b.1].position - edges[b.0].position)));
+    let mut paired = vec![false; edges.len()];
+    for (i, j) in pairs {
+        if paired[i] || paired[j] {
+            continue;
+        }
+        paired[i] = true;
+        paired[j] = true;
+        let width = ((edges[j].position - edges[i].position) * scale).round().max(1f32);
+        match (fitted[i], fitted[j]) {
+            (Some(_), Some(_)) => {},
+            (Some(low), None) => fitted[j] = Some(low + width),
+            (None, Some(high)) => fitted[i] = Some(high - width),
+            (None, None) => {
+                let center = (edges[i].position + edges[j].position) / 2f32 * scale;
+                let low = (center - width / 2f32).round();
+                fitted[i] = Some(low);
+                fitted[j] = Some(low + width);
+            },
+        }
+    }
+
+    let mut anchors: Vec<(f32, f32)> = edges.iter().zip(fitted.iter()).filter_map(|(e, f)| f.map(|f| (e.position, f / scale))).collect();
+    anchors.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // Drop anchors that would fold the outline over itself
+    let mut ordered = Vec::<(f32, f32)>::with_capacity(anchors.len());
+    for anchor in anchors {
+        match ordered.last() {
+            Some(&(original, target)) if anchor.0 <= original || anchor.1 < target => {},
+            _ => ordered.push(anchor),
+        }
+    }
+    return ordered;
+}
+
+fn warp(value: f32, anchors: &[(f32, f32)]) -> f32 {
+    // Moves a coordinate by interpolating between the anchors around it (shifting with the nearest one outside them)
+    if anchors.is_empty() {
+        return value;
+    }
+    let first = anchors[0];
+    let last = anchors[anchors.len() - 1];
+    if value <= first.0 {
+        return value + first.1 - first.0;
+    }
+    if value >= last.0 {
+        return value + last.1 - last.0;
+    }
+    let i = anchors.iter().position(|a| a.0 > value).unwrap();
+    let (low, high) = (anchors[i - 1], anchors[i]);
+    return low.1 + (value - low.0) * (high.1 - low.1) / (high.0 - low.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::Face;
+
+    use crate::curve::{Contour, Curve};
+    use crate::outline::extract_glyph_outline;
+    use super::{autohint_outline, blue_zones, find_edges, signed_area};
+
+    const ROBOTO: &str = "fonts/Roboto-Regular.ttf";
+    const PPEM: f32 = 12f32;
+    // Pixels an edge may be off the grid by (merged edges are averaged over a few font units)
+    const TOLERANCE: f32 = 0.02f32;
+
+    fn hinted(face: &Face, c: char) -> Vec<Contour> {
+        let (contours, bbox) = extract_glyph_outline(face, face.glyph_index(c).unwrap(), &[]);
+        return autohint_outline(face, contours, bbox.unwrap(), PPEM, &blue_zones(face, &[])).0;
+    }
+
+    fn vertical_extent(contours: &[Contour]) -> (f32, f32) {
+        // Lowest and highest point of the outline itself, not its control points
+        return contours.iter().flat_map(|contour| contour.curves.iter()).map(|curve| curve.bounds()).fold((f32::MAX, f32::MIN), |(low, high), b| (low.min(b.1), high.max(b.3)));
+    }
+
+    fn off_grid(pixels: f32) -> f32 {
+        return (pixels - pixels.round()).abs();
+    }
+
+    #[test]
+    fn x_height_and_baseline_on_the_grid() {
+        let data = std::fs::read(ROBOTO).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let scale = PPEM / face.units_per_em() as f32;
+        let mut tops = Vec::<f32>::new();
+        // Flat and round x-height letters (the round ones' overshoot is under half a pixel at this size)
+        for c in ['x', 'z', 'v', 'o', 'e'] {
+            let (bottom, top) = vertical_extent(&hinted(&face, c));
+            assert!(off_grid(top * scale) < TOLERANCE, "top of {:?} at {} pixels", c, top * scale);
+            assert!(bottom.abs() * scale < TOLERANCE, "bottom of {:?} at {} pixels", c, bottom * scale);
+            tops.push((top * scale).round());
+        }
+        // All on the same row
+        assert!(tops.iter().all(|&top| top == tops[0]), "x-height rows {:?}", tops);
+        let x_height = face.tables().os2.and_then(|os2| os2.x_height()).unwrap() as f32;
+        assert_eq!(tops[0], (x_height * scale).round());
+    }
+
+    #[test]
+    fn stems_are_whole_pixels() {
+        // The vertical stems of H and l and the crossbar of H, before and after
+        let data = std::fs::read(ROBOTO).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let units_per_em = face.units_per_em() as f32;
+        let scale = PPEM / units_per_em;
+        for (c, x_axis) in [('H', true), ('l', true), ('H', false)] {
+            let (original, _) = extract_glyph_outline(&face, face.glyph_index(c).unwrap(), &[]);
+            let stems = |contours: &[Contour]| -> Vec<f32> {
+                // Widths of the stems: each edge with ink past it to the next edge facing back
+                let edges = find_edges(contours, x_axis, signed_area(contours) <= 0f32, units_per_em);
+                return edges.iter().filter(|e| e.low).map(|low| {
+                    edges.iter().filter(|e| !e.low && e.position > low.position).map(|e| e.position - low.position).fold(f32::MAX, f32::min)
+                }).collect();
+            };
+            let (before, after) = (stems(&original), stems(&hinted(&face, c)));
+            assert!(!before.is_empty(), "no stems in {:?}", c);
+            assert_eq!(before.len(), after.len(), "{:?}", c);
+            for (before, after) in before.iter().zip(after.iter()) {
+                // Rounded to the nearest whole pixel, but never to nothing
+                assert!(off_grid(after * scale) < TOLERANCE, "{:?} stem {} pixels wide", c, after * scale);
+                assert_eq!((after * scale).round(), (before * scale).round().max(1f32), "{:?} stem was {} pixels", c, before * scale);
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+/* Per-glyph acceleration for inside tests. A ray from (x, y) towards +x
+can only cross segments whose y range takes in y and that reach right of x,
+so the outline is cut into horizontal bands (like Slug's), each holding the
+segments that overlap it, sorted by how far right they reach. A query looks
+at one band and stops at the first segment that ends left of the point,
+instead of testing the whole outline. Ranges are taken from the control
+points, which the curves never leave, so no segment that could be crossed is
+ever skipped and the answers are the same as testing every segment. */
+
+use crate::curve::{Contour, Curve, Element};
+use crate::float::{Float, Precision};
+
+// Bands per segment of the outline, up to MAX_BANDS (segments of a glyph are short, so most fit in a band or two)
+const BANDS_PER_SEGMENT: f32 = 0.5f32;
+const MAX_BANDS: usize = 64;
+
+pub struct SegmentBands {
+    y_min: f32,
+    band_height: f32,
+    // Lines and curves overlapping each band with their rightmost x, rightmost first
+    bands: Vec<Vec<(f32, Element)>>,
+}
+
+impl SegmentBands {
+    pub fn new(contours: &[Contour]) -> SegmentBands {
+        let ranges: Vec<(f32, f32, f32, Element)> = contours.iter().flat_map(|contour| contour.curves.iter()).map(|curve| {
+            let points = curve.points();
+            let (y_min, y_max) = points.iter().fold((f32::MAX, f32::MIN), |(low, high), (_, y)| (low.min(*y), high.max(*y)));
+            let x_max = points.iter().fold(f32::MIN, |high, (x, _)| high.max(*x));
+            return (y_min, y_max, x_max, *curve);
+        }).collect();
+        let y_min = ranges.iter().fold(f32::MAX, |low, range| low.min(range.0));
+        let y_max = ranges.iter().fold(f32::MIN, |high, range| high.max(range.1));
+        let count = ((ranges.len() as f32 * BANDS_PER_SEGMENT).ceil() as usize).clamp(1, MAX_BANDS);
+        let band_height = if y_max > y_min { (y_max - y_min) / count as f32 } else { 1f32 };
+        let mut bands = SegmentBands { y_min: y_min, band_height: band_height, bands: vec![Vec::new(); count] };
+        for (low, high, x_max, curve) in ranges.into_iter() {
+            for band in bands.band(low)..=bands.band(high) {
+                bands.bands[band].push((x_max, curve));
+            }
+        }
+        for band in bands.bands.iter_mut() {
+            band.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        }
+        return bands;
+    }
+
+    fn band(&self, y: f32) -> usize {
+        // Band y is in, clamped to the first and last (which is monotonic in y, so a range of y maps to a range of bands)
+        let index = ((y - self.y_min) / self.band_height).floor();
+        return (index.max(0f32) as usize).min(self.bands.len() - 1);
+    }
+
+    pub fn winding_number(&self, x: f32, y: f32, precision: Precision) -> i32 {
+        /* Winding number of the outline around (x, y): crossings of a ray towards
+        +x, counted +1 going up and -1 going down (signed like curve::winding_number) */
+        let band = &self.bands[self.band(y)];
+        return match precision {
+            Precision::Single => band_crossings::<f32>(band, x, y),
+            Precision::Double => band_crossings::<f64>(band, x, y),
+        };
+    }
+}
+
+fn band_crossings<F: Float>(band: &[(f32, Element)], x: f32, y: f32) -> i32 {
+    // Signed crossings of the curves in a band that reach right of x (see Curve::crossings)
+    let mut winding = 0;
+    for (x_max, curve) in band.iter() {
+        if *x_max < x {
+            break;
+        }
+        winding += curve.crossings::<F>(x, y, 1f32, 0f32);
+    }
+    return winding;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::curve::{winding_number, Contour, Curve};
+    use crate::fixtures::bundled_fonts;
+    use crate::float::Precision;
+    use super::SegmentBands;
+
+    const GLYPHS_PER_FONT: u16 = 200;
+    const POINTS_PER_GLYPH: usize = 50;
+    // Points per glyph for the benchmark
+    const BENCHMARK_POINTS: usize = 2000;
+
+    fn random_point(rng: &mut StdRng, contours: &[Contour], bbox: &ttf_parser::Rect) -> (f32, f32) {
+        // Somewhere around the glyph, at the height of one of its points half the time (where bands meet, too)
+        let x = rng.gen_range(bbox.x_min as f32 - 10f32..bbox.x_max as f32 + 10f32);
+        let ends: Vec<f32> = contours.iter().flat_map(|contour| contour.curves.iter()).map(|curve| curve.evaluate(1f32).1).collect();
+        if !ends.is_empty() && rng.gen_bool(0.5f64) {
+            return (x, ends[rng.gen_range(0..ends.len())]);
+        }
+        return (x, rng.gen_range(bbox.y_min as f32 - 10f32..bbox.y_max as f32 + 10f32));
+    }
+
+    #[test]
+    fn bands_match_every_segment() {
+        let mut rng = StdRng::seed_from_u64(48);
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            for glyph in font.glyphs.iter() {
+                let bands = SegmentBands::new(&glyph.contours);
+                for _ in 0..POINTS_PER_GLYPH {
+                    let (x, y) = random_point(&mut rng, &glyph.contours, &glyph.bbox);
+                    for precision in [Precision::Single, Precision::Double] {
+                        assert_eq!(bands.winding_number(x, y, precision), winding_number(&glyph.contours, x, y, 1f32, 0f32, precision), "{} at ({}, {})", font.path.display(), x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn speedup_per_bundled_font() {
+        // cargo test --release speedup_per_bundled_font -- --ignored --nocapture
+        let mut rng = StdRng::seed_from_u64(48);
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            let glyphs = &font.glyphs;
+            let points: Vec<Vec<(f32, f32)>> = glyphs.iter().map(|glyph| (0..BENCHMARK_POINTS).map(|_| random_point(&mut rng, &glyph.contours, &glyph.bbox)).collect()).collect();
+
+            let start = Instant::now();
+            let mut linear = 0;
+            for (glyph, points) in glyphs.iter().zip(points.iter()) {
+                linear += points.iter().filter(|(x, y)| winding_number(&glyph.contours, *x, *y, 1f32, 0f32, Precision::Double) % 2 != 0).count();
+            }
+            let linear_seconds = start.elapsed().as_secs_f32();
+
+            // Building the bands is part of the cost
+            let start = Instant::now();
+            let mut banded = 0;
+            for (glyph, points) in glyphs.iter().zip(points.iter()) {
+                let bands = SegmentBands::new(&glyph.contours);
+                banded += points.iter().filter(|(x, y)| bands.winding_number(*x, *y, Precision::Double) % 2 != 0).count();
+            }
+            let banded_seconds = start.elapsed().as_secs_f32();
+
+            assert_eq!(linear, banded);
+            let segments = glyphs.iter().flat_map(|glyph| glyph.contours.iter()).map(|contour| contour.curves.len()).sum::<usize>() as f32 / glyphs.len() as f32;
+            println!("{}: {:.1} segments per glyph, every segment {:.3}s, banded {:.3}s, {:.1}x faster",
+                font.path.display(), segments, linear_seconds, banded_seconds, linear_seconds / banded_seconds);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+/* Cache of rasterized outline glyphs, so text that is drawn every frame
+doesn't have to be sampled again. Glyphs are positioned to a fraction of a
+pixel, so each glyph is stored once for every subpixel offset it has been
+drawn at. New glyphs are fitted with the hinting kept alongside. */
+
+use std::collections::HashMap;
+
+use ttf_parser::Tag;
+
+use crate::collection::FaceSelector;
+use crate::float::Precision;
+use crate::hinting::{HintingCache, HintingMode};
+use crate::lcd::SubpixelRendering;
+use crate::raster::Coverage;
+use crate::scanline::Rasterizer;
+use crate::stroke::TextOutline;
+use crate::synthetic::SyntheticStyle;
+use crate::{get_samples_per_axis, RenderSettings};
+
+// Horizontal positions per pixel glyphs can be drawn at (1/4 px)
+pub const SUBPIXEL_POSITIONS: i32 = 4;
+// Sweeping variation axes renders new glyphs every frame, so start over past this
+const MAX_CACHED_GLYPHS: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    font_path: String,
+    face: FaceSelector,
+    character: char,
+    // Axis values as bits, since f32 can't be hashed
+    variations: Vec<(Tag, u32)>,
+    hinting: HintingMode,
+    subpixel: SubpixelRendering,
+    stem_darkening: bool,
+    synthetic: SyntheticStyle,
+    outline: TextOutline,
+    // Linear part of the text transform as bits
+    transform: [u32; 4],
+    precision: Precision,
+    rasterizer: Rasterizer,
+    samples: i32,
+    // Offset in 1/SUBPIXEL_POSITIONS of a pixel
+    x_offset: i32,
+}
+
+pub struct CachedGlyph {
+    pub coverage: Coverage,
+    // Stroke drawn under the glyph for bordered text, over the same pixels as coverage
+    pub border: Option<Coverage>,
+    // Pixel offset of the coverage from where the glyph is drawn, for transforms that reach left or down
+    pub origin: (i32, i32),
+    // Right and top edges of the glyph in pixels
+    pub x_max: f32,
+    pub y_max: f32,
+}
+
+#[derive(Default)]
+pub struct GlyphCache {
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    // Kept when the glyphs start over, it's the same for every glyph of a face
+    pub hinting: HintingCache,
+}
+
+impl GlyphKey {
+    pub fn new(settings: &RenderSettings, character: char, x_offset: i32) -> GlyphKey {
+        // The palette only matters for color glyphs and threads don't change the result, so they're left out
+        let style = &settings.style;
+        return GlyphKey {
+            font_path: settings.font_path.to_string(),
+            face: settings.face_selector.clone(),
+            character,
+            variations: settings.variations.iter().map(|v| (v.axis, v.value.to_bits())).collect(),
+            hinting: settings.hinting,
+            subpixel: settings.subpixel,
+            // Colors are applied when drawing, only the outline changes matter here
+            stem_darkening: style.stem_darkening,
+            synthetic: style.synthetic,
+            outline: style.outline,
+            transform: [style.transform.xx, style.transform.yx, style.transform.xy, style.transform.yy].map(f32::to_bits),
+            precision: style.precision,
+            rasterizer: style.rasterizer,
+            samples: get_samples_per_axis(&settings.sample_mode),
+            x_offset,
+        };
+    }
+}
+
+impl GlyphCache {
+    pub fn get(&self, key: &GlyphKey) -> Option<&CachedGlyph> {
+        return self.glyphs.get(key);
+    }
+
+    pub fn insert(&mut self, key: GlyphKey, glyph: CachedGlyph) {
+        if self.glyphs.len() >= MAX_CACHED_GLYPHS {
+            self.glyphs.clear();
+        }
+        self.glyphs.insert(key, glyph);
+    }
+}
+
+pub fn quantize_position(x: f32) -> (i32, i32) {
+    // Splits a position into whole pixels and the nearest subpixel offset
+    let steps = (x * SUBPIXEL_POSITIONS as f32).round() as i32;
+    return (steps.div_euclid(SUBPIXEL_POSITIONS), steps.rem_euclid(SUBPIXEL_POSITIONS));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collection::FaceSelector;
+    use crate::float::Precision;
+    use crate::gamma::TextStyle;
+    use crate::hinting::HintingMode;
+    use crate::lcd::SubpixelRendering;
+    use crate::raster::Coverage;
+    use crate::scanline::Rasterizer;
+    use crate::stroke::TextOutline;
+    use crate::synthetic::SyntheticStyle;
+    use crate::transform::Transform;
+    use crate::{RenderSettings, Supersampling};
+    use super::{quantize_position, CachedGlyph, GlyphCache, GlyphKey, SUBPIXEL_POSITIONS};
+
+    fn settings(face_selector: &FaceSelector) -> RenderSettings<'_> {
+        let style = TextStyle {
+            foreground: [0f32, 0f32, 0f32, 1f32],
+            background: [1f32, 1f32, 1f32],
+            gamma: 2.2f32,
+            contrast: 0f32,
+            stem_darkening: false,
+            synthetic: SyntheticStyle { bold: false, oblique: false },
+            outline: TextOutline::Fill,
+            border: [1f32, 0f32, 0f32, 1f32],
+            transform: Transform::IDENTITY,
+            precision: Precision::Double,
+            rasterizer: Rasterizer::RayCasting,
+        };
+        return RenderSettings {
+            sample_mode: Supersampling::TwoByTwo,
+            font_path: "fonts/Roboto-Regular.ttf",
+            face_selector: face_selector,
+            variations: &[],
+            palette: 0,
+            hinting: HintingMode::None,
+            subpixel: SubpixelRendering::Off,
+            style: style,
+            threads: 1,
+        };
+    }
+
+    fn glyph() -> CachedGlyph {
+        let coverage = Coverage { x_min: 0, y_min: 0, width: 1, height: 1, data: vec![[1f32; 3]] };
+        return CachedGlyph { coverage, border: None, origin: (0, 0), x_max: 1f32, y_max: 1f32 };
+    }
+
+    #[test]
+    fn positions_snap_to_quarter_pixels() {
+        assert_eq!(quantize_position(0f32), (0, 0));
+        assert_eq!(quantize_position(1.3f32), (1, 1));
+        assert_eq!(quantize_position(2.6f32), (2, 2));
+        // Close enough to the next pixel rounds up to its first offset
+        assert_eq!(quantize_position(1.9f32), (2, 0));
+        assert_eq!(quantize_position(2.88f32), (3, 0));
+        // Negative positions still have offsets from 0 up, counted from the pixel to the left
+        assert_eq!(quantize_position(-0.3f32), (-1, 3));
+        assert_eq!(quantize_position(-0.5f32), (-1, 2));
+        assert_eq!(quantize_position(-1f32), (-1, 0));
+        assert_eq!(quantize_position(-0.1f32), (0, 0));
+
+        // Never further than half a step from where the glyph was asked for
+        for i in -400..400 {
+            let x = i as f32 * 0.0137f32;
+            let (pixel, offset) = quantize_position(x);
+            assert!((0..SUBPIXEL_POSITIONS).contains(&offset));
+            let snapped = pixel as f32 + offset as f32 / SUBPIXEL_POSITIONS as f32;
+            assert!((snapped - x).abs() <= 0.5f32 / SUBPIXEL_POSITIONS as f32 + 1e-5f32, "{} snapped to {}", x, snapped);
+        }
+    }
+
+    #[test]
+    fn keys_are_separate_per_offset() {
+        let face_selector = FaceSelector::Index(0);
+        let settings = settings(&face_selector);
+        let mut cache = GlyphCache::default();
+        cache.insert(GlyphKey::new(&settings, 'a', 1), glyph());
+        assert!(cache.get(&GlyphKey::new(&settings, 'a', 1)).is_some());
+        for offset in [0, 2, 3] {
+            assert!(cache.get(&GlyphKey::new(&settings, 'a', offset)).is_none(), "offset {}", offset);
+        }
+        assert!(cache.get(&GlyphKey::new(&settings, 'b', 1)).is_none());
+
+        // Anything that changes the coverage is part of the key
+        let rotated = RenderSettings { style: TextStyle { transform: Transform::rotate(0.1f32), ..settings.style }, ..settings };
+        let lcd = RenderSettings { subpixel: SubpixelRendering::Rgb, ..settings };
+        let sampled = RenderSettings { sample_mode: Supersampling::ThreeByThree, ..settings };
+        let hinted = RenderSettings { hinting: HintingMode::Full, ..settings };
+        for changed in [rotated, lcd, sampled, hinted] {
+            assert!(cache.get(&GlyphKey::new(&changed, 'a', 1)).is_none());
+        }
+        // What doesn't, isn't
+        let recolored = RenderSettings { palette: 1, threads: 4, style: TextStyle { foreground: [1f32, 0f32, 0f32, 1f32], ..settings.style }, ..settings };
+        assert!(cache.get(&GlyphKey::new(&recolored, 'a', 1)).is_some());
+    }
+}
@@ -0,0 +1,87 @@
+/* Face selection for TrueType/OpenType collections (.ttc/.otc). A plain
+.ttf/.otf file is treated as a collection with a single face at index 0. */
+
+use ttf_parser::Face;
+use ttf_parser::name_id;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FaceSelector {
+    // Face at the given position in the collection
+    Index(u32),
+    // Face whose PostScript name or full name matches (case-insensitive)
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FaceInfo {
+    pub index: u32,
+    pub family: String,
+    pub subfamily: String,
+    pub full_name: String,
+    pub postscript_name: String,
+}
+
+pub fn face_count(data: &[u8]) -> u32 {
+    // Number of faces stored in the file (1 for anything that isn't a collection)
+    return ttf_parser::fonts_in_collection(data).unwrap_or(1);
+}
+
+pub fn enumerate_faces(data: &[u8]) -> Vec<FaceInfo> {
+    /* Lists the family/style names of every face in the file. Faces that fail
+    to parse are skipped rather than aborting the whole listing. */
+    let mut faces = Vec::<FaceInfo>::new();
+    for index in 0..face_count(data) {
+        if let Ok(face) = Face::parse(data, index) {
+            faces.push(get_face_info(&face, index));
+        }
+    }
+    return faces;
+}
+
+pub fn get_face_info(face: &Face, index: u32) -> FaceInfo {
+    // Prefer the typographic names (16/17) since they group all weights under one family
+    let family = get_name(face, name_id::TYPOGRAPHIC_FAMILY)
+        .or_else(|| get_name(face, name_id::FAMILY))
+        .unwrap_or_default();
+    let subfamily = get_name(face, name_id::TYPOGRAPHIC_SUBFAMILY)
+        .or_else(|| get_name(face, name_id::SUBFAMILY))
+        .unwrap_or_default();
+    let full_name = get_name(face, name_id::FULL_NAME).unwrap_or_default();
+    let postscript_name = get_name(face, name_id::POST_SCRIPT_NAME).unwrap_or_default();
+    return FaceInfo { index, family, subfamily, full_name, postscript_name };
+}
+
+pub fn find_face_index(data: &[u8], selector: &FaceSelector) -> Option<u32> {
+    // Resolves a selector to a face index within the file
+    match selector {
+        FaceSelector::Index(index) => {
+            if *index < face_count(data) {
+                return Some(*index);
+            }
+            return None;
+        },
+        FaceSelector::Name(name) => {
+            return enumerate_faces(data).iter()
+                .find(|info| info.postscript_name.eq_ignore_ascii_case(name) || info.full_name.eq_ignore_ascii_case(name))
+                .map(|info| info.index);
+        }
+    }
+}
+
+pub fn load_face<'a>(data: &'a [u8], selector: &FaceSelector) -> Result<Face<'a>, String> {
+    // Parses the face chosen by the selector
+    let index = match find_face_index(data, selector) {
+        Some(i) => i,
+        None => {
+            return Err(format!("no face matching {:?} in font file", selector));
+        }
+    };
+    return Face::parse(data, index).map_err(|e| e.to_string());
+}
+
+fn get_name(face: &Face, id: u16) -> Option<String> {
+    // Returns the first Unicode record with the given name id
+    return face.names().into_iter()
+        .filter(|name| name.name_id == id && name.is_unicode())
+        .find_map(|name| name.to_string());
+}
@@ -0,0 +1,777 @@
+/* Color glyphs from the COLR and CPAL tables.
+https://learn.microsoft.com/en-us/typography/opentype/spec/colr
+https://learn.microsoft.com/en-us/typography/opentype/spec/cpal
+
+Both COLRv0 layer lists and COLRv1 paint graphs are parsed into the same Paint
+tree, which is then rendered into an RgbaBitmap. Every PaintGlyph is filled
+with the same ray-casting test and non-zero rule the monochrome renderer uses,
+on the outline from extract_glyph_outline. Variation deltas on COLRv1 paints (the Var* paint
+formats) are not applied; their default values are used. */
+
+use ttf_parser::{Face, GlyphId, Tag};
+
+use crate::read::{read_f2dot14, read_i16, read_u16, read_u24, read_u32};
+use crate::bitmap::{BitmapBounds, CompositeMode, RgbaBitmap};
+use crate::bands::SegmentBands;
+use crate::float::Precision;
+use crate::outline::extract_glyph_outline;
+use crate::scanline::GLYPH_FILL;
+use crate::transform::Transform;
+use crate::variation::Variation;
+
+// Palette index that stands for the text (foreground) color
+const FOREGROUND_PALETTE_INDEX: u16 = 0xFFFF;
+// Guards against cycles in malformed paint graphs
+const MAX_PAINT_DEPTH: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Extend {
+    Pad,
+    Repeat,
+    Reflect,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ColorLine {
+    pub(crate) extend: Extend,
+    // (offset, unpremultiplied RGBA)
+    pub(crate) stops: Vec<(f32, [f32; 4])>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Paint {
+    Layers(Vec<Paint>),
+    Solid([f32; 4]),
+    LinearGradient { line: ColorLine, p0: (f32, f32), p1: (f32, f32), p2: (f32, f32) },
+    RadialGradient { line: ColorLine, c0: (f32, f32), r0: f32, c1: (f32, f32), r1: f32 },
+    SweepGradient { line: ColorLine, center: (f32, f32), start_angle: f32, end_angle: f32 },
+    Glyph { glyph_id: GlyphId, paint: Box<Paint> },
+    Transform { transform: Transform, paint: Box<Paint> },
+    Composite { source: Box<Paint>, mode: CompositeMode, backdrop: Box<Paint> },
+}
+
+struct Parser<'a> {
+    colr: &'a [u8],
+    cpal: Option<&'a [u8]>,
+    palette: u16,
+    foreground: [f32; 4],
+}
+
+// Everything the renderer needs to turn font units into pixels
+struct Target<'a> {
+    face: &'a Face<'a>,
+    variations: &'a [Variation],
+    bounds: BitmapBounds,
+    ratio: f32,
+    samples: i32,
+    width: usize,
+    height: usize,
+}
+
+pub fn palette_count(face: &Face) -> u16 {
+    return match face.raw_face().table(Tag::from_bytes(b"CPAL")) {
+        Some(cpal) => read_u16(cpal, 4).unwrap_or(0),
+        None => 0,
+    };
+}
+
+pub fn is_color_glyph(face: &Face, glyph_id: GlyphId) -> bool {
+    let colr = match face.raw_face().table(Tag::from_bytes(b"COLR")) {
+        Some(c) => c,
+        None => return false,
+    };
+    return find_v1_base_glyph(colr, glyph_id).is_some() || find_v0_base_glyph(colr, glyph_id).is_some();
+}
+
+pub fn render_color_glyph(face: &Face, glyph_id: GlyphId, palette: u16, foreground: [f32; 4], ratio: f32, samples: i32, variations: &[Variation]) -> Option<(RgbaBitmap, BitmapBounds)> {
+    /* Renders a color glyph at the given scale (pixels per font unit) with
+    samples x samples supersampling of glyph edges. Returns None if the glyph
+    has no color version. foreground is the unpremultiplied text color used for
+    layers that reference palette entry 0xFFFF. */
+    let colr = face.raw_face().table(Tag::from_bytes(b"COLR"))?;
+    let parser = Parser { colr, cpal: face.raw_face().table(Tag::from_bytes(b"CPAL")), palette, foreground };
+    let paint = parser.parse_base_glyph(glyph_id, 0)?;
+
+    let bounds = match find_clip_box(colr, glyph_id) {
+        Some(clip) => clip,
+        None => {
+            let mut bounds: Option<BitmapBounds> = None;
+            paint_bounds(face, &paint, &Transform::IDENTITY, &mut bounds, 0);
+            bounds?
+        }
+    };
+    let width = ((bounds.x_max - bounds.x_min) * ratio).ceil().max(0f32) as usize;
+    let height = ((bounds.y_max - bounds.y_min) * ratio).ceil().max(0f32) as usize;
+    let target = Target { face, variations, bounds, ratio, samples: samples.max(1), width, height };
+    let bitmap = render_paint(&target, &paint, &Transform::IDENTITY, 0);
+    return Some((bitmap, bounds));
+}
+
+impl<'a> Parser<'a> {
+    fn parse_base_glyph(&self, glyph_id: GlyphId, depth: u32) -> Option<Paint> {
+        // COLRv1 takes priority over COLRv0 for glyphs that are in both
+        if depth > MAX_PAINT_DEPTH {
+            return None;
+        }
+        if let Some(offset) = find_v1_base_glyph(self.colr, glyph_id) {
+            return self.parse_paint(offset, depth + 1);
+        }
+        let (first_layer, num_layers) = find_v0_base_glyph(self.colr, glyph_id)?;
+        let layer_records = read_u32(self.colr, 8)? as usize;
+        let mut layers = Vec::<Paint>::new();
+        for i in first_layer..(first_layer + num_layers) {
+            let record = layer_records + i * 4;
+            let layer_glyph = GlyphId(read_u16(self.colr, record)?);
+            let color = self.get_color(read_u16(self.colr, record + 2)?, 1f32);
+            layers.push(Paint::Glyph { glyph_id: layer_glyph, paint: Box::new(Paint::Solid(color)) });
+        }
+        return Some(Paint::Layers(layers));
+    }
+
+    fn parse_paint(&self, offset: usize, depth: u32) -> Option<Paint> {
+        // Reads the paint table at the given offset from the start of COLR
+        if depth > MAX_PAINT_DEPTH {
+            return None;
+        }
+        let data = self.colr;
+        let child = |field: usize| -> Option<Box<Paint>> {
+            let child_offset = read_u24(data, offset + field)? as usize;
+            return Some(Box::new(self.parse_paint(offset + child_offset, depth + 1)?));
+        };
+        let fword = |field: usize| -> Option<f32> {
+            return Some(read_i16(data, offset + field)? as f32);
+        };
+        let f2dot14 = |field: usize| -> Option<f32> {
+            return read_f2dot14(data, offset + field);
+        };
+        let format = *data.get(offset)?;
+        let paint = match format {
+            1 => {
+                // PaintColrLayers
+                let num_layers = *data.get(offset + 1)? as usize;
+                let first_layer = read_u32(data, offset + 2)? as usize;
+                let layer_list = read_u32(data, 18)? as usize;
+                let mut layers = Vec::<Paint>::new();
+                for i in first_layer..(first_layer + num_layers) {
+                    let paint_offset = read_u32(data, layer_list + 4 + i * 4)? as usize;
+                    layers.push(self.parse_paint(layer_list + paint_offset, depth + 1)?);
+                }
+                Paint::Layers(layers)
+            },
+            2 | 3 => {
+                let alpha = f2dot14(3)?;
+                Paint::Solid(self.get_color(read_u16(data, offset + 1)?, alpha))
+            },
+            4 | 5 => {
+                let line = self.parse_color_line(offset + read_u24(data, offset + 1)? as usize, format == 5)?;
+                Paint::LinearGradient {
+                    line,
+                    p0: (fword(4)?, fword(6)?),
+                    p1: (fword(8)?, fword(10)?),
+                    p2: (fword(12)?, fword(14)?),
+                }
+            },
+            6 | 7 => {
+                let line = self.parse_color_line(offset + read_u24(data, offset + 1)? as usize, format == 7)?;
+                Paint::RadialGradient {
+                    line,
+                    c0: (fword(4)?, fword(6)?),
+                    r0: read_u16(data, offset + 8)? as f32,
+                    c1: (fword(10)?, fword(12)?),
+                    r1: read_u16(data, offset + 14)? as f32,
+                }
+            },
+            8 | 9 => {
+                let line = self.parse_color_line(offset + read_u24(data, offset + 1)? as usize, format == 9)?;
+                Paint::SweepGradient {
+                    line,
+                    center: (fword(4)?, fword(6)?),
+                    start_angle: f2dot14(8)? * 180f32,
+                    end_angle: f2dot14(10)? * 180f32,
+                }
+            },
+            10 => Paint::Glyph { glyph_id: GlyphId(read_u16(data, offset + 4)?), paint: child(1)? },
+            11 => self.parse_base_glyph(GlyphId(read_u16(data, offset + 1)?), depth + 1)?,
+            12 | 13 => {
+                let transform_offset = offset + read_u24(data, offset + 4)? as usize;
+                let fixed = |field: usize| -> Option<f32> {
+                    return Some(read_u32(data, transform_offset + field)? as i32 as f32 / 65536f32);
+                };
+                let transform = Transform { xx: fixed(0)?, yx: fixed(4)?, xy: fixed(8)?, yy: fixed(12)?, dx: fixed(16)?, dy: fixed(20)? };
+                Paint::Transform { transform, paint: child(1)? }
+            },
+            14 | 15 => Paint::Transform { transform: Transform::translate(fword(4)?, fword(6)?), paint: child(1)? },
+            16..=23 => {
+                let (sx, sy, center_field) = match format {
+                    16 | 17 => (f2dot14(4)?, f2dot14(6)?, None),
+                    18 | 19 => (f2dot14(4)?, f2dot14(6)?, Some(8)),
+                    20 | 21 => (f2dot14(4)?, f2dot14(4)?, None),
+                    _ => (f2dot14(4)?, f2dot14(4)?, Some(6)),
+                };
+                let mut transform = Transform::scale(sx, sy);
+                if let Some(field) = center_field {
+                    transform = transform.around(fword(field)?, fword(field + 2)?);
+                }
+                Paint::Transform { transform, paint: child(1)? }
+            },
+            24..=27 => {
+                let angle = (f2dot14(4)? * 180f32).to_radians();
+                let mut transform = Transform::rotate(angle);
+                if format >= 26 {
+                    transform = transform.around(fword(6)?, fword(8)?);
+                }
+                Paint::Transform { transform, paint: child(1)? }
+            },
+            28..=31 => {
+                let x_skew = (f2dot14(4)? * 180f32).to_radians();
+                let y_skew = (f2dot14(6)? * 180f32).to_radians();
+                let mut transform = Transform::skew(-x_skew.tan(), y_skew.tan());
+                if format >= 30 {
+                    transform = transform.around(fword(8)?, fword(10)?);
+                }
+                Paint::Transform { transform, paint: child(1)? }
+            },
+            32 => Paint::Composite {
+                source: child(1)?,
+                mode: composite_mode(*data.get(offset + 4)?)?,
+                backdrop: child(5)?,
+            },
+            _ => return None,
+        };
+        return Some(paint);
+    }
+
+    fn parse_color_line(&self, offset: usize, variable: bool) -> Option<ColorLine> {
+        let data = self.colr;
+        let extend = match *data.get(offset)? {
+            1 => Extend::Repeat,
+            2 => Extend::Reflect,
+            _ => Extend::Pad,
+        };
+        let count = read_u16(data, offset + 1)? as usize;
+        // VarColorStop has an extra u32 varIndexBase
+        let stop_size = if variable { 10 } else { 6 };
+        let mut stops = Vec::<(f32, [f32; 4])>::new();
+        for i in 0..count {
+            let stop = offset + 3 + i * stop_size;
+            let color = self.get_color(read_u16(data, stop + 2)?, read_f2dot14(data, stop + 4)?);
+            stops.push((read_f2dot14(data, stop)?, color));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if stops.is_empty() {
+            return None;
+        }
+        return Some(ColorLine { extend, stops });
+    }
+
+    fn get_color(&self, palette_index: u16, alpha: f32) -> [f32; 4] {
+        // Unpremultiplied color for a palette entry, with the extra alpha applied
+        if palette_index == FOREGROUND_PALETTE_INDEX {
+            let mut color = self.foreground;
+            color[3] *= alpha;
+            return color;
+        }
+        let color = self.cpal.and_then(|cpal| read_palette_color(cpal, self.palette, palette_index));
+        let mut color = color.unwrap_or(self.foreground);
+        color[3] *= alpha;
+        return color;
+    }
+}
+
+fn read_palette_color(cpal: &[u8], palette: u16, palette_index: u16) -> Option<[f32; 4]> {
+    // CPAL colors are stored as BGRA bytes
+    let num_entries = read_u16(cpal, 2)?;
+    let num_palettes = read_u16(cpal, 4)?;
+    if palette_index >= num_entries {
+        return None;
+    }
+    let palette = if palette < num_palettes { palette } else { 0 };
+    let records = read_u32(cpal, 8)? as usize;
+    let first = read_u16(cpal, 12 + palette as usize * 2)? as usize;
+    let record = records + (first + palette_index as usize) * 4;
+    let bgra = cpal.get(record..record + 4)?;
+    return Some([bgra[2] as f32 / 255f32, bgra[1] as f32 / 255f32, bgra[0] as f32 / 255f32, bgra[3] as f32 / 255f32]);
+}
+
+fn find_v0_base_glyph(colr: &[u8], glyph_id: GlyphId) -> Option<(usize, usize)> {
+    // Binary search of the BaseGlyphRecords, returns (first layer, layer count)
+    let count = read_u16(colr, 2)? as usize;
+    let records = read_u32(colr, 4)? as usize;
+    let (mut low, mut high) = (0usize, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        let record = records + mid * 6;
+        let id = read_u16(colr, record)?;
+        if id == glyph_id.0 {
+            return Some((read_u16(colr, record + 2)? as usize, read_u16(colr, record + 4)? as usize));
+        } else if id < glyph_id.0 {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    return None;
+}
+
+fn find_v1_base_glyph(colr: &[u8], glyph_id: GlyphId) -> Option<usize> {
+    // Binary search of the BaseGlyphList, returns the paint offset from the start of COLR
+    if read_u16(colr, 0)? < 1 {
+        return None;
+    }
+    let list = read_u32(colr, 14)? as usize;
+    if list == 0 {
+        return None;
+    }
+    let count = read_u32(colr, list)? as usize;
+    let (mut low, mut high) = (0usize, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        let record = list + 4 + mid * 6;
+        let id = read_u16(colr, record)?;
+        if id == glyph_id.0 {
+            return Some(list + read_u32(colr, record + 2)? as usize);
+        } else if id < glyph_id.0 {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    return None;
+}
+
+fn find_clip_box(colr: &[u8], glyph_id: GlyphId) -> Option<BitmapBounds> {
+    // COLRv1 ClipList entry for the glyph, if it has one
+    if read_u16(colr, 0)? < 1 {
+        return None;
+    }
+    let list = read_u32(colr, 22)? as usize;
+    if list == 0 {
+        return None;
+    }
+    let count = read_u32(colr, list + 1)? as usize;
+    for i in 0..count {
+        let clip = list + 5 + i * 7;
+        let start = read_u16(colr, clip)?;
+        let end = read_u16(colr, clip + 2)?;
+        if glyph_id.0 >= start && glyph_id.0 <= end {
+            let clip_box = list + read_u24(colr, clip + 4)? as usize;
+            return Some(BitmapBounds {
+                x_min: read_i16(colr, clip_box + 1)? as f32,
+                y_min: read_i16(colr, clip_box + 3)? as f32,
+                x_max: read_i16(colr, clip_box + 5)? as f32,
+                y_max: read_i16(colr, clip_box + 7)? as f32,
+            });
+        }
+    }
+    return None;
+}
+
+fn composite_mode(value: u8) -> Option<CompositeMode> {
+    let modes = [
+        CompositeMode::Clear, CompositeMode::Source, CompositeMode::Destination,
+        CompositeMode::SourceOver, CompositeMode::DestinationOver, CompositeMode::SourceIn,
+        CompositeMode::DestinationIn, CompositeMode::SourceOut, CompositeMode::DestinationOut,
+        CompositeMode::SourceAtop, CompositeMode::DestinationAtop, CompositeMode::Xor,
+        CompositeMode::Plus, CompositeMode::Screen, CompositeMode::Overlay,
+        CompositeMode::Darken, CompositeMode::Lighten, CompositeMode::ColorDodge,
+        CompositeMode::ColorBurn, CompositeMode::HardLight, CompositeMode::SoftLight,
+        CompositeMode::Difference, CompositeMode::Exclusion, CompositeMode::Multiply,
+        CompositeMode::Hue, CompositeMode::Saturation, CompositeMode::Color,
+        CompositeMode::Luminosity,
+    ];
+    return modes.get(value as usize).copied();
+}
+
+fn paint_bounds(face: &Face, paint: &Paint, transform: &Transform, bounds: &mut Option<BitmapBounds>, depth: u32) {
+    // Union of the (transformed) bounding boxes of every glyph used by the paint graph
+    if depth > MAX_PAINT_DEPTH {
+        return;
+    }
+    match paint {
+        Paint::Layers(layers) => {
+            for layer in layers {
+                paint_bounds(face, layer, transform, bounds, depth + 1);
+            }
+        },
+        Paint::Glyph { glyph_id, .. } => {
+            let rect = match face.glyph_bounding_box(*glyph_id) {
+                Some(r) => r,
+                None => return,
+            };
+            let corners = [
+                (rect.x_min as f32, rect.y_min as f32), (rect.x_max as f32, rect.y_min as f32),
+                (rect.x_min as f32, rect.y_max as f32), (rect.x_max as f32, rect.y_max as f32),
+            ];
+            for (x, y) in corners {
+                let (x, y) = transform.apply(x, y);
+                *bounds = Some(match *bounds {
+                    Some(b) => BitmapBounds { x_min: b.x_min.min(x), y_min: b.y_min.min(y), x_max: b.x_max.max(x), y_max: b.y_max.max(y) },
+                    None => BitmapBounds { x_min: x, y_min: y, x_max: x, y_max: y },
+                });
+            }
+        },
+        Paint::Transform { transform: inner, paint } => {
+            paint_bounds(face, paint, &transform.then(inner), bounds, depth + 1);
+        },
+        Paint::Composite { source, backdrop, .. } => {
+            paint_bounds(face, source, transform, bounds, depth + 1);
+            paint_bounds(face, backdrop, transform, bounds, depth + 1);
+        },
+        _ => {}
+    }
+}
+
+fn render_paint(target: &Target, paint: &Paint, transform: &Transform, depth: u32) -> RgbaBitmap {
+    /* Renders a paint node into a bitmap covering the whole glyph. transform
+    maps the node's coordinate space to glyph space (font units). */
+    let mut bitmap = RgbaBitmap::new(target.width, target.height);
+    if depth > MAX_PAINT_DEPTH {
+        return bitmap;
+    }
+    match paint {
+        Paint::Layers(layers) => {
+            for layer in layers {
+                let layer_bitmap = render_paint(target, layer, transform, depth + 1);
+                bitmap.composite(&layer_bitmap, CompositeMode::SourceOver);
+            }
+        },
+        Paint::Glyph { glyph_id, paint } => {
+            let inverse = match transform.invert() {
+                Some(i) => i,
+                None => return bitmap,
+            };
+            let (contours, _) = extract_glyph_outline(target.face, *glyph_id, target.variations);
+            let bands = SegmentBands::new(&contours);
+            let fill = render_paint(target, paint, transform, depth + 1);
+            for y in 0..target.height {
+                for x in 0..target.width {
+                    let coverage = glyph_coverage(target, &bands, &inverse, x, y);
+                    if coverage > 0f32 {
+                        let color = fill.get(x, y);
+                        bitmap.set(x, y, [color[0] * coverage, color[1] * coverage, color[2] * coverage, color[3] * coverage]);
+                    }
+                }
+            }
+        },
+        Paint::Transform { transform: inner, paint } => {
+            return render_paint(target, paint, &transform.then(inner), depth + 1);
+        },
+        Paint::Composite { source, mode, backdrop } => {
+            let source_bitmap = render_paint(target, source, transform, depth + 1);
+            bitmap = render_paint(target, backdrop, transform, depth + 1);
+            bitmap.composite(&source_bitmap, *mode);
+        },
+        _ => {
+            // Solid colors and gradients are smooth, so sampling pixel centers is enough
+            let inverse = match transform.invert() {
+                Some(i) => i,
+                None => return bitmap,
+            };
+            for y in 0..target.height {
+                for x in 0..target.width {
+                    let (gx, gy) = pixel_to_font_units(target, x as f32 + 0.5f32, y as f32 + 0.5f32);
+                    let (px, py) = inverse.apply(gx, gy);
+                    bitmap.set(x, y, premultiply(fill_color(paint, px, py)));
+                }
+            }
+        },
+    }
+    return bitmap;
+}
+
+fn pixel_to_font_units(target: &Target, x: f32, y: f32) -> (f32, f32) {
+    // Bitmap rows go from the top down, font units go from the bottom up
+    return (target.bounds.x_min + x / target.ratio, target.bounds.y_max - y / target.ratio);
+}
+
+fn glyph_coverage(target: &Target, bands: &SegmentBands, inverse: &Transform, x: usize, y: usize) -> f32 {
+    // Fraction of the samples x samples grid inside the pixel that falls within the outline
+    let n = target.samples;
+    let mut inside = 0;
+    for x_subsample in 0..n {
+        for y_subsample in 0..n {
+            let sample_x = x as f32 + (x_subsample as f32 + 0.5f32) / n as f32;
+            let sample_y = y as f32 + (y_subsample as f32 + 0.5f32) / n as f32;
+            let (gx, gy) = pixel_to_font_units(target, sample_x, sample_y);
+            let (ox, oy) = inverse.apply(gx, gy);
+            if GLYPH_FILL.contains(bands.winding_number(ox, oy, Precision::Double)) {
+                inside += 1;
+            }
+        }
+    }
+    return inside as f32 / (n * n) as f32;
+}
+
+pub(crate) fn fill_color(paint: &Paint, x: f32, y: f32) -> [f32; 4] {
+    // Unpremultiplied color of a solid or gradient paint at (x, y) in paint space
+    match paint {
+        Paint::Solid(color) => return *color,
+        Paint::LinearGradient { line, p0, p1, p2 } => {
+            // p2 only sets the angle of the color bands: project p1 onto the
+            // line through p0 perpendicular to p0->p2
+            let (ux, uy) = (p2.0 - p0.0, p2.1 - p0.1);
+            let (nx, ny) = (uy, -ux);
+            let normal_length = nx * nx + ny * ny;
+            let (dx, dy) = if normal_length == 0f32 {
+                (p1.0 - p0.0, p1.1 - p0.1)
+            } else {
+                let k = ((p1.0 - p0.0) * nx + (p1.1 - p0.1) * ny) / normal_length;
+                (k * nx, k * ny)
+            };
+            let length = dx * dx + dy * dy;
+            if length == 0f32 {
+                return [0f32; 4];
+            }
+            let t = ((x - p0.0) * dx + (y - p0.1) * dy) / length;
+            return line.color_at(t);
+        },
+        Paint::RadialGradient { line, c0, r0, c1, r1 } => {
+            // Two-point conical gradient: largest t with |p - c(t)| = r(t) and r(t) >= 0
+            let (cdx, cdy) = (c1.0 - c0.0, c1.1 - c0.1);
+            let (pdx, pdy) = (x - c0.0, y - c0.1);
+            let dr = r1 - r0;
+            let a = cdx * cdx + cdy * cdy - dr * dr;
+            let b = pdx * cdx + pdy * cdy + r0 * dr;
+            let c = pdx * pdx + pdy * pdy - r0 * r0;
+            let t = if a.abs() < 1e-6 {
+                if b == 0f32 {
+                    return [0f32; 4];
+                }
+                c / (2f32 * b)
+            } else {
+                let discriminant = b * b - a * c;
+                if discriminant < 0f32 {
+                    return [0f32; 4];
+                }
+                let t1 = (b + discriminant.sqrt()) / a;
+                let t2 = (b - discriminant.sqrt()) / a;
+                let (high, low) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+                if r0 + high * dr >= 0f32 {
+                    high
+                } else if r0 + low * dr >= 0f32 {
+                    low
+                } else {
+                    return [0f32; 4];
+                }
+            };
+            return line.color_at(t);
+        },
+        Paint::SweepGradient { line, center, start_angle, end_angle } => {
+            // Counter-clockwise angle from the positive x axis, in degrees
+            let mut angle = (y - center.1).atan2(x - center.0).to_degrees();
+            if angle < 0f32 {
+                angle += 360f32;
+            }
+            if end_angle == start_angle {
+                return line.color_at(if angle < *start_angle { 0f32 } else { 1f32 });
+            }
+            return line.color_at((angle - start_angle) / (end_angle - start_angle));
+        },
+        _ => return [0f32; 4],
+    }
+}
+
+impl ColorLine {
+    fn color_at(&self, t: f32) -> [f32; 4] {
+        // Interpolates between stops in premultiplied space, applying the extend mode
+        let first = self.stops[0].0;
+        let last = self.stops[self.stops.len() - 1].0;
+        let span = last - first;
+        let mut t = t;
+        if span > 0f32 {
+            let u = (t - first) / span;
+            t = match self.extend {
+                Extend::Pad => t,
+                Extend::Repeat => first + (u - u.floor()) * span,
+                Extend::Reflect => {
+                    let period = u.rem_euclid(2f32);
+                    first + (if period > 1f32 { 2f32 - period } else { period }) * span
+                },
+            };
+        }
+        if t <= first {
+            return self.stops[0].1;
+        }
+        if t >= last {
+            return self.stops[self.stops.len() - 1].1;
+        }
+        for pair in self.stops.windows(2) {
+            let (o0, c0) = pair[0];
+            let (o1, c1) = pair[1];
+            if t >= o0 && t <= o1 {
+                let f = if o1 > o0 { (t - o0) / (o1 - o0) } else { 0f32 };
+                let a = premultiply(c0);
+                let b = premultiply(c1);
+                let mixed = [
+                    a[0] + (b[0] - a[0]) * f,
+                    a[1] + (b[1] - a[1]) * f,
+                    a[2] + (b[2] - a[2]) * f,
+                    a[3] + (b[3] - a[3]) * f,
+                ];
+                if mixed[3] <= 0f32 {
+                    return [0f32; 4];
+                }
+                return [mixed[0] / mixed[3], mixed[1] / mixed[3], mixed[2] / mixed[3], mixed[3]];
+            }
+        }
+        return self.stops[self.stops.len() - 1].1;
+    }
+}
+
+pub(crate) fn premultiply(color: [f32; 4]) -> [f32; 4] {
+    return [color[0] * color[3], color[1] * color[3], color[2] * color[3], color[3]];
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{Face, GlyphId};
+
+    use crate::bands::SegmentBands;
+    use crate::bitmap::BitmapBounds;
+    use crate::curve::{Contour, Element, Line};
+    use crate::transform::Transform;
+    use super::{find_v1_base_glyph, fill_color, glyph_coverage, Paint, Parser, Target};
+
+    const RED: [f32; 4] = [1f32, 0f32, 0f32, 1f32];
+    const GREEN: [f32; 4] = [0f32, 1f32, 0f32, 1f32];
+    const BLUE: [f32; 4] = [0f32, 0f32, 1f32, 1f32];
+    const FOREGROUND: [f32; 4] = [0.25f32, 0.25f32, 0.25f32, 1f32];
+
+    fn table(fields: &[&[u8]]) -> Vec<u8> {
+        return fields.concat();
+    }
+
+    fn cpal() -> Vec<u8> {
+        // Two entries in two palettes: red and green, then blue and half transparent white (BGRA)
+        return table(&[
+            &0u16.to_be_bytes(), &2u16.to_be_bytes(), &2u16.to_be_bytes(), &4u16.to_be_bytes(), &16u32.to_be_bytes(),
+            &0u16.to_be_bytes(), &2u16.to_be_bytes(),
+            &[0, 0, 255, 255], &[0, 255, 0, 255],
+            &[255, 0, 0, 255], &[255, 255, 255, 128],
+        ]);
+    }
+
+    fn colr_v0() -> Vec<u8> {
+        // Glyph 5 is two layers (glyphs 20 and 21), glyph 9 one layer (22) in the text color
+        return table(&[
+            &0u16.to_be_bytes(), &2u16.to_be_bytes(), &14u32.to_be_bytes(), &26u32.to_be_bytes(), &3u16.to_be_bytes(),
+            &5u16.to_be_bytes(), &0u16.to_be_bytes(), &2u16.to_be_bytes(),
+            &9u16.to_be_bytes(), &2u16.to_be_bytes(), &1u16.to_be_bytes(),
+            &20u16.to_be_bytes(), &0u16.to_be_bytes(),
+            &21u16.to_be_bytes(), &1u16.to_be_bytes(),
+            &22u16.to_be_bytes(), &0xFFFFu16.to_be_bytes(),
+        ]);
+    }
+
+    fn colr_v1() -> Vec<u8> {
+        /* Glyph 3 is a PaintGlyph of glyph 7 filled with a linear gradient from
+        red at (0, 0) to green at (100, 0), with bands perpendicular to x */
+        return table(&[
+            // Header, with the BaseGlyphList right after it at 34
+            &1u16.to_be_bytes(), &0u16.to_be_bytes(), &0u32.to_be_bytes(), &0u32.to_be_bytes(), &0u16.to_be_bytes(),
+            &34u32.to_be_bytes(), &0u32.to_be_bytes(), &0u32.to_be_bytes(), &0u32.to_be_bytes(), &0u32.to_be_bytes(),
+            // BaseGlyphList: one record, its paint 10 bytes in
+            &1u32.to_be_bytes(), &3u16.to_be_bytes(), &10u32.to_be_bytes(),
+            // PaintGlyph at 44, its paint 6 bytes in
+            &[10, 0, 0, 6], &7u16.to_be_bytes(),
+            // PaintLinearGradient at 50, its color line 16 bytes in
+            &[4, 0, 0, 16], &0i16.to_be_bytes(), &0i16.to_be_bytes(), &100i16.to_be_bytes(), &0i16.to_be_bytes(), &0i16.to_be_bytes(), &100i16.to_be_bytes(),
+            // ColorLine at 66: pad, two stops
+            &[0], &2u16.to_be_bytes(),
+            &0i16.to_be_bytes(), &0u16.to_be_bytes(), &0x4000i16.to_be_bytes(),
+            &0x4000i16.to_be_bytes(), &1u16.to_be_bytes(), &0x4000i16.to_be_bytes(),
+        ]);
+    }
+
+    fn layers(paint: Paint) -> Vec<(u16, [f32; 4])> {
+        // (glyph, color) of each layer of a COLRv0 glyph
+        let layers = match paint {
+            Paint::Layers(layers) => layers,
+            other => panic!("expected layers, got {:?}", other),
+        };
+        return layers.into_iter().map(|layer| match layer {
+            Paint::Glyph { glyph_id, paint } => match *paint {
+                Paint::Solid(color) => (glyph_id.0, color),
+                other => panic!("expected a solid color, got {:?}", other),
+            },
+            other => panic!("expected a glyph, got {:?}", other),
+        }).collect();
+    }
+
+    #[test]
+    fn v0_layers() {
+        let (colr, cpal) = (colr_v0(), cpal());
+        let parser = Parser { colr: &colr, cpal: Some(&cpal), palette: 0, foreground: FOREGROUND };
+        assert_eq!(layers(parser.parse_base_glyph(GlyphId(5), 0).unwrap()), vec![(20, RED), (21, GREEN)]);
+        // Palette entry 0xFFFF is the text color
+        assert_eq!(layers(parser.parse_base_glyph(GlyphId(9), 0).unwrap()), vec![(22, FOREGROUND)]);
+        // Glyphs between and after the records aren't color glyphs
+        assert!(parser.parse_base_glyph(GlyphId(7), 0).is_none());
+        assert!(parser.parse_base_glyph(GlyphId(10), 0).is_none());
+        // The same layers in the second palette
+        let parser = Parser { palette: 1, ..parser };
+        let half_white = [1f32, 1f32, 1f32, 128f32 / 255f32];
+        assert_eq!(layers(parser.parse_base_glyph(GlyphId(5), 0).unwrap()), vec![(20, BLUE), (21, half_white)]);
+    }
+
+    #[test]
+    fn palette_fallback() {
+        let (colr, cpal) = (colr_v0(), cpal());
+        // A palette past the last one falls back to the first
+        let parser = Parser { colr: &colr, cpal: Some(&cpal), palette: 5, foreground: FOREGROUND };
+        assert_eq!(parser.get_color(1, 1f32), GREEN);
+        // Entries past the end of the palette, or no CPAL at all, use the text color
+        assert_eq!(parser.get_color(2, 1f32), FOREGROUND);
+        let parser = Parser { cpal: None, ..parser };
+        assert_eq!(parser.get_color(0, 1f32), FOREGROUND);
+        // The extra alpha multiplies the color's own
+        assert_eq!(parser.get_color(0, 0.5f32), [0.25f32, 0.25f32, 0.25f32, 0.5f32]);
+    }
+
+    #[test]
+    fn v1_linear_gradient() {
+        let (colr, cpal) = (colr_v1(), cpal());
+        assert_eq!(find_v1_base_glyph(&colr, GlyphId(3)), Some(44));
+        assert_eq!(find_v1_base_glyph(&colr, GlyphId(4)), None);
+        let parser = Parser { colr: &colr, cpal: Some(&cpal), palette: 0, foreground: FOREGROUND };
+        let gradient = match parser.parse_base_glyph(GlyphId(3), 0).unwrap() {
+            Paint::Glyph { glyph_id, paint } => {
+                assert_eq!(glyph_id, GlyphId(7));
+                *paint
+            },
+            other => panic!("expected a glyph, got {:?}", other),
+        };
+        match &gradient {
+            Paint::LinearGradient { line, p0, p1, p2 } => {
+                assert_eq!((*p0, *p1, *p2), ((0f32, 0f32), (100f32, 0f32), (0f32, 100f32)));
+                assert_eq!(line.stops, vec![(0f32, RED), (1f32, GREEN)]);
+            },
+            other => panic!("expected a linear gradient, got {:?}", other),
+        }
+        // The ends, padded past them, and half way (the same anywhere along y)
+        assert_eq!(fill_color(&gradient, 0f32, 30f32), RED);
+        assert_eq!(fill_color(&gradient, -50f32, 0f32), RED);
+        assert_eq!(fill_color(&gradient, 150f32, 0f32), GREEN);
+        assert_eq!(fill_color(&gradient, 50f32, -70f32), [0.5f32, 0.5f32, 0f32, 1f32]);
+    }
+
+    fn rectangle(x_min: f32, x_max: f32, y_min: f32, y_max: f32) -> Contour {
+        // Counter-clockwise, as both parts of a glyph drawn as overlapping pieces are
+        let corners = [(x_min, y_min), (x_max, y_min), (x_max, y_max), (x_min, y_max)];
+        let curves = (0..4).map(|i| Element::Line(Line { from: corners[i], to: corners[(i + 1) % 4] })).collect();
+        return Contour { curves: curves, closed: true };
+    }
+
+    #[test]
+    fn overlapping_contours_stay_filled() {
+        // Layers drawn as overlapping contours going the same way are filled where they overlap (non-zero, not even-odd)
+        let data = std::fs::read("fonts/Roboto-Regular.ttf").unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let bounds = BitmapBounds { x_min: 0f32, y_min: 0f32, x_max: 20f32, y_max: 10f32 };
+        let target = Target { face: &face, variations: &[], bounds: bounds, ratio: 1f32, samples: 4, width: 20, height: 10 };
+        let bands = SegmentBands::new(&[rectangle(0f32, 12f32, 0f32, 10f32), rectangle(8f32, 20f32, 0f32, 10f32)]);
+        for x in 0..20 {
+            assert_eq!(glyph_coverage(&target, &bands, &Transform::IDENTITY, x, 5), 1f32, "column {}", x);
+        }
+    }
+}
@@ -0,0 +1,815 @@
+/* Typed curves: lines, quadratic and cubic Beziers each know their own
+geometry behind the Curve trait, so an algorithm is written once per kind
+of curve instead of as a match over every segment type. Element holds any
+of them, and an outline is a list of contours of elements, which is what
+the outline builders make and everything after them (hinting, emboldening,
+transforms, stroking, rasterizing) takes. Points are moved with
+map_points, or taken out and put back in order with Contour::points and
+Contour::set_points. */
+
+use crate::float::Float;
+#[cfg(test)]
+use crate::float::Precision;
+use crate::polynomial::{solve_cubic, solve_quadratic};
+
+// Halvings when looking for where a curve crosses a ray if the solver misses (t to about 1e-15)
+const BISECTION_STEPS: u32 = 50;
+// Arc length is estimated on pieces whose control polygon is within this fraction of their chord
+const LENGTH_TOLERANCE: f32 = 1e-4f32;
+const MAX_LENGTH_DEPTH: u32 = 16;
+/* Gauss-Legendre nodes and weights on 0 to 1, exact for polynomials up to
+degree 5, which covers the area integrand of a cubic */
+const AREA_NODES: [(f32, f32); 3] = [(0.11270166f32, 0.2777778f32), (0.5f32, 0.44444444f32), (0.88729833f32, 0.2777778f32)];
+// Contours enclosing less than this fraction of their bounding box count as having no area
+const AREA_TOLERANCE: f32 = 1e-6f32;
+
+pub type Point = (f32, f32);
+
+pub trait Curve: Sized {
+    // Point at time t (0 at the start, 1 at the end)
+    fn evaluate(&self, t: f32) -> Point;
+    // Derivative at time t (not normalized, and zero where a curve's control point sits on an end)
+    fn derivative(&self, t: f32) -> Point;
+    // The parts before and after time t
+    fn split(&self, t: f32) -> (Self, Self);
+    // Start point, control points and end point
+    fn points(&self) -> Vec<Point>;
+    // (x_min, y_min, x_max, y_max) of the curve itself, which can be well inside its control points
+    fn bounds(&self) -> (f32, f32, f32, f32);
+    fn length(&self) -> f32;
+    /* Signed crossings of a ray from (x, y) towards (dx, dy), worked out in
+    precision F (see winding_number) */
+    fn crossings<F: Float>(&self, x: f32, y: f32, dx: f32, dy: f32) -> i32;
+    // The same kind of curve through the moved points (affine maps keep it the same curve, moved)
+    fn map_points(&self, map: impl Fn(Point) -> Point) -> Self;
+
+    fn signed_area(&self) -> f32 {
+        /* Area swept from the origin to the curve, (x y' - y x') / 2
+        integrated over t: over a closed contour these add up to the area
+        inside it, positive when it goes counter-clockwise (y up) */
+        return AREA_NODES.iter().map(|(t, weight)| {
+            let (x, y) = self.evaluate(*t);
+            let (dx, dy) = self.derivative(*t);
+            weight * (x * dy - y * dx) / 2f32
+        }).sum();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub from: Point,
+    pub to: Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quad {
+    pub from: Point,
+    pub control: Point,
+    pub to: Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cubic {
+    pub from: Point,
+    pub control1: Point,
+    pub control2: Point,
+    pub to: Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Element {
+    Line(Line),
+    Quad(Quad),
+    Cubic(Cubic),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    // Starts where the first curve starts and goes on without gaps
+    pub curves: Vec<Element>,
+    // Ended with a close, which is turned into a line back to the start if it doesn't end there already
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    CounterClockwise,
+    Clockwise,
+    // No area to go around (a point, or curves doubling back on themselves)
+    Degenerate,
+}
+
+impl Contour {
+    pub fn start(&self) -> Option<Point> {
+        return self.curves.first().map(|curve| curve.evaluate(0f32));
+    }
+
+    pub fn end(&self) -> Option<Point> {
+        return self.curves.last().map(|curve| curve.evaluate(1f32));
+    }
+
+    pub fn signed_area(&self) -> f32 {
+        // Area inside the contour, positive counter-clockwise; one that isn't closed is taken as closed by a line
+        let closing = match (self.end(), self.start()) {
+            (Some(end), Some(start)) => Line { from: end, to: start }.signed_area(),
+            _ => 0f32,
+        };
+        return self.curves.iter().map(|curve| curve.signed_area()).sum::<f32>() + closing;
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        /* TrueType outlines go clockwise around filled areas and
+        counter-clockwise around holes, CFF outlines the other way round */
+        let area = self.signed_area();
+        let (x_min, y_min, x_max, y_max) = self.curves.iter().map(|curve| curve.bounds())
+            .fold((f32::MAX, f32::MAX, f32::MIN, f32::MIN), |a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)));
+        if self.curves.is_empty() || !area.is_finite() || area.abs() <= AREA_TOLERANCE * (x_max - x_min) * (y_max - y_min) {
+            return Orientation::Degenerate;
+        }
+        return if area > 0f32 { Orientation::CounterClockwise } else { Orientation::Clockwise };
+    }
+
+    pub fn close(&mut self) {
+        // Closes a contour that was left open with a line back to its start, the way it's filled anyway
+        if let (Some(start), Some(end)) = (self.start(), self.end()) {
+            if !self.closed && end != start {
+                self.curves.push(Element::Line(Line { from: end, to: start }));
+            }
+        }
+        self.closed = true;
+    }
+
+    pub fn points(&self) -> Vec<Point> {
+        // The start, then the control points and end point of each curve in turn
+        let mut points: Vec<Point> = self.start().into_iter().collect();
+        for curve in self.curves.iter() {
+            points.extend(curve.points().into_iter().skip(1));
+        }
+        return points;
+    }
+
+    pub fn set_points(&mut self, points: &[Point]) {
+        // Moves every point to the one at the same place in points (in the order points() gives them)
+        let mut start = 0;
+        for curve in self.curves.iter_mut() {
+            let count = curve.points().len() - 1;
+            *curve = curve.with_points(&points[start..=start + count]);
+            start += count;
+        }
+    }
+
+    pub fn map_points(&mut self, map: impl Fn(Point) -> Point) {
+        for curve in self.curves.iter_mut() {
+            *curve = curve.map_points(&map);
+        }
+    }
+}
+
+// Only the tests use the direct ray cast now, as the reference the banded and scanline fills are checked against
+#[cfg(test)]
+pub fn winding_number(contours: &[Contour], x: f32, y: f32, dx: f32, dy: f32, precision: Precision) -> i32 {
+    /* Returns the number of times a ray with origin (x, y) and direction (dx, dy) crosses the
+    outline, signed: +1 where a curve crosses going counter-clockwise around (x, y) (upwards
+    for a ray going right) and -1 going clockwise. Over closed contours that's the winding
+    number, and its parity says inside or outside for the even-odd rule.
+
+    Everything is worked out in the ray's frame: u along the ray and v to its left. Each curve
+    is cut where v turns around so each piece is monotonic in v, and each piece counts like a
+    line does: it crosses if one end has v <= 0 and the other v > 0 (half-open, so a vertex
+    on the ray is counted once by exactly one of the curves meeting there, pieces lying along
+    the ray never count, and a curve that only touches the ray crosses twice or not at all).
+    The values at shared ends are the same numbers on both sides, so this is consistent no
+    matter how they're rounded. Crossings count if they're ahead of the origin (u > 0). */
+    let curves = contours.iter().flat_map(|contour| contour.curves.iter());
+    return match precision {
+        Precision::Single => curves.map(|curve| curve.crossings::<f32>(x, y, dx, dy)).sum(),
+        Precision::Double => curves.map(|curve| curve.crossings::<f64>(x, y, dx, dy)).sum(),
+    };
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    // Exactly a at t = 0 and b at t = 1
+    return (a.0 * (1f32 - t) + b.0 * t, a.1 * (1f32 - t) + b.1 * t);
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    return ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+}
+
+fn points_bounds(points: &[Point]) -> (f32, f32, f32, f32) {
+    return points.iter().fold((f32::MAX, f32::MAX, f32::MIN, f32::MIN), |(x_min, y_min, x_max, y_max), (x, y)| {
+        (x_min.min(*x), y_min.min(*y), x_max.max(*x), y_max.max(*y))
+    });
+}
+
+fn curve_length<C: Curve>(curve: &C, depth: u32) -> f32 {
+    /* Gravesen's estimate (2 chord + (n - 1) polygon) / (n + 1) for a curve
+    of degree n, on pieces split in half until the control polygon is
+    nearly as short as the chord */
+    let points = curve.points();
+    let chord = distance(points[0], points[points.len() - 1]);
+    let polygon: f32 = points.windows(2).map(|pair| distance(pair[0], pair[1])).sum();
+    if polygon - chord <= LENGTH_TOLERANCE * polygon || depth >= MAX_LENGTH_DEPTH {
+        let degree = (points.len() - 1) as f32;
+        return (2f32 * chord + (degree - 1f32) * polygon) / (degree + 1f32);
+    }
+    let (first, second) = curve.split(0.5f32);
+    return curve_length(&first, depth + 1) + curve_length(&second, depth + 1);
+}
+
+impl Curve for Line {
+    fn evaluate(&self, t: f32) -> Point {
+        return lerp(self.from, self.to, t);
+    }
+
+    fn derivative(&self, _t: f32) -> Point {
+        return (self.to.0 - self.from.0, self.to.1 - self.from.1);
+    }
+
+    fn split(&self, t: f32) -> (Line, Line) {
+        let middle = self.evaluate(t);
+        return (Line { from: self.from, to: middle }, Line { from: middle, to: self.to });
+    }
+
+    fn points(&self) -> Vec<Point> {
+        return vec![self.from, self.to];
+    }
+
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        return points_bounds(&self.points());
+    }
+
+    fn length(&self) -> f32 {
+        return distance(self.from, self.to);
+    }
+
+    fn map_points(&self, map: impl Fn(Point) -> Point) -> Line {
+        return Line { from: map(self.from), to: map(self.to) };
+    }
+
+    fn crossings<F: Float>(&self, x: f32, y: f32, dx: f32, dy: f32) -> i32 {
+        let frame = RayFrame::<F>::new(x, y, dx, dy);
+        let (u0, v0) = frame.apply(self.from);
+        let (u1, v1) = frame.apply(self.to);
+        let direction = crossing_direction(v0, v1);
+        if direction == 0 || (u0 <= F::ZERO && u1 <= F::ZERO) {
+            return 0;
+        }
+        // Where the line meets v = 0
+        let u = u0 + (u1 - u0) * (v0 / (v0 - v1));
+        return if u > F::ZERO { direction } else { 0 };
+    }
+}
+
+impl Curve for Quad {
+    fn evaluate(&self, t: f32) -> Point {
+        let s = 1f32 - t;
+        return (
+            s * s * self.from.0 + 2f32 * s * t * self.control.0 + t * t * self.to.0,
+            s * s * self.from.1 + 2f32 * s * t * self.control.1 + t * t * self.to.1,
+        );
+    }
+
+    fn derivative(&self, t: f32) -> Point {
+        let s = 1f32 - t;
+        return (
+            2f32 * (s * (self.control.0 - self.from.0) + t * (self.to.0 - self.control.0)),
+            2f32 * (s * (self.control.1 - self.from.1) + t * (self.to.1 - self.control.1)),
+        );
+    }
+
+    fn split(&self, t: f32) -> (Quad, Quad) {
+        // de Casteljau
+        let a = lerp(self.from, self.control, t);
+        let b = lerp(self.control, self.to, t);
+        let middle = lerp(a, b, t);
+        return (Quad { from: self.from, control: a, to: middle }, Quad { from: middle, control: b, to: self.to });
+    }
+
+    fn points(&self) -> Vec<Point> {
+        return vec![self.from, self.control, self.to];
+    }
+
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        // The ends, plus where the curve turns around in x or y (the derivative is zero)
+        let mut points = vec![self.from, self.to];
+        for (p0, p1, p2) in [(self.from.0, self.control.0, self.to.0), (self.from.1, self.control.1, self.to.1)] {
+            for t in solve_quadratic(0f32, 2f32 * (p0 - 2f32 * p1 + p2), 2f32 * (p1 - p0)) {
+                if t > 0f32 && t < 1f32 {
+                    points.push(self.evaluate(t));
+                }
+            }
+        }
+        return points_bounds(&points);
+    }
+
+    fn length(&self) -> f32 {
+        return curve_length(self, 0);
+    }
+
+    fn map_points(&self, map: impl Fn(Point) -> Point) -> Quad {
+        return Quad { from: map(self.from), control: map(self.control), to: map(self.to) };
+    }
+
+    fn crossings<F: Float>(&self, x: f32, y: f32, dx: f32, dy: f32) -> i32 {
+        let frame = RayFrame::<F>::new(x, y, dx, dy);
+        let (u0, v0) = frame.apply(self.from);
+        let (u1, v1) = frame.apply(self.control);
+        let (u2, v2) = frame.apply(self.to);
+        // The curve stays within its control points
+        if u0.max(u1).max(u2) <= F::ZERO || (v0.min(v1).min(v2) > F::ZERO) || (v0.max(v1).max(v2) <= F::ZERO) {
+            return 0;
+        }
+        let two = F::from_f32(2f32);
+        // v(t) = a t^2 + b t + c, which turns around vertically where v' = 2 a t + b = 0
+        let (a, b, c) = (v0 - two * v1 + v2, two * (v1 - v0), v0);
+        let turns = if a != F::ZERO { vec![-b / (two * a)] } else { Vec::new() };
+        let u = |t: F| {
+            let s = F::ONE - t;
+            return s * s * u0 + two * s * t * u1 + t * t * u2;
+        };
+        return count_crossings(v0, v2, &turns, &solve_quadratic(a, b, c), u0.min(u1).min(u2),
+            &|t| (a * t + b) * t + c,
+            &u);
+    }
+}
+
+impl Curve for Cubic {
+    fn evaluate(&self, t: f32) -> Point {
+        let s = 1f32 - t;
+        let (a, b, c, d) = (s * s * s, 3f32 * s * s * t, 3f32 * s * t * t, t * t * t);
+        return (
+            a * self.from.0 + b * self.control1.0 + c * self.control2.0 + d * self.to.0,
+            a * self.from.1 + b * self.control1.1 + c * self.control2.1 + d * self.to.1,
+        );
+    }
+
+    fn derivative(&self, t: f32) -> Point {
+        let s = 1f32 - t;
+        let (a, b, c) = (3f32 * s * s, 6f32 * s * t, 3f32 * t * t);
+        return (
+            a * (self.control1.0 - self.from.0) + b * (self.control2.0 - self.control1.0) + c * (self.to.0 - self.control2.0),
+            a * (self.control1.1 - self.from.1) + b * (self.control2.1 - self.control1.1) + c * (self.to.1 - self.control2.1),
+        );
+    }
+
+    fn split(&self, t: f32) -> (Cubic, Cubic) {
+        // de Casteljau
+        let ab = lerp(self.from, self.control1, t);
+        let bc = lerp(self.control1, self.control2, t);
+        let cd = lerp(self.control2, self.to, t);
+        let abc = lerp(ab, bc, t);
+        let bcd = lerp(bc, cd, t);
+        let middle = lerp(abc, bcd, t);
+        return (
+            Cubic { from: self.from, control1: ab, control2: abc, to: middle },
+            Cubic { from: middle, control1: bcd, control2: cd, to: self.to },
+        );
+    }
+
+    fn points(&self) -> Vec<Point> {
+        return vec![self.from, self.control1, self.control2, self.to];
+    }
+
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        // The ends, plus where the curve turns around in x or y (the derivative divided by 3 is a t^2 + b t + c)
+        let mut points = vec![self.from, self.to];
+        for (p0, p1, p2, p3) in [
+            (self.from.0, self.control1.0, self.control2.0, self.to.0),
+            (self.from.1, self.control1.1, self.control2.1, self.to.1),
+        ] {
+            let a = -p0 + 3f32 * p1 - 3f32 * p2 + p3;
+            let b = 2f32 * (p0 - 2f32 * p1 + p2);
+            let c = p1 - p0;
+            for t in solve_quadratic(a, b, c) {
+                if t > 0f32 && t < 1f32 {
+                    points.push(self.evaluate(t));
+                }
+            }
+        }
+        return points_bounds(&points);
+    }
+
+    fn length(&self) -> f32 {
+        return curve_length(self, 0);
+    }
+
+    fn map_points(&self, map: impl Fn(Point) -> Point) -> Cubic {
+        return Cubic { from: map(self.from), control1: map(self.control1), control2: map(self.control2), to: map(self.to) };
+    }
+
+    fn crossings<F: Float>(&self, x: f32, y: f32, dx: f32, dy: f32) -> i32 {
+        // https://www.xarg.org/book/computer-graphics/line-segment-bezier-curve-intersection/
+        // https://math.stackexchange.com/questions/1337440/cubic-bezier-curve-and-a-straight-line-intersection
+        let frame = RayFrame::<F>::new(x, y, dx, dy);
+        // Control points
+        let (c0_u, c0_v) = frame.apply(self.from); // A
+        let (c1_u, c1_v) = frame.apply(self.control1); // B
+        let (c2_u, c2_v) = frame.apply(self.control2); // C
+        let (c3_u, c3_v) = frame.apply(self.to); // D
+        if c0_u.max(c1_u).max(c2_u).max(c3_u) <= F::ZERO
+            || c0_v.min(c1_v).min(c2_v).min(c3_v) > F::ZERO
+            || c0_v.max(c1_v).max(c2_v).max(c3_v) <= F::ZERO {
+            return 0;
+        }
+        let (two, three) = (F::from_f32(2f32), F::from_f32(3f32));
+
+        // v(t) as a polynomial a t^3 + b t^2 + c t + d
+        let a = -c0_v + three * c1_v - three * c2_v + c3_v; // -A + 3B - 3C + D
+        let b = three * c0_v - F::from_f32(6f32) * c1_v + three * c2_v; // 3A - 6B + 3C
+        let c = -three * c0_v + three * c1_v; // -3A + 3B
+        let d = c0_v; // A
+
+        // v' = 3 a t^2 + 2 b t + c
+        let turns = solve_quadratic(three * a, two * b, c);
+        let u = |t: F| {
+            let s = F::ONE - t;
+            return s * s * s * c0_u + three * s * s * t * c1_u + three * s * t * t * c2_u + t * t * t * c3_u;
+        };
+        return count_crossings(c0_v, c3_v, &turns, &solve_cubic(a, b, c, d), c0_u.min(c1_u).min(c2_u).min(c3_u),
+            &|t| ((a * t + b) * t + c) * t + d,
+            &u);
+    }
+}
+
+impl Element {
+    pub fn with_points(&self, points: &[Point]) -> Element {
+        // The same kind of curve through the given start, control and end points
+        return match self {
+            Element::Line(_) => Element::Line(Line { from: points[0], to: points[1] }),
+            Element::Quad(_) => Element::Quad(Quad { from: points[0], control: points[1], to: points[2] }),
+            Element::Cubic(_) => Element::Cubic(Cubic { from: points[0], control1: points[1], control2: points[2], to: points[3] }),
+        };
+    }
+}
+
+impl Curve for Element {
+    fn evaluate(&self, t: f32) -> Point {
+        return match self {
+            Element::Line(line) => line.evaluate(t),
+            Element::Quad(quad) => quad.evaluate(t),
+            Element::Cubic(cubic) => cubic.evaluate(t),
+        };
+    }
+
+    fn derivative(&self, t: f32) -> Point {
+        return match self {
+            Element::Line(line) => line.derivative(t),
+            Element::Quad(quad) => quad.derivative(t),
+            Element::Cubic(cubic) => cubic.derivative(t),
+        };
+    }
+
+    fn split(&self, t: f32) -> (Element, Element) {
+        return match self {
+            Element::Line(line) => {
+                let (first, second) = line.split(t);
+                (Element::Line(first), Element::Line(second))
+            },
+            Element::Quad(quad) => {
+                let (first, second) = quad.split(t);
+                (Element::Quad(first), Element::Quad(second))
+            },
+            Element::Cubic(cubic) => {
+                let (first, second) = cubic.split(t);
+                (Element::Cubic(first), Element::Cubic(second))
+            },
+        };
+    }
+
+    fn points(&self) -> Vec<Point> {
+        return match self {
+            Element::Line(line) => line.points(),
+            Element::Quad(quad) => quad.points(),
+            Element::Cubic(cubic) => cubic.points(),
+        };
+    }
+
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        return match self {
+            Element::Line(line) => line.bounds(),
+            Element::Quad(quad) => quad.bounds(),
+            Element::Cubic(cubic) => cubic.bounds(),
+        };
+    }
+
+    fn length(&self) -> f32 {
+        return match self {
+            Element::Line(line) => line.length(),
+            Element::Quad(quad) => quad.length(),
+            Element::Cubic(cubic) => cubic.length(),
+        };
+    }
+
+    fn crossings<F: Float>(&self, x: f32, y: f32, dx: f32, dy: f32) -> i32 {
+        return match self {
+            Element::Line(line) => line.crossings::<F>(x, y, dx, dy),
+            Element::Quad(quad) => quad.crossings::<F>(x, y, dx, dy),
+            Element::Cubic(cubic) => cubic.crossings::<F>(x, y, dx, dy),
+        };
+    }
+
+    fn map_points(&self, map: impl Fn(Point) -> Point) -> Element {
+        return match self {
+            Element::Line(line) => Element::Line(line.map_points(map)),
+            Element::Quad(quad) => Element::Quad(quad.map_points(map)),
+            Element::Cubic(cubic) => Element::Cubic(cubic.map_points(map)),
+        };
+    }
+}
+
+// Helpers for the crossing tests: everything is worked out in the ray's frame, u along the ray and v to its left
+struct RayFrame<F: Float> {
+    x: F,
+    y: F,
+    dx: F,
+    dy: F,
+}
+
+impl<F: Float> RayFrame<F> {
+    fn new(x: f32, y: f32, dx: f32, dy: f32) -> RayFrame<F> {
+        return RayFrame { x: F::from_f32(x), y: F::from_f32(y), dx: F::from_f32(dx), dy: F::from_f32(dy) };
+    }
+
+    fn apply(&self, point: Point) -> (F, F) {
+        let (qx, qy) = (F::from_f32(point.0) - self.x, F::from_f32(point.1) - self.y);
+        return (self.dx * qx + self.dy * qy, self.dx * qy - self.dy * qx);
+    }
+}
+
+fn crossing_direction<F: Float>(v_start: F, v_end: F) -> i32 {
+    // +1 if a piece going from v_start to v_end crosses v = 0 upwards, -1 downwards (half-open at v = 0)
+    if v_start <= F::ZERO && v_end > F::ZERO {
+        return 1;
+    } else if v_end <= F::ZERO && v_start > F::ZERO {
+        return -1;
+    }
+    return 0;
+}
+
+fn count_crossings<F: Float>(v_start: F, v_end: F, turns: &[F], roots: &[F], u_min: F, v: &dyn Fn(F) -> F, u: &dyn Fn(F) -> F) -> i32 {
+    /* Signed crossings of v = 0 ahead of the origin by a curve going from v_start to v_end and
+    turning around at the given times. roots are where the solver found v = 0, and u_min is the
+    smallest u of the control points. */
+    let mut times = vec![F::ZERO];
+    let mut inner: Vec<F> = turns.iter().cloned().filter(|t| *t > F::ZERO && *t < F::ONE).collect();
+    inner.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    times.extend(inner);
+    times.push(F::ONE);
+    let values: Vec<F> = times.iter().enumerate().map(|(i, t)| {
+        if i == 0 { v_start } else if i == times.len() - 1 { v_end } else { v(*t) }
+    }).collect();
+
+    // Roots can land a little outside the piece they belong to, especially next to a turn
+    let tolerance = F::EPSILON.sqrt();
+    let mut count = 0;
+    for i in 0..times.len() - 1 {
+        let direction = crossing_direction(values[i], values[i + 1]);
+        if direction == 0 {
+            continue;
+        }
+        if u_min > F::ZERO {
+            // Entirely ahead of the origin, no need to find where
+            count += direction;
+            continue;
+        }
+        let (start, end) = (times[i], times[i + 1]);
+        let root = roots.iter().find(|t| **t >= start && **t <= end)
+            .or_else(|| roots.iter().find(|t| **t >= start - tolerance && **t <= end + tolerance));
+        let t = match root {
+            Some(t) => t.max(start).min(end),
+            None => {
+                // v is monotonic on the piece, so bisect keeping v(low) <= 0 < v(high)
+                let (mut low, mut high) = if direction > 0 { (start, end) } else { (end, start) };
+                for _ in 0..BISECTION_STEPS {
+                    let middle = (low + high) / F::from_f32(2f32);
+                    if v(middle) <= F::ZERO {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                (low + high) / F::from_f32(2f32)
+            },
+        };
+        if u(t) > F::ZERO {
+            count += direction;
+        }
+    }
+    return count;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use ttf_parser::OutlineBuilder;
+
+    use crate::fixtures::{bundled_fonts, Glyph};
+    use crate::float::Precision;
+    use crate::outline::Builder;
+    use super::{winding_number, Contour, Cubic, Curve, Element};
+
+    // Pieces each curve is cut into for the reference winding number
+    const REFERENCE_PIECES: usize = 512;
+    const POINTS_PER_FONT: usize = 4000;
+    const GLYPHS_PER_FONT: u16 = 200;
+    const RANDOM_SHAPES: usize = 200;
+
+    fn outline(commands: &[(char, &[f32])]) -> Vec<Contour> {
+        let mut builder = Builder::default();
+        for (command, p) in commands.iter() {
+            match command {
+                'M' => builder.move_to(p[0], p[1]),
+                'L' => builder.line_to(p[0], p[1]),
+                'Q' => builder.quad_to(p[0], p[1], p[2], p[3]),
+                'C' => builder.curve_to(p[0], p[1], p[2], p[3], p[4], p[5]),
+                _ => builder.close(),
+            }
+        }
+        return builder.contours;
+    }
+
+    fn winding(contours: &[Contour], x: f32, y: f32, dx: f32, dy: f32) -> i32 {
+        return winding_number(contours, x, y, dx, dy, Precision::Double);
+    }
+
+    fn reference(contours: &[Contour], x: f64, y: f64) -> (i32, f64) {
+        /* Winding number around (x, y) of the outline flattened finely in f64, and the distance
+        from the point to the flattened outline */
+        let mut winding = 0;
+        let mut distance = f64::MAX;
+        for curve in contours.iter().flat_map(|contour| contour.curves.iter()) {
+            let p: Vec<(f64, f64)> = curve.points().iter().map(|(x, y)| (*x as f64, *y as f64)).collect();
+            let at = |t: f64| {
+                let s = 1f64 - t;
+                return match curve {
+                    Element::Line(_) => (p[0].0 + (p[1].0 - p[0].0) * t, p[0].1 + (p[1].1 - p[0].1) * t),
+                    Element::Quad(_) => (s * s * p[0].0 + 2f64 * s * t * p[1].0 + t * t * p[2].0, s * s * p[0].1 + 2f64 * s * t * p[1].1 + t * t * p[2].1),
+                    Element::Cubic(_) => (
+                        s * s * s * p[0].0 + 3f64 * s * s * t * p[1].0 + 3f64 * s * t * t * p[2].0 + t * t * t * p[3].0,
+                        s * s * s * p[0].1 + 3f64 * s * s * t * p[1].1 + 3f64 * s * t * t * p[2].1 + t * t * t * p[3].1,
+                    ),
+                };
+            };
+            let pieces = if matches!(curve, Element::Line(_)) { 1 } else { REFERENCE_PIECES };
+            let mut a = p[0];
+            for i in 1..=pieces {
+                let b = if i == pieces { p[p.len() - 1] } else { at(i as f64 / pieces as f64) };
+                if a.1 <= y && b.1 > y && a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1) > x {
+                    winding += 1;
+                } else if b.1 <= y && a.1 > y && a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1) > x {
+                    winding -= 1;
+                }
+                let (ex, ey) = (b.0 - a.0, b.1 - a.1);
+                let length = ex * ex + ey * ey;
+                let t = if length > 0f64 { (((x - a.0) * ex + (y - a.1) * ey) / length).clamp(0f64, 1f64) } else { 0f64 };
+                distance = distance.min(((a.0 + ex * t - x).powi(2) + (a.1 + ey * t - y).powi(2)).sqrt());
+                a = b;
+            }
+        }
+        return (winding, distance);
+    }
+
+    #[test]
+    fn ray_through_shared_vertex() {
+        // Diamond with vertices on the ray: counted once where it passes through, not at all where it touches
+        let diamond = outline(&[('M', &[0f32, -10f32]), ('L', &[10f32, 0f32]), ('L', &[0f32, 10f32]), ('L', &[-10f32, 0f32]), ('L', &[0f32, -10f32]), ('Z', &[])]);
+        assert_eq!(winding(&diamond, -20f32, 0f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&diamond, 0f32, 0f32, 1f32, 0f32), 1);
+        assert_eq!(winding(&diamond, -5f32, -10f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&diamond, 0f32, 0f32, 0f32, 1f32), 1);
+        // V touching the ray from above
+        let v = outline(&[('M', &[0f32, 10f32]), ('L', &[5f32, 0f32]), ('L', &[10f32, 10f32]), ('L', &[0f32, 10f32]), ('Z', &[])]);
+        assert_eq!(winding(&v, -5f32, 0f32, 1f32, 0f32), 0);
+    }
+
+    #[test]
+    fn horizontal_edges_on_the_ray() {
+        // Square with the ray running along its bottom and top edges
+        let square = outline(&[('M', &[0f32, 0f32]), ('L', &[10f32, 0f32]), ('L', &[10f32, 10f32]), ('L', &[0f32, 10f32]), ('L', &[0f32, 0f32]), ('Z', &[])]);
+        assert_eq!(winding(&square, -5f32, 0f32, 1f32, 0f32) % 2, 0);
+        assert_eq!(winding(&square, -5f32, 10f32, 1f32, 0f32) % 2, 0);
+        assert_eq!(winding(&square, 5f32, 0f32, 1f32, 0f32), 1);
+        assert_eq!(winding(&square, 5f32, 5f32, 1f32, 0f32), 1);
+        assert_eq!(winding(&square, 5f32, 5f32, -1f32, 0f32), 1);
+        // Step with a horizontal edge in the middle
+        let step = outline(&[('M', &[0f32, 0f32]), ('L', &[20f32, 0f32]), ('L', &[20f32, 5f32]), ('L', &[10f32, 5f32]), ('L', &[10f32, 10f32]), ('L', &[0f32, 10f32]), ('L', &[0f32, 0f32]), ('Z', &[])]);
+        assert_eq!(winding(&step, 5f32, 5f32, 1f32, 0f32), 1);
+        assert_eq!(winding(&step, -5f32, 5f32, 1f32, 0f32), 0);
+    }
+
+    #[test]
+    fn curves_tangent_to_the_ray() {
+        // Arch whose top touches y = 10 and bowl whose bottom touches y = 0
+        let arch = outline(&[('M', &[0f32, 0f32]), ('Q', &[5f32, 20f32, 10f32, 0f32]), ('L', &[0f32, 0f32]), ('Z', &[])]);
+        assert_eq!(winding(&arch, -5f32, 10f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&arch, -5f32, 5f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&arch, 5f32, 5f32, 1f32, 0f32).abs(), 1);
+        let bowl = outline(&[('M', &[0f32, 10f32]), ('Q', &[5f32, -10f32, 10f32, 10f32]), ('L', &[0f32, 10f32]), ('Z', &[])]);
+        assert_eq!(winding(&bowl, -5f32, 0f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&bowl, 5f32, 5f32, 1f32, 0f32).abs(), 1);
+        assert_eq!(winding(&bowl, 5f32, 5f32, 0f32, 1f32).abs(), 1);
+        // Cubic bowl touching y = 0, and an S that crosses y = 5 three times
+        let bowl = outline(&[('M', &[0f32, 10f32]), ('C', &[0f32, -3.3333333f32, 10f32, -3.3333333f32, 10f32, 10f32]), ('L', &[0f32, 10f32]), ('Z', &[])]);
+        assert_eq!(winding(&bowl, -5f32, 0f32, 1f32, 0f32), 0);
+        assert_eq!(winding(&bowl, 5f32, 5f32, 1f32, 0f32).abs(), 1);
+        assert_eq!(winding(&bowl, 5f32, 5f32, 0f32, 1f32).abs(), 1);
+        let s = outline(&[('M', &[0f32, 0f32]), ('C', &[30f32, 20f32, -20f32, -10f32, 10f32, 10f32]), ('L', &[0f32, 0f32]), ('Z', &[])]);
+        for x in [-10f32, 0f32, 3f32, 6f32] {
+            assert_eq!(winding(&s, x, 5f32, 1f32, 0f32), reference(&s, x as f64, 5f64).0, "S at ({}, 5)", x);
+        }
+    }
+
+    fn to_cubics(contours: &[Contour]) -> Vec<Contour> {
+        // Same outline with every quad raised to the cubic that traces it exactly, like a CFF font would have
+        let raise = |curve: &Element| match *curve {
+            Element::Quad(quad) => Element::Cubic(Cubic {
+                from: quad.from,
+                control1: (quad.from.0 + 2f32 / 3f32 * (quad.control.0 - quad.from.0), quad.from.1 + 2f32 / 3f32 * (quad.control.1 - quad.from.1)),
+                control2: (quad.to.0 + 2f32 / 3f32 * (quad.control.0 - quad.to.0), quad.to.1 + 2f32 / 3f32 * (quad.control.1 - quad.to.1)),
+                to: quad.to,
+            }),
+            _ => *curve,
+        };
+        return contours.iter().map(|contour| Contour { curves: contour.curves.iter().map(raise).collect(), closed: contour.closed }).collect();
+    }
+
+    fn check_glyphs(rng: &mut StdRng, name: &str, glyphs: &[Glyph]) {
+        // Inside/outside at random points around the glyphs against the flattened reference, along several rays
+        let mut checked = 0;
+        while checked < POINTS_PER_FONT {
+            let Glyph { id, contours, bbox } = &glyphs[rng.gen_range(0..glyphs.len())];
+            let ends: Vec<f32> = contours.iter().flat_map(|contour| contour.curves.iter()).map(|curve| curve.evaluate(1f32).1).collect();
+            let x = rng.gen_range(bbox.x_min as f32 - 10f32..bbox.x_max as f32 + 10f32);
+            // Half the points at the height of a vertex, where rays pass through end points and along flat edges
+            let y = if rng.gen_bool(0.5) {
+                ends[rng.gen_range(0..ends.len())]
+            } else {
+                rng.gen_range(bbox.y_min as f32 - 10f32..bbox.y_max as f32 + 10f32)
+            };
+            let (expected, distance) = reference(contours, x as f64, y as f64);
+            if distance < 0.5f64 {
+                continue;
+            }
+            assert_eq!(winding(contours, x, y, 1f32, 0f32), expected, "{} glyph {} at ({}, {})", name, id, x, y);
+            let (dx, dy) = [(-1f32, 0f32), (0f32, 1f32), (0.6f32, -0.8f32)][checked % 3];
+            assert_eq!(winding(contours, x, y, dx, dy), expected, "{} glyph {} at ({}, {}) towards ({}, {})", name, id, x, y, dx, dy);
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn bundled_fonts_match_reference() {
+        let mut rng = StdRng::seed_from_u64(41);
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            check_glyphs(&mut rng, &font.path.display().to_string(), &font.glyphs);
+        }
+    }
+
+    #[test]
+    fn cubic_outlines_match_reference() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for font in bundled_fonts(GLYPHS_PER_FONT).into_iter() {
+            let glyphs: Vec<Glyph> = font.glyphs.into_iter().map(|glyph| Glyph { contours: to_cubics(&glyph.contours), ..glyph }).collect();
+            check_glyphs(&mut rng, &format!("{} as cubics", font.path.display()), &glyphs);
+        }
+    }
+
+    #[test]
+    fn cff_outlines_match_reference() {
+        // The CFF font's own cubics (S bends, loops, cusps), not quads raised to cubics
+        let mut rng = StdRng::seed_from_u64(42);
+        let font = bundled_fonts(GLYPHS_PER_FONT).into_iter().find(|font| font.path.extension().is_some_and(|extension| extension == "otf")).expect("no CFF font in fonts/");
+        let cubics = font.glyphs.iter().flat_map(|glyph| glyph.contours.iter()).flat_map(|contour| contour.curves.iter()).filter(|curve| matches!(curve, Element::Cubic(_))).count();
+        assert!(cubics > 0, "no cubics in {}", font.path.display());
+        check_glyphs(&mut rng, &font.path.display().to_string(), &font.glyphs);
+    }
+
+    fn random_cubics(rng: &mut StdRng, id: u16) -> Glyph {
+        /* A contour or two of cubics with control points anywhere in the box,
+        so there are loops, cusps and S bends, unlike quads raised to cubics
+        (which never use the cubic term) */
+        let mut builder = Builder::default();
+        let point = |rng: &mut StdRng| (rng.gen_range(0f32..1000f32), rng.gen_range(0f32..1000f32));
+        for _ in 0..rng.gen_range(1..=2) {
+            let start = point(rng);
+            builder.move_to(start.0, start.1);
+            for _ in 0..rng.gen_range(2..=5) {
+                let (c1, c2, to) = (point(rng), point(rng), point(rng));
+                builder.curve_to(c1.0, c1.1, c2.0, c2.1, to.0, to.1);
+            }
+            let (c1, c2) = (point(rng), point(rng));
+            builder.curve_to(c1.0, c1.1, c2.0, c2.1, start.0, start.1);
+            builder.close();
+        }
+        return Glyph { id: id, contours: builder.contours, bbox: ttf_parser::Rect { x_min: 0, y_min: 0, x_max: 1000, y_max: 1000 } };
+    }
+
+    #[test]
+    fn random_cubics_match_reference() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let shapes: Vec<Glyph> = (0..RANDOM_SHAPES).map(|id| random_cubics(&mut rng, id as u16)).collect();
+        check_glyphs(&mut rng, "random cubics", &shapes);
+    }
+}
@@ -0,0 +1,131 @@
+/* Outline emboldening, ported from FreeType's FT_Outline_EmboldenXY. Every
+point (control points included) is pushed outwards along the bisector of the
+edges meeting at it, so stems get thicker by the given amount while the left
+and bottom sides of the glyph stay where they were. Used for stem darkening,
+which thickens thin stems at small sizes the way FreeType does so text
+doesn't look washed out after gamma-correct blending.
+https://freetype.org/freetype2/docs/reference/ft2-properties.html#darkening-parameters */
+
+use ttf_parser::Rect;
+
+use crate::curve::Contour;
+
+// FreeType's default darkening curve: (stem width, darkening amount) in 1/1000 px
+const DARKENING_PARAMETERS: [(f32, f32); 4] = [(500f32, 400f32), (1000f32, 275f32), (1667f32, 275f32), (2333f32, 0f32)];
+
+pub fn stem_darkening(stem_width: f32) -> f32 {
+    // How much (in pixels) to thicken a stem that is stem_width pixels wide
+    let x = stem_width * 1000f32;
+    let (first, last) = (DARKENING_PARAMETERS[0], DARKENING_PARAMETERS[DARKENING_PARAMETERS.len() - 1]);
+    if x <= first.0 {
+        return first.1 / 1000f32;
+    }
+    if x >= last.0 {
+        return last.1 / 1000f32;
+    }
+    for pair in DARKENING_PARAMETERS.windows(2) {
+        let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+        if x <= x2 {
+            return (y1 + (x - x1) * (y2 - y1) / (x2 - x1)) / 1000f32;
+        }
+    }
+    return 0f32;
+}
+
+pub fn embolden_outline(contours: &mut [Contour], bbox: Rect, x_strength: f32, y_strength: f32) -> Rect {
+    /* Thickens the outline by x_strength horizontally and y_strength
+    vertically (in font units) and returns its new bounding box. */
+    if x_strength == 0f32 && y_strength == 0f32 {
+        return bbox;
+    }
+    // TrueType outlines run clockwise, CFF ones counter-clockwise
+    let clockwise = contours.iter().map(|contour| contour.signed_area()).sum::<f32>() <= 0f32;
+    let (x_half, y_half) = (x_strength / 2f32, y_strength / 2f32);
+
+    for contour in contours.iter_mut() {
+        let mut points = contour.points();
+        // Closed contours come back to the starting point, which shouldn't be moved twice
+        let closed = points.len() > 1 && points[0] == points[points.len() - 1];
+        if closed {
+            points.pop();
+        }
+        let n = points.len();
+        if n < 2 {
+            continue;
+        }
+        let mut moved = points.clone();
+        for i in 0..n {
+            let (x, y) = points[i];
+            // Nearest distinct neighbours on both sides
+            let previous = (1..n).map(|k| points[(i + n - k) % n]).find(|&p| p != (x, y));
+            let next = (1..n).map(|k| points[(i + k) % n]).find(|&p| p != (x, y));
+            let (previous, next) = match (previous, next) {
+                (Some(p), Some(q)) => (p, q),
+                _ => continue,
+            };
+            let (in_x, in_y, in_length) = unit(x - previous.0, y - previous.1);
+            let (out_x, out_y, out_length) = unit(next.0 - x, next.1 - y);
+            let mut d = in_x * out_x + in_y * out_y;
+            let mut shift = (0f32, 0f32);
+            // Points where the outline doubles back on itself stay put
+            if d > -0.9375f32 {
+                d += 1f32;
+                shift = (in_y + out_y, in_x + out_x);
+                let mut q = out_x * in_y - out_y * in_x;
+                if clockwise {
+                    shift.0 = -shift.0;
+                    q = -q;
+                } else {
+                    shift.1 = -shift.1;
+                }
+                let l = in_length.min(out_length);
+                shift.0 = if x_half * q <= l * d { shift.0 * x_half / d } else { shift.0 * l / q };
+                shift.1 = if y_half * q <= l * d { shift.1 * y_half / d } else { shift.1 * l / q };
+            }
+            moved[i] = (x + x_half + shift.0, y + y_half + shift.1);
+        }
+        if closed {
+            moved.push(moved[0]);
+        }
+        contour.set_points(&moved);
+    }
+    return Rect {
+        x_min: bbox.x_min,
+        y_min: bbox.y_min,
+        x_max: bbox.x_max + x_strength.ceil() as i16,
+        y_max: bbox.y_max + y_strength.ceil() as i16,
+    };
+}
+
+fn unit(x: f32, y: f32) -> (f32, f32, f32) {
+    // Unit vector and length
+    let length = (x * x + y * y).sqrt();
+    return (x / length, y / length, length);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stem_darkening;
+
+    #[test]
+    fn darkening_curve() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5f32;
+        // Flat past both ends
+        assert!(close(stem_darkening(0f32), 0.4f32));
+        assert!(close(stem_darkening(0.5f32), 0.4f32));
+        assert!(close(stem_darkening(2.333f32), 0f32));
+        assert!(close(stem_darkening(10f32), 0f32));
+        // The middle points and the plateau between them
+        assert!(close(stem_darkening(1f32), 0.275f32));
+        assert!(close(stem_darkening(1.333f32), 0.275f32));
+        assert!(close(stem_darkening(1.667f32), 0.275f32));
+        // Straight lines in between
+        assert!(close(stem_darkening(0.75f32), 0.3375f32));
+        assert!(close(stem_darkening(2f32), 0.1375f32));
+        // Thicker stems never get more
+        for i in 0..300 {
+            let width = i as f32 * 0.01f32;
+            assert!(stem_darkening(width + 0.01f32) <= stem_darkening(width), "{}", width);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+/* The fonts in fonts/ and their glyphs, for the tests that run over every
+bundled font. Glyphs are taken by id rather than by character, as the
+symbol fonts don't map letters, and everything in fonts/ is used, so the CFF
+font (CubicTest-Regular.otf, made by tools/make_cubic_font.py) gets its cubics
+checked along with the TrueType fonts' quads. */
+
+use std::path::PathBuf;
+
+use ttf_parser::{Face, GlyphId, Rect};
+
+use crate::curve::Contour;
+use crate::outline::Builder;
+
+pub struct Glyph {
+    pub id: u16,
+    pub contours: Vec<Contour>,
+    pub bbox: Rect,
+}
+
+pub struct Font {
+    pub path: PathBuf,
+    pub units_per_em: f32,
+    // The glyphs with an outline among the first few ids
+    pub glyphs: Vec<Glyph>,
+}
+
+pub fn bundled_fonts(glyphs_per_font: u16) -> Vec<Font> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir("fonts").unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+    return paths.into_iter().map(|path| {
+        let data = std::fs::read(&path).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let glyphs: Vec<Glyph> = (0..face.number_of_glyphs().min(glyphs_per_font)).filter_map(|id| {
+            let mut builder = Builder::default();
+            let bbox = face.outline_glyph(GlyphId(id), &mut builder)?;
+            return Some(Glyph { id: id, contours: builder.contours, bbox: bbox });
+        }).collect();
+        assert!(!glyphs.is_empty(), "no glyphs in {}", path.display());
+        return Font { path: path, units_per_em: face.units_per_em() as f32, glyphs: glyphs };
+    }).collect();
+}
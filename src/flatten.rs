@@ -0,0 +1,162 @@
+/* Outlines as polylines, for backends that only know straight lines
+(scanline filling, stroking, previews). Each curve is cut into pieces of
+equal t, as many as Wang's formula says it takes to stay within the
+tolerance: a curve of degree d whose control points have second differences
+no longer than M is never further than d (d - 1) M / (8 n^2) from the chords
+of n equal pieces at the same t, so n = sqrt(d (d - 1) M / (8 tolerance))
+pieces keep every point of the curve within the tolerance of the polyline
+(and the polyline's points are on the curve). Flat curves get few pieces and
+tight bends many, and lines are never cut. */
+
+use crate::curve::{Contour, Curve, Point};
+
+// Most pieces a curve is cut into, so a tolerance of zero can't ask for infinitely many
+const MAX_PIECES: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    // From the start of the contour; closed ones end back on the first point
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+pub fn flatten(contours: &[Contour], tolerance: f32, pixels_per_unit: f32) -> Vec<Polyline> {
+    /* The outline's contours as polylines no further than tolerance device
+    pixels from it, when drawn at pixels_per_unit (see get_ratio). Empty
+    contours are left out. */
+    let tolerance = tolerance / pixels_per_unit;
+    return contours.iter().filter_map(|contour| {
+        let mut points = vec![contour.start()?];
+        for curve in contour.curves.iter() {
+            flatten_curve(curve, tolerance, &mut points);
+        }
+        return Some(Polyline { points: points, closed: contour.closed });
+    }).collect();
+}
+
+fn flatten_curve<C: Curve>(curve: &C, tolerance: f32, points: &mut Vec<Point>) {
+    // Adds the points of the curve after its start, which the polyline already ends on
+    let count = pieces(curve, tolerance);
+    for i in 1..count {
+        points.push(curve.evaluate(i as f32 / count as f32));
+    }
+    points.push(curve.evaluate(1f32));
+}
+
+fn pieces<C: Curve>(curve: &C, tolerance: f32) -> usize {
+    // Wang's formula for how many pieces of equal t keep the chords within tolerance of the curve
+    let points = curve.points();
+    let degree = (points.len() - 1) as f32;
+    let second_difference = points.windows(3).map(|p| {
+        let (x, y) = (p[0].0 - 2f32 * p[1].0 + p[2].0, p[0].1 - 2f32 * p[1].1 + p[2].1);
+        (x * x + y * y).sqrt()
+    }).fold(0f32, f32::max);
+    if second_difference == 0f32 {
+        return 1;
+    }
+    let count = (degree * (degree - 1f32) * second_difference / (8f32 * tolerance)).sqrt().ceil();
+    // Also catches NaN, which casts to 0
+    return (count.min(MAX_PIECES as f32) as usize).max(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use ttf_parser::OutlineBuilder;
+
+    use crate::curve::{Contour, Cubic, Curve, Element, Point};
+    use crate::fixtures::bundled_fonts;
+    use crate::outline::Builder;
+    use super::{flatten, flatten_curve, pieces};
+
+    // Points checked on each piece of a flattened curve
+    const SAMPLES_PER_PIECE: usize = 16;
+    const RANDOM_CUBICS: usize = 2000;
+    // Slack for rounding in f32, in font units
+    const ROUNDING: f32 = 1e-3f32;
+
+    fn distance_to_chord(p: Point, a: Point, b: Point) -> f32 {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx * dx + dy * dy;
+        let t = if length == 0f32 { 0f32 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0f32, 1f32) };
+        return ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt();
+    }
+
+    fn check_curve(curve: &Element, points: &[Point], tolerance: f32) {
+        // points are the curve's polyline from its start; every point of the curve has to be near its piece
+        let count = points.len() - 1;
+        assert_eq!(points[0], curve.evaluate(0f32));
+        assert_eq!(points[count], curve.evaluate(1f32));
+        for (k, chord) in points.windows(2).enumerate() {
+            for i in 0..=SAMPLES_PER_PIECE {
+                let t = (k as f32 + i as f32 / SAMPLES_PER_PIECE as f32) / count as f32;
+                let error = distance_to_chord(curve.evaluate(t), chord[0], chord[1]);
+                assert!(error <= tolerance + ROUNDING, "{:?} is {} from its polyline at t = {} (tolerance {})", curve, error, t, tolerance);
+            }
+        }
+    }
+
+    fn check_outline(contours: &[Contour], tolerance: f32, pixels_per_unit: f32) {
+        // The polylines follow the contours curve by curve, so each curve's points can be picked back out
+        let polylines = flatten(contours, tolerance, pixels_per_unit);
+        let contours: Vec<_> = contours.iter().filter(|contour| !contour.curves.is_empty()).collect();
+        assert_eq!(polylines.len(), contours.len());
+        for (polyline, contour) in polylines.iter().zip(contours.iter()) {
+            assert_eq!(polyline.closed, contour.closed);
+            let mut start = 0;
+            for curve in contour.curves.iter() {
+                let count = pieces(curve, tolerance / pixels_per_unit);
+                check_curve(curve, &polyline.points[start..=start + count], tolerance / pixels_per_unit);
+                start += count;
+            }
+            assert_eq!(start, polyline.points.len() - 1);
+            if polyline.closed {
+                assert_eq!(polyline.points[0], polyline.points[start]);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_are_not_cut() {
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.line_to(100f32, 0f32);
+        builder.line_to(100f32, 100f32);
+        builder.line_to(0f32, 0f32);
+        builder.close();
+        let polylines = flatten(&builder.contours, 0.001f32, 1f32);
+        assert_eq!(polylines.len(), 1);
+        assert_eq!(polylines[0].points, vec![(0f32, 0f32), (100f32, 0f32), (100f32, 100f32), (0f32, 0f32)]);
+        assert!(polylines[0].closed);
+    }
+
+    #[test]
+    fn bundled_fonts_stay_within_tolerance() {
+        // Every glyph of every font, at a small and a large size and a loose and a tight tolerance
+        for font in bundled_fonts(u16::MAX).iter() {
+            for glyph in font.glyphs.iter() {
+                for pixels_per_unit in [0.01f32, 0.2f32] {
+                    for tolerance in [0.5f32, 0.02f32] {
+                        check_outline(&glyph.contours, tolerance, pixels_per_unit);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_cubics_stay_within_tolerance() {
+        // Cubics with loops, cusps and control points far outside, which fonts rarely have
+        let mut rng = StdRng::seed_from_u64(46);
+        let mut point = || (rng.gen_range(-1000f32..1000f32), rng.gen_range(-1000f32..1000f32));
+        for _ in 0..RANDOM_CUBICS {
+            let cubic = Element::Cubic(Cubic { from: point(), control1: point(), control2: point(), to: point() });
+            for tolerance in [10f32, 1f32, 0.1f32] {
+                let mut points = vec![cubic.evaluate(0f32)];
+                flatten_curve(&cubic, tolerance, &mut points);
+                check_curve(&cubic, &points, tolerance);
+            }
+        }
+    }
+}
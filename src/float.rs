@@ -0,0 +1,67 @@
+/* Floating point type for the geometry core. Outlines are stored as f32,
+but the inside/outside test can be worked out in f32 or f64: single
+precision is faster, double keeps crossings exact enough that only points
+right on the outline come out differently. Precision picks one at runtime,
+and Float is what the generic code is written against. */
+
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+pub trait Float: Copy + Debug + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self> {
+    const ZERO: Self;
+    const ONE: Self;
+    // Difference between 1 and the next number up
+    const EPSILON: Self;
+    const PI: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn cbrt(self) -> Self;
+    fn cos(self) -> Self;
+    fn acos(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    // Magnitude of self with the sign of sign
+    fn copysign(self, sign: Self) -> Self;
+}
+
+impl Float for f32 {
+    const ZERO: f32 = 0f32;
+    const ONE: f32 = 1f32;
+    const EPSILON: f32 = f32::EPSILON;
+    const PI: f32 = std::f32::consts::PI;
+
+    fn from_f32(value: f32) -> f32 { return value; }
+    fn abs(self) -> f32 { return f32::abs(self); }
+    fn sqrt(self) -> f32 { return f32::sqrt(self); }
+    fn cbrt(self) -> f32 { return f32::cbrt(self); }
+    fn cos(self) -> f32 { return f32::cos(self); }
+    fn acos(self) -> f32 { return f32::acos(self); }
+    fn min(self, other: f32) -> f32 { return f32::min(self, other); }
+    fn max(self, other: f32) -> f32 { return f32::max(self, other); }
+    fn copysign(self, sign: f32) -> f32 { return f32::copysign(self, sign); }
+}
+
+impl Float for f64 {
+    const ZERO: f64 = 0f64;
+    const ONE: f64 = 1f64;
+    const EPSILON: f64 = f64::EPSILON;
+    const PI: f64 = std::f64::consts::PI;
+
+    fn from_f32(value: f32) -> f64 { return value as f64; }
+    fn abs(self) -> f64 { return f64::abs(self); }
+    fn sqrt(self) -> f64 { return f64::sqrt(self); }
+    fn cbrt(self) -> f64 { return f64::cbrt(self); }
+    fn cos(self) -> f64 { return f64::cos(self); }
+    fn acos(self) -> f64 { return f64::acos(self); }
+    fn min(self, other: f64) -> f64 { return f64::min(self, other); }
+    fn max(self, other: f64) -> f64 { return f64::max(self, other); }
+    fn copysign(self, sign: f64) -> f64 { return f64::copysign(self, sign); }
+}
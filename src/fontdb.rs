@@ -0,0 +1,596 @@
+/* Local font discovery. Scans font directories, indexes every face it finds
+and matches CSS-style queries ("Roboto Bold Italic") against the index using
+the font matching algorithm from CSS Fonts Level 4, section 5.2:
+https://www.w3.org/TR/css-fonts-4/#font-style-matching
+
+Parsing every font on the system is slow, so the index is cached on disk and
+only files whose size or modification time changed are re-parsed. */
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use ttf_parser::{Face, Style};
+
+use crate::collection::{face_count, get_face_info};
+
+const CACHE_HEADER: &str = "ttf-test fontdb v1";
+
+#[derive(Debug, Clone)]
+pub struct FontEntry {
+    pub path: PathBuf,
+    pub index: u32,
+    pub family: String,
+    pub subfamily: String,
+    pub postscript_name: String,
+    // OS/2 usWeightClass (100-900)
+    pub weight: u16,
+    // OS/2 usWidthClass (1 = ultra-condensed, 5 = normal, 9 = ultra-expanded)
+    pub width: u16,
+    pub style: Style,
+    // Sorted, non-overlapping inclusive ranges of code points mapped by the cmap
+    pub coverage: Vec<(u32, u32)>,
+    // Used to invalidate cache entries
+    modified: u64,
+    size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FontQuery {
+    // Families in order of preference, like the CSS font-family list
+    pub families: Vec<String>,
+    pub weight: u16,
+    pub width: u16,
+    pub style: Style,
+}
+
+#[derive(Debug, Default)]
+pub struct FontDatabase {
+    pub entries: Vec<FontEntry>,
+}
+
+pub fn default_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("./fonts"), PathBuf::from("/usr/share/fonts"), PathBuf::from("/usr/local/share/fonts")];
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(Path::new(&home).join(".local/share/fonts"));
+        dirs.push(Path::new(&home).join(".fonts"));
+        dirs.push(Path::new(&home).join("Library/Fonts"));
+    }
+    dirs.push(PathBuf::from("/Library/Fonts"));
+    dirs.push(PathBuf::from("/System/Library/Fonts"));
+    return dirs;
+}
+
+pub fn default_cache_path() -> PathBuf {
+    if let Ok(cache) = std::env::var("XDG_CACHE_HOME") {
+        return Path::new(&cache).join("ttf-test/fontdb.cache");
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        return Path::new(&home).join(".cache/ttf-test/fontdb.cache");
+    }
+    return PathBuf::from("./fontdb.cache");
+}
+
+impl FontDatabase {
+    pub fn load(dirs: &[PathBuf], cache_path: &Path) -> FontDatabase {
+        /* Builds the index for the given directories, reusing cached entries for
+        files that haven't changed since the cache was written. The updated index
+        is written back to the cache (failure to write it is not fatal). */
+        let cached = read_cache(cache_path);
+        let mut files = Vec::<PathBuf>::new();
+        for dir in dirs {
+            collect_font_files(dir, &mut files);
+        }
+        files.sort();
+        files.dedup();
+
+        let mut database = FontDatabase::default();
+        for path in files {
+            let (modified, size) = match file_stamp(&path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            match cached.get(&path) {
+                Some(entries) if entries.iter().all(|e| e.modified == modified && e.size == size) => {
+                    database.entries.extend(entries.iter().cloned());
+                },
+                _ => {
+                    database.entries.extend(index_file(&path, modified, size));
+                }
+            }
+        }
+
+        if let Err(e) = write_cache(cache_path, &database.entries) {
+            eprintln!("Warning: could not write font cache {:?}: {}", cache_path, e);
+        }
+        return database;
+    }
+
+    pub fn query(&self, query: &FontQuery) -> Option<&FontEntry> {
+        // Returns the best face of the first family in the list that has any faces
+        for family in query.families.iter() {
+            let candidates: Vec<&FontEntry> = self.entries.iter()
+                .filter(|e| e.family.eq_ignore_ascii_case(family))
+                .collect();
+            if let Some(entry) = match_style(candidates, query) {
+                return Some(entry);
+            }
+        }
+        return None;
+    }
+
+    pub fn query_for_char(&self, query: &FontQuery, c: char) -> Option<&FontEntry> {
+        /* Like query(), but only considers faces that can render the character, and
+        falls back to any family that covers it if none of the requested ones do. */
+        for family in query.families.iter() {
+            let candidates: Vec<&FontEntry> = self.entries.iter()
+                .filter(|e| e.family.eq_ignore_ascii_case(family) && e.covers(c))
+                .collect();
+            if let Some(entry) = match_style(candidates, query) {
+                return Some(entry);
+            }
+        }
+        let candidates: Vec<&FontEntry> = self.entries.iter().filter(|e| e.covers(c)).collect();
+        let family = candidates.first()?.family.clone();
+        return match_style(candidates.into_iter().filter(|e| e.family == family).collect(), query);
+    }
+}
+
+impl FontEntry {
+    pub fn covers(&self, c: char) -> bool {
+        let c = c as u32;
+        return match self.coverage.binary_search_by(|&(start, _)| start.cmp(&c)) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(i) => c <= self.coverage[i - 1].1,
+        };
+    }
+}
+
+impl FontQuery {
+    pub fn parse(text: &str) -> FontQuery {
+        /* Parses queries like "Roboto Bold Italic" or "Noto Serif JP Light, Roboto".
+        Style keywords at the end of each family name set the weight, width and
+        slant; whatever is left is the family name. Keywords can be written as
+        two words ("Semi Bold", "Extra Light"), so the last two words are tried
+        joined up before the last one on its own. */
+        let mut query = FontQuery { families: Vec::new(), weight: 400, width: 5, style: Style::Normal };
+        for part in text.split(',') {
+            let mut words: Vec<&str> = part.split_whitespace().collect();
+            while words.len() > 1 {
+                let last = words[words.len() - 1].to_ascii_lowercase().replace('-', "");
+                if words.len() > 2 {
+                    let joined = format!("{}{}", words[words.len() - 2].to_ascii_lowercase(), last);
+                    if query.apply_keyword(&joined) {
+                        words.truncate(words.len() - 2);
+                        continue;
+                    }
+                }
+                if !query.apply_keyword(&last) {
+                    break;
+                }
+                words.pop();
+            }
+            if !words.is_empty() {
+                query.families.push(words.join(" "));
+            }
+        }
+        return query;
+    }
+
+    fn apply_keyword(&mut self, word: &str) -> bool {
+        // Sets the property a lowercase style keyword names, or returns false if it isn't one
+        if let Some(weight) = parse_weight(word) {
+            self.weight = weight;
+        } else if let Some(width) = parse_width(word) {
+            self.width = width;
+        } else if word == "italic" {
+            self.style = Style::Italic;
+        } else if word == "oblique" {
+            self.style = Style::Oblique;
+        } else {
+            return false;
+        }
+        return true;
+    }
+}
+
+fn parse_weight(word: &str) -> Option<u16> {
+    return match word {
+        "thin" | "hairline" => Some(100),
+        "extralight" | "ultralight" => Some(200),
+        "light" => Some(300),
+        "regular" | "normal" | "book" => Some(400),
+        "medium" => Some(500),
+        "semibold" | "demibold" => Some(600),
+        "bold" => Some(700),
+        "extrabold" | "ultrabold" => Some(800),
+        "black" | "heavy" => Some(900),
+        _ => None,
+    };
+}
+
+fn parse_width(word: &str) -> Option<u16> {
+    return match word {
+        "ultracondensed" => Some(1),
+        "extracondensed" => Some(2),
+        "condensed" => Some(3),
+        "semicondensed" => Some(4),
+        "semiexpanded" => Some(6),
+        "expanded" => Some(7),
+        "extraexpanded" => Some(8),
+        "ultraexpanded" => Some(9),
+        _ => None,
+    };
+}
+
+fn match_style<'a>(mut candidates: Vec<&'a FontEntry>, query: &FontQuery) -> Option<&'a FontEntry> {
+    /* Narrows the candidates down by width, then style, then weight. Each step
+    keeps only the faces with the best value for that property. */
+    if candidates.is_empty() {
+        return None;
+    }
+
+    // Step 1: font-stretch. Narrower widths are preferred for condensed queries,
+    // wider ones for expanded queries.
+    let width = query.width;
+    let best_width = candidates.iter().map(|e| e.width).min_by_key(|&w| {
+        if w == width {
+            (0, 0)
+        } else if width <= 5 {
+            if w < width { (1, width - w) } else { (2, w - width) }
+        } else if w > width {
+            (1, w - width)
+        } else {
+            (2, width - w)
+        }
+    })?;
+    candidates.retain(|e| e.width == best_width);
+
+    // Step 2: font-style
+    let style_order = match query.style {
+        Style::Italic => [Style::Italic, Style::Oblique, Style::Normal],
+        Style::Oblique => [Style::Oblique, Style::Italic, Style::Normal],
+        Style::Normal => [Style::Normal, Style::Oblique, Style::Italic],
+    };
+    let best_style = style_order.iter().find(|&&s| candidates.iter().any(|e| e.style == s))?;
+    candidates.retain(|e| e.style == *best_style);
+
+    // Step 3: font-weight. 400 and 500 are treated as interchangeable, lighter
+    // queries prefer lighter faces and bolder queries prefer bolder faces.
+    let weight = query.weight;
+    return candidates.into_iter().min_by_key(|e| {
+        let w = e.weight;
+        if w == weight {
+            (0, 0)
+        } else if (400..=500).contains(&weight) {
+            if w > weight && w <= 500 {
+                (1, w - weight)
+            } else if w < weight {
+                (2, weight - w)
+            } else {
+                (3, w - weight)
+            }
+        } else if weight < 400 {
+            if w < weight { (1, weight - w) } else { (2, w - weight) }
+        } else if w > weight {
+            (1, w - weight)
+        } else {
+            (2, weight - w)
+        }
+    });
+}
+
+fn collect_font_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    // Recursively finds font files, ignoring directories we can't read
+    let read_dir = match fs::read_dir(dir) {
+        Ok(r) => r,
+        Err(_) => return,
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_font_files(&path, files);
+            continue;
+        }
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        if matches!(extension.as_str(), "ttf" | "otf" | "ttc" | "otc") {
+            files.push(path);
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    return Some((modified, metadata.len()));
+}
+
+fn index_file(path: &Path, modified: u64, size: u64) -> Vec<FontEntry> {
+    // Parses every face in the file. Unreadable or invalid files produce no entries.
+    let mut entries = Vec::<FontEntry>::new();
+    let data = match fs::read(path) {
+        Ok(d) => d,
+        Err(_) => return entries,
+    };
+    for index in 0..face_count(&data) {
+        let face = match Face::parse(&data, index) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        let info = get_face_info(&face, index);
+        entries.push(FontEntry {
+            path: path.to_path_buf(),
+            index,
+            family: info.family,
+            subfamily: info.subfamily,
+            postscript_name: info.postscript_name,
+            weight: face.weight().to_number(),
+            width: face.width().to_number(),
+            style: face.style(),
+            coverage: get_coverage(&face),
+            modified,
+            size,
+        });
+    }
+    return entries;
+}
+
+fn get_coverage(face: &Face) -> Vec<(u32, u32)> {
+    // Collects every code point in the Unicode cmap subtables as a list of ranges
+    let mut code_points = Vec::<u32>::new();
+    if let Some(cmap) = face.tables().cmap {
+        for subtable in cmap.subtables {
+            if subtable.is_unicode() {
+                subtable.codepoints(|c| code_points.push(c));
+            }
+        }
+    }
+    code_points.sort_unstable();
+    code_points.dedup();
+
+    let mut ranges = Vec::<(u32, u32)>::new();
+    for c in code_points {
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 == c => last.1 = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+    return ranges;
+}
+
+fn style_to_str(style: Style) -> &'static str {
+    return match style {
+        Style::Normal => "normal",
+        Style::Italic => "italic",
+        Style::Oblique => "oblique",
+    };
+}
+
+fn style_from_str(text: &str) -> Option<Style> {
+    return match text {
+        "normal" => Some(Style::Normal),
+        "italic" => Some(Style::Italic),
+        "oblique" => Some(Style::Oblique),
+        _ => None,
+    };
+}
+
+fn write_cache(cache_path: &Path, entries: &[FontEntry]) -> std::io::Result<()> {
+    /* One tab-separated line per face. Names can't contain tabs or newlines in
+    practice, but they're replaced with spaces just in case. */
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    let mut contents = String::from(CACHE_HEADER);
+    contents.push('\n');
+    for e in entries {
+        let coverage: Vec<String> = e.coverage.iter().map(|(start, end)| format!("{:x}-{:x}", start, end)).collect();
+        contents.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            clean(&e.path.to_string_lossy()), e.modified, e.size, e.index,
+            clean(&e.family), clean(&e.subfamily), clean(&e.postscript_name),
+            e.weight, e.width, style_to_str(e.style), coverage.join(",")));
+    }
+    return fs::write(cache_path, contents);
+}
+
+fn read_cache(cache_path: &Path) -> HashMap<PathBuf, Vec<FontEntry>> {
+    // Missing, outdated or corrupt caches just result in a full rescan
+    let mut cached = HashMap::<PathBuf, Vec<FontEntry>>::new();
+    let contents = match fs::read_to_string(cache_path) {
+        Ok(c) => c,
+        Err(_) => return cached,
+    };
+    let mut lines = contents.lines();
+    if lines.next() != Some(CACHE_HEADER) {
+        return cached;
+    }
+    for line in lines {
+        if let Some(entry) = parse_cache_line(line) {
+            cached.entry(entry.path.clone()).or_default().push(entry);
+        }
+    }
+    return cached;
+}
+
+fn parse_cache_line(line: &str) -> Option<FontEntry> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 11 {
+        return None;
+    }
+    let mut coverage = Vec::<(u32, u32)>::new();
+    for range in fields[10].split(',').filter(|r| !r.is_empty()) {
+        let (start, end) = range.split_once('-')?;
+        coverage.push((u32::from_str_radix(start, 16).ok()?, u32::from_str_radix(end, 16).ok()?));
+    }
+    return Some(FontEntry {
+        path: PathBuf::from(fields[0]),
+        modified: fields[1].parse().ok()?,
+        size: fields[2].parse().ok()?,
+        index: fields[3].parse().ok()?,
+        family: fields[4].to_string(),
+        subfamily: fields[5].to_string(),
+        postscript_name: fields[6].to_string(),
+        weight: fields[7].parse().ok()?,
+        width: fields[8].parse().ok()?,
+        style: style_from_str(fields[9])?,
+        coverage,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use ttf_parser::Style;
+
+    use super::{file_stamp, match_style, read_cache, write_cache, FontDatabase, FontEntry, FontQuery};
+
+    const FIXTURE: &str = "fonts/CubicTest-Regular.otf";
+
+    fn entry(weight: u16, width: u16, style: Style) -> FontEntry {
+        return FontEntry {
+            path: PathBuf::from(format!("{}-{}-{:?}.ttf", weight, width, style)),
+            index: 0,
+            family: String::from("Test"),
+            subfamily: String::new(),
+            postscript_name: String::new(),
+            weight: weight,
+            width: width,
+            style: style,
+            coverage: vec![(0x20, 0x7e)],
+            modified: 0,
+            size: 0,
+        };
+    }
+
+    fn best(entries: &[FontEntry], weight: u16, width: u16, style: Style) -> (u16, u16, Style) {
+        let query = FontQuery { families: vec![String::from("Test")], weight: weight, width: width, style: style };
+        let found = match_style(entries.iter().collect(), &query).unwrap();
+        return (found.weight, found.width, found.style);
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        // A fresh directory for a test to write into
+        let dir = std::env::temp_dir().join(format!("ttf-test-fontdb-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    #[test]
+    fn parse_keywords() {
+        let query = FontQuery::parse("Roboto Bold Italic");
+        assert_eq!((query.families, query.weight, query.width, query.style), (vec![String::from("Roboto")], 700, 5, Style::Italic));
+        // Two word keywords, and ones with hyphens, don't leave half of themselves in the family name
+        let query = FontQuery::parse("Roboto Semi Bold");
+        assert_eq!((query.families, query.weight), (vec![String::from("Roboto")], 600));
+        let query = FontQuery::parse("Noto Sans Extra Light");
+        assert_eq!((query.families, query.weight), (vec![String::from("Noto Sans")], 200));
+        let query = FontQuery::parse("Open Sans Ultra Condensed Semi-Bold Oblique");
+        assert_eq!((query.families, query.weight, query.width, query.style), (vec![String::from("Open Sans")], 600, 1, Style::Oblique));
+        // The family keeps at least one word, even when it looks like a keyword
+        let query = FontQuery::parse("Black");
+        assert_eq!((query.families, query.weight), (vec![String::from("Black")], 400));
+        // Fallback families are split on commas
+        let query = FontQuery::parse("Noto Serif JP Light, Roboto");
+        assert_eq!((query.families, query.weight), (vec![String::from("Noto Serif JP"), String::from("Roboto")], 300));
+    }
+
+    #[test]
+    fn width_falls_back_before_style_and_weight() {
+        // Normal and narrower queries look narrower first, wider queries look wider first
+        let entries = [entry(400, 4, Style::Normal), entry(400, 6, Style::Normal)];
+        assert_eq!(best(&entries, 400, 5, Style::Normal).1, 4);
+        let entries = [entry(400, 6, Style::Normal), entry(400, 8, Style::Normal)];
+        assert_eq!(best(&entries, 400, 7, Style::Normal).1, 8);
+        let entries = [entry(400, 8, Style::Normal), entry(400, 9, Style::Normal)];
+        assert_eq!(best(&entries, 400, 5, Style::Normal).1, 8);
+        // The closest width wins even over a face with the right style and weight
+        let entries = [entry(400, 3, Style::Normal), entry(700, 5, Style::Italic)];
+        assert_eq!(best(&entries, 400, 5, Style::Normal), (700, 5, Style::Italic));
+    }
+
+    #[test]
+    fn style_falls_back_before_weight() {
+        let entries = [entry(400, 5, Style::Normal), entry(700, 5, Style::Oblique)];
+        assert_eq!(best(&entries, 400, 5, Style::Italic).2, Style::Oblique);
+        let entries = [entry(400, 5, Style::Normal), entry(400, 5, Style::Italic)];
+        assert_eq!(best(&entries, 400, 5, Style::Oblique).2, Style::Italic);
+        let entries = [entry(400, 5, Style::Italic), entry(400, 5, Style::Oblique)];
+        assert_eq!(best(&entries, 400, 5, Style::Normal).2, Style::Oblique);
+    }
+
+    #[test]
+    fn weight_fallback_order() {
+        let weights: Vec<FontEntry> = [100, 300, 500, 600, 900].iter().map(|&w| entry(w, 5, Style::Normal)).collect();
+        // 400 tries up to 500 first, then lighter, then heavier
+        assert_eq!(best(&weights, 400, 5, Style::Normal).0, 500);
+        assert_eq!(best(&weights[..2], 400, 5, Style::Normal).0, 300);
+        assert_eq!(best(&[entry(600, 5, Style::Normal), entry(900, 5, Style::Normal)], 450, 5, Style::Normal).0, 600);
+        // Light queries look lighter first, bold queries heavier
+        assert_eq!(best(&weights, 200, 5, Style::Normal).0, 100);
+        assert_eq!(best(&weights, 700, 5, Style::Normal).0, 900);
+        assert_eq!(best(&weights[..3], 700, 5, Style::Normal).0, 500);
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = scratch_dir("round-trip");
+        let cache = dir.join("fontdb.cache");
+        let mut original = entry(600, 3, Style::Italic);
+        original.family = String::from("Tab\tFamily");
+        original.coverage = vec![(0x20, 0x7e), (0x4e00, 0x9fff)];
+        original.modified = 1234;
+        original.size = 5678;
+        write_cache(&cache, &[original.clone()]).unwrap();
+        let cached = read_cache(&cache);
+        let read = &cached[&original.path];
+        assert_eq!(read.len(), 1);
+        let read = &read[0];
+        assert_eq!((read.weight, read.width, read.style, read.index), (600, 3, Style::Italic, 0));
+        assert_eq!((read.modified, read.size), (1234, 5678));
+        assert_eq!(read.coverage, original.coverage);
+        // Tabs would break the line up, so they're written as spaces
+        assert_eq!(read.family, "Tab Family");
+        // A cache from another version is ignored
+        fs::write(&cache, "ttf-test fontdb v0\n").unwrap();
+        assert!(read_cache(&cache).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn load_family(font_dir: &Path, cache: &Path, stamp: (u64, u64)) -> String {
+        // Loads the database with a cache that claims the font is "Cached" with the given modification time and size
+        let path = font_dir.join("CubicTest-Regular.otf");
+        let mut cached = entry(400, 5, Style::Normal);
+        cached.path = path.clone();
+        cached.family = String::from("Cached");
+        (cached.modified, cached.size) = stamp;
+        write_cache(cache, &[cached]).unwrap();
+        let database = FontDatabase::load(&[font_dir.to_path_buf()], cache);
+        assert_eq!(database.entries.len(), 1);
+        return database.entries[0].family.clone();
+    }
+
+    #[test]
+    fn cache_invalidation() {
+        let dir = scratch_dir("invalidation");
+        let font_dir = dir.join("fonts");
+        fs::create_dir_all(&font_dir).unwrap();
+        fs::copy(FIXTURE, font_dir.join("CubicTest-Regular.otf")).unwrap();
+        let cache = dir.join("fontdb.cache");
+        let (modified, size) = file_stamp(&font_dir.join("CubicTest-Regular.otf")).unwrap();
+        // An entry for the same file, unchanged, is used instead of parsing the font
+        assert_eq!(load_family(&font_dir, &cache, (modified, size)), "Cached");
+        // A different size or modification time means the file changed, so it's parsed again
+        assert_eq!(load_family(&font_dir, &cache, (modified, size + 1)), "Cubic Test");
+        assert_eq!(load_family(&font_dir, &cache, (modified + 1, size)), "Cubic Test");
+        // And the cache written back has the fresh entry
+        let cached = read_cache(&cache);
+        assert_eq!(cached[&font_dir.join("CubicTest-Regular.otf")][0].family, "Cubic Test");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
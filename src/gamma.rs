@@ -0,0 +1,154 @@
+/* Blends glyph coverage onto the background in linear light. Coverage says
+how much of a pixel the glyph covers, so mixing the colors in gamma-encoded
+(sRGB) values makes edges too thin and dark on light backgrounds and too
+bold on dark ones. Colors are decoded with a power curve, mixed, and encoded
+again. A contrast boost for partly covered pixels helps thin text hold up
+after the correction. */
+
+use crate::float::Precision;
+use crate::scanline::Rasterizer;
+use crate::stroke::TextOutline;
+use crate::synthetic::SyntheticStyle;
+use crate::transform::Transform;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    // Gamma-encoded RGBA, not premultiplied
+    pub foreground: [f32; 4],
+    // Gamma-encoded RGB
+    pub background: [f32; 3],
+    // Exponent of the display's transfer curve, 2.2 for sRGB (1 blends the encoded values)
+    pub gamma: f32,
+    // How much to boost partly covered pixels, 0 leaves coverage as it is
+    pub contrast: f32,
+    // Embolden thin stems at small sizes (see embolden.rs)
+    pub stem_darkening: bool,
+    // Bold and oblique the font doesn't have (see synthetic.rs)
+    pub synthetic: SyntheticStyle,
+    // Filled, hollow, or filled with a border (see stroke.rs)
+    pub outline: TextOutline,
+    // Gamma-encoded RGBA of the border around bordered text
+    pub border: [f32; 4],
+    // Rotation, skew and scale of the glyphs (the translation is ignored)
+    pub transform: Transform,
+    // Precision of the inside/outside test (see float.rs)
+    pub precision: Precision,
+    // Ray per sample or scanline sweep (see scanline.rs)
+    pub rasterizer: Rasterizer,
+}
+
+impl TextStyle {
+    pub fn inverted(&self) -> TextStyle {
+        // Swaps the foreground and background, e.g. for a dark mode
+        let foreground = [self.background[0], self.background[1], self.background[2], self.foreground[3]];
+        let background = [self.foreground[0], self.foreground[1], self.foreground[2]];
+        return TextStyle { foreground, background, ..*self };
+    }
+}
+
+pub fn blend_layers(layers: &[([f32; 3], [f32; 4])], style: &TextStyle) -> [u8; 3] {
+    /* Color of a pixel covered by layers of color (bottom first, e.g. a
+    border under the text), with coverage per channel for subpixel rendering */
+    let mut pixel = [0u8; 3];
+    for channel in 0..3 {
+        let mut value = to_linear(style.background[channel], style.gamma);
+        for (coverage, color) in layers.iter() {
+            let c = coverage[channel].clamp(0f32, 1f32);
+            let alpha = (c + style.contrast * c * (1f32 - c)).min(1f32) * color[3];
+            value = to_linear(color[channel], style.gamma) * alpha + value * (1f32 - alpha);
+        }
+        pixel[channel] = to_byte(from_linear(value, style.gamma));
+    }
+    return pixel;
+}
+
+pub fn blend_premultiplied(color: [f32; 4], style: &TextStyle) -> [u8; 3] {
+    // Color of a premultiplied RGBA pixel (from a color glyph bitmap) over the background
+    let mut pixel = [0u8; 3];
+    let alpha = color[3].clamp(0f32, 1f32);
+    for channel in 0..3 {
+        let straight = if alpha > 0f32 { color[channel] / alpha } else { 0f32 };
+        let foreground = to_linear(straight, style.gamma);
+        let background = to_linear(style.background[channel], style.gamma);
+        pixel[channel] = to_byte(from_linear(foreground * alpha + background * (1f32 - alpha), style.gamma));
+    }
+    return pixel;
+}
+
+pub fn to_byte(value: f32) -> u8 {
+    return (value.clamp(0f32, 1f32) * 255f32).round() as u8;
+}
+
+fn to_linear(value: f32, gamma: f32) -> f32 {
+    return value.clamp(0f32, 1f32).powf(gamma);
+}
+
+fn from_linear(value: f32, gamma: f32) -> f32 {
+    return value.clamp(0f32, 1f32).powf(1f32 / gamma);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::float::Precision;
+    use crate::scanline::Rasterizer;
+    use crate::stroke::TextOutline;
+    use crate::synthetic::SyntheticStyle;
+    use crate::transform::Transform;
+    use super::{blend_layers, to_byte, TextStyle};
+
+    const BLACK: [f32; 4] = [0f32, 0f32, 0f32, 1f32];
+    const RED: [f32; 4] = [1f32, 0f32, 0f32, 1f32];
+
+    fn style(gamma: f32, contrast: f32) -> TextStyle {
+        // Black on white
+        return TextStyle {
+            foreground: BLACK,
+            background: [1f32, 1f32, 1f32],
+            gamma,
+            contrast,
+            stem_darkening: false,
+            synthetic: SyntheticStyle { bold: false, oblique: false },
+            outline: TextOutline::Fill,
+            border: RED,
+            transform: Transform::IDENTITY,
+            precision: Precision::Double,
+            rasterizer: Rasterizer::RayCasting,
+        };
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(to_byte(0f32), 0);
+        assert_eq!(to_byte(1f32), 255);
+        assert_eq!(to_byte(0.5f32), 128);
+        assert_eq!(to_byte(1f32 / 255f32), 1);
+        assert_eq!(to_byte(0.998f32), 254);
+        // Out of range values are clamped instead of wrapping
+        assert_eq!(to_byte(-0.5f32), 0);
+        assert_eq!(to_byte(1.5f32), 255);
+    }
+
+    #[test]
+    fn layers() {
+        let plain = style(1f32, 0f32);
+        // Nothing covered is the background, fully covered is the color
+        assert_eq!(blend_layers(&[], &plain), [255, 255, 255]);
+        assert_eq!(blend_layers(&[([0f32; 3], BLACK)], &plain), [255, 255, 255]);
+        assert_eq!(blend_layers(&[([1f32; 3], BLACK)], &plain), [0, 0, 0]);
+        // Each channel has its own coverage, and the color's alpha scales it
+        assert_eq!(blend_layers(&[([1f32, 0f32, 0.5f32], BLACK)], &plain), [0, 255, 128]);
+        assert_eq!(blend_layers(&[([1f32; 3], [0f32, 0f32, 0f32, 0.5f32])], &plain), [128, 128, 128]);
+        // Later layers go on top: text half over a full border
+        assert_eq!(blend_layers(&[([1f32; 3], RED), ([0.5f32; 3], BLACK)], &plain), [128, 0, 0]);
+        assert_eq!(blend_layers(&[([0.5f32; 3], BLACK), ([1f32; 3], RED)], &plain), [255, 0, 0]);
+
+        // Half coverage is half the light, which is brighter than half the encoded value
+        let encoded = 0.5f32.powf(1f32 / 2.2f32);
+        assert_eq!(blend_layers(&[([0.5f32; 3], BLACK)], &style(2.2f32, 0f32)), [to_byte(encoded); 3]);
+        // Contrast darkens partly covered pixels only
+        let boosted = style(1f32, 0.25f32);
+        assert_eq!(blend_layers(&[([0.5f32; 3], BLACK)], &boosted), [to_byte(1f32 - 0.5625f32); 3]);
+        assert_eq!(blend_layers(&[([1f32; 3], BLACK)], &boosted), [0, 0, 0]);
+        assert_eq!(blend_layers(&[([0f32; 3], BLACK)], &boosted), [255, 255, 255]);
+    }
+}
@@ -0,0 +1,2003 @@
+/* TrueType bytecode hinting.
+https://learn.microsoft.com/en-us/typography/opentype/spec/tt_instructions
+https://developer.apple.com/fonts/TrueType-Reference-Manual/RM05/Chap5.html
+
+Glyphs are read straight from glyf/loca (ttf_parser doesn't expose the
+points or instructions), scaled to 26.6 fixed point pixels at the requested
+ppem and grid-fitted by running the font's programs: fpgm once, prep for the
+size, then each glyph's own instructions. HintingCache keeps the hinter
+for each face, size and mode, so fpgm and prep only run the first time, and
+the auto-hinter's blue zones for each face and set of variations. The
+hinted points are scaled back to font units, so the result is the same list
+of contours that extract_outline gives.
+
+Where the spec leaves room, the interpreter follows FreeType. The
+vertical-only mode works like FreeType's v40 interpreter: moves along x in
+the glyph zone are ignored (unless prep opts out of backwards compatibility
+with INSTCTRL) and nothing moves once IUP has run on both axes. Variation
+deltas (gvar/cvar) aren't applied, so callers should use the unhinted
+outline for variable font instances. */
+
+use std::collections::HashMap;
+
+use ttf_parser::{Face, GlyphId, OutlineBuilder, Rect, Tag};
+
+use crate::autohint::{autohint_outline, blue_zones, BlueZone};
+use crate::collection::FaceSelector;
+use crate::curve::Contour;
+use crate::outline::{extract_glyph_outline, Builder};
+use crate::read::{read_i16, read_u16, read_u32};
+use crate::variation::Variation;
+
+// Guards against runaway programs (infinite loops, deep recursion)
+const MAX_INSTRUCTIONS: u32 = 1_000_000;
+const MAX_CALL_DEPTH: usize = 64;
+const MAX_COMPONENT_DEPTH: u32 = 16;
+// Horizontal origin, advance width, vertical origin and advance height
+const PHANTOM_POINTS: usize = 4;
+
+const ONE_F2DOT14: i32 = 0x4000;
+const GLYPH_ZONE: usize = 1;
+const TWILIGHT_ZONE: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintingMode {
+    None,
+    Full,
+    VerticalOnly,
+    // Ignores the font's instructions and uses the auto-hinter (see autohint.rs)
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoundState {
+    Grid,
+    HalfGrid,
+    DoubleGrid,
+    DownToGrid,
+    UpToGrid,
+    Off,
+    Super { period: i32, phase: i32, threshold: i32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GraphicsState {
+    rp0: usize,
+    rp1: usize,
+    rp2: usize,
+    zp0: usize,
+    zp1: usize,
+    zp2: usize,
+    // Unit vectors in 2.14 fixed point
+    freedom: (i32, i32),
+    projection: (i32, i32),
+    dual_projection: (i32, i32),
+    loop_count: i32,
+    // Distances below are in 26.6 fixed point
+    minimum_distance: i32,
+    round_state: RoundState,
+    auto_flip: bool,
+    control_value_cutin: i32,
+    single_width_cutin: i32,
+    single_width_value: i32,
+    delta_base: i32,
+    delta_shift: i32,
+    instruct_control: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Zone {
+    // Font units, only used by IUP
+    unscaled: Vec<(i32, i32)>,
+    // Scaled, before hinting (26.6)
+    original: Vec<(i32, i32)>,
+    // Scaled, after hinting (26.6)
+    current: Vec<(i32, i32)>,
+    touched_x: Vec<bool>,
+    touched_y: Vec<bool>,
+    on_curve: Vec<bool>,
+    // Index of the last point of each contour (phantom points aren't in a contour)
+    contour_ends: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Function {
+    program: usize,
+    start: usize,
+    end: usize,
+}
+
+// A simple glyph as stored in glyf, before scaling
+struct SimpleGlyph {
+    // Font units
+    points: Vec<(i32, i32)>,
+    on_curve: Vec<bool>,
+    contour_ends: Vec<usize>,
+    instructions: Vec<u8>,
+}
+
+// Programs are referred to by index so that function definitions can point into any of them
+const FPGM: usize = 0;
+const PREP: usize = 1;
+const GLYPH: usize = 2;
+
+#[derive(Clone)]
+pub struct Hinter {
+    mode: HintingMode,
+    ppem: i32,
+    // 26.6 units per font unit, in 16.16 fixed point like FreeType's
+    scale: i32,
+    programs: [Vec<u8>; 3],
+    functions: Vec<Option<Function>>,
+    instruction_defs: Vec<Option<Function>>,
+    cvt: Vec<i32>,
+    storage: Vec<i32>,
+    stack: Vec<i32>,
+    max_stack: usize,
+    gs: GraphicsState,
+    // Graphics state left behind by prep, which glyph programs start from
+    glyph_gs: GraphicsState,
+    zones: [Zone; 2],
+    twilight_after_prep: Zone,
+    in_glyph_program: bool,
+    iup_x_called: bool,
+    iup_y_called: bool,
+}
+
+struct GlyphTables<'a> {
+    // Where glyphs are loaded from, the only part of the face hinting a glyph needs
+    face: &'a Face<'a>,
+    glyf: &'a [u8],
+    loca: &'a [u8],
+    long_offsets: bool,
+}
+
+// Variation axis values by their bits, to be hashed
+type Coordinates = Vec<(Tag, u32)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FaceKey {
+    font_path: String,
+    face: FaceSelector,
+}
+
+#[derive(Default)]
+pub struct HintingCache {
+    // Hinters that have run fpgm and prep, by face, ppem and mode. None where that failed, so it isn't run again.
+    hinters: HashMap<(FaceKey, i32, HintingMode), Option<Hinter>>,
+    // Blue zones for the auto-hinter, by face and variation coordinates
+    blues: HashMap<(FaceKey, Coordinates), Vec<BlueZone>>,
+}
+
+impl FaceKey {
+    pub fn new(font_path: &str, face: &FaceSelector) -> FaceKey {
+        return FaceKey { font_path: font_path.to_string(), face: face.clone() };
+    }
+}
+
+impl HintingCache {
+    pub fn fitted_outline(&mut self, key: &FaceKey, face: &Face, glyph_id: GlyphId, pixels_per_em: f32, mode: HintingMode, variations: &[Variation]) -> (Vec<Contour>, Option<Rect>) {
+        /* Outline of a glyph with the given kind of hinting applied, in the same
+        form as extract_glyph_outline. Bytecode hinting is skipped for variable
+        font instances (gvar/cvar deltas aren't applied to the hinted outline)
+        and for fonts it fails on, which are drawn unhinted instead. */
+        match mode {
+            HintingMode::None => {},
+            HintingMode::Auto => {
+                let (contours, bbox) = extract_glyph_outline(face, glyph_id, variations);
+                return match bbox {
+                    Some(bbox) => {
+                        let entry = (key.clone(), variations.iter().map(|v| (v.axis, v.value.to_bits())).collect());
+                        let blues = self.blues.entry(entry).or_insert_with(|| blue_zones(face, variations));
+                        let (contours, bbox) = autohint_outline(face, contours, bbox, pixels_per_em, blues);
+                        (contours, Some(bbox))
+                    },
+                    None => (contours, None),
+                };
+            },
+            HintingMode::Full | HintingMode::VerticalOnly => {
+                if variations.is_empty() {
+                    if let Some(Ok((contours, bbox))) = self.hinter(key, face, pixels_per_em, mode).map(|hinter| hinter.hint_glyph(face, glyph_id)) {
+                        return (contours, Some(bbox));
+                    }
+                }
+            },
+        }
+        return extract_glyph_outline(face, glyph_id, variations);
+    }
+
+    fn hinter(&mut self, key: &FaceKey, face: &Face, pixels_per_em: f32, mode: HintingMode) -> Option<&Hinter> {
+        // fpgm and prep only run the first time a face is hinted at a size
+        let entry = (key.clone(), whole_ppem(pixels_per_em), mode);
+        return self.hinters.entry(entry).or_insert_with(|| Hinter::new(face, pixels_per_em, mode).ok()).as_ref();
+    }
+}
+
+impl GraphicsState {
+    fn default() -> GraphicsState {
+        return GraphicsState {
+            rp0: 0,
+            rp1: 0,
+            rp2: 0,
+            zp0: GLYPH_ZONE,
+            zp1: GLYPH_ZONE,
+            zp2: GLYPH_ZONE,
+            freedom: (ONE_F2DOT14, 0),
+            projection: (ONE_F2DOT14, 0),
+            dual_projection: (ONE_F2DOT14, 0),
+            loop_count: 1,
+            minimum_distance: 64,
+            round_state: RoundState::Grid,
+            auto_flip: true,
+            control_value_cutin: 68,
+            single_width_cutin: 0,
+            single_width_value: 0,
+            delta_base: 9,
+            delta_shift: 3,
+            instruct_control: 0,
+        };
+    }
+}
+
+impl Zone {
+    fn with_points(unscaled: Vec<(i32, i32)>, original: Vec<(i32, i32)>, on_curve: Vec<bool>, contour_ends: Vec<usize>) -> Zone {
+        let count = original.len();
+        return Zone {
+            unscaled,
+            current: original.clone(),
+            original,
+            touched_x: vec![false; count],
+            touched_y: vec![false; count],
+            on_curve,
+            contour_ends,
+        };
+    }
+
+    fn twilight(count: usize) -> Zone {
+        return Zone::with_points(vec![(0, 0); count], vec![(0, 0); count], vec![false; count], Vec::new());
+    }
+}
+
+impl<'a> GlyphTables<'a> {
+    fn new(face: &'a Face<'a>) -> Result<GlyphTables<'a>, String> {
+        let raw = face.raw_face();
+        let glyf = raw.table(Tag::from_bytes(b"glyf")).ok_or("no glyf table")?;
+        let loca = raw.table(Tag::from_bytes(b"loca")).ok_or("no loca table")?;
+        let head = raw.table(Tag::from_bytes(b"head")).ok_or("no head table")?;
+        let long_offsets = read_i16(head, 50).ok_or("bad head table")? != 0;
+        return Ok(GlyphTables { face, glyf, loca, long_offsets });
+    }
+
+    fn glyph_data(&self, glyph_id: GlyphId) -> Option<&'a [u8]> {
+        let index = glyph_id.0 as usize;
+        let (start, end) = if self.long_offsets {
+            (read_u32(self.loca, index * 4)? as usize, read_u32(self.loca, index * 4 + 4)? as usize)
+        } else {
+            (read_u16(self.loca, index * 2)? as usize * 2, read_u16(self.loca, index * 2 + 2)? as usize * 2)
+        };
+        if end < start {
+            return None;
+        }
+        return self.glyf.get(start..end);
+    }
+
+    fn phantom_points(&self, glyph_id: GlyphId, x_min: i32) -> [(i32, i32); PHANTOM_POINTS] {
+        // Unscaled phantom points from the horizontal metrics (vertical ones from the ascender and descender)
+        let advance = self.face.glyph_hor_advance(glyph_id).unwrap_or(0) as i32;
+        let left_side_bearing = self.face.glyph_hor_side_bearing(glyph_id).unwrap_or(x_min as i16) as i32;
+        let origin = x_min - left_side_bearing;
+        let ascender = self.face.ascender() as i32;
+        let descender = self.face.descender() as i32;
+        return [(origin, 0), (origin + advance, 0), (0, ascender), (0, descender)];
+    }
+}
+
+impl Hinter {
+    pub fn new(face: &Face, pixels_per_em: f32, mode: HintingMode) -> Result<Hinter, String> {
+        // Loads the font programs and runs fpgm and prep for the given size (only TrueType outlines are hinted)
+        GlyphTables::new(face)?;
+        let raw = face.raw_face();
+        let maxp = raw.table(Tag::from_bytes(b"maxp")).ok_or("no maxp table")?;
+
+        let ppem = whole_ppem(pixels_per_em);
+        let scale = div_fix(ppem * 64, face.units_per_em() as i32);
+        let cvt = match raw.table(Tag::from_bytes(b"cvt ")) {
+            Some(data) => (0..data.len() / 2).map(|i| mul_fix(read_i16(data, i * 2).unwrap_or(0) as i32, scale)).collect(),
+            None => Vec::new(),
+        };
+        // maxp version 0.5 (CFF fonts) stops before these fields
+        let twilight_points = read_u16(maxp, 16).unwrap_or(0) as usize;
+        let storage_size = read_u16(maxp, 18).unwrap_or(0) as usize;
+        let function_count = read_u16(maxp, 20).unwrap_or(0) as usize;
+        let max_stack = read_u16(maxp, 24).unwrap_or(0) as usize;
+
+        let program = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag)).map(|p| p.to_vec()).unwrap_or_default();
+        let mut hinter = Hinter {
+            mode,
+            ppem,
+            scale,
+            programs: [program(b"fpgm"), program(b"prep"), Vec::new()],
+            functions: vec![None; function_count],
+            instruction_defs: vec![None; 256],
+            cvt,
+            storage: vec![0; storage_size],
+            stack: Vec::new(),
+            // Some fonts underestimate their stack use, so leave some headroom
+            max_stack: max_stack + 32,
+            gs: GraphicsState::default(),
+            glyph_gs: GraphicsState::default(),
+            zones: [Zone::twilight(twilight_points), Zone::default()],
+            twilight_after_prep: Zone::default(),
+            in_glyph_program: false,
+            iup_x_called: false,
+            iup_y_called: false,
+        };
+        hinter.run(FPGM)?;
+        hinter.gs = GraphicsState::default();
+        hinter.run(PREP)?;
+        hinter.glyph_gs = hinter.gs;
+        hinter.twilight_after_prep = hinter.zones[TWILIGHT_ZONE].clone();
+        return Ok(hinter);
+    }
+
+    pub fn hint_glyph(&self, face: &Face, glyph_id: GlyphId) -> Result<(Vec<Contour>, Rect), String> {
+        // Hinted outline of a glyph of the face the hinter was made for in font units, with its bounding box
+        let zone = self.hint_points(face, glyph_id)?;
+        let point_count = zone.current.len() - PHANTOM_POINTS;
+        if point_count == 0 {
+            return Err("empty glyph".to_string());
+        }
+        let scale = self.scale as f32 / 65536f32;
+        let to_font_units = |p: (i32, i32)| (p.0 as f32 / scale, p.1 as f32 / scale);
+
+        let mut x_min = f32::MAX;
+        let mut y_min = f32::MAX;
+        let mut x_max = f32::MIN;
+        let mut y_max = f32::MIN;
+        for &point in zone.current[..point_count].iter() {
+            let (x, y) = to_font_units(point);
+            x_min = x_min.min(x);
+            y_min = y_min.min(y);
+            x_max = x_max.max(x);
+            y_max = y_max.max(y);
+        }
+        let bbox = Rect { x_min: x_min.floor() as i16, y_min: y_min.floor() as i16, x_max: x_max.ceil() as i16, y_max: y_max.ceil() as i16 };
+
+        let mut builder = Builder::default();
+        let mut start = 0;
+        for &end in zone.contour_ends.iter() {
+            let points: Vec<(f32, f32, bool)> = (start..=end)
+                .map(|i| {
+                    let (x, y) = to_font_units(zone.current[i]);
+                    (x, y, zone.on_curve[i])
+                })
+                .collect();
+            start = end + 1;
+            add_contour(&mut builder, &points);
+        }
+        return Ok((builder.contours, bbox));
+    }
+
+    fn hint_points(&self, face: &Face, glyph_id: GlyphId) -> Result<Zone, String> {
+        /* The glyph's points after its instructions, in 26.6. Every glyph
+        starts from a copy of the state prep left behind, so hinting one can't
+        change how another comes out (glyph programs can write to the CVT and
+        storage, and even define functions). */
+        let tables = GlyphTables::new(face)?;
+        return self.clone().load_glyph(&tables, glyph_id, 0);
+    }
+
+    fn scale_point(&self, point: (i32, i32)) -> (i32, i32) {
+        return (mul_fix(point.0, self.scale), mul_fix(point.1, self.scale));
+    }
+
+    fn load_glyph(&mut self, tables: &GlyphTables, glyph_id: GlyphId, depth: u32) -> Result<Zone, String> {
+        /* Loads a glyph into a zone (with its phantom points at the end) and
+        runs its instructions. Components of composite glyphs are hinted on
+        their own first, then the composite's instructions run on the result. */
+        if depth > MAX_COMPONENT_DEPTH {
+            return Err("composite glyph nesting too deep".to_string());
+        }
+        let data = tables.glyph_data(glyph_id).ok_or("bad loca entry")?;
+        if data.is_empty() {
+            let phantoms = tables.phantom_points(glyph_id, 0).to_vec();
+            let original = phantoms.iter().map(|&p| self.scale_point(p)).collect();
+            return Ok(Zone::with_points(phantoms, original, vec![true; PHANTOM_POINTS], Vec::new()));
+        }
+        let contour_count = read_i16(data, 0).ok_or("bad glyph header")?;
+        let x_min = read_i16(data, 2).ok_or("bad glyph header")? as i32;
+
+        let (mut zone, instructions) = if contour_count >= 0 {
+            let glyph = parse_simple_glyph(data, contour_count as usize).ok_or("bad simple glyph")?;
+            let mut unscaled = glyph.points;
+            unscaled.extend_from_slice(&tables.phantom_points(glyph_id, x_min));
+            let original = unscaled.iter().map(|&p| self.scale_point(p)).collect();
+            let mut on_curve = glyph.on_curve;
+            on_curve.extend_from_slice(&[true; PHANTOM_POINTS]);
+            (Zone::with_points(unscaled, original, on_curve, glyph.contour_ends), glyph.instructions)
+        } else {
+            self.load_composite(tables, glyph_id, data, x_min, depth)?
+        };
+
+        // Phantom points start out on the pixel grid
+        let point_count = zone.current.len();
+        for i in (point_count - PHANTOM_POINTS)..point_count {
+            let (x, y) = zone.original[i];
+            zone.original[i] = (round_to_grid(x), round_to_grid(y));
+            zone.current[i] = zone.original[i];
+        }
+
+        let instructions_enabled = self.gs.instruct_control & 1 == 0 && self.glyph_gs.instruct_control & 1 == 0;
+        if !instructions.is_empty() && instructions_enabled {
+            self.zones[GLYPH_ZONE] = zone;
+            self.zones[TWILIGHT_ZONE] = self.twilight_after_prep.clone();
+            self.programs[GLYPH] = instructions;
+            // instruct_control bit 2 asks for the default graphics state instead of prep's
+            self.gs = if self.glyph_gs.instruct_control & 2 != 0 { GraphicsState::default() } else { self.glyph_gs };
+            self.gs.rp0 = 0;
+            self.gs.rp1 = 0;
+            self.gs.rp2 = 0;
+            self.gs.zp0 = GLYPH_ZONE;
+            self.gs.zp1 = GLYPH_ZONE;
+            self.gs.zp2 = GLYPH_ZONE;
+            self.gs.freedom = (ONE_F2DOT14, 0);
+            self.gs.projection = (ONE_F2DOT14, 0);
+            self.gs.dual_projection = (ONE_F2DOT14, 0);
+            self.gs.loop_count = 1;
+            self.in_glyph_program = true;
+            self.iup_x_called = false;
+            self.iup_y_called = false;
+            self.stack.clear();
+            let result = self.run(GLYPH);
+            self.in_glyph_program = false;
+            zone = std::mem::take(&mut self.zones[GLYPH_ZONE]);
+            result?;
+        }
+        return Ok(zone);
+    }
+
+    fn load_composite(&mut self, tables: &GlyphTables, glyph_id: GlyphId, data: &[u8], x_min: i32, depth: u32) -> Result<(Zone, Vec<u8>), String> {
+        // Flags from https://learn.microsoft.com/en-us/typography/opentype/spec/glyf#composite-glyph-description
+        const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const ROUND_XY_TO_GRID: u16 = 0x0004;
+        const WE_HAVE_A_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+        const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+        const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+        let mut unscaled = Vec::<(i32, i32)>::new();
+        let mut current = Vec::<(i32, i32)>::new();
+        let mut on_curve = Vec::<bool>::new();
+        let mut contour_ends = Vec::<usize>::new();
+        let mut offset = 10;
+        let mut flags = MORE_COMPONENTS;
+        while flags & MORE_COMPONENTS != 0 {
+            flags = read_u16(data, offset).ok_or("bad component")?;
+            let component = GlyphId(read_u16(data, offset + 2).ok_or("bad component")?);
+            offset += 4;
+            let (arg1, arg2) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                offset += 4;
+                if flags & ARGS_ARE_XY_VALUES != 0 {
+                    (read_i16(data, offset - 4).ok_or("bad component")? as i32, read_i16(data, offset - 2).ok_or("bad component")? as i32)
+                } else {
+                    (read_u16(data, offset - 4).ok_or("bad component")? as i32, read_u16(data, offset - 2).ok_or("bad component")? as i32)
+                }
+            } else {
+                offset += 2;
+                let a = *data.get(offset - 2).ok_or("bad component")?;
+                let b = *data.get(offset - 1).ok_or("bad component")?;
+                if flags & ARGS_ARE_XY_VALUES != 0 { (a as i8 as i32, b as i8 as i32) } else { (a as i32, b as i32) }
+            };
+            let f2dot14 = |at: usize| -> Result<f64, String> { return Ok(read_i16(data, at).ok_or("bad component")? as f64 / 16384f64); };
+            // (xx, yx, xy, yy)
+            let matrix = if flags & WE_HAVE_A_SCALE != 0 {
+                offset += 2;
+                let s = f2dot14(offset - 2)?;
+                (s, 0f64, 0f64, s)
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                offset += 4;
+                (f2dot14(offset - 4)?, 0f64, 0f64, f2dot14(offset - 2)?)
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                offset += 8;
+                (f2dot14(offset - 8)?, f2dot14(offset - 6)?, f2dot14(offset - 4)?, f2dot14(offset - 2)?)
+            } else {
+                (1f64, 0f64, 0f64, 1f64)
+            };
+
+            let child = self.load_glyph(tables, component, depth + 1)?;
+            let child_points = child.current.len() - PHANTOM_POINTS;
+            let transform = |p: (i32, i32)| -> (i32, i32) {
+                let (x, y) = (p.0 as f64, p.1 as f64);
+                return ((matrix.0 * x + matrix.2 * y).round() as i32, (matrix.1 * x + matrix.3 * y).round() as i32);
+            };
+            let child_unscaled: Vec<(i32, i32)> = child.unscaled[..child_points].iter().map(|&p| transform(p)).collect();
+            let child_current: Vec<(i32, i32)> = child.current[..child_points].iter().map(|&p| transform(p)).collect();
+
+            let (unscaled_offset, scaled_offset) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                let (mut dx, mut dy) = self.scale_point((arg1, arg2));
+                if flags & ROUND_XY_TO_GRID != 0 {
+                    dx = round_to_grid(dx);
+                    dy = round_to_grid(dy);
+                }
+                ((arg1, arg2), (dx, dy))
+            } else {
+                // Align point arg2 of the component with point arg1 of the glyph so far
+                let (parent, child_point) = (arg1 as usize, arg2 as usize);
+                if parent >= current.len() || child_point >= child_points {
+                    return Err("bad component anchor point".to_string());
+                }
+                (
+                    (unscaled[parent].0 - child_unscaled[child_point].0, unscaled[parent].1 - child_unscaled[child_point].1),
+                    (current[parent].0 - child_current[child_point].0, current[parent].1 - child_current[child_point].1),
+                )
+            };
+
+            let base = current.len();
+            for &end in child.contour_ends.iter() {
+                contour_ends.push(base + end);
+            }
+            unscaled.extend(child_unscaled.iter().map(|p| (p.0 + unscaled_offset.0, p.1 + unscaled_offset.1)));
+            current.extend(child_current.iter().map(|p| (p.0 + scaled_offset.0, p.1 + scaled_offset.1)));
+            on_curve.extend_from_slice(&child.on_curve[..child_points]);
+        }
+
+        let instructions = if flags & WE_HAVE_INSTRUCTIONS != 0 {
+            let length = read_u16(data, offset).ok_or("bad composite instructions")? as usize;
+            data.get(offset + 2..offset + 2 + length).ok_or("bad composite instructions")?.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        // The composite's instructions see the hinted components as the original outline
+        for phantom in tables.phantom_points(glyph_id, x_min) {
+            unscaled.push(phantom);
+            current.push(self.scale_point(phantom));
+        }
+        on_curve.extend_from_slice(&[true; PHANTOM_POINTS]);
+        return Ok((Zone::with_points(unscaled, current, on_curve, contour_ends), instructions));
+    }
+
+    // Interpreter
+
+    fn run(&mut self, program: usize) -> Result<(), String> {
+        /* Executes a program from the start. Calls into functions defined in
+        any program keep a stack of (program, return address, function,
+        remaining iterations) frames. */
+        let mut calls = Vec::<(usize, usize, Function, i32)>::new();
+        let mut current_program = program;
+        let mut pc = 0usize;
+        let mut end = self.programs[program].len();
+        let mut executed = 0u32;
+        loop {
+            if pc >= end {
+                if calls.is_empty() {
+                    return Ok(());
+                }
+                return Err("function without ENDF".to_string());
+            }
+            executed += 1;
+            if executed > MAX_INSTRUCTIONS {
+                return Err("too many instructions".to_string());
+            }
+            let opcode = self.programs[current_program][pc];
+            let mut next = pc + 1;
+            match opcode {
+                // Push instructions read their data from the instruction stream
+                0x40 | 0x41 | 0xB0..=0xBF => {
+                    let code = &self.programs[current_program];
+                    let (count, words, data_start) = match opcode {
+                        0x40 => (*code.get(pc + 1).ok_or("truncated push")? as usize, false, pc + 2),
+                        0x41 => (*code.get(pc + 1).ok_or("truncated push")? as usize, true, pc + 2),
+                        0xB0..=0xB7 => ((opcode - 0xB0) as usize + 1, false, pc + 1),
+                        _ => ((opcode - 0xB8) as usize + 1, true, pc + 1),
+                    };
+                    let size = if words { 2 } else { 1 };
+                    if data_start + count * size > end {
+                        return Err("truncated push".to_string());
+                    }
+                    let values: Vec<i32> = (0..count)
+                        .map(|i| {
+                            let at = data_start + i * size;
+                            if words { i16::from_be_bytes([code[at], code[at + 1]]) as i32 } else { code[at] as i32 }
+                        })
+                        .collect();
+                    for value in values {
+                        self.push(value)?;
+                    }
+                    next = data_start + count * size;
+                },
+                // IF
+                0x58 => {
+                    if self.pop()? == 0 {
+                        next = self.skip_conditional(current_program, next, end, true)?;
+                    }
+                },
+                // ELSE, reached after running the IF branch
+                0x1B => next = self.skip_conditional(current_program, next, end, false)?,
+                // EIF
+                0x59 => {},
+                // JMPR, JROT, JROF: offsets are relative to the jump instruction
+                0x1C | 0x78 | 0x79 => {
+                    let jump = match opcode {
+                        0x1C => true,
+                        0x78 => self.pop()? != 0,
+                        _ => self.pop()? == 0,
+                    };
+                    let offset = self.pop()?;
+                    if jump {
+                        let target = pc as i64 + offset as i64;
+                        if target < 0 || target as usize > end {
+                            return Err("jump out of range".to_string());
+                        }
+                        next = target as usize;
+                    }
+                },
+                // FDEF
+                0x2C => {
+                    let number = self.pop()?;
+                    let endf = self.find_endf(current_program, next, end)?;
+                    let slot = self.functions.get_mut(number as usize).ok_or("function number out of range")?;
+                    *slot = Some(Function { program: current_program, start: next, end: endf + 1 });
+                    next = endf + 1;
+                },
+                // IDEF
+                0x89 => {
+                    let number = self.pop()?;
+                    let endf = self.find_endf(current_program, next, end)?;
+                    let slot = self.instruction_defs.get_mut(number as usize).ok_or("instruction number out of range")?;
+                    *slot = Some(Function { program: current_program, start: next, end: endf + 1 });
+                    next = endf + 1;
+                },
+                // ENDF
+                0x2D => {
+                    let (return_program, return_pc, function, remaining) = calls.pop().ok_or("ENDF outside a function")?;
+                    if remaining > 1 {
+                        calls.push((return_program, return_pc, function, remaining - 1));
+                        next = function.start;
+                    } else {
+                        current_program = return_program;
+                        end = if calls.is_empty() { self.programs[current_program].len() } else { calls[calls.len() - 1].2.end };
+                        next = return_pc;
+                    }
+                },
+                // CALL, LOOPCALL
+                0x2B | 0x2A => {
+                    let number = self.pop()?;
+                    let count = if opcode == 0x2A { self.pop()? } else { 1 };
+                    let function = self.functions.get(number as usize).copied().flatten().ok_or("call to undefined function")?;
+                    if count > 0 {
+                        if calls.len() >= MAX_CALL_DEPTH {
+                            return Err("call stack overflow".to_string());
+                        }
+                        calls.push((current_program, next, function, count));
+                        current_program = function.program;
+                        end = function.end;
+                        next = function.start;
+                    }
+                },
+                _ => {
+                    if let Some(function) = self.instruction_defs[opcode as usize] {
+                        // Instructions defined by the font with IDEF take priority
+                        if calls.len() >= MAX_CALL_DEPTH {
+                            return Err("call stack overflow".to_string());
+                        }
+                        calls.push((current_program, next, function, 1));
+                        current_program = function.program;
+                        end = function.end;
+                        next = function.start;
+                    } else {
+                        self.execute(opcode)?;
+                    }
+                },
+            }
+            pc = next;
+        }
+    }
+
+    fn skip_conditional(&self, program: usize, start: usize, end: usize, stop_at_else: bool) -> Result<usize, String> {
+        // Returns the position after the matching ELSE (if stop_at_else) or EIF
+        let code = &self.programs[program];
+        let mut depth = 0;
+        let mut pc = start;
+        while pc < end {
+            match code[pc] {
+                0x58 => depth += 1,
+                0x1B if depth == 0 && stop_at_else => return Ok(pc + 1),
+                0x59 => {
+                    if depth == 0 {
+                        return Ok(pc + 1);
+                    }
+                    depth -= 1;
+                },
+                _ => {},
+            }
+            pc += instruction_length(code, pc)?;
+        }
+        return Err("IF without EIF".to_string());
+    }
+
+    fn find_endf(&self, program: usize, start: usize, end: usize) -> Result<usize, String> {
+        let code = &self.programs[program];
+        let mut pc = start;
+        while pc < end {
+            match code[pc] {
+                0x2D => return Ok(pc),
+                0x2C | 0x89 => return Err("nested function definition".to_string()),
+                _ => {},
+            }
+            pc += instruction_length(code, pc)?;
+        }
+        return Err("FDEF without ENDF".to_string());
+    }
+
+    fn push(&mut self, value: i32) -> Result<(), String> {
+        if self.stack.len() >= self.max_stack {
+            return Err("stack overflow".to_string());
+        }
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn pop(&mut self) -> Result<i32, String> {
+        return self.stack.pop().ok_or_else(|| "stack underflow".to_string());
+    }
+
+    fn pop_point(&mut self, zone: usize) -> Result<usize, String> {
+        let point = self.pop()?;
+        if point < 0 || point as usize >= self.zones[zone].current.len() {
+            return Err("point out of range".to_string());
+        }
+        return Ok(point as usize);
+    }
+
+    fn check_point(&self, zone: usize, point: usize) -> Result<(), String> {
+        if point >= self.zones[zone].current.len() {
+            return Err("reference point out of range".to_string());
+        }
+        return Ok(());
+    }
+
+    fn pop_zone(&mut self) -> Result<usize, String> {
+        return match self.pop()? {
+            0 => Ok(TWILIGHT_ZONE),
+            1 => Ok(GLYPH_ZONE),
+            _ => Err("invalid zone".to_string()),
+        };
+    }
+
+    fn take_loop(&mut self) -> i32 {
+        let count = self.gs.loop_count;
+        self.gs.loop_count = 1;
+        return count;
+    }
+
+    fn backward_compatibility(&self) -> bool {
+        // v40 mode, unless prep asked for native ClearType behaviour
+        return self.mode == HintingMode::VerticalOnly && self.gs.instruct_control & 4 == 0 && self.glyph_gs.instruct_control & 4 == 0;
+    }
+
+    fn project(&self, dx: i32, dy: i32) -> i32 {
+        return dot(dx, dy, self.gs.projection);
+    }
+
+    fn dual_project(&self, dx: i32, dy: i32) -> i32 {
+        return dot(dx, dy, self.gs.dual_projection);
+    }
+
+    fn current_distance(&self, zone_a: usize, a: usize, zone_b: usize, b: usize) -> i32 {
+        let pa = self.zones[zone_a].current[a];
+        let pb = self.zones[zone_b].current[b];
+        return self.project(pa.0 - pb.0, pa.1 - pb.1);
+    }
+
+    fn original_distance(&self, zone_a: usize, a: usize, zone_b: usize, b: usize) -> i32 {
+        let pa = self.zones[zone_a].original[a];
+        let pb = self.zones[zone_b].original[b];
+        return self.dual_project(pa.0 - pb.0, pa.1 - pb.1);
+    }
+
+    fn unscaled_distance(&self, zone_a: usize, a: usize, zone_b: usize, b: usize) -> i32 {
+        let pa = self.zones[zone_a].unscaled[a];
+        let pb = self.zones[zone_b].unscaled[b];
+        return self.dual_project(pa.0 - pb.0, pa.1 - pb.1);
+    }
+
+    fn scaled_distance(&self, zone_a: usize, a: usize, zone_b: usize, b: usize) -> i32 {
+        // Outside the twilight zone FreeType scales the distance in font units rather than measuring between rounded points
+        if zone_a == TWILIGHT_ZONE || zone_b == TWILIGHT_ZONE {
+            return self.original_distance(zone_a, a, zone_b, b);
+        }
+        return mul_fix(self.unscaled_distance(zone_a, a, zone_b, b), self.scale);
+    }
+
+    fn freedom_dot_projection(&self) -> i32 {
+        let (fx, fy) = self.gs.freedom;
+        let (px, py) = self.gs.projection;
+        let dot = ((fx as i64 * px as i64 + fy as i64 * py as i64) >> 14) as i32;
+        // Nearly perpendicular vectors would send points off to infinity
+        if dot.abs() < 0x400 {
+            return ONE_F2DOT14;
+        }
+        return dot;
+    }
+
+    fn moves_ignored(&self, zone: usize) -> bool {
+        return zone == GLYPH_ZONE && self.in_glyph_program && self.backward_compatibility() && self.iup_x_called && self.iup_y_called;
+    }
+
+    fn move_point(&mut self, zone: usize, point: usize, distance: i32) {
+        // Moves a point along the freedom vector so that its projection changes by distance
+        let dot = self.freedom_dot_projection();
+        let (fx, fy) = self.gs.freedom;
+        self.shift_point(zone, point, mul_div(distance, fx, dot), mul_div(distance, fy, dot), true);
+    }
+
+    fn shift_point(&mut self, zone: usize, point: usize, dx: i32, dy: i32, touch: bool) {
+        if self.moves_ignored(zone) {
+            return;
+        }
+        let skip_x = zone == GLYPH_ZONE && self.in_glyph_program && self.backward_compatibility();
+        let (fx, fy) = self.gs.freedom;
+        let z = &mut self.zones[zone];
+        if fx != 0 {
+            if !skip_x {
+                z.current[point].0 = z.current[point].0.wrapping_add(dx);
+            }
+            if touch {
+                z.touched_x[point] = true;
+            }
+        }
+        if fy != 0 {
+            z.current[point].1 = z.current[point].1.wrapping_add(dy);
+            if touch {
+                z.touched_y[point] = true;
+            }
+        }
+    }
+
+    fn round(&self, distance: i32) -> i32 {
+        let with_sign = |f: &dyn Fn(i32) -> i32| -> i32 {
+            if distance >= 0 {
+                return f(distance).max(0);
+            }
+            return (-f(distance.wrapping_neg())).min(0);
+        };
+        return match self.gs.round_state {
+            RoundState::Off => distance,
+            RoundState::Grid => with_sign(&|d| d.wrapping_add(32) & !63),
+            RoundState::HalfGrid => with_sign(&|d| (d & !63) + 32),
+            RoundState::DoubleGrid => with_sign(&|d| d.wrapping_add(16) & !31),
+            RoundState::DownToGrid => with_sign(&|d| d & !63),
+            RoundState::UpToGrid => with_sign(&|d| d.wrapping_add(63) & !63),
+            RoundState::Super { period, phase, threshold } => {
+                if distance >= 0 {
+                    let value = (distance.wrapping_add(threshold - phase) & -period).wrapping_add(phase);
+                    if value < 0 { phase } else { value }
+                } else {
+                    let value = -((threshold - phase).wrapping_sub(distance) & -period) - phase;
+                    if value > 0 { -phase } else { value }
+                }
+            },
+        };
+    }
+
+    fn super_round(&mut self, selector: i32, grid_period: i32) {
+        // SROUND/S45ROUND, grid_period is in 1/256 of 26.6 units
+        let period = match selector & 0xC0 {
+            0x00 => grid_period / 2,
+            0x80 => grid_period * 2,
+            _ => grid_period,
+        };
+        let phase = match selector & 0x30 {
+            0x10 => period / 4,
+            0x20 => period / 2,
+            0x30 => period * 3 / 4,
+            _ => 0,
+        };
+        let threshold = match selector & 0x0F {
+            0 => period - 1,
+            t => (t - 4) * period / 8,
+        };
+        self.gs.round_state = RoundState::Super { period: (period >> 8).max(1), phase: phase >> 8, threshold: threshold >> 8 };
+    }
+
+    fn line_vector(&mut self, opcode: u8, dual: bool) -> Result<(i32, i32), String> {
+        // Unit vector along (or, for odd opcodes, perpendicular to) the line through two points
+        let a = self.pop_point(self.gs.zp2)?;
+        let b = self.pop_point(self.gs.zp1)?;
+        let points = if dual { (&self.zones[self.gs.zp1].original, &self.zones[self.gs.zp2].original) } else { (&self.zones[self.gs.zp1].current, &self.zones[self.gs.zp2].current) };
+        let (mut dx, mut dy) = (points.0[b].0 - points.1[a].0, points.0[b].1 - points.1[a].1);
+        if dx == 0 && dy == 0 {
+            dx = ONE_F2DOT14;
+            dy = 0;
+        }
+        if opcode & 1 != 0 {
+            let rotated = (-dy, dx);
+            dx = rotated.0;
+            dy = rotated.1;
+        }
+        return Ok(normalize(dx, dy));
+    }
+
+    fn reference_displacement(&self, opcode: u8) -> Result<(usize, usize, i32, i32), String> {
+        // For SHP/SHC/SHZ: how far the reference point (rp2 in zp1, or rp1 in zp0 for odd opcodes) has moved
+        let (zone, point) = if opcode & 1 != 0 { (self.gs.zp0, self.gs.rp1) } else { (self.gs.zp1, self.gs.rp2) };
+        self.check_point(zone, point)?;
+        let z = &self.zones[zone];
+        let distance = self.project(z.current[point].0 - z.original[point].0, z.current[point].1 - z.original[point].1);
+        let dot = self.freedom_dot_projection();
+        return Ok((zone, point, mul_div(distance, self.gs.freedom.0, dot), mul_div(distance, self.gs.freedom.1, dot)));
+    }
+
+    fn execute(&mut self, opcode: u8) -> Result<(), String> {
+        // Everything except pushes, branches and function calls, which run() handles
+        match opcode {
+            // SVTCA, SPVTCA, SFVTCA
+            0x00..=0x05 => {
+                let axis = if opcode & 1 == 0 { (0, ONE_F2DOT14) } else { (ONE_F2DOT14, 0) };
+                if opcode <= 0x03 {
+                    self.gs.projection = axis;
+                    self.gs.dual_projection = axis;
+                }
+                if opcode <= 0x01 || opcode >= 0x04 {
+                    self.gs.freedom = axis;
+                }
+            },
+            // SPVTL
+            0x06 | 0x07 => {
+                let vector = self.line_vector(opcode, false)?;
+                self.gs.projection = vector;
+                self.gs.dual_projection = vector;
+            },
+            // SFVTL
+            0x08 | 0x09 => self.gs.freedom = self.line_vector(opcode, false)?,
+            // SPVFS, SFVFS
+            0x0A | 0x0B => {
+                let y = self.pop()? as i16 as i32;
+                let x = self.pop()? as i16 as i32;
+                let vector = normalize(x, y);
+                if opcode == 0x0A {
+                    self.gs.projection = vector;
+                    self.gs.dual_projection = vector;
+                } else {
+                    self.gs.freedom = vector;
+                }
+            },
+            // GPV, GFV
+            0x0C | 0x0D => {
+                let vector = if opcode == 0x0C { self.gs.projection } else { self.gs.freedom };
+                self.push(vector.0)?;
+                self.push(vector.1)?;
+            },
+            // SFVTPV
+            0x0E => self.gs.freedom = self.gs.projection,
+            // ISECT
+            0x0F => {
+                let b1 = self.pop_point(self.gs.zp0)?;
+                let b0 = self.pop_point(self.gs.zp0)?;
+                let a1 = self.pop_point(self.gs.zp1)?;
+                let a0 = self.pop_point(self.gs.zp1)?;
+                let point = self.pop_point(self.gs.zp2)?;
+                let (pa0, pa1) = (self.zones[self.gs.zp1].current[a0], self.zones[self.gs.zp1].current[a1]);
+                let (pb0, pb1) = (self.zones[self.gs.zp0].current[b0], self.zones[self.gs.zp0].current[b1]);
+                let (dax, day) = (pa1.0 - pa0.0, pa1.1 - pa0.1);
+                let (dbx, dby) = (pb1.0 - pb0.0, pb1.1 - pb0.1);
+                let (dx, dy) = (pb0.0 - pa0.0, pb0.1 - pa0.1);
+                let discriminant = mul_div(dax, -dby, 0x40) + mul_div(day, dbx, 0x40);
+                let dot_product = mul_div(dax, dbx, 0x40) + mul_div(day, dby, 0x40);
+                let result = if 19 * discriminant.abs() > dot_product.abs() {
+                    let value = mul_div(dx, -dby, 0x40) + mul_div(dy, dbx, 0x40);
+                    (pa0.0 + mul_div(value, dax, discriminant), pa0.1 + mul_div(value, day, discriminant))
+                } else {
+                    // Parallel lines: use the middle of the four points
+                    ((pa0.0 + pa1.0 + pb0.0 + pb1.0) / 4, (pa0.1 + pa1.1 + pb0.1 + pb1.1) / 4)
+                };
+                let zone = &mut self.zones[self.gs.zp2];
+                zone.current[point] = result;
+                zone.touched_x[point] = true;
+                zone.touched_y[point] = true;
+            },
+            // SRP0, SRP1, SRP2
+            0x10 => self.gs.rp0 = self.pop()? as usize,
+            0x11 => self.gs.rp1 = self.pop()? as usize,
+            0x12 => self.gs.rp2 = self.pop()? as usize,
+            // SZP0, SZP1, SZP2, SZPS
+            0x13 => self.gs.zp0 = self.pop_zone()?,
+            0x14 => self.gs.zp1 = self.pop_zone()?,
+            0x15 => self.gs.zp2 = self.pop_zone()?,
+            0x16 => {
+                let zone = self.pop_zone()?;
+                self.gs.zp0 = zone;
+                self.gs.zp1 = zone;
+                self.gs.zp2 = zone;
+            },
+            // SLOOP
+            0x17 => {
+                let count = self.pop()?;
+                if count < 0 {
+                    return Err("negative loop count".to_string());
+                }
+                self.gs.loop_count = count;
+            },
+            // RTG, RTHG, SMD
+            0x18 => self.gs.round_state = RoundState::Grid,
+            0x19 => self.gs.round_state = RoundState::HalfGrid,
+            0x1A => self.gs.minimum_distance = self.pop()?,
+            // SCVTCI, SSWCI, SSW
+            0x1D => self.gs.control_value_cutin = self.pop()?,
+            0x1E => self.gs.single_width_cutin = self.pop()?,
+            0x1F => {
+                let value = self.pop()?;
+                self.gs.single_width_value = mul_fix(value, self.scale);
+            },
+            // DUP, POP, CLEAR, SWAP, DEPTH
+            0x20 => {
+                let value = *self.stack.last().ok_or("stack underflow")?;
+                self.push(value)?;
+            },
+            0x21 => {
+                self.pop()?;
+            },
+            0x22 => self.stack.clear(),
+            0x23 => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(a)?;
+                self.push(b)?;
+            },
+            0x24 => {
+                let depth = self.stack.len() as i32;
+                self.push(depth)?;
+            },
+            // CINDEX, MINDEX
+            0x25 | 0x26 => {
+                let k = self.pop()?;
+                if k <= 0 || k as usize > self.stack.len() {
+                    return Err("stack index out of range".to_string());
+                }
+                let index = self.stack.len() - k as usize;
+                let value = if opcode == 0x25 { self.stack[index] } else { self.stack.remove(index) };
+                self.push(value)?;
+            },
+            // ALIGNPTS
+            0x27 => {
+                let p2 = self.pop_point(self.gs.zp0)?;
+                let p1 = self.pop_point(self.gs.zp1)?;
+                let distance = self.current_distance(self.gs.zp0, p2, self.gs.zp1, p1) / 2;
+                self.move_point(self.gs.zp1, p1, distance);
+                self.move_point(self.gs.zp0, p2, -distance);
+            },
+            // UTP
+            0x29 => {
+                let point = self.pop_point(self.gs.zp0)?;
+                let zone = &mut self.zones[self.gs.zp0];
+                if self.gs.freedom.0 != 0 {
+                    zone.touched_x[point] = false;
+                }
+                if self.gs.freedom.1 != 0 {
+                    zone.touched_y[point] = false;
+                }
+            },
+            // MDAP
+            0x2E | 0x2F => {
+                let point = self.pop_point(self.gs.zp0)?;
+                let distance = if opcode == 0x2F {
+                    let p = self.zones[self.gs.zp0].current[point];
+                    let position = self.project(p.0, p.1);
+                    self.round(position) - position
+                } else {
+                    0
+                };
+                self.move_point(self.gs.zp0, point, distance);
+                self.gs.rp0 = point;
+                self.gs.rp1 = point;
+            },
+            // IUP
+            0x30 | 0x31 => {
+                let x_axis = opcode == 0x31;
+                if self.moves_ignored(GLYPH_ZONE) {
+                    return Ok(());
+                }
+                self.interpolate_untouched(x_axis);
+                if x_axis {
+                    self.iup_x_called = true;
+                } else {
+                    self.iup_y_called = true;
+                }
+            },
+            // SHP
+            0x32 | 0x33 => {
+                let (_, _, dx, dy) = self.reference_displacement(opcode)?;
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(self.gs.zp2)?;
+                    self.shift_point(self.gs.zp2, point, dx, dy, true);
+                }
+            },
+            // SHC
+            0x34 | 0x35 => {
+                let (ref_zone, ref_point, dx, dy) = self.reference_displacement(opcode)?;
+                let contour = self.pop()? as usize;
+                let zone = self.gs.zp2;
+                let ends = &self.zones[zone].contour_ends;
+                let end = *ends.get(contour).ok_or("contour out of range")?;
+                let start = if contour == 0 { 0 } else { ends[contour - 1] + 1 };
+                for point in start..=end {
+                    if zone != ref_zone || point != ref_point {
+                        self.shift_point(zone, point, dx, dy, true);
+                    }
+                }
+            },
+            // SHZ
+            0x36 | 0x37 => {
+                let (ref_zone, ref_point, dx, dy) = self.reference_displacement(opcode)?;
+                let zone = self.pop_zone()?;
+                let count = if zone == GLYPH_ZONE { self.zones[zone].current.len() - PHANTOM_POINTS } else { self.zones[zone].current.len() };
+                for point in 0..count {
+                    if zone != ref_zone || point != ref_point {
+                        self.shift_point(zone, point, dx, dy, false);
+                    }
+                }
+            },
+            // SHPIX
+            0x38 => {
+                let distance = self.pop()?;
+                let dx = mul_f2dot14(distance, self.gs.freedom.0);
+                let dy = mul_f2dot14(distance, self.gs.freedom.1);
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(self.gs.zp2)?;
+                    self.shift_point(self.gs.zp2, point, dx, dy, true);
+                }
+            },
+            // IP
+            0x39 => {
+                let (zp0, zp1, zp2) = (self.gs.zp0, self.gs.zp1, self.gs.zp2);
+                let (rp1, rp2) = (self.gs.rp1, self.gs.rp2);
+                self.check_point(zp0, rp1)?;
+                self.check_point(zp1, rp2)?;
+                // Like FreeType, outside the twilight zone the original distances are taken in font units, before rounding
+                let twilight = zp0 == TWILIGHT_ZONE || zp1 == TWILIGHT_ZONE || zp2 == TWILIGHT_ZONE;
+                let original = |hinter: &Self, zone_a: usize, a: usize, zone_b: usize, b: usize| if twilight {
+                    hinter.original_distance(zone_a, a, zone_b, b)
+                } else {
+                    hinter.unscaled_distance(zone_a, a, zone_b, b)
+                };
+                let original_range = original(self, zp1, rp2, zp0, rp1);
+                let current_range = self.current_distance(zp1, rp2, zp0, rp1);
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(zp2)?;
+                    let original_distance = original(self, zp2, point, zp0, rp1);
+                    let current_distance = self.current_distance(zp2, point, zp0, rp1);
+                    let new_distance = if original_distance != 0 && original_range != 0 {
+                        mul_div(original_distance, current_range, original_range)
+                    } else {
+                        original_distance
+                    };
+                    self.move_point(zp2, point, new_distance - current_distance);
+                }
+            },
+            // MSIRP
+            0x3A | 0x3B => {
+                let distance = self.pop()?;
+                let point = self.pop_point(self.gs.zp1)?;
+                let rp0 = self.gs.rp0;
+                self.check_point(self.gs.zp0, rp0)?;
+                if self.gs.zp1 == TWILIGHT_ZONE {
+                    let origin = self.zones[self.gs.zp0].original[rp0];
+                    self.zones[TWILIGHT_ZONE].original[point] = origin;
+                    self.zones[TWILIGHT_ZONE].current[point] = origin;
+                }
+                let current = self.current_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                self.move_point(self.gs.zp1, point, distance.wrapping_sub(current));
+                self.gs.rp1 = rp0;
+                self.gs.rp2 = point;
+                if opcode == 0x3B {
+                    self.gs.rp0 = point;
+                }
+            },
+            // ALIGNRP
+            0x3C => {
+                let rp0 = self.gs.rp0;
+                self.check_point(self.gs.zp0, rp0)?;
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(self.gs.zp1)?;
+                    let distance = self.current_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                    self.move_point(self.gs.zp1, point, -distance);
+                }
+            },
+            // RTDG
+            0x3D => self.gs.round_state = RoundState::DoubleGrid,
+            // MIAP
+            0x3E | 0x3F => {
+                let cvt_index = self.pop()?;
+                let point = self.pop_point(self.gs.zp0)?;
+                let mut distance = self.read_cvt(cvt_index);
+                if self.gs.zp0 == TWILIGHT_ZONE {
+                    let position = (mul_f2dot14(distance, self.gs.freedom.0), mul_f2dot14(distance, self.gs.freedom.1));
+                    self.zones[TWILIGHT_ZONE].original[point] = position;
+                    self.zones[TWILIGHT_ZONE].current[point] = position;
+                }
+                let p = self.zones[self.gs.zp0].current[point];
+                let original_distance = self.project(p.0, p.1);
+                if opcode == 0x3F {
+                    if (distance - original_distance).abs() > self.gs.control_value_cutin {
+                        distance = original_distance;
+                    }
+                    distance = self.round(distance);
+                }
+                self.move_point(self.gs.zp0, point, distance - original_distance);
+                self.gs.rp0 = point;
+                self.gs.rp1 = point;
+            },
+            // WS, RS
+            0x42 => {
+                let value = self.pop()?;
+                let index = self.pop()?;
+                if let Some(slot) = self.storage.get_mut(index as usize) {
+                    *slot = value;
+                }
+            },
+            0x43 => {
+                let index = self.pop()?;
+                let value = self.storage.get(index as usize).copied().unwrap_or(0);
+                self.push(value)?;
+            },
+            // WCVTP
+            0x44 => {
+                let value = self.pop()?;
+                let index = self.pop()?;
+                if let Some(slot) = self.cvt.get_mut(index as usize) {
+                    *slot = value;
+                }
+            },
+            // RCVT
+            0x45 => {
+                let index = self.pop()?;
+                let value = self.read_cvt(index);
+                self.push(value)?;
+            },
+            // GC
+            0x46 | 0x47 => {
+                let point = self.pop_point(self.gs.zp2)?;
+                let value = if opcode == 0x46 {
+                    let p = self.zones[self.gs.zp2].current[point];
+                    self.project(p.0, p.1)
+                } else {
+                    let p = self.zones[self.gs.zp2].original[point];
+                    self.dual_project(p.0, p.1)
+                };
+                self.push(value)?;
+            },
+            // SCFS
+            0x48 => {
+                let value = self.pop()?;
+                let point = self.pop_point(self.gs.zp2)?;
+                let p = self.zones[self.gs.zp2].current[point];
+                let position = self.project(p.0, p.1);
+                self.move_point(self.gs.zp2, point, value.wrapping_sub(position));
+                if self.gs.zp2 == TWILIGHT_ZONE {
+                    self.zones[TWILIGHT_ZONE].original[point] = self.zones[TWILIGHT_ZONE].current[point];
+                }
+            },
+            // MD: odd opcode measures the grid-fitted outline, even the original one
+            0x49 | 0x4A => {
+                let k = self.pop_point(self.gs.zp1)?;
+                let l = self.pop_point(self.gs.zp0)?;
+                let distance = if opcode == 0x4A {
+                    self.current_distance(self.gs.zp0, l, self.gs.zp1, k)
+                } else {
+                    self.original_distance(self.gs.zp0, l, self.gs.zp1, k)
+                };
+                self.push(distance)?;
+            },
+            // MPPEM, MPS
+            0x4B | 0x4C => self.push(self.ppem)?,
+            // FLIPON, FLIPOFF
+            0x4D => self.gs.auto_flip = true,
+            0x4E => self.gs.auto_flip = false,
+            // DEBUG
+            0x4F => {
+                self.pop()?;
+            },
+            // LT, LTEQ, GT, GTEQ, EQ, NEQ
+            0x50..=0x55 => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let result = match opcode {
+                    0x50 => a < b,
+                    0x51 => a <= b,
+                    0x52 => a > b,
+                    0x53 => a >= b,
+                    0x54 => a == b,
+                    _ => a != b,
+                };
+                self.push(result as i32)?;
+            },
+            // ODD, EVEN: of the value rounded with the current round state
+            0x56 | 0x57 => {
+                let value = self.pop()?;
+                let odd = (self.round(value) & 127) == 64;
+                self.push((odd == (opcode == 0x56)) as i32)?;
+            },
+            // AND, OR, NOT
+            0x5A => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push((a != 0 && b != 0) as i32)?;
+            },
+            0x5B => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push((a != 0 || b != 0) as i32)?;
+            },
+            0x5C => {
+                let a = self.pop()?;
+                self.push((a == 0) as i32)?;
+            },
+            // DELTAP1, DELTAP2, DELTAP3
+            0x5D | 0x71 | 0x72 => {
+                let base = match opcode {
+                    0x5D => 0,
+                    0x71 => 16,
+                    _ => 32,
+                };
+                let count = self.pop()?;
+                for _ in 0..count {
+                    let point = self.pop()?;
+                    let argument = self.pop()?;
+                    if let Some(delta) = self.delta(argument, base) {
+                        if point < 0 || point as usize >= self.zones[self.gs.zp0].current.len() {
+                            return Err("point out of range".to_string());
+                        }
+                        self.move_point(self.gs.zp0, point as usize, delta);
+                    }
+                }
+            },
+            // DELTAC1, DELTAC2, DELTAC3
+            0x73..=0x75 => {
+                let base = (opcode as i32 - 0x73) * 16;
+                let count = self.pop()?;
+                for _ in 0..count {
+                    let index = self.pop()?;
+                    let argument = self.pop()?;
+                    if let Some(delta) = self.delta(argument, base) {
+                        if let Some(slot) = self.cvt.get_mut(index as usize) {
+                            *slot = slot.wrapping_add(delta);
+                        }
+                    }
+                }
+            },
+            // SDB, SDS
+            0x5E => self.gs.delta_base = self.pop()?,
+            0x5F => {
+                let shift = self.pop()?;
+                if !(0..=6).contains(&shift) {
+                    return Err("invalid delta shift".to_string());
+                }
+                self.gs.delta_shift = shift;
+            },
+            // ADD, SUB, DIV, MUL
+            0x60..=0x63 => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let result = match opcode {
+                    0x60 => a.wrapping_add(b),
+                    0x61 => a.wrapping_sub(b),
+                    0x62 => {
+                        if b == 0 {
+                            return Err("division by zero".to_string());
+                        }
+                        ((a as i64 * 64) / b as i64) as i32
+                    },
+                    _ => mul_div(a, b, 64),
+                };
+                self.push(result)?;
+            },
+            // ABS, NEG, FLOOR, CEILING
+            0x64 => {
+                let a = self.pop()?;
+                self.push(a.wrapping_abs())?;
+            },
+            0x65 => {
+                let a = self.pop()?;
+                self.push(a.wrapping_neg())?;
+            },
+            0x66 => {
+                let a = self.pop()?;
+                self.push(a & !63)?;
+            },
+            0x67 => {
+                let a = self.pop()?;
+                self.push(a.wrapping_add(63) & !63)?;
+            },
+            // ROUND, NROUND (no engine compensation)
+            0x68..=0x6B => {
+                let a = self.pop()?;
+                let rounded = self.round(a);
+                self.push(rounded)?;
+            },
+            0x6C..=0x6F => {},
+            // WCVTF
+            0x70 => {
+                let value = self.pop()?;
+                let index = self.pop()?;
+                if let Some(slot) = self.cvt.get_mut(index as usize) {
+                    *slot = mul_fix(value, self.scale);
+                }
+            },
+            // SROUND, S45ROUND
+            0x76 => {
+                let selector = self.pop()?;
+                self.super_round(selector, 0x4000);
+            },
+            0x77 => {
+                let selector = self.pop()?;
+                self.super_round(selector, 0x2D41);
+            },
+            // ROFF, RUTG, RDTG
+            0x7A => self.gs.round_state = RoundState::Off,
+            0x7C => self.gs.round_state = RoundState::UpToGrid,
+            0x7D => self.gs.round_state = RoundState::DownToGrid,
+            // SANGW, AA (obsolete)
+            0x7E | 0x7F => {
+                self.pop()?;
+            },
+            // FLIPPT
+            0x80 => {
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(GLYPH_ZONE)?;
+                    let flag = &mut self.zones[GLYPH_ZONE].on_curve[point];
+                    *flag = !*flag;
+                }
+            },
+            // FLIPRGON, FLIPRGOFF
+            0x81 | 0x82 => {
+                let high = self.pop_point(GLYPH_ZONE)?;
+                let low = self.pop_point(GLYPH_ZONE)?;
+                for point in low..=high {
+                    self.zones[GLYPH_ZONE].on_curve[point] = opcode == 0x81;
+                }
+            },
+            // SCANCTRL
+            0x85 => {
+                self.pop()?;
+            },
+            // SDPVTL: projection from the current outline, dual projection from the original one
+            0x86 | 0x87 => {
+                let p2 = self.pop()?;
+                let p1 = self.pop()?;
+                self.stack.extend_from_slice(&[p1, p2]);
+                let dual = self.line_vector(opcode, true)?;
+                self.stack.extend_from_slice(&[p1, p2]);
+                self.gs.projection = self.line_vector(opcode, false)?;
+                self.gs.dual_projection = dual;
+            },
+            // GETINFO
+            0x88 => {
+                let selector = self.pop()?;
+                let mut result = 0;
+                if selector & 1 != 0 {
+                    result = if self.mode == HintingMode::VerticalOnly { 40 } else { 35 };
+                }
+                if self.mode == HintingMode::VerticalOnly {
+                    // Subpixel hinting, symmetrical smoothing, ClearType with grayscale
+                    if selector & 64 != 0 {
+                        result |= 1 << 13;
+                    }
+                    if selector & 1024 != 0 {
+                        result |= 1 << 17;
+                    }
+                    if selector & 2048 != 0 {
+                        result |= 1 << 18;
+                    }
+                } else if selector & 32 != 0 {
+                    result |= 1 << 12;
+                }
+                self.push(result)?;
+            },
+            // ROLL, MAX, MIN
+            0x8A => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                let c = self.pop()?;
+                self.stack.extend_from_slice(&[b, a, c]);
+            },
+            0x8B => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.max(b))?;
+            },
+            0x8C => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.min(b))?;
+            },
+            // SCANTYPE
+            0x8D => {
+                self.pop()?;
+            },
+            // INSTCTRL, only has an effect in prep
+            0x8E => {
+                let selector = self.pop()?;
+                let value = self.pop()?;
+                if !(1..=3).contains(&selector) {
+                    return Err("invalid INSTCTRL selector".to_string());
+                }
+                if !self.in_glyph_program {
+                    let bit = 1 << (selector - 1);
+                    self.gs.instruct_control = (self.gs.instruct_control & !bit) | if value != 0 { bit } else { 0 };
+                }
+            },
+            // MDRP
+            0xC0..=0xDF => {
+                let point = self.pop_point(self.gs.zp1)?;
+                let rp0 = self.gs.rp0;
+                self.check_point(self.gs.zp0, rp0)?;
+                let mut original_distance = self.scaled_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                let (cutin, width) = (self.gs.single_width_cutin, self.gs.single_width_value);
+                if cutin > 0 && original_distance < width + cutin && original_distance > width - cutin {
+                    original_distance = if original_distance >= 0 { width } else { -width };
+                }
+                let mut distance = if opcode & 4 != 0 { self.round(original_distance) } else { original_distance };
+                if opcode & 8 != 0 {
+                    distance = self.keep_minimum_distance(distance, original_distance);
+                }
+                let current = self.current_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                self.move_point(self.gs.zp1, point, distance.wrapping_sub(current));
+                self.gs.rp1 = rp0;
+                self.gs.rp2 = point;
+                if opcode & 16 != 0 {
+                    self.gs.rp0 = point;
+                }
+            },
+            // MIRP
+            0xE0..=0xFF => {
+                let cvt_index = self.pop()?;
+                let point = self.pop_point(self.gs.zp1)?;
+                let rp0 = self.gs.rp0;
+                self.check_point(self.gs.zp0, rp0)?;
+                let mut cvt_distance = self.read_cvt(cvt_index);
+                let (cutin, width) = (self.gs.single_width_cutin, self.gs.single_width_value);
+                if (cvt_distance - width).abs() < cutin {
+                    cvt_distance = if cvt_distance >= 0 { width } else { -width };
+                }
+                if self.gs.zp1 == TWILIGHT_ZONE {
+                    let origin = self.zones[self.gs.zp0].original[rp0];
+                    let position = (origin.0 + mul_f2dot14(cvt_distance, self.gs.freedom.0), origin.1 + mul_f2dot14(cvt_distance, self.gs.freedom.1));
+                    self.zones[TWILIGHT_ZONE].original[point] = position;
+                    self.zones[TWILIGHT_ZONE].current[point] = position;
+                }
+                let original_distance = self.original_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                let current = self.current_distance(self.gs.zp1, point, self.gs.zp0, rp0);
+                if self.gs.auto_flip && (original_distance ^ cvt_distance) < 0 {
+                    cvt_distance = -cvt_distance;
+                }
+                let mut distance = if opcode & 4 != 0 {
+                    if self.gs.zp0 == self.gs.zp1 && (cvt_distance - original_distance).abs() > self.gs.control_value_cutin {
+                        cvt_distance = original_distance;
+                    }
+                    self.round(cvt_distance)
+                } else {
+                    cvt_distance
+                };
+                if opcode & 8 != 0 {
+                    distance = self.keep_minimum_distance(distance, original_distance);
+                }
+                self.move_point(self.gs.zp1, point, distance.wrapping_sub(current));
+                self.gs.rp1 = rp0;
+                self.gs.rp2 = point;
+                if opcode & 16 != 0 {
+                    self.gs.rp0 = point;
+                }
+            },
+            _ => return Err(format!("unknown instruction 0x{:02X}", opcode)),
+        }
+        return Ok(());
+    }
+
+    fn read_cvt(&self, index: i32) -> i32 {
+        return self.cvt.get(index as usize).copied().unwrap_or(0);
+    }
+
+    fn keep_minimum_distance(&self, distance: i32, original_distance: i32) -> i32 {
+        let minimum = self.gs.minimum_distance;
+        if original_distance >= 0 {
+            return distance.max(minimum);
+        }
+        return distance.min(-minimum);
+    }
+
+    fn delta(&self, argument: i32, base: i32) -> Option<i32> {
+        // The 26.6 amount a DELTAP/DELTAC argument moves by, if it applies at the current ppem
+        let ppem = self.gs.delta_base + base + ((argument >> 4) & 0xF);
+        if ppem != self.ppem {
+            return None;
+        }
+        let mut steps = (argument & 0xF) - 8;
+        if steps >= 0 {
+            steps += 1;
+        }
+        return Some(steps * 64 / (1 << self.gs.delta_shift));
+    }
+
+    fn interpolate_untouched(&mut self, x_axis: bool) {
+        /* IUP: moves the points that weren't touched along the axis so that
+        they keep their relative position between the touched points on either
+        side of them in the contour (or just shift along if outside them). */
+        let zone = &mut self.zones[GLYPH_ZONE];
+        let coordinate = |p: (i32, i32)| if x_axis { p.0 } else { p.1 };
+        let mut start = 0;
+        for &end in zone.contour_ends.iter() {
+            if end < start || end >= zone.current.len() {
+                break;
+            }
+            let touched: Vec<usize> = (start..=end).filter(|&i| if x_axis { zone.touched_x[i] } else { zone.touched_y[i] }).collect();
+            let contour_start = start;
+            start = end + 1;
+            if touched.is_empty() {
+                continue;
+            }
+            let next_point = |p: usize| if p == end { contour_start } else { p + 1 };
+            for k in 0..touched.len() {
+                let t1 = touched[k];
+                let t2 = touched[(k + 1) % touched.len()];
+                let (u1, u2) = (coordinate(zone.unscaled[t1]), coordinate(zone.unscaled[t2]));
+                let (o1, o2) = (coordinate(zone.original[t1]), coordinate(zone.original[t2]));
+                let (c1, c2) = (coordinate(zone.current[t1]), coordinate(zone.current[t2]));
+                /* Order the pair by unscaled position. Like FreeType, which side a
+                point is on is decided by the scaled positions and where it goes
+                between them by the unscaled ones, through a 16.16 scale. */
+                let ((lo_u, lo_o, lo_c), (hi_u, hi_o, hi_c)) = if u1 <= u2 { ((u1, o1, c1), (u2, o2, c2)) } else { ((u2, o2, c2), (u1, o1, c1)) };
+                let mut p = next_point(t1);
+                while p != t2 {
+                    let u = coordinate(zone.unscaled[p]);
+                    let o = coordinate(zone.original[p]);
+                    let value = if o <= lo_o {
+                        o + lo_c - lo_o
+                    } else if o >= hi_o {
+                        o + hi_c - hi_o
+                    } else if lo_c == hi_c || lo_u == hi_u {
+                        lo_c
+                    } else {
+                        lo_c + mul_fix(u - lo_u, div_fix(hi_c - lo_c, hi_u - lo_u))
+                    };
+                    if x_axis {
+                        zone.current[p].0 = value;
+                    } else {
+                        zone.current[p].1 = value;
+                    }
+                    p = next_point(p);
+                }
+                if touched.len() == 1 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn parse_simple_glyph(data: &[u8], contour_count: usize) -> Option<SimpleGlyph> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let mut contour_ends = Vec::<usize>::new();
+    for i in 0..contour_count {
+        contour_ends.push(read_u16(data, 10 + i * 2)? as usize);
+    }
+    let point_count = match contour_ends.last() {
+        Some(&last) => last + 1,
+        None => 0,
+    };
+    let instructions_offset = 10 + contour_count * 2;
+    let instruction_length = read_u16(data, instructions_offset)? as usize;
+    let instructions = data.get(instructions_offset + 2..instructions_offset + 2 + instruction_length)?.to_vec();
+
+    let mut offset = instructions_offset + 2 + instruction_length;
+    let mut flags = Vec::<u8>::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = *data.get(offset)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let repeat = *data.get(offset)?;
+            offset += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    let mut read_coordinates = |short: u8, same_or_positive: u8| -> Option<Vec<i32>> {
+        let mut values = Vec::<i32>::with_capacity(point_count);
+        let mut value = 0i32;
+        for &flag in flags.iter() {
+            if flag & short != 0 {
+                let delta = *data.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += read_i16(data, offset)? as i32;
+                offset += 2;
+            }
+            values.push(value);
+        }
+        return Some(values);
+    };
+    let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+    let points = xs.into_iter().zip(ys).collect();
+    let on_curve = flags.iter().map(|f| f & ON_CURVE != 0).collect();
+    return Some(SimpleGlyph { points, on_curve, contour_ends, instructions });
+}
+
+fn add_contour(builder: &mut Builder, points: &[(f32, f32, bool)]) {
+    // Turns a TrueType contour (quadratic, with implied on-curve points between off-curve ones) into curves
+    let n = points.len();
+    if n == 0 {
+        return;
+    }
+    let midpoint = |a: (f32, f32, bool), b: (f32, f32, bool)| ((a.0 + b.0) / 2f32, (a.1 + b.1) / 2f32);
+    // Start on an on-curve point, or between the last and first points if there are none
+    let (start, first) = match points.iter().position(|p| p.2) {
+        Some(i) => ((points[i].0, points[i].1), i + 1),
+        None => (midpoint(points[n - 1], points[0]), 0),
+    };
+    builder.move_to(start.0, start.1);
+    let mut control: Option<(f32, f32)> = None;
+    let count = if first == 0 { n } else { n - 1 };
+    for k in 0..count {
+        let p = points[(first + k) % n];
+        if p.2 {
+            match control {
+                Some(c) => builder.quad_to(c.0, c.1, p.0, p.1),
+                None => builder.line_to(p.0, p.1),
+            }
+            control = None;
+        } else {
+            if let Some(c) = control {
+                let (mx, my) = ((c.0 + p.0) / 2f32, (c.1 + p.1) / 2f32);
+                builder.quad_to(c.0, c.1, mx, my);
+            }
+            control = Some((p.0, p.1));
+        }
+    }
+    match control {
+        Some(c) => builder.quad_to(c.0, c.1, start.0, start.1),
+        None => builder.line_to(start.0, start.1),
+    }
+    builder.close();
+}
+
+fn instruction_length(code: &[u8], pc: usize) -> Result<usize, String> {
+    let opcode = code[pc];
+    return match opcode {
+        0x40 => Ok(2 + *code.get(pc + 1).ok_or("truncated NPUSHB")? as usize),
+        0x41 => Ok(2 + 2 * *code.get(pc + 1).ok_or("truncated NPUSHW")? as usize),
+        0xB0..=0xB7 => Ok(2 + (opcode - 0xB0) as usize),
+        0xB8..=0xBF => Ok(3 + 2 * (opcode - 0xB8) as usize),
+        _ => Ok(1),
+    };
+}
+
+fn whole_ppem(pixels_per_em: f32) -> i32 {
+    // Instructions only see whole sizes
+    return pixels_per_em.round().max(1f32) as i32;
+}
+
+fn round_to_grid(value: i32) -> i32 {
+    return value.wrapping_add(32) & !63;
+}
+
+fn dot(dx: i32, dy: i32, vector: (i32, i32)) -> i32 {
+    // Projection of a 26.6 vector onto a 2.14 unit vector
+    return ((dx as i64 * vector.0 as i64 + dy as i64 * vector.1 as i64 + 0x2000) >> 14) as i32;
+}
+
+fn mul_f2dot14(value: i32, factor: i32) -> i32 {
+    return ((value as i64 * factor as i64 + 0x2000) >> 14) as i32;
+}
+
+fn mul_div(a: i32, b: i32, c: i32) -> i32 {
+    // a * b / c rounded to nearest, saturating instead of dividing by zero
+    let product = a as i64 * b as i64;
+    if c == 0 {
+        return if product < 0 { -0x7FFF_FFFF } else { 0x7FFF_FFFF };
+    }
+    let divisor = (c as i64).abs();
+    let result = (product.abs() + divisor / 2) / divisor;
+    let negative = (product < 0) != (c < 0);
+    let result = if negative { -result } else { result };
+    return result.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+}
+
+fn div_fix(a: i32, b: i32) -> i32 {
+    // a / b in 16.16 fixed point, rounded to nearest (b is never 0 here)
+    let quotient = ((a.unsigned_abs() as i64) * 0x10000 + (b.unsigned_abs() as i64) / 2) / b.unsigned_abs() as i64;
+    return if (a < 0) != (b < 0) { -quotient as i32 } else { quotient as i32 };
+}
+
+fn mul_fix(a: i32, b: i32) -> i32 {
+    // a times the 16.16 fixed point b, rounded to nearest
+    let product = ((a.unsigned_abs() as i64) * (b.unsigned_abs() as i64) + 0x8000) >> 16;
+    return if (a < 0) != (b < 0) { -product as i32 } else { product as i32 };
+}
+
+fn normalize(x: i32, y: i32) -> (i32, i32) {
+    // Scales a vector to unit length in 2.14 fixed point
+    let length = ((x as f64).powi(2) + (y as f64).powi(2)).sqrt();
+    if length == 0f64 {
+        return (ONE_F2DOT14, 0);
+    }
+    return ((x as f64 / length * 16384f64).round() as i32, (y as f64 / length * 16384f64).round() as i32);
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::Face;
+
+    use crate::autohint::{autohint_outline, blue_zones};
+    use crate::collection::FaceSelector;
+    use crate::outline::extract_glyph_outline;
+    use super::{FaceKey, GraphicsState, Hinter, HintingCache, HintingMode, Zone, GLYPH, GLYPH_ZONE, PHANTOM_POINTS};
+
+    const ROBOTO: &str = "fonts/Roboto-Regular.ttf";
+    // Roboto's 'c' at 12 ppem as FreeType hints it (26.6), with the v35 interpreter for Full and v40 for VerticalOnly
+    const C_FULL: [(i32, i32); 30] = [
+        (205, 64), (242, 64), (298, 105), (301, 135), (367, 135), (365, 100), (320, 37), (247, 0), (205, 0), (122, 0),
+        (25, 118), (25, 220), (25, 232), (25, 295), (68, 394), (149, 448), (205, 448), (273, 448), (364, 366), (367, 301),
+        (301, 301), (298, 337), (244, 384), (205, 384), (152, 384), (94, 305), (94, 230), (94, 216), (94, 143), (152, 64),
+    ];
+    const C_VERTICAL: [(i32, i32); 30] = [
+        (215, 64), (252, 64), (308, 105), (311, 135), (377, 135), (375, 100), (330, 37), (257, 0), (215, 0), (132, 0),
+        (35, 118), (35, 220), (35, 232), (35, 295), (78, 394), (159, 448), (215, 448), (283, 448), (374, 366), (377, 301),
+        (311, 301), (308, 337), (254, 384), (215, 384), (162, 384), (104, 305), (104, 230), (104, 216), (104, 143), (162, 64),
+    ];
+
+    fn interpreter(points: &[(i32, i32)], cvt: Vec<i32>) -> Hinter {
+        // A hinter without a font, at one 26.6 unit per font unit, with the points as one contour
+        let points = points.to_vec();
+        let zone = Zone::with_points(points.clone(), points.clone(), vec![true; points.len()], vec![points.len() - 1]);
+        return Hinter {
+            mode: HintingMode::Full,
+            ppem: 12,
+            scale: 0x10000,
+            programs: [Vec::new(), Vec::new(), Vec::new()],
+            functions: Vec::new(),
+            instruction_defs: vec![None; 256],
+            cvt: cvt,
+            storage: Vec::new(),
+            stack: Vec::new(),
+            max_stack: 32,
+            gs: GraphicsState::default(),
+            glyph_gs: GraphicsState::default(),
+            zones: [Zone::twilight(0), zone],
+            twilight_after_prep: Zone::twilight(0),
+            in_glyph_program: true,
+            iup_x_called: false,
+            iup_y_called: false,
+        };
+    }
+
+    fn run(hinter: &mut Hinter, program: &[u8]) -> Result<(), String> {
+        hinter.programs[GLYPH] = program.to_vec();
+        return hinter.run(GLYPH);
+    }
+
+    fn x_positions(hinter: &Hinter) -> Vec<i32> {
+        return hinter.zones[GLYPH_ZONE].current.iter().map(|p| p.0).collect();
+    }
+
+    #[test]
+    fn stack_instructions() {
+        let mut hinter = interpreter(&[(0, 0)], Vec::new());
+        // PUSHB 1 2 3 4, DUP, POP, SWAP
+        run(&mut hinter, &[0xB3, 1, 2, 3, 4, 0x20, 0x21, 0x23]).unwrap();
+        assert_eq!(hinter.stack, vec![1, 2, 4, 3]);
+        // ROLL brings the third element up
+        run(&mut hinter, &[0x8A]).unwrap();
+        assert_eq!(hinter.stack, vec![1, 4, 3, 2]);
+        // CINDEX copies and MINDEX moves the element that many down to the top
+        run(&mut hinter, &[0xB0, 3, 0x25]).unwrap();
+        assert_eq!(hinter.stack, vec![1, 4, 3, 2, 4]);
+        run(&mut hinter, &[0xB0, 5, 0x26]).unwrap();
+        assert_eq!(hinter.stack, vec![4, 3, 2, 4, 1]);
+        // DEPTH, then CLEAR
+        run(&mut hinter, &[0x24]).unwrap();
+        assert_eq!(hinter.stack, vec![4, 3, 2, 4, 1, 5]);
+        run(&mut hinter, &[0x22]).unwrap();
+        assert!(hinter.stack.is_empty());
+        assert!(run(&mut hinter, &[0x21]).is_err());
+    }
+
+    #[test]
+    fn direct_moves_round_and_keep_the_minimum_distance() {
+        let points = [(0, 0), (100, 0), (20, 0), (90, 0), (100, 0), (70, 0)];
+        let mut hinter = interpreter(&points, Vec::new());
+        // SRP0 0, then MDRP rounded, rounded with the minimum distance, and neither
+        run(&mut hinter, &[0xB0, 0, 0x10, 0xB0, 1, 0xC4, 0xB0, 2, 0xCC, 0xB0, 3, 0xC0]).unwrap();
+        // RDTG then MDRP rounded, RUTG then MDRP rounded
+        run(&mut hinter, &[0x7D, 0xB0, 4, 0xC4, 0x7C, 0xB0, 5, 0xC4]).unwrap();
+        assert_eq!(x_positions(&hinter), vec![0, 128, 64, 90, 64, 128]);
+        assert!(hinter.zones[GLYPH_ZONE].touched_x[1..].iter().all(|&touched| touched));
+    }
+
+    #[test]
+    fn indirect_moves_use_the_cvt_within_the_cutin() {
+        // The first distance is close to cvt 0 and the second isn't, the third is rounded to nothing
+        let points = [(0, 0), (90, 0), (300, 0), (10, 0)];
+        let mut hinter = interpreter(&points, vec![100, 10]);
+        // SRP0 0, then MIRP rounded (point, cvt index) twice and MIRP rounded with the minimum distance
+        run(&mut hinter, &[0xB0, 0, 0x10, 0xB1, 1, 0, 0xE4, 0xB1, 2, 0, 0xE4, 0xB1, 3, 1, 0xEC]).unwrap();
+        assert_eq!(x_positions(&hinter), vec![0, 128, 320, 64]);
+    }
+
+    #[test]
+    fn untouched_points_are_interpolated() {
+        let points = [(0, 0), (50, 10), (100, 0), (150, 10)];
+        let mut hinter = interpreter(&points, Vec::new());
+        // MDAP touches point 0 where it is, SHPIX moves point 2 a pixel along x, then IUP[x]
+        run(&mut hinter, &[0xB0, 0, 0x2E, 0xB1, 2, 64, 0x38, 0x31]).unwrap();
+        // Point 1 is stretched between them, point 3 is past point 2 so moves with it
+        assert_eq!(x_positions(&hinter), vec![0, 82, 164, 214]);
+        assert!(hinter.iup_x_called && !hinter.iup_y_called);
+    }
+
+    #[test]
+    fn hinted_glyph_matches_freetype() {
+        let data = std::fs::read(ROBOTO).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let glyph_id = face.glyph_index('c').unwrap();
+        for (mode, expected) in [(HintingMode::Full, C_FULL), (HintingMode::VerticalOnly, C_VERTICAL)] {
+            let hinter = Hinter::new(&face, 12f32, mode).unwrap();
+            let zone = hinter.hint_points(&face, glyph_id).unwrap();
+            assert_eq!(zone.current[..zone.current.len() - PHANTOM_POINTS], expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn cached_hinters_give_the_same_glyphs() {
+        /* Glyphs from a cached hinter, after other glyphs have run on it,
+        come out as from a hinter made just for them */
+        let data = std::fs::read(ROBOTO).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let key = FaceKey::new(ROBOTO, &FaceSelector::Index(0));
+        let mut cache = HintingCache::default();
+        for ppem in [9f32, 12f32] {
+            for character in "ozaeso".chars() {
+                let glyph_id = face.glyph_index(character).unwrap();
+                let (contours, bbox) = cache.fitted_outline(&key, &face, glyph_id, ppem, HintingMode::Full, &[]);
+                let (fresh, fresh_bbox) = Hinter::new(&face, ppem, HintingMode::Full).unwrap().hint_glyph(&face, glyph_id).unwrap();
+                assert_eq!(contours, fresh);
+                assert_eq!(bbox, Some(fresh_bbox));
+            }
+        }
+        assert_eq!(cache.hinters.len(), 2);
+    }
+
+    #[test]
+    fn blue_zones_are_measured_once_per_face() {
+        let data = std::fs::read(ROBOTO).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        let key = FaceKey::new(ROBOTO, &FaceSelector::Index(0));
+        let blues = blue_zones(&face, &[]);
+        let mut cache = HintingCache::default();
+        for ppem in [9f32, 12f32] {
+            for character in "Hamburgefonts".chars() {
+                let glyph_id = face.glyph_index(character).unwrap();
+                let (contours, bbox) = cache.fitted_outline(&key, &face, glyph_id, ppem, HintingMode::Auto, &[]);
+                let (unhinted, unhinted_bbox) = extract_glyph_outline(&face, glyph_id, &[]);
+                let (expected, expected_bbox) = autohint_outline(&face, unhinted, unhinted_bbox.unwrap(), ppem, &blues);
+                assert_eq!(contours, expected);
+                assert_eq!(bbox, Some(expected_bbox));
+            }
+        }
+        assert_eq!(cache.blues.len(), 1);
+        assert!(cache.hinters.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+/* Subpixel (ClearType-style) rendering for LCD panels, where each pixel is
+made of three colored stripes. Coverage is sampled for each stripe on its
+own, tripling the resolution along the stripe axis, then run through a FIR
+filter that spreads it over the neighbouring stripes so edges don't show
+color fringes.
+https://freetype.org/freetype2/docs/reference/ft2-lcd_rendering.html */
+
+use crate::raster::Coverage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubpixelRendering {
+    // Grayscale antialiasing
+    Off,
+    // Stripes left to right
+    Rgb,
+    Bgr,
+    // Stripes top to bottom
+    VerticalRgb,
+    VerticalBgr,
+}
+
+// FreeType's FT_LCD_FILTER_DEFAULT weights (FT_LCD_FILTER_LIGHT is 0, 85, 86, 85, 0)
+pub const DEFAULT_LCD_FILTER: [f32; 5] = [8f32 / 256f32, 77f32 / 256f32, 86f32 / 256f32, 77f32 / 256f32, 8f32 / 256f32];
+
+pub fn render_subpixels(width: i32, height: i32, mode: SubpixelRendering, filter: &[f32; 5], samples: i32, inside: impl Fn(f32, f32) -> bool) -> Coverage {
+    /* Samples coverage for each stripe of a width by height pixel area, with
+    samples by samples points per stripe (the same grid the grayscale
+    supersampling uses, squeezed into a third of a pixel). inside takes pixel
+    coordinates with y going up. The result has a pixel of padding on both
+    sides along the stripe axis for the filter to spread into. */
+    let vertical = mode == SubpixelRendering::VerticalRgb || mode == SubpixelRendering::VerticalBgr;
+    let (x_min, y_min) = if vertical { (0, -1) } else { (-1, 0) };
+    let (columns, rows) = if vertical { (width, height + 2) } else { (width + 2, height) };
+    // Stripes along the stripe axis and lines across it
+    let (stripe_count, line_count) = if vertical { (rows * 3, columns) } else { (columns * 3, rows) };
+
+    let mut data = vec![[0f32; 3]; (columns * rows) as usize];
+    let mut stripes = vec![0f32; stripe_count as usize];
+    let step = 1f32 / samples as f32;
+    for line in 0..line_count {
+        for stripe in 0..stripe_count {
+            let mut count = 0;
+            for along in 0..samples {
+                for across in 0..samples {
+                    // Position along the stripe axis and across it, in pixels
+                    let s = (stripe as f32 + (along as f32 + 0.5f32) * step) / 3f32;
+                    let t = line as f32 + (across as f32 + 0.5f32) * step;
+                    let hit = if vertical { inside(x_min as f32 + t, y_min as f32 + s) } else { inside(x_min as f32 + s, y_min as f32 + t) };
+                    if hit {
+                        count += 1;
+                    }
+                }
+            }
+            stripes[stripe as usize] = count as f32 / (samples * samples) as f32;
+        }
+
+        let filtered = apply_filter(&stripes, filter);
+        for pixel in 0..stripe_count / 3 {
+            let mut value = [0f32; 3];
+            for k in 0..3 {
+                // Stripes are in increasing coordinate order, so bottom to top for vertical panels
+                let channel = match mode {
+                    SubpixelRendering::Rgb | SubpixelRendering::VerticalBgr => k,
+                    _ => 2 - k,
+                };
+                value[channel] = filtered[(pixel * 3 + k as i32) as usize];
+            }
+            let (x, y) = if vertical { (line, pixel) } else { (pixel, line) };
+            data[(y * columns + x) as usize] = value;
+        }
+    }
+    return Coverage { x_min, y_min, width: columns, height: rows, data };
+}
+
+fn apply_filter(stripes: &[f32], filter: &[f32; 5]) -> Vec<f32> {
+    // Convolves one line of stripe coverage with the 5-tap filter (centred, zero outside the line)
+    let n = stripes.len() as i32;
+    return (0..n)
+        .map(|i| {
+            let mut sum = 0f32;
+            for (tap, weight) in filter.iter().enumerate() {
+                let j = i + tap as i32 - 2;
+                if j >= 0 && j < n {
+                    sum += stripes[j as usize] * weight;
+                }
+            }
+            return sum.min(1f32);
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_subpixels, SubpixelRendering, DEFAULT_LCD_FILTER};
+
+    // Leaves each stripe's coverage where it is
+    const NO_FILTER: [f32; 5] = [0f32, 0f32, 1f32, 0f32, 0f32];
+
+    #[test]
+    fn channels_follow_the_stripe_order() {
+        // Covers one third of pixel (0, 0) at a time along the stripe axis and checks which channel lights up
+        let modes = [
+            (SubpixelRendering::Rgb, false, [0, 1, 2]),
+            (SubpixelRendering::Bgr, false, [2, 1, 0]),
+            // Thirds go bottom to top, so red is the last one with red on top
+            (SubpixelRendering::VerticalRgb, true, [2, 1, 0]),
+            (SubpixelRendering::VerticalBgr, true, [0, 1, 2]),
+        ];
+        for (mode, vertical, channels) in modes {
+            for (third, &channel) in channels.iter().enumerate() {
+                let (low, high) = (third as f32 / 3f32, (third + 1) as f32 / 3f32);
+                let coverage = render_subpixels(2, 2, mode, &NO_FILTER, 4, |x, y| {
+                    let (along, across) = if vertical { (y, x) } else { (x, y) };
+                    return along >= low && along < high && (0f32..1f32).contains(&across);
+                });
+                // A pixel of padding on both ends of the stripe axis only
+                assert_eq!((coverage.x_min, coverage.y_min), if vertical { (0, -1) } else { (-1, 0) });
+                assert_eq!((coverage.width, coverage.height), if vertical { (2, 4) } else { (4, 2) });
+                let mut expected = [0f32; 3];
+                expected[channel] = 1f32;
+                assert_eq!(coverage.get(-coverage.x_min, -coverage.y_min), expected, "{:?}, third {}", mode, third);
+                let total: f32 = coverage.data.iter().flatten().sum();
+                assert_eq!(total, 1f32, "{:?}, third {}", mode, third);
+            }
+        }
+    }
+
+    #[test]
+    fn filter_keeps_full_coverage() {
+        // The weights add up to one, so inside a solid area every stripe stays fully covered
+        assert_eq!(DEFAULT_LCD_FILTER.iter().sum::<f32>(), 1f32);
+        for mode in [SubpixelRendering::Rgb, SubpixelRendering::Bgr, SubpixelRendering::VerticalRgb, SubpixelRendering::VerticalBgr] {
+            let vertical = mode == SubpixelRendering::VerticalRgb || mode == SubpixelRendering::VerticalBgr;
+            let coverage = render_subpixels(6, 6, mode, &DEFAULT_LCD_FILTER, 4, |x, y| {
+                let along = if vertical { y } else { x };
+                return (1f32..5f32).contains(&along);
+            });
+            for along in 2..4 {
+                for across in 0..6 {
+                    let (x, y) = if vertical { (across, along) } else { (along, across) };
+                    assert_eq!(coverage.get(x - coverage.x_min, y - coverage.y_min), [1f32; 3], "{:?} at ({}, {})", mode, x, y);
+                }
+            }
+            // The edges are softened, but nothing is lost or gained
+            let edge = if vertical { coverage.get(0, 1 - coverage.y_min) } else { coverage.get(1 - coverage.x_min, 0) };
+            assert!(edge.iter().any(|&c| c < 1f32));
+            let total: f32 = coverage.data.iter().flatten().sum();
+            assert!((total - 4f32 * 6f32 * 3f32).abs() < 1e-4f32, "{:?}: {}", mode, total);
+        }
+    }
+}
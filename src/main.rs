@@ -1,5 +1,4 @@
 extern crate sdl2;
-extern crate roots;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
@@ -8,18 +7,99 @@ use sdl2::rect::Point;
 use sdl2::video::Window;
 use sdl2::render::Canvas;
 
-use ttf_parser::Face;
 use std::fs;
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::segment::Segment;
-mod segment;
+use ttf_parser::{Face, GlyphId, Rect};
 
-use crate::outline::extract_outline;
+use crate::curve::Contour;
+mod curve;
+
+use crate::float::Precision;
+mod float;
+
+mod polynomial;
+
+use crate::flatten::flatten;
+mod flatten;
+
+use crate::bands::SegmentBands;
+mod bands;
+
+use crate::scanline::{render_scanline_rows, EdgeTable, FillRule, Rasterizer, GLYPH_FILL};
+mod scanline;
+
+use crate::outline::{close_contours, extract_advance, extract_checked_outline, extract_outline, is_finite};
 mod outline;
 
-use crate::metrics::get_render_score;
+use crate::metrics::{get_precision_score, get_render_score, get_scanline_score};
 mod metrics;
 
+use crate::collection::{enumerate_faces, load_face, FaceSelector};
+mod collection;
+
+use crate::fontdb::{default_cache_path, default_font_dirs, FontDatabase, FontQuery};
+mod fontdb;
+
+use crate::variation::{get_axes, parse_variations, Variation};
+mod variation;
+
+use crate::bitmap::RgbaBitmap;
+mod bitmap;
+
+use crate::colr::{is_color_glyph, palette_count, render_color_glyph};
+mod colr;
+
+use crate::strike::render_strike_glyph;
+mod strike;
+
+use crate::svg::{parse_path, render_svg_glyph};
+mod svg;
+
+use crate::hinting::{FaceKey, HintingCache, HintingMode};
+use crate::autohint::standard_stem_widths;
+mod hinting;
+
+mod autohint;
+
+use crate::lcd::{render_subpixels, SubpixelRendering, DEFAULT_LCD_FILTER};
+mod lcd;
+
+use crate::raster::{render_grayscale_rows, stitch, tiles, Coverage};
+mod raster;
+
+mod simd;
+
+use crate::parallel::{thread_count, ThreadPool};
+mod parallel;
+
+use crate::cache::{quantize_position, CachedGlyph, GlyphCache, GlyphKey, SUBPIXEL_POSITIONS};
+mod cache;
+
+use crate::gamma::{blend_layers, blend_premultiplied, to_byte, TextStyle};
+mod gamma;
+
+use crate::embolden::{embolden_outline, stem_darkening};
+mod embolden;
+
+use crate::synthetic::{apply_synthetic_style, synthetic_advance, SyntheticStyle};
+mod synthetic;
+
+use crate::stroke::{parse_stroke_style, stroke_outline, StrokeStyle, TextOutline};
+mod stroke;
+
+use crate::transform::{outline_bounds, transform_outline, Transform};
+mod transform;
+
+use crate::textpath::{PathAlign, PathLayout, PathRendering, TextPath};
+mod textpath;
+
+mod read;
+
+#[cfg(test)]
+mod fixtures;
+
 const WINDOW_WIDTH: u32 = 1300u32;
 const WINDOW_HEIGHT: u32 = 800u32;
 const CANVAS_MARGIN: u32 = 100u32;
@@ -33,6 +113,8 @@ const POINT_SIZE: f32 = 32f32;
 const TEST_CHARACTER: char = 'a';
 // Samples for performance testing
 const TEST_SAMPLES: i32 = 10000;
+// Sizes (pixels per em) the hinting modes are scored at; well above these grid fitting hardly moves anything
+const HINTING_TEST_PPEM: [f32; 3] = [12f32, 14f32, 16f32];
 
 // const FONT_PATH: &str = "./fonts/DancingScript-Regular.ttf";
 // const FONT_PATH: &str = "./fonts/Chopinscript-gxXE.ttf";
@@ -42,27 +124,169 @@ const TEST_SAMPLES: i32 = 10000;
 // const FONT_PATH: &str = "./fonts/DarumadropOne-Regular.ttf";
 // const FONT_PATH: &str = "./fonts/wingding.ttf";
  const FONT_PATH: &str = "./fonts/Creepster-Regular.ttf";
+// const FONT_PATH: &str = "/System/Library/Fonts/Helvetica.ttc";
+
+// Face to use within a collection (.ttc/.otc); plain .ttf/.otf files only have face 0
+const FACE_INDEX: u32 = 0;
+// PostScript or full name of the face to use, takes priority over FACE_INDEX if set
+const FACE_NAME: Option<&str> = None;
+// const FACE_NAME: Option<&str> = Some("Helvetica-Bold");
+
+// Looks the font up by family and style in the local font directories
+// (./fonts, /usr/share/fonts, ~/.local/share/fonts, ...) instead of using
+// FONT_PATH and FACE_INDEX/FACE_NAME
+const FONT_QUERY: Option<&str> = None;
+// Fake bold and oblique styles for FONT_PATH. With FONT_QUERY these are turned
+// on when the query asks for a style the matched face doesn't have.
+const SYNTHETIC_BOLD: bool = false;
+const SYNTHETIC_OBLIQUE: bool = false;
+// const FONT_QUERY: Option<&str> = Some("Roboto Regular");
 
 const SAMPLE_MODE: Supersampling = Supersampling::ThreeByThree;
+// Precision of the inside/outside test (F toggles it): single is faster, double
+// is more exact right at the edges of the outline
+const PRECISION: Precision = Precision::Double;
+// Furthest curves turned into polylines may stray from the outline, in pixels
+const FLATTEN_TOLERANCE: f32 = 0.1f32;
+// Sample with a ray each or sweep rows of samples over the flattened outline (E toggles it).
+// Subpixel rendering always casts rays.
+const RASTERIZER: Rasterizer = Rasterizer::RayCasting;
+// Subpixel rendering for LCD panels (D cycles off, RGB, BGR, vertical RGB and
+// vertical BGR), sampled with the grid above in each third of a pixel
+const SUBPIXEL_RENDERING: SubpixelRendering = SubpixelRendering::Off;
+// FIR filter for subpixel rendering, weights should add up to about 1
+const LCD_FILTER: [f32; 5] = DEFAULT_LCD_FILTER;
+
+// Axis settings for variable fonts, e.g. "wght=700, wdth=87.5". Ignored for
+// static fonts and for axes the font doesn't have.
+const VARIATIONS: &str = "";
+// Frames it takes to sweep an axis from its minimum to its maximum (V key)
+const SWEEP_FRAMES: f32 = 120f32;
+
+// CPAL palette for color (COLR) glyphs, P cycles through the font's palettes
+const PALETTE_INDEX: u16 = 0;
+// Text color (RGBA), also used by color glyph layers that use the foreground color
+const FOREGROUND_COLOR: [f32; 4] = [0f32, 0f32, 0f32, 1f32];
+// Window color (RGB) that text is blended onto, B swaps it with the text color
+const BACKGROUND_COLOR: [f32; 3] = [1f32, 1f32, 1f32];
+// Coverage is blended in linear light using this display gamma (1 for the old naive blending)
+const GAMMA: f32 = 2.2f32;
+// Boost for partly covered pixels so thin text doesn't fade after gamma correction (0 to turn off)
+const CONTRAST: f32 = 0.25f32;
+// Thicken thin stems at small sizes like FreeType does, K toggles it
+const STEM_DARKENING: bool = false;
+
+// Filled, hollow or bordered text (O cycles them)
+const TEXT_OUTLINE: TextOutline = TextOutline::Fill;
+// Color (RGBA) of the border around bordered text
+const BORDER_COLOR: [f32; 4] = [1f32, 0.5f32, 0f32, 1f32];
+// Stroke for hollow and bordered text, e.g. "width=3, join=round, miter-limit=4, cap=butt".
+// The width is in pixels, joins are miter, round or bevel and caps (for open
+// contours) butt, round or square.
+const STROKE: &str = "width=3, join=round";
 
+// Rotation of the text in degrees (counter-clockwise, R turns it another 15)
+// and horizontal skew (x shift per unit of height). Glyphs turn about the
+// bottom left of their box and follow the rotated baseline.
+const TEXT_ROTATION: f32 = 0f32;
+const TEXT_SKEW: f32 = 0f32;
+// Horizontal and vertical scale of the glyphs on top of POINT_SIZE
+const TEXT_SCALE: [f32; 2] = [1f32, 1f32];
 
-#[derive(Debug, PartialEq, Eq)]
+// Path for text along a path, as SVG path data in window coordinates (y going down)
+const TEXT_PATH: &str = "M 150 600 C 400 150, 900 150, 1150 600";
+// Whether the text follows TEXT_PATH, and whether glyphs are turned or bent to follow
+// it (T cycles straight text, turned glyphs and bent glyphs)
+const TEXT_PATH_RENDERING: Option<PathRendering> = None;
+// Whether the text starts, is centred or ends at TEXT_PATH_START (L cycles them)
+const TEXT_PATH_ALIGN: PathAlign = PathAlign::Middle;
+// Where along the path the text is aligned, as a fraction of its length
+const TEXT_PATH_START: f32 = 0.5f32;
+// Height of the baseline above the path in pixels
+const TEXT_PATH_OFFSET: f32 = 0f32;
+// Longest piece of outline bent as one curve when warping glyphs onto the path, in pixels
+const WARP_STEP: f32 = 4f32;
+
+// Hinting of outlines (H cycles unhinted, hinted, vertical-only and auto-hinted).
+// Bytecode hinting is only used for static instances, variable fonts are drawn
+// unhinted unless the auto-hinter is selected.
+const HINTING_MODE: HintingMode = HintingMode::None;
+
+// Threads draw_text rasterizes glyphs on (0 for one per core, 1 for just the main thread).
+// The text comes out the same with any number.
+const RENDER_THREADS: usize = 0;
+// Rows of pixels per tile, so a big glyph can be shared out between threads
+const TILE_ROWS: i32 = 16;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Supersampling {
     None,
     TwoByTwo,
     ThreeByThree,
 }
 
+#[derive(Clone, Copy)]
+struct RenderSettings<'a> {
+    // Everything that decides how text is drawn, the same for every glyph of it
+    sample_mode: Supersampling,
+    font_path: &'a str,
+    face_selector: &'a FaceSelector,
+    variations: &'a [Variation],
+    palette: u16,
+    hinting: HintingMode,
+    subpixel: SubpixelRendering,
+    style: TextStyle,
+    // Threads draw_text rasterizes on (0 for one per core)
+    threads: usize,
+}
+
+trait Surface {
+    // Where text is drawn: the window's canvas, or an image in the tests
+    fn height(&self) -> i32;
+    fn set_pixel(&mut self, point: Point, color: Color) -> Result<(), String>;
+}
+
+impl Surface for Canvas<Window> {
+    fn height(&self) -> i32 {
+        return self.logical_size().1 as i32;
+    }
+
+    fn set_pixel(&mut self, point: Point, color: Color) -> Result<(), String> {
+        self.set_draw_color(color);
+        return self.draw_point(point);
+    }
+}
+
 fn main() {
     // "Oh we can just do the project in Rust; it can't
     // be *that* weird to learn" -- me, an idiot, a week ago
-    let metrics = get_render_score(TEST_CHARACTER, FONT_PATH, TEST_SAMPLES);
-    println!("Score for default sampling: {:?}", metrics.none);
-    println!("Score for 2x2 supersampling: {:?}", metrics.two_by_two);
-    println!("Score for 3x3 supersampling: {:?}", metrics.three_by_three);
-    println!("Score for 4x4 supersampling: {:?}", metrics.four_by_four);
+    let (font_path, face_selector, synthetic) = get_font();
+    print_faces(&font_path);
 
-    match sdl_demo() {
+    let variations = parse_variations(VARIATIONS);
+    print_outline_report(&font_path, &face_selector, &variations, TEST_CHARACTER);
+    // Scores for each kind of hinting at text sizes, so they can be compared side by side
+    for ppem in HINTING_TEST_PPEM {
+        for hinting in [HintingMode::None, HintingMode::Full, HintingMode::VerticalOnly, HintingMode::Auto] {
+            let metrics = get_render_score(TEST_CHARACTER, &font_path, &face_selector, &variations, hinting, ppem, TEST_SAMPLES);
+            println!("Hinting: {:?} at {} ppem", hinting, ppem);
+            println!("Score for default sampling: {:?}", metrics.none);
+            println!("Score for 2x2 supersampling: {:?}", metrics.two_by_two);
+            println!("Score for 3x3 supersampling: {:?}", metrics.three_by_three);
+            println!("Score for 4x4 supersampling: {:?}", metrics.four_by_four);
+        }
+    }
+    // How much double precision costs, and how often single precision gets a different answer
+    let precision = get_precision_score(TEST_CHARACTER, &font_path, &face_selector, &variations, HINTING_MODE, TEST_SAMPLES);
+    println!("Single precision agrees with double on {:?} of samples", precision.agreement);
+    println!("Time in single precision: {:?}s, in double: {:?}s", precision.single_seconds, precision.double_seconds);
+    // How much faster the scanline rasterizer is, and how often flattening changes a pixel
+    let scanline = get_scanline_score(TEST_CHARACTER, &font_path, &face_selector, &variations, HINTING_MODE, FLATTEN_TOLERANCE);
+    println!("Scanline rasterizer agrees with ray casting on {:?} of pixels", scanline.agreement);
+    println!("Time ray casting: {:?}s, with scanlines: {:?}s", scanline.ray_seconds, scanline.scanline_seconds);
+
+    match sdl_demo(&font_path, &face_selector, synthetic, &variations) {
         Ok(()) => {}
         Err(e) => {
             eprint!("Error {}.", e);
@@ -71,34 +295,269 @@ fn main() {
     };
 }
 
-fn draw_text(text: &str, start_x: i32, start_y: i32, canvas: &mut Canvas<Window>, current_sample_mode: &Supersampling, font_path: Option<&str>) -> Point {
-    let (canvas_width, canvas_height) = canvas.logical_size();
+fn get_font() -> (String, FaceSelector, SyntheticStyle) {
+    // Resolves the font file and face to render from the consts at the top of the file
+    if let Some(query) = FONT_QUERY {
+        let database = FontDatabase::load(&default_font_dirs(), &default_cache_path());
+        let font_query = FontQuery::parse(query);
+        // The metrics need TEST_CHARACTER, so fall back to a face that has it
+        let entry = match database.query(&font_query) {
+            Some(e) if e.covers(TEST_CHARACTER) => Some(e),
+            _ => database.query_for_char(&font_query, TEST_CHARACTER),
+        };
+        match entry {
+            Some(entry) => {
+                let synthetic = SyntheticStyle::for_query(&font_query, entry);
+                println!("Using {} {} ({}) for {:?}", entry.family, entry.subfamily, entry.postscript_name, query);
+                if synthetic.bold || synthetic.oblique {
+                    println!("Synthesizing {:?}", synthetic);
+                }
+                return (entry.path.to_string_lossy().into_owned(), FaceSelector::Index(entry.index), synthetic);
+            },
+            None => {
+                eprintln!("No installed font matches {:?}, using {}.", query, FONT_PATH);
+            }
+        }
+    }
+    let face_selector = match FACE_NAME {
+        Some(name) => FaceSelector::Name(name.to_string()),
+        None => FaceSelector::Index(FACE_INDEX),
+    };
+    return (FONT_PATH.to_string(), face_selector, SyntheticStyle { bold: SYNTHETIC_BOLD, oblique: SYNTHETIC_OBLIQUE });
+}
+
+fn print_faces(font_path: &str) {
+    // Lists every face in the font file so you know what to put in FACE_INDEX/FACE_NAME
+    let file = fs::read(font_path).unwrap();
+    for info in enumerate_faces(&file) {
+        println!("Face {}: {} {} (full name: {:?}, PostScript name: {:?})",
+            info.index, info.family, info.subfamily, info.full_name, info.postscript_name);
+    }
+}
+
+fn print_outline_report(font_path: &str, face_selector: &FaceSelector, variations: &[Variation], character: char) {
+    // Contours of the test character and anything wrong with its outline
+    let file = fs::read(font_path).unwrap();
+    let face = match load_face(&file, face_selector) {
+        Ok(f) => f,
+        Err(e) => {
+            eprint!("Error: {}.", e);
+            std::process::exit(1);
+        }
+    };
+    let outline = extract_checked_outline(&face, character, variations);
+    let report = outline.validate();
+    let polylines = flatten(&outline.contours, FLATTEN_TOLERANCE, get_ratio(face.units_per_em() as f32));
+    let points: usize = polylines.iter().map(|polyline| polyline.points.len()).sum();
+    let closed = polylines.iter().filter(|polyline| polyline.closed).count();
+    println!("Flattened to within {:?}px: {} points in {} polylines ({} closed)", FLATTEN_TOLERANCE, points, polylines.len(), closed);
+    for (index, contour) in report.contours.iter().enumerate() {
+        println!("Contour {}: {} curves, area {:?} ({:?})", index, contour.curves, contour.area, contour.orientation);
+    }
+    if report.is_valid() {
+        println!("Outline of {:?} has no problems", character);
+    }
+    for issue in report.issues.iter() {
+        println!("Outline problem: {:?}", issue);
+    }
+    if !report.can_rasterize() {
+        println!("Outline of {:?} can't be drawn and is left out", character);
+    }
+}
+
+fn draw_text(text: &str, start_x: i32, start_y: i32, canvas: &mut impl Surface, settings: &RenderSettings, cache: &mut GlyphCache, pool: &mut ThreadPool) -> Point {
+    /* Lays the text out, rasterizes the outline glyphs that aren't cached on
+    up to settings.threads of the pool's threads, big ones a tile at a time,
+    and draws every glyph in text order, so the result is the same as drawing
+    one character after another whatever the thread count */
     let x_spacing = 0; // Hard coded - additional space between charafcters
     let y_spacing = POINT_SIZE as i32 * 4; // Hard coded - vertical space between characters
-    let mut last_x = start_x;
-    let mut last_y = start_y;
+    // Glyphs are placed at fractional x positions, so spacing isn't rounded to whole pixels
+    // Rotated text moves up or down as it goes along, so y is fractional too
+    let mut last_x = start_x as f32;
+    let mut line_y = start_y;
     let mut bottom_right = Point::new(start_x, start_y);
+    let mut glyphs = Vec::<PreparedGlyph>::new();
     for line in text.lines() {
-        last_x = start_x;
+        last_x = start_x as f32;
+        let mut last_y = line_y as f32;
         for c in line.chars() {
-            if last_x > WINDOW_WIDTH as i32 - (2 * CANVAS_MARGIN) as i32 {
-                last_x = start_x; // Go to new line
-                last_y = bottom_right.y + y_spacing;
+            if last_x > (WINDOW_WIDTH - 2 * CANVAS_MARGIN) as f32 {
+                last_x = start_x as f32; // Go to new line
+                line_y = bottom_right.y + y_spacing;
+                last_y = line_y as f32;
             }
-            bottom_right = draw_character(c, last_x, last_y, canvas, current_sample_mode, font_path);
-            last_x = bottom_right.x + x_spacing;
+            let (glyph, (next_x, next_y), corner) = prepare_character(c, last_x, last_y, canvas, settings, cache);
+            glyphs.push(glyph);
+            bottom_right = corner;
+            last_x = next_x + x_spacing as f32;
+            last_y = next_y;
         }
-        last_y = bottom_right.y + y_spacing;
+        line_y = bottom_right.y + y_spacing;
     }
-    return Point::new(last_x - x_spacing, last_y - y_spacing); // Remove the uncessary last space that is produced by adding x_spacing at the end
+
+    // Each glyph that repeats is only rasterized once, the jobs go to the pool's threads and the glyphs keep where they're drawn
+    let mut jobs = Vec::<GlyphJob>::new();
+    let placed: Vec<(PreparedGlyph, Option<usize>)> = glyphs.into_iter().map(|glyph| {
+        let job = match glyph {
+            PreparedGlyph::Outline(job) => job,
+            glyph => return (glyph, None),
+        };
+        let position = job.position;
+        let index = match jobs.iter().position(|other| other.key == job.key) {
+            Some(index) => index,
+            None => {
+                jobs.push(job);
+                jobs.len() - 1
+            }
+        };
+        return (PreparedGlyph::Rendered(position), Some(index));
+    }).collect();
+    let tasks: Vec<(usize, Range<i32>)> = jobs.iter().enumerate()
+        .flat_map(|(index, job)| job.tiles().into_iter().map(move |rows| (index, rows)))
+        .collect();
+    let jobs = Arc::new(jobs);
+    let shared = jobs.clone();
+    let mut tiles = pool.map(tasks, thread_count(settings.threads), move |(index, rows)| shared[*index].rasterize(rows.clone())).into_iter();
+    let rendered: Vec<CachedGlyph> = jobs.iter().map(|job| {
+        let count = job.tiles().len();
+        return job.render(tiles.by_ref().take(count).collect());
+    }).collect();
+
+    for (glyph, index) in placed.iter() {
+        draw_prepared(glyph, index.map(|i| &rendered[i]), cache, &settings.style, canvas);
+    }
+    // Only cached once everything is drawn, since a full cache starts over and would drop glyphs still to be drawn
+    for (job, glyph) in jobs.iter().zip(rendered) {
+        cache.insert(job.key.clone(), glyph);
+    }
+    return Point::new(last_x as i32 - x_spacing, line_y - y_spacing); // Remove the uncessary last space that is produced by adding x_spacing at the end
+}
+
+enum PreparedGlyph {
+    // Spaces and glyphs with nothing to draw
+    Empty,
+    // Color and embedded bitmap glyphs, with where their bottom left corner goes
+    Bitmap(RgbaBitmap, (i32, i32)),
+    // Outline glyphs already rasterized at this subpixel offset, with where they're drawn
+    Cached(GlyphKey, (i32, i32)),
+    // Outline glyphs still to be rasterized
+    Outline(GlyphJob),
+    // Outline glyphs draw_text has made a job of (shared with their repeats), with where they're drawn
+    Rendered((i32, i32)),
+}
+
+struct GlyphJob {
+    // Everything it takes to rasterize an outline glyph, which can be done on any thread
+    key: GlyphKey,
+    // Where the glyph is drawn, and where its coverage starts from there
+    position: (i32, i32),
+    origin: (i32, i32),
+    // Right and top edges of the glyph in pixels
+    x_max: f32,
+    y_max: f32,
+    width: i32,
+    height: i32,
+    samples: i32,
+    subpixel: SubpixelRendering,
+    style: TextStyle,
+    // Offset of the samples in pixels and box of the outline in font units (see should_draw_point)
+    shift: f32,
+    y_shift: f32,
+    x_min: f32,
+    y_min: f32,
+    units_per_em: f32,
+    // The outline and the stroke around it, only for the styles that draw them
+    fill: Option<OutlineLayer>,
+    stroke: Option<OutlineLayer>,
 }
 
-fn draw_character(character: char, start_x: i32, start_y: i32, canvas: &mut Canvas<Window>, current_sample_mode: &Supersampling, font_path: Option<&str>) -> Point {
-    // Draws character to canvas and returns right most x coordiante in canvas coordinates
+enum OutlineLayer {
+    // Bands of the curves to cast rays against
+    Rays(SegmentBands),
+    // The curves flattened for the scanline rasterizer
+    Scanlines(EdgeTable),
+}
+
+impl GlyphJob {
+    fn tiles(&self) -> Vec<Range<i32>> {
+        // Bands of rows rasterized on their own. Subpixel filters reach across rows, so LCD glyphs are done whole.
+        if self.subpixel != SubpixelRendering::Off {
+            return std::iter::once(0..self.height).collect();
+        }
+        return tiles(self.height, TILE_ROWS);
+    }
+
+    fn rasterize(&self, rows: Range<i32>) -> (Coverage, Option<Coverage>) {
+        // Coverage of some rows of the glyph, and of its border for bordered text
+        let fill = self.fill.as_ref().map(|layer| rasterize_outline(self.width, rows.clone(), self.subpixel, self.samples, layer, GLYPH_FILL,
+            |bands, x, y| should_draw_point(x - self.shift, y - self.y_shift, self.x_min, self.y_min, self.units_per_em, bands, self.style.precision)));
+        let stroke = self.stroke.as_ref().map(|layer| rasterize_outline(self.width, rows.clone(), self.subpixel, self.samples, layer, FillRule::NonZero,
+            |bands, x, y| should_draw_stroke_point(x - self.shift, y - self.y_shift, self.x_min, self.y_min, self.units_per_em, bands, self.style.precision)));
+        return text_coverage(fill, stroke);
+    }
+
+    fn render(&self, tiles: Vec<(Coverage, Option<Coverage>)>) -> CachedGlyph {
+        // The glyph from its tiles, in the order tiles() gives them
+        let (coverage, border): (Vec<Coverage>, Vec<Option<Coverage>>) = tiles.into_iter().unzip();
+        let border: Option<Vec<Coverage>> = border.into_iter().collect();
+        return CachedGlyph { coverage: stitch(coverage), border: border.map(stitch), origin: self.origin, x_max: self.x_max, y_max: self.y_max };
+    }
+}
+
+fn draw_character(character: char, start_x: f32, start_y: f32, canvas: &mut impl Surface, settings: &RenderSettings, cache: &mut GlyphCache) -> ((f32, f32), Point) {
+    // Draws character to canvas starting at start_x (which can be between pixels) and returns
+    // where the next character goes along the baseline, along with the bottom right corner in
+    // canvas coordinates
+    let (glyph, next, corner) = prepare_character(character, start_x, start_y, canvas, settings, cache);
+    let rendered = match &glyph {
+        PreparedGlyph::Outline(job) => Some(job.render(vec![job.rasterize(0..job.height)])),
+        _ => None,
+    };
+    draw_prepared(&glyph, rendered.as_ref(), cache, &settings.style, canvas);
+    if let (PreparedGlyph::Outline(job), Some(rendered)) = (glyph, rendered) {
+        cache.insert(job.key, rendered);
+    }
+    return (next, corner);
+}
+
+fn draw_prepared(glyph: &PreparedGlyph, rendered: Option<&CachedGlyph>, cache: &GlyphCache, style: &TextStyle, canvas: &mut impl Surface) {
+    // Draws a glyph from prepare_character, with rendered the coverage of an outline glyph
+    let (cached, (pixel_x, pixel_y)) = match glyph {
+        PreparedGlyph::Empty => return,
+        PreparedGlyph::Bitmap(bitmap, (x, y)) => {
+            draw_bitmap(bitmap, *x, *y, style, canvas);
+            return;
+        }
+        PreparedGlyph::Cached(key, position) => (cache.get(key), *position),
+        PreparedGlyph::Outline(job) => (rendered, job.position),
+        PreparedGlyph::Rendered(position) => (rendered, *position),
+    };
+    if let Some(glyph) = cached {
+        draw_coverage(&glyph.coverage, glyph.border.as_ref(), pixel_x + glyph.origin.0, pixel_y - glyph.origin.1, style, canvas);
+    }
+}
+
+fn prepare_character(character: char, start_x: f32, start_y: f32, canvas: &impl Surface, settings: &RenderSettings, cache: &mut GlyphCache) -> (PreparedGlyph, (f32, f32), Point) {
+    /* Everything draw_character does short of rasterizing outlines and
+    drawing: the glyph to draw, where the next character goes and the bottom
+    right corner in canvas coordinates. Color and bitmap glyphs are rendered
+    here. */
+    let RenderSettings { font_path, face_selector, variations, palette, hinting, subpixel, ref style, .. } = *settings;
+    let samples = get_samples_per_axis(&settings.sample_mode);
+
+    // Outline glyphs are only rasterized the first time they're drawn at each subpixel offset
+    let (pixel_x, x_offset) = quantize_position(start_x);
+    let pixel_y = start_y.round() as i32;
+    let key = GlyphKey::new(settings, character, x_offset);
+    if let Some(glyph) = cache.get(&key) {
+        let (next, corner) = glyph_extent(start_x, start_y, glyph.x_max, glyph.y_max, style, canvas);
+        return (PreparedGlyph::Cached(key, (pixel_x, pixel_y)), next, corner);
+    }
     
     // STEP 2: extract data from font file
-    let file = fs::read(font_path.unwrap_or_else(|| FONT_PATH)).unwrap();
-    let face = match Face::parse(&file, 0) {
+    let file = fs::read(font_path).unwrap();
+    let face = match load_face(&file, face_selector) {
         Ok(f) => f,
         Err(e) => {
             eprint!("Error: {}.", e);
@@ -107,21 +566,59 @@ fn draw_character(character: char, start_x: i32, start_y: i32, canvas: &mut Canv
     };
     let units_per_em = face.units_per_em() as f32;
 
-    if character == ' ' { // Handle space character separately since it is not included in the ttf file
-        let (segments, bbox) = extract_outline(&face, 'a'); // Use the bounding box of lowercase a
-        let bbox_height = bbox.unwrap().height() as f32;
-        let bbox_width = bbox.unwrap().width() as f32;
-        let (bbox_width_pixel_units, bbox_height_pixel_units) = font_to_pixel_units(bbox.unwrap().x_max.into(), bbox.unwrap().y_max.into(), units_per_em);
-        let bottom_right = sdl_to_canvas(start_x + bbox_width_pixel_units as i32, -start_y + bbox_height_pixel_units as i32, canvas);
-        return bottom_right;
+    if character == ' ' { // Handle space character separately since it has no outline
+        let (_, bbox) = extract_outline(&face, 'a', variations); // Use the bounding box of lowercase a
+        // Bitmap-only fonts have no outline for 'a', use the ascender instead
+        let bbox = bbox.unwrap_or(Rect { x_min: 0, y_min: 0, x_max: face.ascender() / 2, y_max: face.ascender() });
+        // Use the advance of the space glyph if there is one (includes HVAR deltas for variable fonts)
+        let space_width = synthetic_advance(extract_advance(&face, ' ', variations).unwrap_or(bbox.x_max as f32), units_per_em, style.synthetic);
+        let (bbox_width_pixel_units, bbox_height_pixel_units) = font_to_pixel_units(space_width, bbox.y_max.into(), units_per_em);
+        let (next, corner) = glyph_extent(start_x, start_y, bbox_width_pixel_units, bbox_height_pixel_units, style, canvas);
+        return (PreparedGlyph::Empty, next, corner);
+    }
+
+    // Color glyphs (COLR/CPAL, SVG) and embedded bitmaps (sbix, CBDT, EBDT)
+    // are rendered to an RGBA bitmap instead
+    let glyph_id = face.glyph_index(character).unwrap_or(GlyphId(0));
+    let ratio = get_ratio(units_per_em);
+    let color_bitmap = if is_color_glyph(&face, glyph_id) {
+        render_color_glyph(&face, glyph_id, palette, style.foreground, ratio, samples, variations)
+    } else {
+        render_svg_glyph(&face, glyph_id, style.foreground, ratio, samples)
+    };
+    if let Some((bitmap, bounds)) = color_bitmap.or_else(|| render_strike_glyph(&face, glyph_id, ratio * units_per_em, style.foreground)) {
+        let (x_max_pixel_units, y_max_pixel_units) = font_to_pixel_units(bounds.x_max, bounds.y_max, units_per_em);
+        let (next, corner) = glyph_extent(start_x, start_y, x_max_pixel_units, y_max_pixel_units, style, canvas);
+        return (PreparedGlyph::Bitmap(bitmap, (start_x.round() as i32, pixel_y)), next, corner);
     }
 
     //let (canvas_width, canvas_height) = canvas.logical_size();
 
     // STEP 3: get bounding box for characters 
-    let (segments, bbox) = extract_outline(&face, character);
-    let bbox_height = bbox.unwrap().height() as f32;
-    let bbox_width = bbox.unwrap().width() as f32;
+    let (mut contours, mut stroke, bbox) = match glyph_outline(&face, glyph_id, ratio, hinting, settings, &mut cache.hinting) {
+        Some(outline) => outline,
+        None => {
+            // Nothing to draw (no outline and no bitmap), just move along by the advance
+            let advance = synthetic_advance(extract_advance(&face, character, variations).unwrap_or(0f32), units_per_em, style.synthetic);
+            let (advance_pixel_units, _) = font_to_pixel_units(advance, 0f32, units_per_em);
+            let (next, corner) = glyph_extent(start_x, start_y, advance_pixel_units, 0f32, style, canvas);
+            return (PreparedGlyph::Empty, next, corner);
+        }
+    };
+    // The untransformed box sets how far along the baseline the next glyph goes
+    let (bbox_width_pixel_units, bbox_height_pixel_units) = font_to_pixel_units(bbox.x_max.into(), bbox.y_max.into(), units_per_em);
+
+    // Rotation and skew turn the glyph about the bottom left of its box, which stays at the start point
+    let (anchor_x, anchor_y) = (bbox.x_min as f32, bbox.y_min as f32);
+    let bbox = if style.transform.is_identity() {
+        bbox
+    } else {
+        let transform = style.transform.around(anchor_x, anchor_y);
+        let fill_bbox = transform_outline(&mut contours, &transform).unwrap_or(bbox);
+        union_rect(fill_bbox, transform_outline(&mut stroke, &transform))
+    };
+    let bbox_height = bbox.height() as f32;
+    let bbox_width = bbox.width() as f32;
     let (x_pixels, y_pixels) = get_glyph_size(bbox_height, bbox_width, units_per_em);
 
     // STEP 5: draw pixels corresponding to character
@@ -130,82 +627,251 @@ fn draw_character(character: char, start_x: i32, start_y: i32, canvas: &mut Canv
     // and SDL coordinates have (0, 0) in the top-left.
     // Had to trace a letter out manually to figure this out
     // lmao. -- James M
-    let mut x = 0;
-    let mut y = 0;
-
-    let x_min = bbox.unwrap().x_min as f32;
-    let y_min = bbox.unwrap().y_min as f32;
-
-    //let factor = 1;
-
-    while x < x_pixels {
-        while y < y_pixels {
-            let x_center = x as f32 + 0.5f32;
-            let y_center = y as f32 + 0.5f32;
-            let x_baseline = x as f32;
-            let y_baseline = y as f32;
-            let mut color_factor = 0;
-            let division_factor: i32;
-            if *current_sample_mode == Supersampling::ThreeByThree {
-                division_factor = 3;
-            } else if *current_sample_mode == Supersampling::TwoByTwo {
-                division_factor = 2;
-            } else {
-                division_factor = 1;
-            }
+    let x_min = bbox.x_min as f32;
+    let y_min = bbox.y_min as f32;
 
-            if *current_sample_mode == Supersampling::TwoByTwo || *current_sample_mode == Supersampling::ThreeByThree {
-                for x_subsample in 0..division_factor {
-                    for y_subsample in 0..division_factor {
-                        let curr_x_coord = x_baseline + ((x_subsample as f32)/(division_factor as f32)) + (0.5/(division_factor as f32));
-                        let curr_y_coord = y_baseline + ((y_subsample as f32)/(division_factor as f32)) + (0.5/(division_factor as f32));
-                        if should_draw_point(curr_x_coord, curr_y_coord, x_min, y_min, units_per_em, &segments) {
-                            color_factor += 1;
-                        }
-                        // print!("{}", color_factor);
-                    }
-                }
-                
-                let color_factor_ratio = (255f32) / (division_factor as f32 * division_factor as f32);
-                let actual_color = (255f32 - (color_factor as f32)*color_factor_ratio) as u8;
-                
-                canvas.set_draw_color(Color::RGBA(actual_color,actual_color, actual_color, 255));
-                // match canvas.draw_point(Point::new(100 + x + start_x, 700 - y - start_y)) {
-                match canvas.draw_point(sdl_to_canvas(start_x + x, y - start_y, canvas)) {
-                
-                    Ok(()) => {}
-                    Err(e) => {
-                        eprint!("Error {}.", e);
-                        std::process::exit(1);
-                    }
-                };
-            } else if *current_sample_mode == Supersampling::None {
-                if should_draw_point(x_center, y_center, x_min, y_min, units_per_em, &segments) {
-                    canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
-                    match canvas.draw_point(sdl_to_canvas(start_x + x, y - start_y, canvas)) {
-                        Ok(()) => {}
-                        Err(e) => {
-                            eprint!("Error {}.", e);
-                            std::process::exit(1);
-                        }
-                    };
+    // Sampling the outline shifted right by the subpixel offset, which can spill into one more column.
+    // A transformed glyph can reach left of and below its anchor, by a fraction of a pixel too.
+    let (anchor_pixel_x, anchor_pixel_y) = font_to_pixel_units(anchor_x - x_min, anchor_y - y_min, units_per_em);
+    let shift = x_offset as f32 / SUBPIXEL_POSITIONS as f32 - anchor_pixel_x;
+    let y_shift = -anchor_pixel_y;
+    let origin = (shift.floor() as i32, y_shift.floor() as i32);
+    let (shift, y_shift) = (shift - origin.0 as f32, y_shift - origin.1 as f32);
+    let width = if shift > 0f32 { x_pixels + 1 } else { x_pixels };
+    let y_pixels = if y_shift > 0f32 { y_pixels + 1 } else { y_pixels };
+    // Where the scanline rasterizer puts the outline, the same place should_draw_point takes samples from
+    let to_pixels = Transform::translate(shift, y_shift).then(&Transform::scale(ratio, ratio)).then(&Transform::translate(-x_min, -y_min));
+    let job = GlyphJob {
+        key: key,
+        position: (pixel_x, pixel_y),
+        origin: origin,
+        x_max: bbox_width_pixel_units,
+        y_max: bbox_height_pixel_units,
+        width: width,
+        height: y_pixels,
+        samples: samples,
+        subpixel: subpixel,
+        style: *style,
+        shift: shift,
+        y_shift: y_shift,
+        x_min: x_min,
+        y_min: y_min,
+        units_per_em: units_per_em,
+        fill: (style.outline != TextOutline::Hollow).then(|| outline_layer(&contours, subpixel, style, &to_pixels, ratio)),
+        stroke: (style.outline != TextOutline::Fill).then(|| outline_layer(&stroke, subpixel, style, &to_pixels, ratio)),
+    };
+    let (next, corner) = glyph_extent(start_x, start_y, bbox_width_pixel_units, bbox_height_pixel_units, style, canvas);
+    return (PreparedGlyph::Outline(job), next, corner);
+}
+
+fn draw_text_on_path(text: &str, path: &TextPath, layout: &PathLayout, canvas: &mut impl Surface, settings: &RenderSettings, cache: &mut GlyphCache) {
+    /* Lays text out along a path in window coordinates turned y-up (so y is
+    minus the SDL y), spaced by the glyphs' advances. Line breaks are spaces. */
+    let RenderSettings { font_path, face_selector, variations, hinting, subpixel, ref style, .. } = *settings;
+    let file = fs::read(font_path).unwrap();
+    let face = match load_face(&file, face_selector) {
+        Ok(f) => f,
+        Err(e) => {
+            eprint!("Error: {}.", e);
+            std::process::exit(1);
+        }
+    };
+    let units_per_em = face.units_per_em() as f32;
+    let ratio = get_ratio(units_per_em);
+    let characters: Vec<char> = text.chars().map(|c| if c == '\n' { ' ' } else { c }).collect();
+    let advances: Vec<f32> = characters.iter()
+        .map(|c| synthetic_advance(extract_advance(&face, *c, variations).unwrap_or(0f32), units_per_em, style.synthetic) * ratio)
+        .collect();
+    let samples = get_samples_per_axis(&settings.sample_mode);
+
+    for (character, placement) in characters.iter().zip(path.place_glyphs(&advances, layout)) {
+        let placement = match placement {
+            Some(p) if *character != ' ' => p,
+            _ => continue,
+        };
+        let glyph_id = face.glyph_index(*character).unwrap_or(GlyphId(0));
+        // Unhinted, since it's only needed for where the glyph sits and the hinted box is within a pixel of it
+        let hinting = if layout.rendering == PathRendering::Warped { hinting } else { HintingMode::None };
+        let outline = glyph_outline(&face, glyph_id, ratio, hinting, settings, &mut cache.hinting);
+        let (mut contours, mut stroke, bbox) = match outline {
+            Some(o) => o,
+            // Color and bitmap glyphs can only be turned as a whole
+            None => (Vec::new(), Vec::new(), Rect { x_min: 0, y_min: 0, x_max: 0, y_max: 0 }),
+        };
+        let transform = Transform::rotate(placement.angle).then(&style.transform);
+        if layout.rendering == PathRendering::PerGlyph || contours.is_empty() {
+            // draw_character turns the glyph about the bottom left of its box, so put that where it lands
+            let (anchor_x, anchor_y) = transform.apply_vector(bbox.x_min as f32 * ratio, bbox.y_min as f32 * ratio);
+            let glyph_settings = RenderSettings { style: TextStyle { transform, ..*style }, ..*settings };
+            draw_character(*character, placement.x + anchor_x, -(placement.y + anchor_y), canvas, &glyph_settings, cache);
+            continue;
+        }
+
+        // Bent glyphs depend on where they are on the path, so they're rasterized every time
+        let to_pixels = style.transform.then(&Transform::scale(ratio, ratio));
+        transform_outline(&mut contours, &to_pixels);
+        transform_outline(&mut stroke, &to_pixels);
+        let contours = path.warp_outline(&contours, placement.distance, layout.offset, WARP_STEP);
+        let stroke = path.warp_outline(&stroke, placement.distance, layout.offset, WARP_STEP);
+        let bounds = match outline_bounds(&contours) {
+            Some(b) => union_rect(b, outline_bounds(&stroke)),
+            None => continue,
+        };
+        let (x_min, y_min) = (bounds.x_min as f32, bounds.y_min as f32);
+        let (width, height) = (bounds.width() as i32, bounds.height() as i32);
+        // Already in pixels, just moved to the corner of the box
+        let to_pixels = Transform::translate(-x_min, -y_min);
+        let fill = (style.outline != TextOutline::Hollow).then(|| {
+            let layer = outline_layer(&contours, subpixel, style, &to_pixels, 1f32);
+            return rasterize_outline(width, 0..height, subpixel, samples, &layer, GLYPH_FILL, |bands, x, y| GLYPH_FILL.contains(bands.winding_number(x_min + x, y_min + y, style.precision)));
+        });
+        let stroke = (style.outline != TextOutline::Fill).then(|| {
+            let layer = outline_layer(&stroke, subpixel, style, &to_pixels, 1f32);
+            return rasterize_outline(width, 0..height, subpixel, samples, &layer, FillRule::NonZero, |bands, x, y| bands.winding_number(x_min + x, y_min + y, style.precision) != 0);
+        });
+        let (coverage, border) = text_coverage(fill, stroke);
+        draw_coverage(&coverage, border.as_ref(), bounds.x_min as i32, -(bounds.y_min as i32), style, canvas);
+    }
+}
+
+fn glyph_outline(face: &Face, glyph_id: GlyphId, ratio: f32, hinting: HintingMode, settings: &RenderSettings, cache: &mut HintingCache) -> Option<(Vec<Contour>, Vec<Contour>, Rect)> {
+    /* The outline of a glyph in font units as the style draws it (hinted, with
+    synthetic styles and stem darkening), the stroke around it for hollow and
+    bordered text (empty otherwise) and the box around both. None if the
+    glyph has no outline. */
+    let RenderSettings { variations, ref style, .. } = *settings;
+    let units_per_em = face.units_per_em() as f32;
+    let key = FaceKey::new(settings.font_path, settings.face_selector);
+    let (mut contours, bbox) = cache.fitted_outline(&key, face, glyph_id, ratio * units_per_em, hinting, variations);
+    let bbox = bbox?;
+    // Synthetic styles change the outline (and so the box the next glyph is placed after)
+    let bbox = apply_synthetic_style(&mut contours, bbox, units_per_em, style.synthetic);
+    let bbox = if style.stem_darkening {
+        // Darkening depends on how many pixels wide the font's stems are at this size
+        let (vertical_stem, horizontal_stem) = standard_stem_widths(face, variations);
+        let x_strength = stem_darkening(vertical_stem * ratio) / ratio;
+        let y_strength = stem_darkening(horizontal_stem * ratio) / ratio;
+        embolden_outline(&mut contours, bbox, x_strength, y_strength)
+    } else {
+        bbox
+    };
+    /* Outlines the rasterizer can't make sense of are left out, and open
+    contours closed. Only the one check drawing needs is made per glyph, the
+    rest of what validate() looks for is for print_outline_report. */
+    if !is_finite(&contours) {
+        eprintln!("Glyph {} can't be drawn: it has coordinates that aren't numbers", glyph_id.0);
+        return None;
+    }
+    close_contours(&mut contours);
+    // Hollow and bordered text also need the stroke around the outline, which reaches outside it
+    let (stroke, stroke_bbox) = if style.outline != TextOutline::Fill {
+        let stroke_style = parse_stroke_style(STROKE);
+        let stroke_style = StrokeStyle { width: stroke_style.width / ratio, ..stroke_style };
+        stroke_outline(&contours, &stroke_style)
+    } else {
+        (Vec::new(), None)
+    };
+    return Some((contours, stroke, union_rect(bbox, stroke_bbox)));
+}
+
+fn glyph_extent(start_x: f32, start_y: f32, width: f32, height: f32, style: &TextStyle, canvas: &impl Surface) -> ((f32, f32), Point) {
+    // Start of the next glyph after one width wide drawn at (start_x, start_y) (along the
+    // transformed baseline) and its bottom right corner in canvas coordinates
+    let (advance_x, advance_y) = style.transform.apply_vector(width, 0f32);
+    let (next_x, next_y) = (start_x + advance_x, start_y - advance_y);
+    return ((next_x, next_y), sdl_to_canvas(next_x as i32, -(next_y.round() as i32) + height as i32, canvas));
+}
+
+fn union_rect(a: Rect, b: Option<Rect>) -> Rect {
+    return match b {
+        Some(b) => Rect { x_min: a.x_min.min(b.x_min), y_min: a.y_min.min(b.y_min), x_max: a.x_max.max(b.x_max), y_max: a.y_max.max(b.y_max) },
+        None => a,
+    };
+}
+
+fn draw_bitmap(bitmap: &RgbaBitmap, start_x: i32, start_y: i32, style: &TextStyle, canvas: &mut impl Surface) {
+    // Draws an RGBA glyph bitmap over the background, with its bottom-left corner at (start_x, start_y)
+    for row in 0..bitmap.height {
+        let y = (bitmap.height - 1 - row) as i32;
+        for x in 0..bitmap.width {
+            let pixel = bitmap.get(x, row);
+            if pixel[3] <= 0f32 {
+                continue;
+            }
+            let [r, g, b] = blend_premultiplied(pixel, style);
+            let point = sdl_to_canvas(start_x + x as i32, y - start_y, canvas);
+            match canvas.set_pixel(point, Color::RGBA(r, g, b, 255)) {
+                Ok(()) => {}
+                Err(e) => {
+                    eprint!("Error {}.", e);
+                    std::process::exit(1);
                 }
+            };
+        }
+    }
+}
+
+fn outline_layer(contours: &[Contour], subpixel: SubpixelRendering, style: &TextStyle, to_pixels: &Transform, pixels_per_unit: f32) -> OutlineLayer {
+    // Only what the style's rasterizer needs, the outline flattened for scanlines (which only do grayscale) or bands for rays
+    if style.rasterizer == Rasterizer::Scanline && subpixel == SubpixelRendering::Off {
+        return OutlineLayer::Scanlines(EdgeTable::new(&flatten(contours, FLATTEN_TOLERANCE, pixels_per_unit), to_pixels));
+    }
+    return OutlineLayer::Rays(SegmentBands::new(contours));
+}
+
+fn rasterize_outline(width: i32, rows: Range<i32>, subpixel: SubpixelRendering, samples: i32, layer: &OutlineLayer, rule: FillRule, inside: impl Fn(&SegmentBands, f32, f32) -> bool) -> Coverage {
+    /* Coverage of some rows of an outline, swept from its edge table if it has
+    one and otherwise with inside, which gives the same answers as rule by
+    casting rays. Subpixel filters reach across rows, so LCD coverage is
+    always for rows from 0. */
+    return match layer {
+        OutlineLayer::Scanlines(table) => render_scanline_rows(width, rows, samples, table, rule),
+        OutlineLayer::Rays(bands) if subpixel != SubpixelRendering::Off => render_subpixels(width, rows.end, subpixel, &LCD_FILTER, samples, |x, y| inside(bands, x, y)),
+        OutlineLayer::Rays(bands) => render_grayscale_rows(width, rows, samples, |x, y| inside(bands, x, y)),
+    };
+}
+
+fn text_coverage(fill: Option<Coverage>, stroke: Option<Coverage>) -> (Coverage, Option<Coverage>) {
+    // The text's coverage and its border's: hollow text is just the stroke, in the text color
+    return match (fill, stroke) {
+        (Some(fill), stroke) => (fill, stroke),
+        (None, Some(stroke)) => (stroke, None),
+        (None, None) => unreachable!("every style draws the outline or its stroke"),
+    };
+}
+
+fn draw_coverage(coverage: &Coverage, border: Option<&Coverage>, start_x: i32, start_y: i32, style: &TextStyle, canvas: &mut impl Surface) {
+    // Draws text in the style's colors, with separate coverage for each color channel for LCDs.
+    // The border (rasterized over the same pixels) goes under the text.
+    for y in 0..coverage.height {
+        for x in 0..coverage.width {
+            let pixel = coverage.get(x, y);
+            let border_pixel = border.map_or([0f32; 3], |b| b.get(x, y));
+            if pixel == [0f32; 3] && border_pixel == [0f32; 3] {
+                continue;
             }
-            y += 1;
+            let [r, g, b] = blend_layers(&[(border_pixel, style.border), (pixel, style.foreground)], style);
+            let point = sdl_to_canvas(start_x + coverage.x_min + x, coverage.y_min + y - start_y, canvas);
+            match canvas.set_pixel(point, Color::RGBA(r, g, b, 255)) {
+                Ok(()) => {}
+                Err(e) => {
+                    eprint!("Error {}.", e);
+                    std::process::exit(1);
+                }
+            };
         }
-        y = 0;
-        x += 1;
     }
+}
 
-    let (bbox_width_pixel_units, bbox_height_pixel_units) = font_to_pixel_units(bbox.unwrap().x_max.into(), bbox.unwrap().y_max.into(), units_per_em);
-    let bottom_right = sdl_to_canvas(start_x + bbox_width_pixel_units as i32, -start_y + bbox_height_pixel_units as i32, canvas);
-    return bottom_right;
-    // let rightmost_x = bottom_right.x;
-    // let bottommost_y = bottom_right.y;
-    // return rightmost_x;
+fn get_samples_per_axis(sample_mode: &Supersampling) -> i32 {
+    return match sample_mode {
+        Supersampling::None => 1,
+        Supersampling::TwoByTwo => 2,
+        Supersampling::ThreeByThree => 3,
+    };
 }
 
-fn sdl_demo() -> Result<(), String> {
+fn sdl_demo(font_path: &str, face_selector: &FaceSelector, synthetic: SyntheticStyle, variations: &[Variation]) -> Result<(), String> {
     /* Starts a window render. If this is not working, you need to install SDL2 on
     your system. If you use Mac OS the easiest way to do this is to install Homebrew
     and run `brew install sdl2`. Windows is a little more complicated, but
@@ -234,6 +900,39 @@ fn sdl_demo() -> Result<(), String> {
 
     let mut current_sample_mode = SAMPLE_MODE;
 
+    // Variable font axes that can be swept with V (A picks the next axis)
+    let file = fs::read(font_path).map_err(|e| e.to_string())?;
+    let axes = get_axes(&load_face(&file, face_selector)?);
+    let mut current_variations = variations.to_vec();
+    let mut sweep_axis = 0;
+    let mut sweep_direction = 0f32;
+    let mut palette = PALETTE_INDEX;
+    let palettes = palette_count(&load_face(&file, face_selector)?);
+    let mut hinting = HINTING_MODE;
+    let mut subpixel = SUBPIXEL_RENDERING;
+    let mut glyph_cache = GlyphCache::default();
+    let mut pool = ThreadPool::default();
+    let mut style = TextStyle {
+        foreground: FOREGROUND_COLOR,
+        background: BACKGROUND_COLOR,
+        gamma: GAMMA,
+        contrast: CONTRAST,
+        stem_darkening: STEM_DARKENING,
+        synthetic,
+        outline: TEXT_OUTLINE,
+        border: BORDER_COLOR,
+        transform: text_transform(TEXT_ROTATION, TEXT_SKEW),
+        precision: PRECISION,
+        rasterizer: RASTERIZER,
+    };
+    let mut rotation = TEXT_ROTATION;
+    // The path is given y-down like the window, glyphs are placed y-up
+    let mut path_contours = parse_path(TEXT_PATH);
+    transform_outline(&mut path_contours, &Transform::scale(1f32, -1f32));
+    let text_path = TextPath::new(&path_contours);
+    let mut path_rendering = TEXT_PATH_RENDERING;
+    let mut path_align = TEXT_PATH_ALIGN;
+
     // STEP 4: start loop for render (the next few lines are unimportant)
     let mut event_pump = sdl_context.event_pump()?;
     'running: loop {
@@ -256,9 +955,148 @@ fn sdl_demo() -> Result<(), String> {
                         current_sample_mode = Supersampling::None;
                     }
                 },
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => {
+                    subpixel = match subpixel {
+                        SubpixelRendering::Off => SubpixelRendering::Rgb,
+                        SubpixelRendering::Rgb => SubpixelRendering::Bgr,
+                        SubpixelRendering::Bgr => SubpixelRendering::VerticalRgb,
+                        SubpixelRendering::VerticalRgb => SubpixelRendering::VerticalBgr,
+                        SubpixelRendering::VerticalBgr => SubpixelRendering::Off,
+                    };
+                    println!("Subpixel rendering: {:?}", subpixel);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => {
+                    style = style.inverted();
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::K),
+                    ..
+                } => {
+                    style.stem_darkening = !style.stem_darkening;
+                    println!("Stem darkening: {}", style.stem_darkening);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    style.precision = match style.precision {
+                        Precision::Single => Precision::Double,
+                        Precision::Double => Precision::Single,
+                    };
+                    println!("Precision: {:?}", style.precision);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => {
+                    style.rasterizer = match style.rasterizer {
+                        Rasterizer::RayCasting => Rasterizer::Scanline,
+                        Rasterizer::Scanline => Rasterizer::RayCasting,
+                    };
+                    println!("Rasterizer: {:?}", style.rasterizer);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    ..
+                } => {
+                    style.outline = match style.outline {
+                        TextOutline::Fill => TextOutline::Hollow,
+                        TextOutline::Hollow => TextOutline::Bordered,
+                        TextOutline::Bordered => TextOutline::Fill,
+                    };
+                    println!("Text outline: {:?}", style.outline);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => {
+                    rotation = (rotation + 15f32) % 360f32;
+                    style.transform = text_transform(rotation, TEXT_SKEW);
+                    println!("Rotation: {} degrees", rotation);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::T),
+                    ..
+                } => {
+                    path_rendering = match path_rendering {
+                        None => Some(PathRendering::PerGlyph),
+                        Some(PathRendering::PerGlyph) => Some(PathRendering::Warped),
+                        Some(PathRendering::Warped) => None,
+                    };
+                    println!("Text on a path: {:?}", path_rendering);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    ..
+                } => {
+                    path_align = match path_align {
+                        PathAlign::Start => PathAlign::Middle,
+                        PathAlign::Middle => PathAlign::End,
+                        PathAlign::End => PathAlign::Start,
+                    };
+                    println!("Path alignment: {:?}", path_align);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => {
+                    sweep_direction = if sweep_direction == 0f32 { 1f32 } else { 0f32 };
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } if palettes > 0 => {
+                    palette = (palette + 1) % palettes;
+                    println!("Using palette {}", palette);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => {
+                    hinting = match hinting {
+                        HintingMode::None => HintingMode::Full,
+                        HintingMode::Full => HintingMode::VerticalOnly,
+                        HintingMode::VerticalOnly => HintingMode::Auto,
+                        HintingMode::Auto => HintingMode::None,
+                    };
+                    println!("Hinting: {:?}", hinting);
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    ..
+                } if !axes.is_empty() => {
+                    sweep_axis = (sweep_axis + 1) % axes.len();
+                    println!("Sweeping axis {}", axes[sweep_axis].tag);
+                },
                 _ => {}
             }
         }
+        if sweep_direction != 0f32 && !axes.is_empty() {
+            // Move the selected axis a step and bounce off the ends of its range
+            let axis = axes[sweep_axis];
+            let index = match current_variations.iter().position(|v| v.axis == axis.tag) {
+                Some(i) => i,
+                None => {
+                    current_variations.push(Variation { axis: axis.tag, value: axis.default_value });
+                    current_variations.len() - 1
+                }
+            };
+            let step = (axis.max_value - axis.min_value) / SWEEP_FRAMES;
+            let mut value = current_variations[index].value + sweep_direction * step;
+            if value >= axis.max_value || value <= axis.min_value {
+                value = value.clamp(axis.min_value, axis.max_value);
+                sweep_direction = -sweep_direction;
+            }
+            current_variations[index].value = value;
+        }
+
+        canvas.set_draw_color(sdl_color(style.background));
         canvas.clear();
         
         // STEP 5: draw pixels corresponding to character
@@ -266,7 +1104,26 @@ fn sdl_demo() -> Result<(), String> {
          let hello = "pls give us 25/25 \nprof. ng and prof. o'brien <3";
         // let hello = "We the People of the United States, in Order to form a more perfect Union, establish Justice, insure domestic Tranquility, provide for the common defense, promote the general Welfare, and secure the Blessings of Liberty to ourselves and our Posterity, do ordain and establish this Constitution for the United States of America.";
         // let hello = "We the People of the United States, in Order\nto form a more perfect Union, establish\nJustice, insure domestic Tranquility, provide for\nthe common defense, promote the general\nWelfare, and secure the Blessings of Liberty\nto ourselves and our Posterity, do ordain and\nestablish this Constitution for the United\nStates of America.";
-         draw_text(hello, (CANVAS_MARGIN + POINT_SIZE as u32) as i32, (CANVAS_MARGIN + POINT_SIZE as u32 * 3) as i32, &mut canvas, &current_sample_mode, Some(FONT_PATH));
+        let settings = RenderSettings {
+            sample_mode: current_sample_mode,
+            font_path: font_path,
+            face_selector: face_selector,
+            variations: &current_variations,
+            palette: palette,
+            hinting: hinting,
+            subpixel: subpixel,
+            style: style,
+            threads: RENDER_THREADS,
+        };
+        match path_rendering {
+            Some(rendering) => {
+                let layout = PathLayout { align: path_align, start_offset: TEXT_PATH_START * text_path.length(), offset: TEXT_PATH_OFFSET, rendering };
+                draw_text_on_path(hello, &text_path, &layout, &mut canvas, &settings, &mut glyph_cache);
+            },
+            None => {
+                draw_text(hello, (CANVAS_MARGIN + POINT_SIZE as u32) as i32, (CANVAS_MARGIN + POINT_SIZE as u32 * 3) as i32, &mut canvas, &settings, &mut glyph_cache, &mut pool);
+            },
+        }
 
         //draw_text("We the People of the United States, in Order", (CANVAS_MARGIN + POINT_SIZE as u32) as i32, (CANVAS_MARGIN + POINT_SIZE as u32 * 3) as i32, &mut canvas, &current_sample_mode, Some(FONT_PATH));
         //draw_text("to form a more perfect Union, establish", (CANVAS_MARGIN + POINT_SIZE as u32) as i32, (CANVAS_MARGIN + POINT_SIZE as u32 * 3) as i32 + 100, &mut canvas, &current_sample_mode, Some("./fonts/Roboto-Regular.ttf"));
@@ -285,7 +1142,7 @@ fn sdl_demo() -> Result<(), String> {
 
         // STEP 6: draw border around render area (just looks nicer idk)
         let rect = sdl2::rect::Rect::new(100, 100, WINDOW_WIDTH - CANVAS_MARGIN * 2, WINDOW_HEIGHT - CANVAS_MARGIN * 2);
-        canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
+        canvas.set_draw_color(sdl_color([style.foreground[0], style.foreground[1], style.foreground[2]]));
         match canvas.draw_rect(rect) {
             Ok(()) => {}
             Err(e) => {
@@ -294,33 +1151,47 @@ fn sdl_demo() -> Result<(), String> {
             }
         };
 
-        // Fill in any undrawn pixels with white
-        canvas.set_draw_color(Color::RGBA(255, 255, 255, 255));
+        // Fill in any undrawn pixels with the background
+        canvas.set_draw_color(sdl_color(style.background));
         canvas.present();
     }
 
     return Ok(());
 }
 
-fn sdl_to_canvas(x: i32, y: i32, canvas: &Canvas<Window>) -> Point {
-    return Point::new(x, (canvas.logical_size().1 as i32) - y);
+fn sdl_color(color: [f32; 3]) -> Color {
+    return Color::RGBA(to_byte(color[0]), to_byte(color[1]), to_byte(color[2]), 255);
+}
+
+fn text_transform(rotation: f32, skew: f32) -> Transform {
+    // Scales and skews the glyphs upright, then rotates them
+    let upright = Transform::skew(skew, 0f32).then(&Transform::scale(TEXT_SCALE[0], TEXT_SCALE[1]));
+    return Transform::rotate(rotation.to_radians()).then(&upright);
+}
+
+fn sdl_to_canvas(x: i32, y: i32, canvas: &impl Surface) -> Point {
+    return Point::new(x, canvas.height() - y);
 }
 
-fn should_draw_point(x: f32, y:f32, x_min: f32, y_min: f32, units_per_em:f32, segments: &Vec<Segment>) -> bool {
+fn should_draw_point(x: f32, y:f32, x_min: f32, y_min: f32, units_per_em:f32, bands: &SegmentBands, precision: Precision) -> bool {
     // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM02/Chap2.html
     let (mut x_units, mut y_units) = pixels_to_font_units(x, y, units_per_em);
     x_units += x_min;
     y_units += y_min;
-    let mut count: i32 = 0;
     // println!("Checking point ({:?}, {:?})", x_units, y_units);
 
-    for segment in segments.into_iter() {
-        count = count + segment.intersect(x_units, y_units, 1.0, 0.0);
-    }
+    // Only the segments in the point's band that reach right of it can be crossed
+    let count = bands.winding_number(x_units, y_units, precision);
 
-    return count % 2 == 1;
+    return GLYPH_FILL.contains(count);
+}
 
-    // return count != 0;
+fn should_draw_stroke_point(x: f32, y: f32, x_min: f32, y_min: f32, units_per_em: f32, bands: &SegmentBands, precision: Precision) -> bool {
+    // Strokes overlap themselves, so they're filled by the nonzero rule instead
+    let (mut x_units, mut y_units) = pixels_to_font_units(x, y, units_per_em);
+    x_units += x_min;
+    y_units += y_min;
+    return bands.winding_number(x_units, y_units, precision) != 0;
 }
 
 fn get_glyph_size(height: f32, width: f32, units_per_em:f32) -> (i32, i32) {
@@ -353,4 +1224,96 @@ fn get_ratio(units_per_em: f32) -> f32 {
     
     // control output size via consts at top of file
     return POINT_SIZE * RESOLUTION / (POINTS_PER_INCH * units_per_em);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use sdl2::pixels::Color;
+    use sdl2::rect::Point;
+
+    use crate::collection::FaceSelector;
+    use crate::gamma::TextStyle;
+    use crate::hinting::HintingMode;
+    use crate::lcd::SubpixelRendering;
+    use crate::parallel::ThreadPool;
+    use crate::scanline::Rasterizer;
+    use crate::stroke::TextOutline;
+    use crate::synthetic::SyntheticStyle;
+    use super::*;
+
+    // Repeated letters are rasterized once, and script fonts draw over the glyph before
+    const TEXT: &str = "abracadabra jazz";
+    const START: (i32, i32) = (130, 400);
+    const THREADS: usize = 3;
+
+    struct Image {
+        height: i32,
+        pixels: HashMap<(i32, i32), Color>,
+    }
+
+    impl Surface for Image {
+        fn height(&self) -> i32 {
+            return self.height;
+        }
+
+        fn set_pixel(&mut self, point: Point, color: Color) -> Result<(), String> {
+            self.pixels.insert((point.x(), point.y()), color);
+            return Ok(());
+        }
+    }
+
+    fn style(outline: TextOutline, rasterizer: Rasterizer, rotation: f32) -> TextStyle {
+        return TextStyle {
+            foreground: FOREGROUND_COLOR,
+            background: BACKGROUND_COLOR,
+            gamma: GAMMA,
+            contrast: CONTRAST,
+            stem_darkening: false,
+            synthetic: SyntheticStyle { bold: false, oblique: false },
+            outline: outline,
+            border: BORDER_COLOR,
+            transform: text_transform(rotation, 0f32),
+            precision: PRECISION,
+            rasterizer: rasterizer,
+        };
+    }
+
+    #[test]
+    fn text_on_threads_matches_one_character_at_a_time() {
+        // Every pixel ends up the color it would have if each character were rasterized and drawn in turn
+        let face_selector = FaceSelector::Index(0);
+        let mut pool = ThreadPool::default();
+        for font_path in ["fonts/Roboto-Regular.ttf", "fonts/DancingScript-Regular.ttf"] {
+            for (outline, rasterizer, subpixel) in [
+                (TextOutline::Fill, Rasterizer::RayCasting, SubpixelRendering::Off),
+                (TextOutline::Fill, Rasterizer::Scanline, SubpixelRendering::Off),
+                (TextOutline::Bordered, Rasterizer::Scanline, SubpixelRendering::Off),
+                (TextOutline::Hollow, Rasterizer::RayCasting, SubpixelRendering::Rgb),
+            ] {
+                for rotation in [0f32, 10f32] {
+                    let settings = RenderSettings {
+                        sample_mode: Supersampling::TwoByTwo,
+                        font_path: font_path,
+                        face_selector: &face_selector,
+                        variations: &[],
+                        palette: 0,
+                        hinting: HintingMode::None,
+                        subpixel: subpixel,
+                        style: style(outline, rasterizer, rotation),
+                        threads: THREADS,
+                    };
+                    let mut text = Image { height: WINDOW_HEIGHT as i32, pixels: HashMap::new() };
+                    draw_text(TEXT, START.0, START.1, &mut text, &settings, &mut GlyphCache::default(), &mut pool);
+                    let mut characters = Image { height: WINDOW_HEIGHT as i32, pixels: HashMap::new() };
+                    let (mut cache, mut start) = (GlyphCache::default(), (START.0 as f32, START.1 as f32));
+                    for character in TEXT.chars() {
+                        start = draw_character(character, start.0, start.1, &mut characters, &settings, &mut cache).0;
+                    }
+                    assert!(!text.pixels.is_empty());
+                    assert!(text.pixels == characters.pixels, "{} {:?} {:?} {:?} turned {}", font_path, outline, rasterizer, subpixel, rotation);
+                }
+            }
+        }
+    }
+}
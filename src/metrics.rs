@@ -1,13 +1,22 @@
 /* Analyzes rendering performance based on the metrics defined
 in the project proposal. */
 
-use ttf_parser::Face;
-use ttf_parser::Rect;
+use ttf_parser::{GlyphId, Rect};
 use std::fs;
+use std::time::Instant;
 
-use crate::segment::Segment;
+use crate::collection::{load_face, FaceSelector};
+use crate::variation::Variation;
 
-use crate::outline::extract_outline;
+use crate::bands::SegmentBands;
+use crate::curve::Contour;
+use crate::flatten::flatten;
+use crate::float::Precision;
+use crate::raster::render_grayscale;
+use crate::scanline::{render_scanlines, EdgeTable, GLYPH_FILL};
+use crate::transform::Transform;
+
+use crate::hinting::{FaceKey, HintingCache, HintingMode};
 
 use rand::prelude::*;
 
@@ -15,6 +24,8 @@ use rand::prelude::*;
 const RESOLUTION: f32 = 144f32;
 // Font size for output
 const POINT_SIZE: f32 = 192f32;
+// Samples per axis in each pixel when comparing rasterizers
+const SCANLINE_SAMPLES: i32 = 4;
 
 #[derive(Debug)]
 pub struct Metrics {
@@ -25,6 +36,24 @@ pub struct Metrics {
     pub samples: i32
 }
 
+#[derive(Debug)]
+pub struct PrecisionMetrics {
+    // Fraction of samples where single precision agrees with double
+    pub agreement: f32,
+    // Time taken to classify all the samples in each precision
+    pub single_seconds: f32,
+    pub double_seconds: f32,
+}
+
+#[derive(Debug)]
+pub struct ScanlineMetrics {
+    // Fraction of pixels where the scanline rasterizer gives the same coverage as ray casting
+    pub agreement: f32,
+    // Time taken to rasterize the char each way
+    pub ray_seconds: f32,
+    pub scanline_seconds: f32,
+}
+
 /*
 pub fn get_supersample_score(test_character: char, test_samples: i32, test_pixels_samples: i32) -> f32
 {
@@ -96,30 +125,12 @@ pub fn get_supersample_score(test_character: char, test_samples: i32, test_pixel
 }
 */
 
-pub fn get_render_score(test_character: char, font_path:&str, test_samples: i32) -> Metrics {
-    /* Takes TEST_SAMPLES samples from within the char's bbox and returns
-    the percentage of them that are correctly classified */
-    // Step 1: extract outline from font file
-    let file = fs::read(font_path).unwrap();
-    let face = match Face::parse(&file, 0) {
-        Ok(f) => f,
-        Err(e) => {
-            eprint!("Error: {}.", e);
-            std::process::exit(1);
-        }
-    };
-    let (segments, bbox) = extract_outline(&face, test_character);
-    let units_per_em = face.units_per_em() as f32;
-
-    // Step 2: get bounding box for character
-    let bbox_unwrapped: Rect = bbox.unwrap();
-    let x_min = bbox_unwrapped.x_min as f32;
-    // let x_max = bbox_unwrapped.x_max as f32;
-    let y_min = bbox_unwrapped.y_min as f32;
-    // let y_max = bbox_unwrapped.y_max as f32;
-    let width = bbox_unwrapped.width() as f32;
-    let height = bbox_unwrapped.height() as f32;
-    let (x_pixels, y_pixels) = get_glyph_size(height, width, units_per_em);
+pub fn get_render_score(test_character: char, font_path:&str, face_selector: &FaceSelector, variations: &[Variation], hinting: HintingMode, ppem: f32, test_samples: i32) -> Metrics {
+    /* Takes TEST_SAMPLES samples from within the char's bbox at ppem pixels
+    per em and returns the percentage of them that are correctly classified */
+    // Step 1 and 2: extract outline from font file and get bounding box for character
+    let (contours, x_min, y_min, ratio, (x_pixels, y_pixels)) = load_test_glyph(test_character, font_path, face_selector, variations, hinting, ppem);
+    let bands = SegmentBands::new(&contours);
 
     // Step 3: Take TEST_SAMPLES samples from character
     //let mut valid_samples = 0;
@@ -142,11 +153,11 @@ pub fn get_render_score(test_character: char, font_path:&str, test_samples: i32)
         // Step 3c: Check whether the pixel is filled in at (1) the random sample position
         // and (2) the corresponding sample point with default sampling, 2x2 supersampling,
         // and 3x3 supersampling
-        let sample_default = should_draw_point(x + 0.5f32, y + 0.5f32, x_min, y_min, units_per_em, &segments);
-        let sample_2x2 = should_draw_point(x + x_offset_2x2, y + y_offset_2x2, x_min, y_min, units_per_em, &segments);
-        let sample_3x3 = should_draw_point(x + x_offset_3x3, y + y_offset_3x3, x_min, y_min, units_per_em, &segments);
-        let sample_4x4 = should_draw_point(x + x_offset_4x4, y + y_offset_4x4, x_min, y_min, units_per_em, &segments);
-        let sample_random = should_draw_point(x + x_offset, y + y_offset, x_min, y_min, units_per_em, &segments);
+        let sample_default = should_draw_point(x + 0.5f32, y + 0.5f32, x_min, y_min, ratio, &bands, Precision::Double);
+        let sample_2x2 = should_draw_point(x + x_offset_2x2, y + y_offset_2x2, x_min, y_min, ratio, &bands, Precision::Double);
+        let sample_3x3 = should_draw_point(x + x_offset_3x3, y + y_offset_3x3, x_min, y_min, ratio, &bands, Precision::Double);
+        let sample_4x4 = should_draw_point(x + x_offset_4x4, y + y_offset_4x4, x_min, y_min, ratio, &bands, Precision::Double);
+        let sample_random = should_draw_point(x + x_offset, y + y_offset, x_min, y_min, ratio, &bands, Precision::Double);
 
         correct_samples_default += if sample_default == sample_random {1} else {0};
         correct_samples_2x2 += if sample_2x2 == sample_random {1} else {0};
@@ -164,46 +175,120 @@ pub fn get_render_score(test_character: char, font_path:&str, test_samples: i32)
 
 }
 
-fn should_draw_point(x: f32, y:f32, x_min: f32, y_min: f32, units_per_em:f32, segments: &Vec<Segment>) -> bool {
+pub fn get_precision_score(test_character: char, font_path: &str, face_selector: &FaceSelector, variations: &[Variation], hinting: HintingMode, test_samples: i32) -> PrecisionMetrics {
+    /* Classifies TEST_SAMPLES random points within the char's bbox in single
+    and double precision, timing each, and returns how often they agree */
+    let (contours, x_min, y_min, ratio, (x_pixels, y_pixels)) = load_test_glyph(test_character, font_path, face_selector, variations, hinting, test_ppem());
+    let bands = SegmentBands::new(&contours);
+    let mut rng = rand::thread_rng();
+    let points: Vec<(f32, f32)> = (0..test_samples).map(|_| {
+        (rng.gen::<f32>() * x_pixels as f32, rng.gen::<f32>() * y_pixels as f32)
+    }).collect();
+
+    let mut results = Vec::<(Vec<bool>, f32)>::new();
+    for precision in [Precision::Single, Precision::Double] {
+        let start = Instant::now();
+        let inside: Vec<bool> = points.iter().map(|(x, y)| should_draw_point(*x, *y, x_min, y_min, ratio, &bands, precision)).collect();
+        results.push((inside, start.elapsed().as_secs_f32()));
+    }
+    let agreeing = results[0].0.iter().zip(results[1].0.iter()).filter(|(single, double)| single == double).count();
+    return PrecisionMetrics {
+        agreement: agreeing as f32 / test_samples as f32,
+        single_seconds: results[0].1,
+        double_seconds: results[1].1,
+    };
+}
+
+pub fn get_scanline_score(test_character: char, font_path: &str, face_selector: &FaceSelector, variations: &[Variation], hinting: HintingMode, tolerance: f32) -> ScanlineMetrics {
+    /* Rasterizes the char at the test size by casting rays and with the
+    scanline rasterizer (flattened to within tolerance pixels), timing each,
+    and returns how many pixels come out the same */
+    let (contours, x_min, y_min, ratio, (x_pixels, y_pixels)) = load_test_glyph(test_character, font_path, face_selector, variations, hinting, test_ppem());
+
+    let start = Instant::now();
+    let bands = SegmentBands::new(&contours);
+    let rays = render_grayscale(x_pixels, y_pixels, SCANLINE_SAMPLES, |x, y| should_draw_point(x, y, x_min, y_min, ratio, &bands, Precision::Double));
+    let ray_seconds = start.elapsed().as_secs_f32();
+
+    let start = Instant::now();
+    let to_pixels = Transform::scale(ratio, ratio).then(&Transform::translate(-x_min, -y_min));
+    let table = EdgeTable::new(&flatten(&contours, tolerance, ratio), &to_pixels);
+    let scanlines = render_scanlines(x_pixels, y_pixels, SCANLINE_SAMPLES, &table, GLYPH_FILL);
+    let scanline_seconds = start.elapsed().as_secs_f32();
+
+    let agreeing = rays.data.iter().zip(scanlines.data.iter()).filter(|(ray, scanline)| ray == scanline).count();
+    return ScanlineMetrics {
+        agreement: agreeing as f32 / rays.data.len().max(1) as f32,
+        ray_seconds: ray_seconds,
+        scanline_seconds: scanline_seconds,
+    };
+}
+
+fn load_test_glyph(test_character: char, font_path: &str, face_selector: &FaceSelector, variations: &[Variation], hinting: HintingMode, ppem: f32) -> (Vec<Contour>, f32, f32, f32, (i32, i32)) {
+    // Outline of the char at ppem pixels per em, with its bbox origin, pixels per font unit and size in pixels
+    // Step 1: extract outline from font file
+    let file = fs::read(font_path).unwrap();
+    let face = match load_face(&file, face_selector) {
+        Ok(f) => f,
+        Err(e) => {
+            eprint!("Error: {}.", e);
+            std::process::exit(1);
+        }
+    };
+    let ratio = ppem / face.units_per_em() as f32;
+    let glyph_id = face.glyph_index(test_character).unwrap_or(GlyphId(0));
+    let key = FaceKey::new(font_path, face_selector);
+    let (contours, bbox) = HintingCache::default().fitted_outline(&key, &face, glyph_id, ppem, hinting, variations);
+
+    // Step 2: get bounding box for character
+    let bbox_unwrapped: Rect = match bbox {
+        Some(b) => b,
+        None => {
+            eprint!("Error: {:?} has no outline to measure.", test_character);
+            std::process::exit(1);
+        }
+    };
+    let x_min = bbox_unwrapped.x_min as f32;
+    // let x_max = bbox_unwrapped.x_max as f32;
+    let y_min = bbox_unwrapped.y_min as f32;
+    // let y_max = bbox_unwrapped.y_max as f32;
+    let width = bbox_unwrapped.width() as f32;
+    let height = bbox_unwrapped.height() as f32;
+    let (x_pixels, y_pixels) = get_glyph_size(height, width, ratio);
+    return (contours, x_min, y_min, ratio, (x_pixels, y_pixels));
+}
+
+fn should_draw_point(x: f32, y:f32, x_min: f32, y_min: f32, ratio: f32, bands: &SegmentBands, precision: Precision) -> bool {
     // https://developer.apple.com/fonts/TrueType-Reference-Manual/RM02/Chap2.html
-    let (mut x_units, mut y_units) = pixels_to_font_units(x, y, units_per_em);
+    let (mut x_units, mut y_units) = pixels_to_font_units(x, y, ratio);
     x_units += x_min;
     y_units += y_min;
-    let mut count: i32 = 0;
     // println!("Checking point ({:?}, {:?})", x_units, y_units);
 
-    for segment in segments.into_iter() {
-        count = count + segment.intersect(x_units, y_units, 1.0, 0.0);
-    }
+    let count = bands.winding_number(x_units, y_units, precision);
 
-    return count % 2 == 1;
-
-    // return count != 0;
+    return GLYPH_FILL.contains(count);
 }
 
-fn get_glyph_size(height: f32, width: f32, units_per_em:f32) -> (i32, i32) {
+fn get_glyph_size(height: f32, width: f32, ratio: f32) -> (i32, i32) {
     // Given the height and width of a glyph in font units, converts to the 
     // number of pixels (x, y) that need to be drawn on the display
 
-    let ratio = get_ratio(units_per_em);
-
     let x_pixels = (width * ratio).ceil() as i32;
     let y_pixels = (height * ratio).ceil() as i32;
     return (x_pixels, y_pixels);
 }
 
-fn pixels_to_font_units(x: f32, y: f32, units_per_em:f32) -> (f32, f32) {
-    let ratio = get_ratio(units_per_em);
-
+fn pixels_to_font_units(x: f32, y: f32, ratio: f32) -> (f32, f32) {
     return (x / ratio, y / ratio);
 }
 
-fn get_ratio(units_per_em: f32) -> f32 {
+fn test_ppem() -> f32 {
     // Scaling factor in formula, do not change
     const POINTS_PER_INCH: f32 = 72f32;
     
     // control output size via consts at top of file
-    return POINT_SIZE * RESOLUTION / (POINTS_PER_INCH * units_per_em);
+    return POINT_SIZE * RESOLUTION / POINTS_PER_INCH;
 }
 
 fn get_subpixel(x_offset: f32, y_offset: f32, n: i32) -> (f32, f32) {
@@ -1,87 +1,360 @@
 use ttf_parser::Face;
+use ttf_parser::GlyphId;
 use ttf_parser::Rect;
 
-use crate::segment::SegmentType;
-use crate::segment::Segment;
+use crate::curve::{Contour, Cubic, Curve, Element, Line, Orientation, Point, Quad};
 
-struct Builder(Vec<Segment>);
+use crate::variation::{apply_variations, Variation};
+
+// Pieces each curve is cut into when looking for contours that cross themselves
+const INTERSECTION_PIECES: usize = 16;
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {
+    pub(crate) contours: Vec<Contour>,
+    // Lines and curves drawn before any move_to, which belong to no contour and are left out
+    pub(crate) stray_segments: usize,
+    // Start of the current contour, and where the next line or curve starts
+    start: Point,
+    current: Point,
+}
+
+impl Builder {
+    fn push(&mut self, curve: Element) {
+        /* Adds a line or curve from the current point. Drawing on after a
+        close starts a new contour where the last one started, and drawing
+        before any move_to starts from (0, 0) like PostScript's pen; the
+        validation report (see Outline::validate) counts those. */
+        self.current = curve.evaluate(1f32);
+        if self.contours.last().is_some_and(|contour| contour.closed) {
+            self.contours.push(Contour { curves: Vec::new(), closed: false });
+        }
+        match self.contours.last_mut() {
+            Some(contour) => contour.curves.push(curve),
+            None => self.stray_segments += 1,
+        }
+    }
+
+    pub(crate) fn finish(self) -> Outline {
+        return Outline { contours: self.contours, stray_segments: self.stray_segments };
+    }
+}
 
 impl ttf_parser::OutlineBuilder for Builder {
     fn move_to(&mut self, x: f32, y: f32) {
-        let seg:Segment = Segment {
-            segment_type: SegmentType::Origin,
-            x_start: x, y_start: y,
-            x1: 0f32, y1: 0f32,
-            x2: 0f32, y2: 0f32,
-            x_end: x, y_end: y
-
-        };
-        self.0.push(seg);
+        self.contours.push(Contour { curves: Vec::new(), closed: false });
+        self.start = (x, y);
+        self.current = (x, y);
     }
-    fn line_to(&mut self, x: f32, y: f32) {
-        let last = self.0.get(self.0.len() - 1).unwrap();
-        let x_last = last.x_end;
-        let y_last = last.y_end;
-        let seg:Segment = Segment {
-            segment_type: SegmentType::Line,
-            x_start: x_last, y_start:y_last,
-            x1: 0f32, y1: 0f32,
-            x2: 0f32, y2: 0f32,
-            x_end: x, y_end: y
 
-        };
-        self.0.push(seg);
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(Element::Line(Line { from: self.current, to: (x, y) }));
     }
 
     fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
-        let last = self.0.get(self.0.len() - 1).unwrap();
-        let x_last = last.x_end;
-        let y_last = last.y_end;
-        let seg:Segment = Segment {
-            segment_type: SegmentType::Quad,
-            x_start: x_last, y_start:y_last,
-            x1: x1, y1: y1,
-            x2: 0f32, y2: 0f32,
-            x_end: x, y_end: y
-
-        };
-        self.0.push(seg);
+        self.push(Element::Quad(Quad { from: self.current, control: (x1, y1), to: (x, y) }));
     }
 
     fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
-        let last = self.0.get(self.0.len() - 1).unwrap();
-        let x_last = last.x_end;
-        let y_last = last.y_end;
-        let seg:Segment = Segment {
-            segment_type: SegmentType::Cubic,
-            x_start: x_last, y_start:y_last,
-            x1: x1, y1: y1,
-            x2: x2, y2: y2,
-            x_end: x, y_end: y
-
-        };
-        self.0.push(seg);
+        self.push(Element::Cubic(Cubic { from: self.current, control1: (x1, y1), control2: (x2, y2), to: (x, y) }));
     }
 
     fn close(&mut self) {
-        let seg:Segment = Segment {
-            segment_type: SegmentType::Close,
-            x_start: 0f32, y_start: 0f32,
-            x1: 0f32, y1: 0f32,
-            x2: 0f32, y2: 0f32,
-            x_end: 0f32, y_end: 0f32
-
-        };
-        self.0.push(seg);
-    }
-}
-
-pub fn extract_outline(face:&Face, code_point:char) -> (Vec::<Segment>, Option<Rect>) {
-    /* Given a face (parsed form of font file), returns a tuple containing the segments
-    the segments of the face and its bounding box. The given code point can be the char
-    of a character or a unicode symbol. */
-    let glyph_id = face.glyph_index(code_point).unwrap();
-    let mut glyph_builder = Builder(Vec::<Segment>::new());
-    let bbox = face.outline_glyph(glyph_id, &mut glyph_builder);
-    return (glyph_builder.0, bbox);
-}
\ No newline at end of file
+        // Ends the contour with a line back to its start if it doesn't end there already
+        if let Some(contour) = self.contours.last_mut() {
+            if !contour.closed && !contour.curves.is_empty() && self.current != self.start {
+                contour.curves.push(Element::Line(Line { from: self.current, to: self.start }));
+            }
+            contour.closed = true;
+        }
+        self.current = self.start;
+    }
+}
+
+pub fn extract_outline(face:&Face, code_point:char, variations: &[Variation]) -> (Vec::<Contour>, Option<Rect>) {
+    /* Given a face (parsed form of font file), returns a tuple containing the contours
+    of the glyph and its bounding box. The given code point can be the char
+    of a character or a unicode symbol. For variable fonts, the outline is taken at the
+    instance given by the variation settings (pass an empty slice for the default). */
+    // Characters missing from the font get the .notdef glyph (usually an empty box)
+    let glyph_id = face.glyph_index(code_point).unwrap_or(GlyphId(0));
+    return extract_glyph_outline(face, glyph_id, variations);
+}
+
+pub fn extract_glyph_outline(face:&Face, glyph_id:GlyphId, variations: &[Variation]) -> (Vec::<Contour>, Option<Rect>) {
+    // Same as extract_outline, for glyphs that aren't mapped to a character (e.g. color layers)
+    let (outline, bbox) = build_outline(face, glyph_id, variations);
+    return (outline.contours, bbox);
+}
+
+pub fn extract_checked_outline(face:&Face, code_point:char, variations: &[Variation]) -> Outline {
+    // Same as extract_outline, keeping count of what the font drew outside any contour for validate()
+    let glyph_id = face.glyph_index(code_point).unwrap_or(GlyphId(0));
+    return build_outline(face, glyph_id, variations).0;
+}
+
+fn build_outline(face:&Face, glyph_id:GlyphId, variations: &[Variation]) -> (Outline, Option<Rect>) {
+    let mut glyph_builder = Builder::default();
+    let bbox = if variations.is_empty() {
+        face.outline_glyph(glyph_id, &mut glyph_builder)
+    } else {
+        let mut instance = face.clone();
+        apply_variations(&mut instance, variations);
+        instance.outline_glyph(glyph_id, &mut glyph_builder)
+    };
+    return (glyph_builder.finish(), bbox);
+}
+
+pub fn extract_advance(face:&Face, code_point:char, variations: &[Variation]) -> Option<f32> {
+    // Horizontal advance of the glyph in font units, including HVAR deltas for variable fonts
+    let glyph_id = face.glyph_index(code_point)?;
+    if variations.is_empty() {
+        return face.glyph_hor_advance(glyph_id).map(|a| a as f32);
+    }
+    let mut instance = face.clone();
+    apply_variations(&mut instance, variations);
+    return instance.glyph_hor_advance(glyph_id).map(|a| a as f32);
+}
+
+/* An outline as its contours, for checking it over before it's drawn.
+Nothing stops a path from drawing before any move_to, leaving contours
+open or having curves that don't go anywhere, and the rasterizer takes
+whatever it's given; validate() says what's wrong with an outline. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline {
+    pub contours: Vec<Contour>,
+    // Lines and curves before the first move_to, which belong to no contour and are left out
+    pub stray_segments: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    // Lines or curves before the first move_to
+    MissingMoveTo { segments: usize },
+    // A contour that isn't closed (it's filled as if it were)
+    Unclosed { contour: usize },
+    // A line or curve with all its points in the same place
+    Degenerate { contour: usize, curve: usize },
+    // Two curves of a contour (or a curve and itself) crossing at about this point
+    SelfIntersection { contour: usize, curves: (usize, usize), at: Point },
+    // NaN or infinite coordinates
+    NonFinite { contour: usize, curve: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourReport {
+    pub curves: usize,
+    pub area: f32,
+    pub orientation: Orientation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+    pub contours: Vec<ContourReport>,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        return self.issues.is_empty();
+    }
+
+    pub fn can_rasterize(&self) -> bool {
+        /* Everything but coordinates that aren't numbers can be drawn: stray
+        segments are left out, open contours are closed, and crossings and
+        curves that go nowhere are just more edges to the crossing test */
+        return !self.issues.iter().any(|issue| matches!(issue, Issue::NonFinite { .. }));
+    }
+}
+
+impl Outline {
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::<Issue>::new();
+        if self.stray_segments > 0 {
+            issues.push(Issue::MissingMoveTo { segments: self.stray_segments });
+        }
+        let mut reports = Vec::<ContourReport>::new();
+        for (index, contour) in self.contours.iter().enumerate() {
+            if !contour.closed && contour.start() != contour.end() {
+                issues.push(Issue::Unclosed { contour: index });
+            }
+            let mut finite = true;
+            for (curve_index, curve) in contour.curves.iter().enumerate() {
+                let points = curve.points();
+                if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+                    issues.push(Issue::NonFinite { contour: index, curve: curve_index });
+                    finite = false;
+                } else if points.iter().all(|point| *point == points[0]) {
+                    issues.push(Issue::Degenerate { contour: index, curve: curve_index });
+                }
+            }
+            // Crossings can't be looked for (or areas worked out) with coordinates that aren't numbers
+            if finite {
+                issues.extend(self_intersections(contour).into_iter()
+                    .map(|(curves, at)| Issue::SelfIntersection { contour: index, curves: curves, at: at }));
+            }
+            reports.push(ContourReport { curves: contour.curves.len(), area: contour.signed_area(), orientation: contour.orientation() });
+        }
+        return ValidationReport { contours: reports, issues: issues };
+    }
+}
+
+pub fn is_finite(contours: &[Contour]) -> bool {
+    /* Whether every coordinate is a number, the one thing the rasterizer
+    can't cope with (see can_rasterize), without looking for the rest of
+    what validate() reports */
+    return contours.iter().flat_map(|contour| contour.curves.iter())
+        .all(|curve| curve.points().iter().all(|(x, y)| x.is_finite() && y.is_finite()));
+}
+
+pub fn close_contours(contours: &mut Vec<Contour>) {
+    // Closes contours left open (which are filled as if they were closed) and drops empty ones
+    contours.retain(|contour| !contour.curves.is_empty());
+    for contour in contours.iter_mut() {
+        contour.close();
+    }
+}
+
+fn self_intersections(contour: &Contour) -> Vec<((usize, usize), Point)> {
+    /* Where curves of the contour cross each other (or themselves), found on
+    each curve cut into straight pieces. Only pieces that properly cross
+    count: curves meeting end to end touch without crossing. */
+    let polylines: Vec<Vec<Point>> = contour.curves.iter().map(|curve| match curve {
+        Element::Line(line) => vec![line.from, line.to],
+        _ => (0..=INTERSECTION_PIECES).map(|i| curve.evaluate(i as f32 / INTERSECTION_PIECES as f32)).collect(),
+    }).collect();
+    let bounds: Vec<(f32, f32, f32, f32)> = contour.curves.iter().map(|curve| curve.bounds()).collect();
+    let mut found = Vec::<((usize, usize), Point)>::new();
+    for i in 0..polylines.len() {
+        for j in i..polylines.len() {
+            let (a, b) = (bounds[i], bounds[j]);
+            if a.0 > b.2 || b.0 > a.2 || a.1 > b.3 || b.1 > a.3 {
+                continue;
+            }
+            let crossing = polylines[i].windows(2).enumerate().find_map(|(k, first)| {
+                // A curve's own pieces only cross if they aren't next to each other
+                let skip = if i == j { k + 2 } else { 0 };
+                polylines[j].windows(2).skip(skip).find_map(|second| segment_crossing(first[0], first[1], second[0], second[1]))
+            });
+            if let Some(at) = crossing {
+                found.push(((i, j), at));
+            }
+        }
+    }
+    return found;
+}
+
+fn segment_crossing(a: Point, b: Point, c: Point, d: Point) -> Option<Point> {
+    // Where line a-b crosses line c-d, if each one's ends are strictly on opposite sides of the other
+    let side = |p: Point, q: Point, r: Point| (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0);
+    let (c_side, d_side) = (side(a, b, c), side(a, b, d));
+    let (a_side, b_side) = (side(c, d, a), side(c, d, b));
+    if c_side * d_side >= 0f32 || a_side * b_side >= 0f32 {
+        return None;
+    }
+    let t = a_side / (a_side - b_side);
+    return Some((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::OutlineBuilder;
+
+    use crate::curve::Orientation;
+    use super::{close_contours, is_finite, Builder, Issue, Outline};
+
+    fn outline(commands: &[(char, &[f32])]) -> Outline {
+        let mut builder = Builder::default();
+        for (command, p) in commands.iter() {
+            match command {
+                'M' => builder.move_to(p[0], p[1]),
+                'L' => builder.line_to(p[0], p[1]),
+                'Q' => builder.quad_to(p[0], p[1], p[2], p[3]),
+                _ => builder.close(),
+            }
+        }
+        return builder.finish();
+    }
+
+    const SQUARE: [(char, &[f32]); 5] = [('M', &[0f32, 0f32]), ('L', &[10f32, 0f32]), ('L', &[10f32, 10f32]), ('L', &[0f32, 10f32]), ('Z', &[])];
+
+    #[test]
+    fn square_is_valid() {
+        let report = outline(&SQUARE).validate();
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert_eq!(report.contours.len(), 1);
+        // The close adds the fourth side
+        assert_eq!(report.contours[0].curves, 4);
+        assert!((report.contours[0].area - 100f32).abs() < 1e-3f32);
+    }
+
+    #[test]
+    fn missing_move_to() {
+        let mut commands = vec![('L', &[5f32, 5f32][..])];
+        commands.extend_from_slice(&SQUARE);
+        let outline = outline(&commands);
+        assert_eq!(outline.validate().issues, vec![Issue::MissingMoveTo { segments: 1 }]);
+        // The stray line is left out of the contours
+        assert_eq!(outline.contours.len(), 1);
+        assert!(outline.validate().can_rasterize());
+    }
+
+    #[test]
+    fn unclosed() {
+        let mut outline = outline(&SQUARE[..4]);
+        let report = outline.validate();
+        assert_eq!(report.issues, vec![Issue::Unclosed { contour: 0 }]);
+        assert!(report.can_rasterize());
+        // Closing it adds the missing side
+        close_contours(&mut outline.contours);
+        assert!(outline.validate().is_valid());
+        assert_eq!(outline.contours[0].curves.len(), 4);
+    }
+
+    #[test]
+    fn degenerate() {
+        // A line that stays put and a quad with every point in the same place
+        let report = outline(&[('M', &[0f32, 0f32]), ('L', &[10f32, 0f32]), ('L', &[10f32, 0f32]), ('Q', &[10f32, 0f32, 10f32, 0f32]), ('L', &[10f32, 10f32]), ('Z', &[])]).validate();
+        assert_eq!(report.issues, vec![Issue::Degenerate { contour: 0, curve: 1 }, Issue::Degenerate { contour: 0, curve: 2 }]);
+        assert!(report.can_rasterize());
+    }
+
+    #[test]
+    fn self_intersection() {
+        // A bow tie crosses itself in the middle, and its two halves cancel out
+        let report = outline(&[('M', &[0f32, 0f32]), ('L', &[10f32, 10f32]), ('L', &[10f32, 0f32]), ('L', &[0f32, 10f32]), ('Z', &[])]).validate();
+        assert_eq!(report.issues, vec![Issue::SelfIntersection { contour: 0, curves: (0, 2), at: (5f32, 5f32) }]);
+        assert_eq!(report.contours[0].orientation, Orientation::Degenerate);
+        assert!(report.can_rasterize());
+        // Curves that only meet end to end don't count, even when they fold back on each other
+        let report = outline(&[('M', &[0f32, 0f32]), ('Q', &[10f32, 10f32, 20f32, 0f32]), ('Q', &[10f32, 5f32, 0f32, 0f32]), ('Z', &[])]).validate();
+        assert!(report.is_valid(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn non_finite() {
+        let broken = outline(&[('M', &[0f32, 0f32]), ('L', &[10f32, 0f32]), ('L', &[f32::NAN, 10f32]), ('L', &[0f32, f32::INFINITY]), ('Z', &[])]);
+        let report = broken.validate();
+        assert_eq!(report.issues[..3], [Issue::NonFinite { contour: 0, curve: 1 }, Issue::NonFinite { contour: 0, curve: 2 }, Issue::NonFinite { contour: 0, curve: 3 }]);
+        assert!(!report.can_rasterize());
+        assert!(!is_finite(&broken.contours));
+        assert!(is_finite(&outline(&SQUARE).contours));
+    }
+
+    #[test]
+    fn orientation() {
+        let counter_clockwise = outline(&SQUARE);
+        assert_eq!(counter_clockwise.contours[0].orientation(), Orientation::CounterClockwise);
+        let clockwise = outline(&[('M', &[0f32, 0f32]), ('L', &[0f32, 10f32]), ('L', &[10f32, 10f32]), ('L', &[10f32, 0f32]), ('Z', &[])]);
+        assert_eq!(clockwise.contours[0].orientation(), Orientation::Clockwise);
+        assert!((clockwise.contours[0].signed_area() + 100f32).abs() < 1e-3f32);
+        // Curves count with their bulge: a quad bowing out of the square adds two thirds of its triangle
+        let bulging = outline(&[('M', &[0f32, 0f32]), ('Q', &[5f32, -10f32, 10f32, 0f32]), ('L', &[10f32, 10f32]), ('L', &[0f32, 10f32]), ('Z', &[])]);
+        assert!((bulging.contours[0].signed_area() - (100f32 + 100f32 / 3f32)).abs() < 1e-3f32);
+        // Out and back along a line encloses nothing
+        let line = outline(&[('M', &[0f32, 0f32]), ('L', &[10f32, 0f32]), ('Z', &[])]);
+        assert_eq!(line.contours[0].orientation(), Orientation::Degenerate);
+        let empty = outline(&[('M', &[0f32, 0f32]), ('Z', &[])]);
+        assert_eq!(empty.contours[0].orientation(), Orientation::Degenerate);
+    }
+}
@@ -0,0 +1,186 @@
+/* A small thread pool for rendering. Its threads are started the first time
+they're needed and then wait for work for the rest of the run, so drawing a
+frame doesn't start any. Work items are handed out one at a time from a
+shared counter, so threads that get quick items (small glyphs, empty tiles)
+go back for more instead of waiting on the slow ones, and results come back
+in the order of the items whatever thread did them, which keeps drawing
+deterministic. A panic in the work is caught where it happens, so the
+thread lives on, and raised again on the calling thread once the other
+items are done. */
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+pub fn thread_count(setting: usize) -> usize {
+    // 0 means one thread per core
+    if setting > 0 {
+        return setting;
+    }
+    return thread::available_parallelism().map_or(1, |count| count.get());
+}
+
+pub struct ThreadPool {
+    // Taken on drop, which tells the workers to stop
+    tasks: Option<Sender<Task>>,
+    // Shared between the workers, the free one takes the next task
+    queue: Arc<Mutex<Receiver<Task>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Default for ThreadPool {
+    fn default() -> Self {
+        let (tasks, queue) = channel();
+        return ThreadPool { tasks: Some(tasks), queue: Arc::new(Mutex::new(queue)), workers: Vec::new() };
+    }
+}
+
+impl ThreadPool {
+    pub fn map<T, R, F>(&mut self, items: Vec<T>, threads: usize, work: F) -> Vec<R>
+    where
+        T: Send + Sync + 'static,
+        R: Send + 'static,
+        F: Fn(&T) -> R + Send + Sync + 'static,
+    {
+        // work applied to every item on up to threads threads (this one included), results in item order
+        let threads = threads.min(items.len());
+        if threads <= 1 {
+            return items.iter().map(work).collect();
+        }
+        while self.workers.len() < threads - 1 {
+            let queue = self.queue.clone();
+            self.workers.push(thread::spawn(move || {
+                loop {
+                    // The lock is let go before the task runs
+                    let task = match queue.lock().unwrap().recv() {
+                        Ok(task) => task,
+                        Err(_) => break,
+                    };
+                    task();
+                }
+            }));
+        }
+        let count = items.len();
+        let (items, work, next) = (Arc::new(items), Arc::new(work), Arc::new(AtomicUsize::new(0)));
+        let (results, finished) = channel();
+        for _ in 1..threads {
+            let (items, work, next, results) = (items.clone(), work.clone(), next.clone(), results.clone());
+            self.tasks.as_ref().unwrap().send(Box::new(move || run(&items, &*work, &next, &results))).unwrap();
+        }
+        run(&items, &*work, &next, &results);
+        // Every result is in once the workers' senders are gone too
+        drop(results);
+        let mut ordered: Vec<Option<thread::Result<R>>> = (0..count).map(|_| None).collect();
+        for (index, result) in finished {
+            ordered[index] = Some(result);
+        }
+        let mut results = Vec::<R>::with_capacity(count);
+        for result in ordered {
+            match result.expect("every item is taken by some thread") {
+                Ok(result) => results.push(result),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        return results;
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.tasks = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run<T, R>(items: &[T], work: &impl Fn(&T) -> R, next: &AtomicUsize, results: &Sender<(usize, thread::Result<R>)>) {
+    // Items from the shared counter until there are none left
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        if index >= items.len() {
+            break;
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| work(&items[index])));
+        results.send((index, result)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+
+    use crate::bands::SegmentBands;
+    use crate::fixtures::bundled_fonts;
+    use crate::flatten::flatten;
+    use crate::float::Precision;
+    use crate::raster::{render_grayscale, render_grayscale_rows, stitch, tiles, Coverage};
+    use crate::scanline::{render_scanline_rows, render_scanlines, EdgeTable, FillRule};
+    use crate::transform::Transform;
+    use super::ThreadPool;
+
+    const SIZE: f32 = 60f32;
+    const SAMPLES: i32 = 3;
+    const GLYPHS_PER_FONT: u16 = 40;
+    const TILE_ROWS: i32 = 7;
+
+    #[test]
+    fn results_come_back_in_order() {
+        // From the same pool each time, which only ever starts as many threads as it's asked for
+        let mut pool = ThreadPool::default();
+        let items: Vec<usize> = (0..1000).collect();
+        for threads in [1, 2, 8, 3] {
+            assert_eq!(pool.map(items.clone(), threads, |item| item * 2), items.iter().map(|item| item * 2).collect::<Vec<usize>>());
+        }
+        assert_eq!(pool.workers.len(), 7);
+    }
+
+    #[test]
+    fn panics_reach_the_caller() {
+        // The panic comes out of map with its message, and the pool still works afterwards
+        let mut pool = ThreadPool::default();
+        let items: Vec<usize> = (0..100).collect();
+        for _ in 0..3 {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| pool.map(items.clone(), 4, |item| if *item % 50 == 7 { panic!("item {}", item) } else { *item })));
+            let payload = result.unwrap_err();
+            assert_eq!(payload.downcast_ref::<String>().map(String::as_str), Some("item 7"));
+            assert_eq!(pool.map(items.clone(), 4, |item| item + 1), (1..101).collect::<Vec<usize>>());
+        }
+        assert_eq!(pool.workers.len(), 3);
+    }
+
+    #[test]
+    fn tiles_on_threads_match_one_pass() {
+        // Every bit of the coverage, for both rasterizers and with tiles shared out between any number of threads
+        let mut pool = ThreadPool::default();
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            let ratio = SIZE / font.units_per_em;
+            for glyph in font.glyphs.iter() {
+                let (contours, bbox) = (&glyph.contours, glyph.bbox);
+                let (x_min, y_min) = (bbox.x_min as f32, bbox.y_min as f32);
+                let (width, height) = ((bbox.width() as f32 * ratio).ceil() as i32, (bbox.height() as f32 * ratio).ceil() as i32);
+                // Shared with the pool's threads, which outlive the glyph
+                let to_pixels = Transform::scale(ratio, ratio).then(&Transform::translate(-x_min, -y_min));
+                let (bands, table) = (Arc::new(SegmentBands::new(contours)), Arc::new(EdgeTable::new(&flatten(contours, 0.1f32, ratio), &to_pixels)));
+                let inside = move |bands: &SegmentBands, x: f32, y: f32| bands.winding_number(x / ratio + x_min, y / ratio + y_min, Precision::Double) % 2 != 0;
+                let rays = render_grayscale(width, height, SAMPLES, |x, y| inside(&bands, x, y));
+                let scanlines = render_scanlines(width, height, SAMPLES, &table, FillRule::EvenOdd);
+                let rows = tiles(height, TILE_ROWS);
+                for threads in [1, 2, 5] {
+                    let (bands, table) = (bands.clone(), table.clone());
+                    let tiled_rays: Vec<Coverage> = pool.map(rows.clone(), threads, move |rows| render_grayscale_rows(width, rows.clone(), SAMPLES, |x, y| inside(&bands, x, y)));
+                    let tiled_scanlines: Vec<Coverage> = pool.map(rows.clone(), threads, move |rows| render_scanline_rows(width, rows.clone(), SAMPLES, &table, FillRule::EvenOdd));
+                    for (whole, tiled) in [(&rays, stitch(tiled_rays)), (&scanlines, stitch(tiled_scanlines))] {
+                        assert_eq!((tiled.y_min, tiled.width, tiled.height), (whole.y_min, whole.width, whole.height));
+                        assert!(tiled.data == whole.data, "{} glyph {} differs on {} threads", font.path.display(), glyph.id, threads);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+/* Real roots of quadratics and cubics, for where curves cross rays and
+where they turn around. The textbook formulas lose the small root of a
+quadratic to cancellation when b^2 is much bigger than 4ac, and fall apart
+when the leading coefficient is almost zero (a curve that is nearly a line
+or a quad), which happens a lot with font outlines: control points close to
+the end points, or nearly collinear with them. The quadratic formula is
+used in the form that avoids cancellation (citardauq), cubics that are
+nearly quadratic are solved as quadratics, and cubic roots are polished
+with Newton steps on the original polynomial. */
+
+use crate::float::Float;
+
+// Newton steps to polish each root with
+const POLISH_STEPS: u32 = 3;
+
+pub fn solve_quadratic<F: Float>(a: F, b: F, c: F) -> Vec<F> {
+    // Real roots of a t^2 + b t + c in increasing order (a double root is returned once)
+    if a == F::ZERO {
+        if b == F::ZERO {
+            return Vec::new();
+        }
+        return vec![-c / b];
+    }
+    let mut discriminant = b * b - F::from_f32(4f32) * a * c;
+    if discriminant < F::ZERO {
+        // Rounding can push a double root just below zero
+        if discriminant < -F::from_f32(8f32) * F::EPSILON * b * b {
+            return Vec::new();
+        }
+        discriminant = F::ZERO;
+    }
+    // q has the same sign as b, so nothing cancels, and the roots are q / a and c / q
+    let q = -(b + discriminant.sqrt().copysign(b)) / F::from_f32(2f32);
+    if q == F::ZERO {
+        // b and c are both zero
+        return vec![F::ZERO];
+    }
+    let (r1, r2) = (q / a, c / q);
+    let mut roots = if r1 < r2 { vec![r1, r2] } else { vec![r2, r1] };
+    if roots[0] == roots[1] {
+        roots.pop();
+    }
+    return roots;
+}
+
+pub fn solve_cubic<F: Float>(a: F, b: F, c: F, d: F) -> Vec<F> {
+    // Real roots of a t^3 + b t^2 + c t + d in increasing order
+    let scale = b.abs().max(c.abs()).max(d.abs());
+    if a.abs() <= F::EPSILON.cbrt() * scale {
+        /* Nearly a quadratic, which Cardano's formula can't cope with: it
+        shifts t by b / 3a and the roots get lost in the rounding. The
+        cubic has the quadratic's roots, moved a little (which polishing
+        fixes), and one more root that's left out: a that small puts it at
+        least (1 / a)^(1/3) times further out, far past the 0 to 1 that
+        curves care about. */
+        return solve_quadratic(b, c, d).into_iter().map(|t| polish(a, b, c, d, t)).collect();
+    }
+
+    // t = x - b / 3a takes it to x^3 + p x + q
+    let (b, c, d) = (b / a, c / a, d / a);
+    let three = F::from_f32(3f32);
+    let shift = b / three;
+    let p = c - b * b / three;
+    let q = F::from_f32(2f32) * shift * shift * shift - shift * c + d;
+    let half_q = q / F::from_f32(2f32);
+    let third_p = p / three;
+    let mut discriminant = half_q * half_q + third_p * third_p * third_p;
+    // Rounding in p and q (from the terms they're worked out from) can push a double root just above zero
+    let p_error = F::EPSILON * (c.abs() + (b * b / three).abs());
+    let q_error = F::EPSILON * (F::from_f32(2f32) * (shift * shift * shift).abs() + (shift * c).abs() + d.abs());
+    if discriminant > F::ZERO && discriminant <= F::from_f32(4f32) * (half_q.abs() * q_error + third_p * third_p * p_error) {
+        discriminant = F::ZERO;
+    }
+
+    let mut roots = Vec::<F>::new();
+    if p == F::ZERO && q == F::ZERO {
+        roots.push(-shift);
+    } else if discriminant > F::ZERO {
+        // One real root, Cardano's formula with the bigger cube root taken first so the pair doesn't cancel
+        let u = -(half_q.abs() + discriminant.sqrt()).cbrt().copysign(half_q);
+        let v = if u != F::ZERO { -third_p / u } else { F::ZERO };
+        roots.push(u + v - shift);
+    } else {
+        // Three real roots (two or more of them equal when the discriminant is zero)
+        let r = (-third_p).sqrt();
+        let cosine = (-half_q / (r * r * r)).max(-F::ONE).min(F::ONE);
+        let phi = cosine.acos();
+        for k in 0..3 {
+            let angle = (phi + F::from_f32(2f32 * k as f32) * F::PI) / three;
+            roots.push(F::from_f32(2f32) * r * angle.cos() - shift);
+        }
+    }
+    let mut roots: Vec<F> = roots.into_iter().map(|t| polish(F::ONE, b, c, d, t)).collect();
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+    roots.dedup();
+    return roots;
+}
+
+fn polish<F: Float>(a: F, b: F, c: F, d: F, mut t: F) -> F {
+    // Newton steps on a t^3 + b t^2 + c t + d, kept only while they get closer to zero
+    let value = |t: F| ((a * t + b) * t + c) * t + d;
+    for _ in 0..POLISH_STEPS {
+        let slope = (F::from_f32(3f32) * a * t + F::from_f32(2f32) * b) * t + c;
+        if slope == F::ZERO {
+            break;
+        }
+        let next = t - value(t) / slope;
+        if value(next).abs() >= value(t).abs() {
+            break;
+        }
+        t = next;
+    }
+    return t;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{solve_cubic, solve_quadratic};
+
+    fn assert_roots(found: &[f64], expected: &[f64], tolerance: f64) {
+        // Every root expected is found and nothing else (a double root may come back as two close ones)
+        let near = |a: f64, roots: &[f64]| roots.iter().any(|b| (a - b).abs() <= tolerance * b.abs().max(1f64));
+        assert!(expected.iter().all(|t| near(*t, found)) && found.iter().all(|t| near(*t, expected)), "found {:?}, expected {:?}", found, expected);
+        assert!(found.windows(2).all(|pair| pair[0] <= pair[1]), "{:?} out of order", found);
+    }
+
+    #[test]
+    fn quadratics() {
+        assert_roots(&solve_quadratic(1f64, -3f64, 2f64), &[1f64, 2f64], 1e-12);
+        assert_roots(&solve_quadratic(-2f64, 0f64, 0.5f64), &[-0.5f64, 0.5f64], 1e-12);
+        // A line, nothing at all, and no real roots
+        assert_eq!(solve_quadratic(0f64, 2f64, -1f64), vec![0.5f64]);
+        assert!(solve_quadratic(0f64, 0f64, 1f64).is_empty());
+        assert!(solve_quadratic(1f64, 0f64, 1f64).is_empty());
+        // Double root, returned once, also when rounding puts the discriminant just below zero
+        assert_eq!(solve_quadratic(1f64, -1f64, 0.25f64), vec![0.5f64]);
+        let (a, b) = (0.1f32, -0.2f32);
+        assert_roots(&solve_quadratic(a, b, b * b / (4f32 * a)).iter().map(|t| *t as f64).collect::<Vec<f64>>(), &[1f64], 1e-3);
+    }
+
+    #[test]
+    fn quadratic_small_root_survives_cancellation() {
+        // b^2 is so much bigger than 4ac that -b + sqrt(b^2 - 4ac) would come out as zero
+        let roots = solve_quadratic(1f64, -1e8f64, 1f64);
+        assert_roots(&roots, &[1e-8f64, 1e8f64], 1e-12);
+        let roots = solve_quadratic(1f32, -1e4f32, 1f32);
+        assert!((roots[0] - 1e-4f32).abs() < 1e-9f32, "{:?}", roots);
+    }
+
+    #[test]
+    fn cubics() {
+        // (t - 0.1)(t - 0.5)(t - 0.9), one real root, and a triple root
+        assert_roots(&solve_cubic(1f64, -1.5f64, 0.59f64, -0.045f64), &[0.1f64, 0.5f64, 0.9f64], 1e-12);
+        assert_roots(&solve_cubic(1f64, 0f64, 1f64, -2f64), &[1f64], 1e-12);
+        assert_roots(&solve_cubic(1f64, -1.5f64, 0.75f64, -0.125f64), &[0.5f64], 1e-5);
+        // (t - 1)^2 (t - 2), with the double root returned once or as two close ones
+        assert_roots(&solve_cubic(1f64, -4f64, 5f64, -2f64), &[1f64, 2f64], 1e-6);
+        assert_roots(&solve_cubic(1f32, -4f32, 5f32, -2f32).iter().map(|t| *t as f64).collect::<Vec<f64>>(), &[1f64, 2f64], 1e-3);
+        // Roots six orders of magnitude apart: 1e-3, 1 and 1000
+        assert_roots(&solve_cubic(1f64, -1001.001f64, 1001.001f64, -1f64), &[1e-3f64, 1f64, 1000f64], 1e-9);
+    }
+
+    #[test]
+    fn nearly_quadratic_cubics() {
+        // a too small for Cardano's formula: the quadratic's roots, and the far off third one left out
+        assert_roots(&solve_cubic(1e-12f64, 1f64, -3f64, 2f64), &[1f64, 2f64], 1e-9);
+        assert_roots(&solve_cubic(-1e-7f32, 1f32, -1f32, 0.21f32).iter().map(|t| *t as f64).collect::<Vec<f64>>(), &[0.3f64, 0.7f64], 1e-4);
+        // All the way down to a line and a constant
+        assert_roots(&solve_cubic(0f64, 0f64, 4f64, -1f64), &[0.25f64], 1e-12);
+        assert!(solve_cubic(0f64, 0f64, 0f64, 1f64).is_empty());
+    }
+
+    #[test]
+    fn random_cubics_in_f32_match_f64() {
+        // Cubics with three known roots in 0 to 1, solved in f32
+        let mut rng = StdRng::seed_from_u64(43);
+        for _ in 0..10000 {
+            let mut expected: Vec<f64> = (0..3).map(|_| rng.gen_range(0f64..1f64)).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // Keep them apart, as roots closer than about sqrt(epsilon) can't be told apart in f32
+            if expected.windows(2).any(|pair| pair[1] - pair[0] < 1e-2f64) {
+                continue;
+            }
+            let (r, s, t) = (expected[0], expected[1], expected[2]);
+            let scale = rng.gen_range(0.1f64..100f64);
+            let (a, b, c, d) = (scale, -scale * (r + s + t), scale * (r * s + s * t + r * t), -scale * r * s * t);
+            let found: Vec<f64> = solve_cubic(a as f32, b as f32, c as f32, d as f32).iter().map(|t| *t as f64).collect();
+            assert_roots(&found, &expected, 1e-3);
+        }
+    }
+}
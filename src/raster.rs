@@ -0,0 +1,77 @@
+/* Coverage bitmaps for outline glyphs. Coverage is sampled on a regular
+grid inside each pixel (the supersampling modes), the LCD variant lives in
+lcd.rs. Rows don't depend on each other, so a glyph can be rendered as bands
+of rows (tiles) on different threads and stitched back together. */
+
+use std::ops::Range;
+
+use crate::simd::{counts_to_coverage, detect};
+
+pub struct Coverage {
+    // Pixel position of the first column and row, relative to the sampled area
+    pub x_min: i32,
+    pub y_min: i32,
+    pub width: i32,
+    pub height: i32,
+    // Red, green and blue coverage (all the same for grayscale), rows from the bottom up
+    pub data: Vec<[f32; 3]>,
+}
+
+impl Coverage {
+    pub fn get(&self, x: i32, y: i32) -> [f32; 3] {
+        return self.data[(y * self.width + x) as usize];
+    }
+}
+
+pub fn tiles(height: i32, tile_rows: i32) -> Vec<Range<i32>> {
+    // Rows 0 to height in bands of tile_rows (the last one can be shorter), at least one band
+    if height <= tile_rows {
+        return std::iter::once(0..height).collect();
+    }
+    return (0..height).step_by(tile_rows as usize).map(|start| start..(start + tile_rows).min(height)).collect();
+}
+
+pub fn stitch(tiles: Vec<Coverage>) -> Coverage {
+    // Tiles rendered from consecutive bands of rows, bottom first, back into one coverage bitmap
+    let mut tiles = tiles.into_iter();
+    let mut coverage = tiles.next().unwrap();
+    for tile in tiles {
+        coverage.height += tile.height;
+        coverage.data.extend(tile.data);
+    }
+    return coverage;
+}
+
+pub fn render_grayscale(width: i32, height: i32, samples: i32, inside: impl Fn(f32, f32) -> bool) -> Coverage {
+    /* Samples a width by height pixel area with samples by samples points per
+    pixel (just the centre for 1). inside takes pixel coordinates with y
+    going up. */
+    return render_grayscale_rows(width, 0..height, samples, inside);
+}
+
+pub fn render_grayscale_rows(width: i32, rows: Range<i32>, samples: i32, inside: impl Fn(f32, f32) -> bool) -> Coverage {
+    // Same as render_grayscale for just some rows of the area, which the coverage starts at
+    let height = rows.end - rows.start;
+    let mut data = vec![[0f32; 3]; (width * height) as usize];
+    let step = 1f32 / samples as f32;
+    let simd = detect();
+    let mut counts = vec![0; width as usize];
+    for y in rows.clone() {
+        for x in 0..width {
+            let mut count = 0;
+            for x_subsample in 0..samples {
+                for y_subsample in 0..samples {
+                    let x_coord = x as f32 + (x_subsample as f32 + 0.5f32) * step;
+                    let y_coord = y as f32 + (y_subsample as f32 + 0.5f32) * step;
+                    if inside(x_coord, y_coord) {
+                        count += 1;
+                    }
+                }
+            }
+            counts[x as usize] = count;
+        }
+        let row = ((y - rows.start) * width) as usize;
+        counts_to_coverage(&counts, samples, &mut data[row..row + width as usize], simd);
+    }
+    return Coverage { x_min: 0, y_min: rows.start, width, height, data };
+}
@@ -0,0 +1,26 @@
+/* Big-endian readers for the font tables we parse by hand. They all return
+None instead of panicking when the offset runs past the end of the data. */
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    return Some(u16::from_be_bytes([bytes[0], bytes[1]]));
+}
+
+pub fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    return Some(read_u16(data, offset)? as i16);
+}
+
+pub fn read_u24(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    return Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]));
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    return Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+}
+
+pub fn read_f2dot14(data: &[u8], offset: usize) -> Option<f32> {
+    // Signed 2.14 fixed point
+    return Some(read_i16(data, offset)? as f32 / 16384f32);
+}
@@ -0,0 +1,330 @@
+/* Scanline rasterizer: instead of casting a ray against every segment for
+every sample, the outline is flattened (see flatten.rs) into an edge table
+sorted by where each edge starts, and each row of samples is swept with the
+list of edges it crosses (the active edges), which is only updated as edges
+start and end. Samples are the same grid render_grayscale uses, and a sample
+counts the edges crossing its row strictly to its right, from the bottom end
+included up to the top end excluded, like the ray test does (see
+curve::winding_number), so with a polygon it gets exactly the same answers and
+with curves it only differs within the flattening tolerance of the outline. */
+
+use std::ops::Range;
+
+use crate::flatten::Polyline;
+use crate::raster::Coverage;
+use crate::simd::{add_one, counts_to_coverage, detect, Simd};
+use crate::transform::Transform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rasterizer {
+    // A ray per sample against the curves themselves
+    RayCasting,
+    // Rows of samples swept with an active edge list
+    Scanline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillRule {
+    // Inside where an odd number of edges are crossed (SVG fill-rule="evenodd")
+    EvenOdd,
+    // Inside where edges going up and down don't cancel out (glyphs and strokes, which overlap themselves)
+    NonZero,
+}
+
+/* TrueType and CFF both fill with the non-zero rule, so contours that overlap
+   (including ones synthetic bold has grown into each other) stay filled */
+pub const GLYPH_FILL: FillRule = FillRule::NonZero;
+
+impl FillRule {
+    pub fn contains(self, winding: i32) -> bool {
+        return match self {
+            FillRule::EvenOdd => winding % 2 != 0,
+            FillRule::NonZero => winding != 0,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    // The edge goes from y_min (included) to y_max (excluded)
+    y_min: f32,
+    y_max: f32,
+    // x at y_min, and how far x moves per unit of y
+    x: f32,
+    slope: f32,
+    // 1 going up, -1 going down
+    direction: i32,
+}
+
+pub struct EdgeTable {
+    // Sorted by y_min
+    edges: Vec<Edge>,
+}
+
+impl EdgeTable {
+    pub fn new(polylines: &[Polyline], transform: &Transform) -> EdgeTable {
+        // Edges of the polylines moved into pixel space by transform. Horizontal edges are never crossed and left out.
+        let mut edges = Vec::<Edge>::new();
+        for polyline in polylines.iter() {
+            let points: Vec<(f32, f32)> = polyline.points.iter().map(|(x, y)| transform.apply(*x, *y)).collect();
+            for pair in points.windows(2) {
+                let (start, end) = (pair[0], pair[1]);
+                if start.1 == end.1 {
+                    continue;
+                }
+                let (bottom, top, direction) = if start.1 < end.1 { (start, end, 1) } else { (end, start, -1) };
+                edges.push(Edge {
+                    y_min: bottom.1,
+                    y_max: top.1,
+                    x: bottom.0,
+                    slope: (top.0 - bottom.0) / (top.1 - bottom.1),
+                    direction: direction,
+                });
+            }
+        }
+        edges.sort_by(|a, b| a.y_min.partial_cmp(&b.y_min).unwrap_or(std::cmp::Ordering::Equal));
+        return EdgeTable { edges: edges };
+    }
+}
+
+pub fn render_scanlines(width: i32, height: i32, samples: i32, table: &EdgeTable, rule: FillRule) -> Coverage {
+    /* Coverage of a width by height pixel area with samples by samples
+    points per pixel, like render_grayscale, for an edge table in pixel
+    coordinates with y going up */
+    return render_scanline_rows(width, 0..height, samples, table, rule);
+}
+
+pub fn render_scanline_rows(width: i32, rows: Range<i32>, samples: i32, table: &EdgeTable, rule: FillRule) -> Coverage {
+    /* Same as render_scanlines for just some rows of the area, which the
+    coverage starts at. The active list for the first row is built from
+    scratch, so the rows come out the same as when the whole area is swept. */
+    return sweep(width, rows, samples, table, rule, detect());
+}
+
+fn sweep(width: i32, rows: Range<i32>, samples: i32, table: &EdgeTable, rule: FillRule, simd: Simd) -> Coverage {
+    /* Between two crossings every sample of a row is on the same side of the
+    outline, so the samples are counted a run at a time: for each run
+    inside, each x_subsample adds one to the pixels whose sample there falls
+    in the run. */
+    let height = rows.end - rows.start;
+    let mut data = vec![[0f32; 3]; (width * height) as usize];
+    let step = 1f32 / samples as f32;
+    let mut next = 0;
+    let mut active = Vec::<Edge>::new();
+    let mut crossings = Vec::<(f32, i32)>::new();
+    let mut counts = vec![0; width as usize];
+    // The first pixel whose sample is at or right of each crossing, for each x_subsample in turn
+    let mut starts = Vec::<usize>::new();
+    for y in rows.clone() {
+        counts.iter_mut().for_each(|count| *count = 0);
+        for y_subsample in 0..samples {
+            let y_coord = y as f32 + (y_subsample as f32 + 0.5f32) * step;
+            // Rows go up, so edges only ever join the active list in table order, and leave it once they end below the row
+            while next < table.edges.len() && table.edges[next].y_min <= y_coord {
+                active.push(table.edges[next]);
+                next += 1;
+            }
+            active.retain(|edge| edge.y_max > y_coord);
+
+            crossings.clear();
+            crossings.extend(active.iter().map(|edge| (edge.x + (y_coord - edge.y_min) * edge.slope, edge.direction)));
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            let total_winding: i32 = crossings.iter().map(|(_, direction)| direction).sum();
+
+            starts.clear();
+            for x_subsample in 0..samples {
+                let offset = (x_subsample as f32 + 0.5f32) * step;
+                starts.push(0);
+                starts.extend(crossings.iter().map(|(x, _)| first_sample_from(*x, offset, width)));
+                starts.push(width as usize);
+            }
+            // Runs start at the left end and at each crossing, with what a ray to the right of them would see
+            let mut left_winding = 0;
+            for run in 0..=crossings.len() {
+                if run > 0 {
+                    left_winding += crossings[run - 1].1;
+                }
+                let inside = match rule {
+                    FillRule::EvenOdd => !(crossings.len() - run).is_multiple_of(2),
+                    FillRule::NonZero => total_winding - left_winding != 0,
+                };
+                if !inside {
+                    continue;
+                }
+                for x_subsample in 0..samples as usize {
+                    let column = &starts[x_subsample * (crossings.len() + 2)..];
+                    // Runs only go backwards next to a NaN crossing, which sorts anywhere
+                    add_one(&mut counts[column[run]..column[run + 1].max(column[run])], simd);
+                }
+            }
+        }
+        let row = ((y - rows.start) * width) as usize;
+        counts_to_coverage(&counts, samples, &mut data[row..row + width as usize], simd);
+    }
+    return Coverage { x_min: 0, y_min: rows.start, width, height, data };
+}
+
+fn first_sample_from(x: f32, offset: f32, width: i32) -> usize {
+    /* The first pixel whose sample at offset is at or right of x (width if
+    there's none), compared the same way the ray test would at each sample */
+    let mut pixel = ((x - offset).ceil().max(0f32) as i32).min(width);
+    while pixel > 0 && x <= (pixel - 1) as f32 + offset {
+        pixel -= 1;
+    }
+    while pixel < width && x > pixel as f32 + offset {
+        pixel += 1;
+    }
+    return pixel as usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use ttf_parser::OutlineBuilder;
+
+    use crate::curve::{winding_number, Contour};
+    use crate::fixtures::bundled_fonts;
+    use crate::outline::Builder;
+    use crate::flatten::flatten;
+    use crate::float::Precision;
+    use crate::raster::render_grayscale;
+    use crate::simd::{detect, Simd};
+    use crate::transform::{transform_outline, Transform};
+    use super::{render_scanlines, sweep, EdgeTable, FillRule};
+
+    const SIZE: i32 = 40;
+    const SAMPLES: i32 = 3;
+    const RANDOM_POLYGONS: usize = 300;
+    const GLYPHS_PER_FONT: u16 = 60;
+    const TOLERANCE: f32 = 0.1f32;
+    const BENCHMARK_SIZE: f32 = 200f32;
+    const BENCHMARK_REPEATS: usize = 20;
+
+    fn ray_casting(contours: &[Contour], rule: FillRule, x: f32, y: f32) -> bool {
+        // Against every curve, as the reference
+        let winding = winding_number(contours, x, y, 1f32, 0f32, Precision::Double);
+        return match rule {
+            FillRule::EvenOdd => winding % 2 != 0,
+            FillRule::NonZero => winding != 0,
+        };
+    }
+
+    fn distance_to_outline(contours: &[Contour], x: f32, y: f32) -> f32 {
+        // Near enough the distance to the curves, from a polyline far closer to them than the tolerance
+        let mut nearest = f32::MAX;
+        for polyline in flatten(contours, TOLERANCE / 100f32, 1f32).iter() {
+            for pair in polyline.points.windows(2) {
+                let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+                let (dx, dy) = (bx - ax, by - ay);
+                let length = dx * dx + dy * dy;
+                let t = if length == 0f32 { 0f32 } else { (((x - ax) * dx + (y - ay) * dy) / length).clamp(0f32, 1f32) };
+                nearest = nearest.min(((x - ax - t * dx).powi(2) + (y - ay - t * dy).powi(2)).sqrt());
+            }
+        }
+        return nearest + TOLERANCE / 100f32;
+    }
+
+    fn random_point(rng: &mut StdRng, step: f32) -> (f32, f32) {
+        // Around the sampled area, and on a row of samples half the time
+        let mut y = rng.gen_range(-5f32..SIZE as f32 + 5f32);
+        if rng.gen_bool(0.5f64) {
+            y = ((y / step).floor() + 0.5f32) * step;
+        }
+        return (rng.gen_range(-5f32..SIZE as f32 + 5f32), y);
+    }
+
+    #[test]
+    fn polygons_match_ray_casting() {
+        /* Polygons have nothing to flatten, so the two have to agree on every
+        sample, including vertices and horizontal edges right on a row of
+        samples and self-intersections */
+        let mut rng = StdRng::seed_from_u64(47);
+        let step = 1f32 / SAMPLES as f32;
+        for _ in 0..RANDOM_POLYGONS {
+            let mut builder = Builder::default();
+            let start = random_point(&mut rng, step);
+            builder.move_to(start.0, start.1);
+            for _ in 0..rng.gen_range(3..12) {
+                let (x, y) = random_point(&mut rng, step);
+                builder.line_to(x, y);
+                if rng.gen_bool(0.2f64) {
+                    // A horizontal edge
+                    builder.line_to(rng.gen_range(-5f32..SIZE as f32 + 5f32), y);
+                }
+            }
+            builder.line_to(start.0, start.1);
+            builder.close();
+            let contours = builder.contours;
+            let table = EdgeTable::new(&flatten(&contours, TOLERANCE, 1f32), &Transform::IDENTITY);
+            for rule in [FillRule::EvenOdd, FillRule::NonZero] {
+                let scanlines = render_scanlines(SIZE, SIZE, SAMPLES, &table, rule);
+                let rays = render_grayscale(SIZE, SIZE, SAMPLES, |x, y| ray_casting(&contours, rule, x, y));
+                assert!(scanlines.data == rays.data, "{:?} differs for {:?}", rule, contours);
+                assert!(sweep(SIZE, 0..SIZE, SAMPLES, &table, rule, Simd::Scalar).data == scanlines.data, "{:?} differs without {:?}", rule, detect());
+            }
+        }
+    }
+
+    #[test]
+    fn glyphs_match_ray_casting_away_from_the_outline() {
+        // Pixel centres can only come out differently where flattening moves the outline across them
+        for font in bundled_fonts(GLYPHS_PER_FONT).into_iter() {
+            let ratio = SIZE as f32 / font.units_per_em;
+            for glyph in font.glyphs.into_iter() {
+                let bbox = glyph.bbox;
+                // In pixels, with the box's corner at the origin
+                let to_pixels = Transform::scale(ratio, ratio).then(&Transform::translate(-bbox.x_min as f32, -bbox.y_min as f32));
+                let mut contours = glyph.contours;
+                transform_outline(&mut contours, &to_pixels);
+                let (width, height) = ((bbox.width() as f32 * ratio).ceil() as i32, (bbox.height() as f32 * ratio).ceil() as i32);
+                let table = EdgeTable::new(&flatten(&contours, TOLERANCE, 1f32), &Transform::IDENTITY);
+                let scanlines = render_scanlines(width, height, 1, &table, FillRule::EvenOdd);
+                let rays = render_grayscale(width, height, 1, |x, y| ray_casting(&contours, FillRule::EvenOdd, x, y));
+                for y in 0..height {
+                    for x in 0..width {
+                        if scanlines.get(x, y) != rays.get(x, y) {
+                            let (cx, cy) = (x as f32 + 0.5f32, y as f32 + 0.5f32);
+                            let distance = distance_to_outline(&contours, cx, cy);
+                            assert!(distance <= TOLERANCE, "{} glyph {} differs at ({}, {}), {} from the outline", font.path.display(), glyph.id, cx, cy, distance);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn speedup_per_bundled_font() {
+        /* cargo test --release scanline::tests::speedup_per_bundled_font -- --ignored --nocapture
+        Large glyphs, where counting the samples in runs is most of the work */
+        for samples in [1, 3, 4] {
+            for font in bundled_fonts(GLYPHS_PER_FONT).into_iter() {
+                let ratio = BENCHMARK_SIZE / font.units_per_em;
+                let tables: Vec<(i32, i32, EdgeTable)> = font.glyphs.into_iter().map(|glyph| {
+                    let bbox = glyph.bbox;
+                    let to_pixels = Transform::scale(ratio, ratio).then(&Transform::translate(-bbox.x_min as f32, -bbox.y_min as f32));
+                    let mut contours = glyph.contours;
+                    transform_outline(&mut contours, &to_pixels);
+                    let (width, height) = ((bbox.width() as f32 * ratio).ceil() as i32, (bbox.height() as f32 * ratio).ceil() as i32);
+                    return (width, height, EdgeTable::new(&flatten(&contours, TOLERANCE, 1f32), &Transform::IDENTITY));
+                }).collect();
+                let mut seconds = Vec::new();
+                for simd in [Simd::Scalar, detect()] {
+                    let start = Instant::now();
+                    for _ in 0..BENCHMARK_REPEATS {
+                        for (width, height, table) in tables.iter() {
+                            sweep(*width, 0..*height, samples, table, FillRule::EvenOdd, simd);
+                        }
+                    }
+                    seconds.push((simd, start.elapsed().as_secs_f32()));
+                }
+                let timings: Vec<String> = seconds.iter().map(|(simd, time)| format!("{:?} {:.3}s ({:.2}x)", simd, time, seconds[0].1 / time)).collect();
+                println!("{} with {} samples: {}", font.path.display(), samples, timings.join(", "));
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+/* Passes over a row of pixels with the widest vector instructions the CPU
+has (picked at run time, with plain Rust for anything else): counting the
+samples inside a run of pixels at a time, for the scanline rasterizer, and
+turning the counts into coverage, the last pass of both rasterizers. Counts
+are small whole numbers, so converting them to floats is exact, and division
+is correctly rounded in every instruction set, so each path gives the same
+bits as the scalar one. The ray casting rasterizer calls back for every
+sample to count it, so only its last pass is vectorized. */
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simd {
+    // One pixel at a time
+    Scalar,
+    // 4 pixels at a time
+    #[cfg(target_arch = "x86_64")]
+    Sse2,
+    // 8 pixels at a time
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    // 4 pixels at a time
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+}
+
+pub fn detect() -> Simd {
+    // The widest instructions this CPU has
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return Simd::Avx2;
+        }
+        if is_x86_feature_detected!("sse2") {
+            return Simd::Sse2;
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return Simd::Neon;
+        }
+    }
+    return Simd::Scalar;
+}
+
+pub fn counts_to_coverage(counts: &[i32], samples: i32, coverage: &mut [[f32; 3]], simd: Simd) {
+    /* Coverage of pixels with counts of their samples by samples points inside,
+    the same in all three channels. Instructions the CPU turns out not to have
+    fall back to scalar. */
+    assert_eq!(counts.len(), coverage.len());
+    match simd {
+        // SAFETY: the guard has just checked the CPU has AVX2
+        #[cfg(target_arch = "x86_64")]
+        Simd::Avx2 if is_x86_feature_detected!("avx2") => unsafe { counts_to_coverage_avx2(counts, samples, coverage) },
+        // SAFETY: the guard has just checked the CPU has SSE2
+        #[cfg(target_arch = "x86_64")]
+        Simd::Sse2 if is_x86_feature_detected!("sse2") => unsafe { counts_to_coverage_sse2(counts, samples, coverage) },
+        // SAFETY: the guard has just checked the CPU has NEON
+        #[cfg(target_arch = "aarch64")]
+        Simd::Neon if std::arch::is_aarch64_feature_detected!("neon") => unsafe { counts_to_coverage_neon(counts, samples, coverage) },
+        _ => counts_to_coverage_scalar(counts, samples, coverage),
+    };
+}
+
+pub fn add_one(counts: &mut [i32], simd: Simd) {
+    // Counts one more sample inside each of a run of pixels
+    match simd {
+        // SAFETY: the guard has just checked the CPU has AVX2
+        #[cfg(target_arch = "x86_64")]
+        Simd::Avx2 if is_x86_feature_detected!("avx2") => unsafe { add_one_avx2(counts) },
+        // SAFETY: the guard has just checked the CPU has SSE2
+        #[cfg(target_arch = "x86_64")]
+        Simd::Sse2 if is_x86_feature_detected!("sse2") => unsafe { add_one_sse2(counts) },
+        // SAFETY: the guard has just checked the CPU has NEON
+        #[cfg(target_arch = "aarch64")]
+        Simd::Neon if std::arch::is_aarch64_feature_detected!("neon") => unsafe { add_one_neon(counts) },
+        _ => add_one_scalar(counts),
+    };
+}
+
+fn add_one_scalar(counts: &mut [i32]) {
+    for count in counts.iter_mut() {
+        *count += 1;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn add_one_sse2(counts: &mut [i32]) {
+    let one = _mm_set1_epi32(1);
+    let whole = counts.len() / 4 * 4;
+    for i in (0..whole).step_by(4) {
+        // SAFETY: i + 4 <= whole <= counts.len(), and the load and store are unaligned
+        let count = counts.as_mut_ptr().add(i) as *mut __m128i;
+        _mm_storeu_si128(count, _mm_add_epi32(_mm_loadu_si128(count), one));
+    }
+    add_one_scalar(&mut counts[whole..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn add_one_avx2(counts: &mut [i32]) {
+    let one = _mm256_set1_epi32(1);
+    let whole = counts.len() / 8 * 8;
+    for i in (0..whole).step_by(8) {
+        // SAFETY: i + 8 <= whole <= counts.len(), and the load and store are unaligned
+        let count = counts.as_mut_ptr().add(i) as *mut __m256i;
+        _mm256_storeu_si256(count, _mm256_add_epi32(_mm256_loadu_si256(count), one));
+    }
+    // SAFETY: every CPU with AVX2 has SSE2
+    add_one_sse2(&mut counts[whole..]);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn add_one_neon(counts: &mut [i32]) {
+    let one = vdupq_n_s32(1);
+    let whole = counts.len() / 4 * 4;
+    for i in (0..whole).step_by(4) {
+        // SAFETY: i + 4 <= whole <= counts.len()
+        let count = counts.as_mut_ptr().add(i);
+        vst1q_s32(count, vaddq_s32(vld1q_s32(count), one));
+    }
+    add_one_scalar(&mut counts[whole..]);
+}
+
+fn counts_to_coverage_scalar(counts: &[i32], samples: i32, coverage: &mut [[f32; 3]]) {
+    for (count, pixel) in counts.iter().zip(coverage.iter_mut()) {
+        *pixel = [*count as f32 / (samples * samples) as f32; 3];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn store_pixels_sse2(pixels: *mut f32, values: __m128) {
+    // Four values a, b, c, d as the pixels aaa bbb ccc ddd, which is three vectors a a a b, b b c c and c d d d
+    // SAFETY: callers pass a pointer to four pixels, 12 floats, and the stores are unaligned
+    _mm_storeu_ps(pixels, _mm_shuffle_ps::<0b01_00_00_00>(values, values));
+    _mm_storeu_ps(pixels.add(4), _mm_shuffle_ps::<0b10_10_01_01>(values, values));
+    _mm_storeu_ps(pixels.add(8), _mm_shuffle_ps::<0b11_11_11_10>(values, values));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn counts_to_coverage_sse2(counts: &[i32], samples: i32, coverage: &mut [[f32; 3]]) {
+    let total = _mm_set1_ps((samples * samples) as f32);
+    let whole = counts.len() / 4 * 4;
+    for i in (0..whole).step_by(4) {
+        // SAFETY: i + 4 <= whole, and the caller checked coverage is as long as counts. Loads and stores are unaligned.
+        let values = _mm_div_ps(_mm_cvtepi32_ps(_mm_loadu_si128(counts.as_ptr().add(i) as *const __m128i)), total);
+        store_pixels_sse2(coverage.as_mut_ptr().add(i) as *mut f32, values);
+    }
+    counts_to_coverage_scalar(&counts[whole..], samples, &mut coverage[whole..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn counts_to_coverage_avx2(counts: &[i32], samples: i32, coverage: &mut [[f32; 3]]) {
+    // Converted and divided 8 at a time, then stored as two halves like SSE2 does
+    let total = _mm256_set1_ps((samples * samples) as f32);
+    let whole = counts.len() / 8 * 8;
+    for i in (0..whole).step_by(8) {
+        // SAFETY: i + 8 <= whole, and the caller checked coverage is as long as counts, so both halves are in bounds
+        let values = _mm256_div_ps(_mm256_cvtepi32_ps(_mm256_loadu_si256(counts.as_ptr().add(i) as *const __m256i)), total);
+        let pixels = coverage.as_mut_ptr().add(i) as *mut f32;
+        store_pixels_sse2(pixels, _mm256_castps256_ps128(values));
+        store_pixels_sse2(pixels.add(12), _mm256_extractf128_ps::<1>(values));
+    }
+    // SAFETY: every CPU with AVX2 has SSE2
+    counts_to_coverage_sse2(&counts[whole..], samples, &mut coverage[whole..]);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn counts_to_coverage_neon(counts: &[i32], samples: i32, coverage: &mut [[f32; 3]]) {
+    // vst3q stores three vectors interleaved, so storing the values three times over gives aaa bbb ccc ddd
+    let total = vdupq_n_f32((samples * samples) as f32);
+    let whole = counts.len() / 4 * 4;
+    for i in (0..whole).step_by(4) {
+        // SAFETY: i + 4 <= whole, and the caller checked coverage is as long as counts, so the 12 floats stored are in bounds
+        let values = vdivq_f32(vcvtq_f32_s32(vld1q_s32(counts.as_ptr().add(i))), total);
+        vst3q_f32(coverage.as_mut_ptr().add(i) as *mut f32, float32x4x3_t(values, values, values));
+    }
+    counts_to_coverage_scalar(&counts[whole..], samples, &mut coverage[whole..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::bands::SegmentBands;
+    use crate::fixtures::{bundled_fonts, Font};
+    use crate::float::Precision;
+    use super::{add_one, counts_to_coverage, detect, Simd};
+
+    const RANDOM_ROWS: usize = 2000;
+    const GLYPHS_PER_FONT: u16 = 100;
+    const SIZE: f32 = 60f32;
+    const SAMPLES: i32 = 3;
+    // Times the benchmark converts every row
+    const BENCHMARK_REPEATS: usize = 200;
+
+    fn available() -> Vec<Simd> {
+        // Every path this CPU can run, the scalar one first
+        let mut simd = vec![Simd::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse2") {
+                simd.push(Simd::Sse2);
+            }
+            if is_x86_feature_detected!("avx2") {
+                simd.push(Simd::Avx2);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                simd.push(Simd::Neon);
+            }
+        }
+        return simd;
+    }
+
+    fn convert(counts: &[i32], samples: i32, simd: Simd) -> Vec<u32> {
+        // The coverage's bits, so -0 and NaN would show up too
+        let mut coverage = vec![[f32::NAN; 3]; counts.len()];
+        counts_to_coverage(counts, samples, &mut coverage, simd);
+        return coverage.iter().flatten().map(|value| value.to_bits()).collect();
+    }
+
+    fn glyph_rows(font: &Font) -> Vec<Vec<i32>> {
+        /* Rows of sample counts from the bundled font's glyphs, counted here
+        rather than taken back out of rendered coverage, which has already been
+        through counts_to_coverage */
+        let ratio = SIZE / font.units_per_em;
+        let mut rows = Vec::new();
+        for glyph in font.glyphs.iter() {
+            let bbox = glyph.bbox;
+            let bands = SegmentBands::new(&glyph.contours);
+            let (width, height) = ((bbox.width() as f32 * ratio).ceil() as i32, (bbox.height() as f32 * ratio).ceil() as i32);
+            let step = 1f32 / SAMPLES as f32;
+            for y in 0..height {
+                rows.push((0..width).map(|x| {
+                    let mut count = 0;
+                    for x_subsample in 0..SAMPLES {
+                        for y_subsample in 0..SAMPLES {
+                            let x_coord = (x as f32 + (x_subsample as f32 + 0.5f32) * step) / ratio + bbox.x_min as f32;
+                            let y_coord = (y as f32 + (y_subsample as f32 + 0.5f32) * step) / ratio + bbox.y_min as f32;
+                            if bands.winding_number(x_coord, y_coord, Precision::Double) % 2 != 0 {
+                                count += 1;
+                            }
+                        }
+                    }
+                    return count;
+                }).collect());
+            }
+        }
+        return rows;
+    }
+
+    #[test]
+    fn random_counts_match_scalar() {
+        // Every length up to a few vectors, so every way a row can end after the last whole vector is covered
+        let mut rng = StdRng::seed_from_u64(50);
+        for _ in 0..RANDOM_ROWS {
+            let samples = rng.gen_range(1..=4);
+            let counts: Vec<i32> = (0..rng.gen_range(0..40)).map(|_| rng.gen_range(0..=samples * samples)).collect();
+            let scalar = convert(&counts, samples, Simd::Scalar);
+            for simd in available() {
+                assert_eq!(convert(&counts, samples, simd), scalar, "{:?} with {:?} samples {}", simd, counts, samples);
+            }
+        }
+    }
+
+    #[test]
+    fn random_runs_match_scalar() {
+        let mut rng = StdRng::seed_from_u64(50);
+        for _ in 0..RANDOM_ROWS {
+            let counts: Vec<i32> = (0..rng.gen_range(0..40)).map(|_| rng.gen_range(0..=16)).collect();
+            let mut scalar = counts.clone();
+            add_one(&mut scalar, Simd::Scalar);
+            assert_eq!(scalar, counts.iter().map(|count| count + 1).collect::<Vec<i32>>());
+            for simd in available() {
+                let mut added = counts.clone();
+                add_one(&mut added, simd);
+                assert_eq!(added, scalar, "{:?} with {:?}", simd, counts);
+            }
+        }
+    }
+
+    #[test]
+    fn bundled_fonts_match_scalar() {
+        assert!(available().contains(&detect()));
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            for counts in glyph_rows(font).iter() {
+                let scalar = convert(counts, SAMPLES, Simd::Scalar);
+                for simd in available() {
+                    assert_eq!(convert(counts, SAMPLES, simd), scalar, "{:?} on {}", simd, font.path.display());
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn speedup_per_bundled_font() {
+        // cargo test --release speedup_per_bundled_font -- --ignored --nocapture
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            let rows = glyph_rows(font);
+            let mut coverage: Vec<Vec<[f32; 3]>> = rows.iter().map(|row| vec![[0f32; 3]; row.len()]).collect();
+            let mut seconds = Vec::new();
+            for simd in available() {
+                let start = Instant::now();
+                for _ in 0..BENCHMARK_REPEATS {
+                    for (counts, pixels) in rows.iter().zip(coverage.iter_mut()) {
+                        counts_to_coverage(counts, SAMPLES, pixels, simd);
+                    }
+                }
+                seconds.push((simd, start.elapsed().as_secs_f32()));
+            }
+            let pixels = rows.iter().map(|row| row.len()).sum::<usize>() * BENCHMARK_REPEATS;
+            let timings: Vec<String> = seconds.iter().map(|(simd, time)| format!("{:?} {:.4}s ({:.1}x)", simd, time, seconds[0].1 / time)).collect();
+            println!("{}: {} pixels, {}", font.path.display(), pixels, timings.join(", "));
+        }
+    }
+}
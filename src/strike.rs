@@ -0,0 +1,523 @@
+/* Embedded bitmap glyphs ("strikes"): sbix, CBLC/CBDT and EBLC/EBDT.
+https://learn.microsoft.com/en-us/typography/opentype/spec/eblc
+https://learn.microsoft.com/en-us/typography/opentype/spec/cbdt
+https://learn.microsoft.com/en-us/typography/opentype/spec/sbix
+
+A strike is a set of bitmaps drawn for one pixels-per-em size. Fonts with
+outlines usually only ship strikes for a few small sizes, so for those glyphs
+a strike is only used when its size matches the requested size exactly. For
+bitmap-only glyphs the closest strike is scaled to the requested size. */
+
+use std::io::Cursor;
+
+use ttf_parser::{Face, GlyphId, Tag};
+
+use crate::read::{read_u16, read_u32};
+use crate::bitmap::{BitmapBounds, RgbaBitmap};
+
+#[derive(Debug, Clone, Copy)]
+struct GlyphMetrics {
+    // In pixels at the strike's size
+    width: u8,
+    height: u8,
+    bearing_x: i8,
+    bearing_y: i8,
+}
+
+// A glyph image as stored in the font, before scaling
+struct StrikeImage {
+    bitmap: RgbaBitmap,
+    // Position of the top-left corner relative to the glyph origin, y up, in strike pixels
+    left: f32,
+    top: f32,
+    pixels_per_em: f32,
+}
+
+pub fn render_strike_glyph(face: &Face, glyph_id: GlyphId, pixels_per_em: f32, foreground: [f32; 4]) -> Option<(RgbaBitmap, BitmapBounds)> {
+    /* Returns the glyph's embedded bitmap scaled to pixels_per_em, with its
+    bounds in font units. Monochrome and grayscale strikes are tinted with the
+    (unpremultiplied) foreground color. Returns None if no usable strike has
+    the glyph, in which case the outline should be rendered instead. */
+    let has_outline = face.glyph_bounding_box(glyph_id).is_some();
+    let image = find_sbix_image(face, glyph_id, pixels_per_em, has_outline)
+        .or_else(|| find_bdt_image(face, b"CBLC", b"CBDT", glyph_id, pixels_per_em, has_outline, foreground))
+        .or_else(|| find_bdt_image(face, b"EBLC", b"EBDT", glyph_id, pixels_per_em, has_outline, foreground))?;
+
+    let scale = pixels_per_em / image.pixels_per_em;
+    let bitmap = if scale == 1f32 { image.bitmap } else { resample(&image.bitmap, scale) };
+
+    // Convert the placement from strike pixels to font units
+    let units_per_pixel = face.units_per_em() as f32 / image.pixels_per_em;
+    let x_min = image.left * units_per_pixel;
+    let y_max = image.top * units_per_pixel;
+    let bounds = BitmapBounds {
+        x_min,
+        y_min: y_max - bitmap.height as f32 / scale * units_per_pixel,
+        x_max: x_min + bitmap.width as f32 / scale * units_per_pixel,
+        y_max,
+    };
+    return Some((bitmap, bounds));
+}
+
+fn choose_strike(sizes: &[f32], pixels_per_em: f32, exact_only: bool) -> Option<usize> {
+    /* Index of the strike to use: an exact match if there is one, otherwise
+    the smallest strike that's larger than requested (so it gets scaled down),
+    otherwise the largest strike. */
+    if let Some(i) = sizes.iter().position(|&s| s == pixels_per_em) {
+        return Some(i);
+    }
+    if exact_only {
+        return None;
+    }
+    let larger = sizes.iter().enumerate()
+        .filter(|(_, &s)| s > pixels_per_em)
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i);
+    return larger.or_else(|| sizes.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(i, _)| i));
+}
+
+fn find_sbix_image(face: &Face, glyph_id: GlyphId, pixels_per_em: f32, exact_only: bool) -> Option<StrikeImage> {
+    // sbix strikes hold PNGs; the offsets are to the bottom-left corner
+    let sbix = face.tables().sbix?;
+    let strikes: Vec<_> = sbix.strikes.into_iter().filter(|s| s.get(glyph_id).is_some()).collect();
+    let sizes: Vec<f32> = strikes.iter().map(|s| s.pixels_per_em as f32).collect();
+    let strike = strikes[choose_strike(&sizes, pixels_per_em, exact_only)?];
+    let image = strike.get(glyph_id)?;
+    let bitmap = decode_png(image.data)?;
+    return Some(StrikeImage {
+        left: image.x as f32,
+        top: image.y as f32 + bitmap.height as f32,
+        bitmap,
+        pixels_per_em: strike.pixels_per_em as f32,
+    });
+}
+
+fn find_bdt_image(face: &Face, location_tag: &[u8; 4], data_tag: &[u8; 4], glyph_id: GlyphId, pixels_per_em: f32, exact_only: bool, foreground: [f32; 4]) -> Option<StrikeImage> {
+    // CBLC/CBDT and EBLC/EBDT share the same layout, CBDT adds PNG image formats
+    let location = face.raw_face().table(Tag::from_bytes(location_tag))?;
+    let data = face.raw_face().table(Tag::from_bytes(data_tag))?;
+    let num_sizes = read_u32(location, 4)? as usize;
+
+    // Only consider strikes whose glyph range contains the glyph
+    let mut candidates = Vec::<usize>::new();
+    let mut sizes = Vec::<f32>::new();
+    for i in 0..num_sizes {
+        let record = 8 + i * 48;
+        let start = read_u16(location, record + 40)?;
+        let end = read_u16(location, record + 42)?;
+        if glyph_id.0 >= start && glyph_id.0 <= end {
+            candidates.push(record);
+            sizes.push(*location.get(record + 45)? as f32);
+        }
+    }
+    // A glyph can be missing from a strike even if it's in its range, so try them in order of preference
+    while !sizes.is_empty() {
+        let chosen = choose_strike(&sizes, pixels_per_em, exact_only)?;
+        let record = candidates[chosen];
+        if let Some(image) = read_bdt_glyph(location, data, record, glyph_id, foreground) {
+            return Some(image);
+        }
+        candidates.remove(chosen);
+        sizes.remove(chosen);
+    }
+    return None;
+}
+
+fn read_bdt_glyph(location: &[u8], data: &[u8], record: usize, glyph_id: GlyphId, foreground: [f32; 4]) -> Option<StrikeImage> {
+    // Finds the glyph in the strike's index subtables and decodes its image
+    let array = read_u32(location, record)? as usize;
+    let count = read_u32(location, record + 8)? as usize;
+    let bit_depth = *location.get(record + 46)?;
+    let pixels_per_em = *location.get(record + 45)? as f32;
+
+    for i in 0..count {
+        let entry = array + i * 8;
+        let first = read_u16(location, entry)?;
+        let last = read_u16(location, entry + 2)?;
+        if glyph_id.0 < first || glyph_id.0 > last {
+            continue;
+        }
+        let subtable = array + read_u32(location, entry + 4)? as usize;
+        let index_format = read_u16(location, subtable)?;
+        let image_format = read_u16(location, subtable + 2)?;
+        let image_offset = read_u32(location, subtable + 4)? as usize;
+        let glyph_index = (glyph_id.0 - first) as usize;
+
+        // Location of the glyph data in the data table, plus the shared metrics for formats 2 and 5
+        let (start, end, index_metrics) = match index_format {
+            1 => {
+                let offset = read_u32(location, subtable + 8 + glyph_index * 4)? as usize;
+                let next = read_u32(location, subtable + 12 + glyph_index * 4)? as usize;
+                (image_offset + offset, image_offset + next, None)
+            },
+            2 => {
+                let size = read_u32(location, subtable + 8)? as usize;
+                let start = image_offset + size * glyph_index;
+                (start, start + size, Some(read_big_metrics(location, subtable + 12)?))
+            },
+            3 => {
+                let offset = read_u16(location, subtable + 8 + glyph_index * 2)? as usize;
+                let next = read_u16(location, subtable + 10 + glyph_index * 2)? as usize;
+                (image_offset + offset, image_offset + next, None)
+            },
+            4 => {
+                let num_glyphs = read_u32(location, subtable + 8)? as usize;
+                let mut found = None;
+                for j in 0..num_glyphs {
+                    let pair = subtable + 12 + j * 4;
+                    if read_u16(location, pair)? == glyph_id.0 {
+                        let offset = read_u16(location, pair + 2)? as usize;
+                        let next = read_u16(location, pair + 6)? as usize;
+                        found = Some((image_offset + offset, image_offset + next, None));
+                        break;
+                    }
+                }
+                found?
+            },
+            5 => {
+                let size = read_u32(location, subtable + 8)? as usize;
+                let metrics = read_big_metrics(location, subtable + 12)?;
+                let num_glyphs = read_u32(location, subtable + 20)? as usize;
+                let mut found = None;
+                for j in 0..num_glyphs {
+                    if read_u16(location, subtable + 24 + j * 2)? == glyph_id.0 {
+                        found = Some((image_offset + size * j, image_offset + size * (j + 1), Some(metrics)));
+                        break;
+                    }
+                }
+                found?
+            },
+            _ => return None,
+        };
+        if end <= start {
+            return None;
+        }
+        let glyph = data.get(start..end)?;
+        return decode_bdt_image(glyph, image_format, index_metrics, bit_depth, pixels_per_em, foreground);
+    }
+    return None;
+}
+
+fn decode_bdt_image(glyph: &[u8], image_format: u16, index_metrics: Option<GlyphMetrics>, bit_depth: u8, pixels_per_em: f32, foreground: [f32; 4]) -> Option<StrikeImage> {
+    // Image formats 1, 2, 5, 6, 7 (raw bitmaps) and 17, 18, 19 (PNG)
+    let (metrics, image, byte_aligned) = match image_format {
+        1 => (read_small_metrics(glyph, 0)?, glyph.get(5..)?, true),
+        2 => (read_small_metrics(glyph, 0)?, glyph.get(5..)?, false),
+        5 => (index_metrics?, glyph, false),
+        6 => (read_big_metrics(glyph, 0)?, glyph.get(8..)?, true),
+        7 => (read_big_metrics(glyph, 0)?, glyph.get(8..)?, false),
+        17 => (read_small_metrics(glyph, 0)?, png_data(glyph, 5)?, true),
+        18 => (read_big_metrics(glyph, 0)?, png_data(glyph, 8)?, true),
+        19 => (index_metrics?, png_data(glyph, 0)?, true),
+        // Formats 8 and 9 are composites of other bitmaps, which we don't support
+        _ => return None,
+    };
+    let bitmap = if image_format >= 17 {
+        decode_png(image)?
+    } else {
+        decode_raw_bitmap(image, metrics, bit_depth, byte_aligned, foreground)?
+    };
+    return Some(StrikeImage {
+        bitmap,
+        left: metrics.bearing_x as f32,
+        top: metrics.bearing_y as f32,
+        pixels_per_em,
+    });
+}
+
+fn png_data(glyph: &[u8], offset: usize) -> Option<&[u8]> {
+    // PNG formats store a u32 length before the PNG data
+    let length = read_u32(glyph, offset)? as usize;
+    return glyph.get(offset + 4..offset + 4 + length);
+}
+
+fn decode_raw_bitmap(image: &[u8], metrics: GlyphMetrics, bit_depth: u8, byte_aligned: bool, foreground: [f32; 4]) -> Option<RgbaBitmap> {
+    /* Raw EBDT bitmaps store bit_depth bits per pixel (1, 2, 4 or 8), most
+    significant bit first. Byte-aligned formats pad every row to a whole byte,
+    bit-aligned ones don't. */
+    if !matches!(bit_depth, 1 | 2 | 4 | 8) {
+        return None;
+    }
+    let width = metrics.width as usize;
+    let height = metrics.height as usize;
+    let depth = bit_depth as usize;
+    let max_value = ((1u32 << depth) - 1) as f32;
+    let row_bits = if byte_aligned { (width * depth).div_ceil(8) * 8 } else { width * depth };
+    let mut bitmap = RgbaBitmap::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let bit = y * row_bits + x * depth;
+            let byte = *image.get(bit / 8)? as u32;
+            let value = (byte >> (8 - depth - bit % 8)) & ((1u32 << depth) - 1);
+            let alpha = value as f32 / max_value * foreground[3];
+            bitmap.set(x, y, [foreground[0] * alpha, foreground[1] * alpha, foreground[2] * alpha, alpha]);
+        }
+    }
+    return Some(bitmap);
+}
+
+fn decode_png(data: &[u8]) -> Option<RgbaBitmap> {
+    // Decodes to 8-bit samples, then converts to premultiplied floats
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return None,
+    };
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut bitmap = RgbaBitmap::new(width, height);
+    for y in 0..height {
+        let row = &buffer[y * info.line_size..];
+        for x in 0..width {
+            let pixel = &row[x * channels..(x + 1) * channels];
+            let (r, g, b, a) = match channels {
+                1 => (pixel[0], pixel[0], pixel[0], 255),
+                2 => (pixel[0], pixel[0], pixel[0], pixel[1]),
+                3 => (pixel[0], pixel[1], pixel[2], 255),
+                _ => (pixel[0], pixel[1], pixel[2], pixel[3]),
+            };
+            let alpha = a as f32 / 255f32;
+            bitmap.set(x, y, [r as f32 / 255f32 * alpha, g as f32 / 255f32 * alpha, b as f32 / 255f32 * alpha, alpha]);
+        }
+    }
+    return Some(bitmap);
+}
+
+fn resample(source: &RgbaBitmap, scale: f32) -> RgbaBitmap {
+    /* Scales a premultiplied bitmap. Shrinking averages every source pixel
+    under the destination pixel (box filter), enlarging interpolates between
+    the four nearest source pixels (bilinear). */
+    let width = ((source.width as f32 * scale).round() as usize).max(1);
+    let height = ((source.height as f32 * scale).round() as usize).max(1);
+    let mut bitmap = RgbaBitmap::new(width, height);
+    if source.width == 0 || source.height == 0 {
+        return bitmap;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let color = if scale < 1f32 {
+                let x0 = (x as f32 / scale).floor() as usize;
+                let y0 = (y as f32 / scale).floor() as usize;
+                let x1 = (((x + 1) as f32 / scale).ceil() as usize).clamp(x0 + 1, source.width);
+                let y1 = (((y + 1) as f32 / scale).ceil() as usize).clamp(y0 + 1, source.height);
+                let mut sum = [0f32; 4];
+                for sy in y0.min(source.height - 1)..y1 {
+                    for sx in x0.min(source.width - 1)..x1 {
+                        let pixel = source.get(sx, sy);
+                        for c in 0..4 {
+                            sum[c] += pixel[c];
+                        }
+                    }
+                }
+                let count = ((y1 - y0.min(source.height - 1)) * (x1 - x0.min(source.width - 1))) as f32;
+                [sum[0] / count, sum[1] / count, sum[2] / count, sum[3] / count]
+            } else {
+                let sx = ((x as f32 + 0.5f32) / scale - 0.5f32).clamp(0f32, (source.width - 1) as f32);
+                let sy = ((y as f32 + 0.5f32) / scale - 0.5f32).clamp(0f32, (source.height - 1) as f32);
+                let (x0, y0) = (sx.floor() as usize, sy.floor() as usize);
+                let (x1, y1) = ((x0 + 1).min(source.width - 1), (y0 + 1).min(source.height - 1));
+                let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+                let (top_left, top_right, bottom_left, bottom_right) = (source.get(x0, y0), source.get(x1, y0), source.get(x0, y1), source.get(x1, y1));
+                let mut color = [0f32; 4];
+                for (c, value) in color.iter_mut().enumerate() {
+                    let top = top_left[c] * (1f32 - fx) + top_right[c] * fx;
+                    let bottom = bottom_left[c] * (1f32 - fx) + bottom_right[c] * fx;
+                    *value = top * (1f32 - fy) + bottom * fy;
+                }
+                color
+            };
+            bitmap.set(x, y, color);
+        }
+    }
+    return bitmap;
+}
+
+fn read_small_metrics(data: &[u8], offset: usize) -> Option<GlyphMetrics> {
+    let bytes = data.get(offset..offset + 5)?;
+    return Some(GlyphMetrics { height: bytes[0], width: bytes[1], bearing_x: bytes[2] as i8, bearing_y: bytes[3] as i8 });
+}
+
+fn read_big_metrics(data: &[u8], offset: usize) -> Option<GlyphMetrics> {
+    // Only the horizontal metrics are used
+    let bytes = data.get(offset..offset + 8)?;
+    return Some(GlyphMetrics { height: bytes[0], width: bytes[1], bearing_x: bytes[2] as i8, bearing_y: bytes[3] as i8 });
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::GlyphId;
+
+    use crate::bitmap::RgbaBitmap;
+    use super::{choose_strike, decode_raw_bitmap, read_bdt_glyph, resample, GlyphMetrics};
+
+    const WHITE: [f32; 4] = [1f32; 4];
+    // Glyph 11 in every strike below: 3 x 2 pixels, 1 right of the origin and 2 above it
+    const GLYPH_11: [f32; 6] = [1f32, 0f32, 1f32, 0f32, 1f32, 0f32];
+
+    fn location(index_subtable: &[u8]) -> Vec<u8> {
+        /* EBLC with one 1 bit strike at 12 ppem for glyphs 10 to 15, with an
+        index subtable array of one entry (for the same glyphs) at 56 */
+        return [
+            &0x00020000u32.to_be_bytes()[..], &1u32.to_be_bytes(),
+            &56u32.to_be_bytes(), &(8 + index_subtable.len() as u32).to_be_bytes(), &1u32.to_be_bytes(), &0u32.to_be_bytes(),
+            &[0u8; 24], &10u16.to_be_bytes(), &15u16.to_be_bytes(), &[12, 12, 1, 1],
+            &10u16.to_be_bytes(), &15u16.to_be_bytes(), &8u32.to_be_bytes(),
+            index_subtable,
+        ].concat();
+    }
+
+    fn header(index_format: u16, image_format: u16) -> Vec<u8> {
+        // Images start right after EBDT's version
+        return [&index_format.to_be_bytes()[..], &image_format.to_be_bytes(), &4u32.to_be_bytes()].concat();
+    }
+
+    fn byte_aligned_data() -> Vec<u8> {
+        // Image format 1 (small metrics, rows padded to bytes) for glyphs 10, 11 and 12, 7 bytes each
+        return [
+            &0x00020000u32.to_be_bytes()[..],
+            &[2, 3, 0, 2, 4, 0b11100000, 0b00000000],
+            &[2, 3, 1, 2, 4, 0b10100000, 0b01000000],
+            &[2, 3, 2, 2, 4, 0b00000000, 0b11100000],
+        ].concat();
+    }
+
+    fn bit_aligned_data() -> Vec<u8> {
+        // Image format 5 (metrics in the index, rows not padded) for glyphs 10, 11 and 12, a byte each
+        return [&0x00020000u32.to_be_bytes()[..], &[0b11100000, 0b10101000, 0b00011100]].concat();
+    }
+
+    fn big_metrics() -> [u8; 8] {
+        return [2, 3, 1, 2, 4, 0, 0, 0];
+    }
+
+    fn alpha(bitmap: &RgbaBitmap) -> Vec<f32> {
+        return bitmap.data.iter().map(|pixel| pixel[3]).collect();
+    }
+
+    fn check_glyph_11(location: &[u8], data: &[u8]) {
+        let image = read_bdt_glyph(location, data, 8, GlyphId(11), WHITE).unwrap();
+        assert_eq!((image.bitmap.width, image.bitmap.height), (3, 2));
+        assert_eq!(alpha(&image.bitmap), GLYPH_11);
+        assert_eq!((image.left, image.top, image.pixels_per_em), (1f32, 2f32, 12f32));
+    }
+
+    #[test]
+    fn index_format_1() {
+        // u32 offsets for every glyph in the range, and one past the last
+        let offsets: Vec<u8> = [0u32, 7, 14, 21, 21, 21, 21].iter().flat_map(|offset| offset.to_be_bytes()).collect();
+        let location = location(&[header(1, 1), offsets].concat());
+        check_glyph_11(&location, &byte_aligned_data());
+        // Glyphs in the range with no data aren't there
+        assert!(read_bdt_glyph(&location, &byte_aligned_data(), 8, GlyphId(13), WHITE).is_none());
+    }
+
+    #[test]
+    fn index_format_2() {
+        // Every glyph the same size, with shared metrics
+        let location = location(&[header(2, 5), 1u32.to_be_bytes().to_vec(), big_metrics().to_vec()].concat());
+        check_glyph_11(&location, &bit_aligned_data());
+    }
+
+    #[test]
+    fn index_format_3() {
+        // Like format 1 with u16 offsets
+        let offsets: Vec<u8> = [0u16, 7, 14, 21, 21, 21, 21].iter().flat_map(|offset| offset.to_be_bytes()).collect();
+        let location = location(&[header(3, 1), offsets].concat());
+        check_glyph_11(&location, &byte_aligned_data());
+        assert!(read_bdt_glyph(&location, &byte_aligned_data(), 8, GlyphId(14), WHITE).is_none());
+    }
+
+    #[test]
+    fn index_format_4() {
+        // Sparse (glyph, offset) pairs, with one more for the end of the last glyph
+        let pairs: Vec<u8> = [(10u16, 0u16), (11, 7), (12, 14), (0, 21)].iter().flat_map(|(id, offset)| [id.to_be_bytes(), offset.to_be_bytes()].concat()).collect();
+        let location = location(&[header(4, 1), 3u32.to_be_bytes().to_vec(), pairs].concat());
+        check_glyph_11(&location, &byte_aligned_data());
+        assert!(read_bdt_glyph(&location, &byte_aligned_data(), 8, GlyphId(15), WHITE).is_none());
+    }
+
+    #[test]
+    fn index_format_5() {
+        // Sparse glyph ids, every glyph the same size with shared metrics
+        let ids: Vec<u8> = [10u16, 11, 12].iter().flat_map(|id| id.to_be_bytes()).collect();
+        let location = location(&[header(5, 5), 1u32.to_be_bytes().to_vec(), big_metrics().to_vec(), 3u32.to_be_bytes().to_vec(), ids].concat());
+        check_glyph_11(&location, &bit_aligned_data());
+        assert!(read_bdt_glyph(&location, &bit_aligned_data(), 8, GlyphId(15), WHITE).is_none());
+    }
+
+    #[test]
+    fn raw_bit_depths() {
+        // 3 x 2 pixels with the values 0, 1, 2 / 3, 2, 1 at each depth (scaled to fill its range)
+        let metrics = GlyphMetrics { width: 3, height: 2, bearing_x: 0, bearing_y: 0 };
+        let values = [0f32, 1f32, 2f32, 3f32, 2f32, 1f32];
+        let cases: [(u8, bool, &[u8]); 6] = [
+            (2, false, &[0b00011011, 0b10010000]),
+            (2, true, &[0b00011000, 0b11100100]),
+            (4, false, &[0x01, 0x23, 0x21]),
+            (4, true, &[0x01, 0x20, 0x32, 0x10]),
+            (8, false, &[0, 1, 2, 3, 2, 1]),
+            (8, true, &[0, 1, 2, 3, 2, 1]),
+        ];
+        for (depth, byte_aligned, image) in cases {
+            let bitmap = decode_raw_bitmap(image, metrics, depth, byte_aligned, WHITE).unwrap();
+            let max = ((1u32 << depth) - 1) as f32;
+            assert_eq!(alpha(&bitmap), values.map(|value| value / max), "{} bits, byte aligned {}", depth, byte_aligned);
+        }
+        // 1 bit rows padded to a byte, tinted with a half transparent color (premultiplied)
+        let bitmap = decode_raw_bitmap(&[0b10100000, 0b01000000], metrics, 1, true, [1f32, 0.5f32, 0f32, 0.5f32]).unwrap();
+        assert_eq!(bitmap.get(0, 0), [0.5f32, 0.25f32, 0f32, 0.5f32]);
+        assert_eq!(bitmap.get(1, 0), [0f32; 4]);
+        // Other depths and images cut short aren't decoded
+        assert!(decode_raw_bitmap(&[0xff; 8], metrics, 3, true, WHITE).is_none());
+        assert!(decode_raw_bitmap(&[0xff], metrics, 8, true, WHITE).is_none());
+    }
+
+    fn bitmap(width: usize, height: usize, values: &[f32]) -> RgbaBitmap {
+        let mut bitmap = RgbaBitmap::new(width, height);
+        bitmap.data = values.iter().map(|&value| [value; 4]).collect();
+        return bitmap;
+    }
+
+    #[test]
+    fn resample_shrinks_with_a_box_filter() {
+        // A checkerboard averages out to grey, and halves of the image stay apart
+        let checkerboard = bitmap(4, 2, &[1f32, 0f32, 1f32, 0f32, 0f32, 1f32, 0f32, 1f32]);
+        let shrunk = resample(&checkerboard, 0.5f32);
+        assert_eq!((shrunk.width, shrunk.height), (2, 1));
+        assert_eq!(alpha(&shrunk), [0.5f32, 0.5f32]);
+        let halves = bitmap(4, 2, &[1f32, 1f32, 0f32, 0f32, 1f32, 1f32, 0f32, 0f32]);
+        assert_eq!(alpha(&resample(&halves, 0.5f32)), [1f32, 0f32]);
+    }
+
+    #[test]
+    fn resample_enlarges_bilinearly() {
+        // Pixel centres are interpolated between, and held at the edges
+        let enlarged = resample(&bitmap(2, 1, &[0f32, 1f32]), 2f32);
+        assert_eq!((enlarged.width, enlarged.height), (4, 2));
+        assert_eq!(alpha(&enlarged), [0f32, 0.25f32, 0.75f32, 1f32, 0f32, 0.25f32, 0.75f32, 1f32]);
+        // A flat image stays flat at any scale
+        for scale in [0.3f32, 0.7f32, 1.6f32, 3f32] {
+            let resampled = resample(&bitmap(5, 3, &[0.6f32; 15]), scale);
+            assert!(resampled.data.iter().all(|pixel| (pixel[3] - 0.6f32).abs() < 1e-6f32), "scale {}", scale);
+        }
+    }
+
+    #[test]
+    fn strike_choice() {
+        let sizes = [12f32, 16f32, 32f32];
+        assert_eq!(choose_strike(&sizes, 16f32, false), Some(1));
+        // The next larger strike, scaled down, then the largest one, scaled up
+        assert_eq!(choose_strike(&sizes, 20f32, false), Some(2));
+        assert_eq!(choose_strike(&sizes, 10f32, false), Some(0));
+        assert_eq!(choose_strike(&sizes, 40f32, false), Some(2));
+        // Outline glyphs only use an exact match
+        assert_eq!(choose_strike(&sizes, 20f32, true), None);
+    }
+}
@@ -0,0 +1,766 @@
+/* Outline stroking, for hollow text and text with a border. Each contour is
+offset by half the stroke width on both sides: closed contours give an outer
+and an inner contour, open ones a single contour with caps at the ends.
+Corners get joins on their outer side and a detour through the corner point
+on the inner side, the way FreeType's stroker does it.
+
+Curves are offset as quadratics: quads are split until they turn by less
+than MAX_TURN and each piece is offset by moving its end points along their
+normals and putting the control point where the offset tangents meet. Cubics
+are first approximated by quads. The result only has lines and quads.
+
+Stroke outlines overlap themselves at joins, so they have to be filled with
+the nonzero winding rule (see SegmentBands::winding_number), not by counting crossings
+mod 2.
+
+offset_outline moves an outline to one side only, for synthetic bold. There
+the inner side of a corner can't take a detour (nothing on the other side
+covers the loop it makes), so the two offset pieces are cut back to where
+they cross instead, and pieces that end up nearer the contour than the
+distance (in bends and parts narrower than twice the distance) are left out.
+Each contour is offset on its own, so a counter that a stem grows across is
+bridged rather than split in two. */
+
+use std::f32::consts::PI;
+
+use ttf_parser::{OutlineBuilder, Rect};
+
+use crate::curve::{Contour, Element};
+use crate::outline::Builder;
+use crate::transform::outline_bounds;
+
+// Largest change in direction (radians) across a curve piece before it is split for offsetting
+const MAX_TURN: f32 = PI / 6f32;
+const MAX_SUBDIVISIONS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextOutline {
+    // Just the glyph
+    Fill,
+    // Just the stroke around the glyph
+    Hollow,
+    // The glyph over its stroke, in a different color
+    Bordered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrokeStyle {
+    // Full width of the stroke, in the units of the outline
+    pub width: f32,
+    pub join: LineJoin,
+    // Longest miter, as a multiple of half the width, before it is beveled instead
+    pub miter_limit: f32,
+    // For contours that aren't closed
+    pub cap: LineCap,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> StrokeStyle {
+        // SVG's defaults
+        return StrokeStyle { width: 1f32, join: LineJoin::Miter, miter_limit: 4f32, cap: LineCap::Butt };
+    }
+}
+
+type Point = (f32, f32);
+
+#[derive(Debug, Clone, Copy)]
+enum Piece {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+}
+
+pub fn stroke_outline(contours: &[Contour], style: &StrokeStyle) -> (Vec<Contour>, Option<Rect>) {
+    /* Returns the outline of the stroke around the given outline, along with
+    its bounding box (None if there was nothing to stroke). */
+    let half = style.width / 2f32;
+    let mut builder = Builder::default();
+    if half <= 0f32 {
+        return (builder.contours, None);
+    }
+    let tolerance = (style.width / 100f32).max(1e-3f32);
+    for (pieces, closed) in split_contours(contours, tolerance) {
+        if pieces.is_empty() {
+            continue;
+        }
+        let left = offset_side(&pieces, half, closed, style);
+        let right: Vec<Piece> = offset_side(&pieces, -half, closed, style).iter().rev().map(|p| p.reversed()).collect();
+        if closed {
+            emit(&mut builder, &left);
+            emit(&mut builder, &right);
+        } else {
+            // Around the end, back along the other side and around the start
+            let last = pieces[pieces.len() - 1];
+            let first = pieces[0];
+            let mut path = left;
+            add_cap(&mut path, last.end(), last.end_tangent(), half, style.cap);
+            path.extend(right);
+            let start_tangent = first.start_tangent();
+            add_cap(&mut path, first.start(), (-start_tangent.0, -start_tangent.1), half, style.cap);
+            emit(&mut builder, &path);
+        }
+    }
+    let bbox = outline_bounds(&builder.contours);
+    return (builder.contours, bbox);
+}
+
+pub fn offset_outline(contours: &[Contour], distance: f32, style: &StrokeStyle) -> Vec<Contour> {
+    /* Moves every contour sideways by distance (to the left of its direction
+    for positive distances), with the style's joins on the outside of corners.
+    Contours are taken as closed, the way they're filled. */
+    let mut builder = Builder::default();
+    if distance == 0f32 {
+        return contours.to_vec();
+    }
+    let tolerance = (distance.abs() / 50f32).max(1e-3f32);
+    for (mut pieces, closed) in split_contours(contours, tolerance) {
+        let (first, last) = match (pieces.first(), pieces.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => continue,
+        };
+        if !closed {
+            pieces.push(Piece::Line(last.end(), first.start()));
+        }
+        emit(&mut builder, &offset_closed(&pieces, distance, tolerance, style));
+    }
+    return builder.contours;
+}
+
+pub fn parse_stroke_style(text: &str) -> StrokeStyle {
+    /* Parses settings written like "width=3, join=round, miter-limit=4,
+    cap=butt" (joins are miter, round or bevel and caps butt, round or
+    square). Missing settings keep their defaults and malformed ones are
+    ignored. */
+    let mut style = StrokeStyle::default();
+    for setting in text.split(',') {
+        let (name, value) = match setting.split_once('=') {
+            Some(pair) => (pair.0.trim(), pair.1.trim()),
+            None => continue,
+        };
+        match (name, value) {
+            ("width", _) => style.width = value.parse::<f32>().unwrap_or(style.width),
+            ("miter-limit", _) => style.miter_limit = value.parse::<f32>().unwrap_or(style.miter_limit),
+            ("join", "miter") => style.join = LineJoin::Miter,
+            ("join", "round") => style.join = LineJoin::Round,
+            ("join", "bevel") => style.join = LineJoin::Bevel,
+            ("cap", "butt") => style.cap = LineCap::Butt,
+            ("cap", "round") => style.cap = LineCap::Round,
+            ("cap", "square") => style.cap = LineCap::Square,
+            _ => {},
+        }
+    }
+    return style;
+}
+
+impl Piece {
+    fn start(&self) -> Point {
+        return match *self {
+            Piece::Line(a, _) | Piece::Quad(a, _, _) => a,
+        };
+    }
+
+    fn end(&self) -> Point {
+        return match *self {
+            Piece::Line(_, b) | Piece::Quad(_, _, b) => b,
+        };
+    }
+
+    fn start_tangent(&self) -> Point {
+        return match *self {
+            Piece::Line(a, b) => direction(a, b),
+            Piece::Quad(a, c, b) => if a == c { direction(a, b) } else { direction(a, c) },
+        };
+    }
+
+    fn end_tangent(&self) -> Point {
+        return match *self {
+            Piece::Line(a, b) => direction(a, b),
+            Piece::Quad(a, c, b) => if c == b { direction(a, b) } else { direction(c, b) },
+        };
+    }
+
+    fn reversed(&self) -> Piece {
+        return match *self {
+            Piece::Line(a, b) => Piece::Line(b, a),
+            Piece::Quad(a, c, b) => Piece::Quad(b, c, a),
+        };
+    }
+
+    fn with_start(&self, start: Point) -> Piece {
+        // Moving an end along its tangent keeps a quad's control point where it was
+        return match *self {
+            Piece::Line(_, b) => Piece::Line(start, b),
+            Piece::Quad(_, c, b) => Piece::Quad(start, c, b),
+        };
+    }
+
+    fn with_end(&self, end: Point) -> Piece {
+        return match *self {
+            Piece::Line(a, _) => Piece::Line(a, end),
+            Piece::Quad(a, c, _) => Piece::Quad(a, c, end),
+        };
+    }
+
+    fn point(&self, t: f32) -> Point {
+        return match *self {
+            Piece::Line(a, b) => add(a, scale(sub(b, a), t)),
+            Piece::Quad(a, c, b) => add(add(scale(a, (1f32 - t) * (1f32 - t)), scale(c, 2f32 * t * (1f32 - t))), scale(b, t * t)),
+        };
+    }
+
+    fn split(&self, t0: f32, t1: f32) -> Piece {
+        // The part between t0 and t1, with the control point on the tangent at t0
+        return match *self {
+            Piece::Line(_, _) => Piece::Line(self.point(t0), self.point(t1)),
+            Piece::Quad(a, c, b) => {
+                let derivative = add(scale(sub(c, a), 2f32 * (1f32 - t0)), scale(sub(b, c), 2f32 * t0));
+                Piece::Quad(self.point(t0), add(self.point(t0), scale(derivative, (t1 - t0) / 2f32)), self.point(t1))
+            },
+        };
+    }
+
+    fn offset(&self, distance: f32) -> Piece {
+        // The piece moved sideways by distance (to the left of its direction for positive distances)
+        let n0 = normal(self.start_tangent());
+        let n1 = normal(self.end_tangent());
+        let q0 = add(self.start(), scale(n0, distance));
+        let q1 = add(self.end(), scale(n1, distance));
+        return match *self {
+            Piece::Line(_, _) => Piece::Line(q0, q1),
+            Piece::Quad(_, c, _) => {
+                let control = match intersect_lines(q0, self.start_tangent(), q1, self.end_tangent()) {
+                    Some(p) => p,
+                    // Straight enough that the tangents are parallel
+                    None => add(c, scale(normalize(add(n0, n1)), distance)),
+                };
+                Piece::Quad(q0, control, q1)
+            },
+        };
+    }
+}
+
+fn split_contours(contours: &[Contour], tolerance: f32) -> Vec<(Vec<Piece>, bool)> {
+    // The outline as contours of lines and flat enough quads, and whether each one is closed
+    let mut result = Vec::<(Vec<Piece>, bool)>::new();
+    for contour in contours.iter() {
+        let mut pieces = Vec::<Piece>::new();
+        for curve in contour.curves.iter() {
+            match *curve {
+                Element::Line(line) => {
+                    if line.from != line.to {
+                        pieces.push(Piece::Line(line.from, line.to));
+                    }
+                },
+                Element::Quad(quad) => split_quad(&mut pieces, quad.from, quad.control, quad.to, 0),
+                Element::Cubic(cubic) => split_cubic(&mut pieces, cubic.from, cubic.control1, cubic.control2, cubic.to, tolerance, 0),
+            }
+        }
+        // Contours that end where they started are closed even without a close
+        let closed = contour.closed || match (pieces.first(), pieces.last()) {
+            (Some(first), Some(last)) => first.start() == last.end(),
+            _ => false,
+        };
+        result.push((pieces, closed));
+    }
+    return result;
+}
+
+fn split_quad(pieces: &mut Vec<Piece>, a: Point, c: Point, b: Point, depth: u32) {
+    if a == b && a == c {
+        return;
+    }
+    let turn = if a == c || c == b { 0f32 } else { angle_between(direction(a, c), direction(c, b)) };
+    if turn <= MAX_TURN || depth >= MAX_SUBDIVISIONS {
+        pieces.push(Piece::Quad(a, c, b));
+        return;
+    }
+    // de Casteljau at t = 0.5
+    let ac = midpoint(a, c);
+    let cb = midpoint(c, b);
+    let middle = midpoint(ac, cb);
+    split_quad(pieces, a, ac, middle, depth + 1);
+    split_quad(pieces, middle, cb, b, depth + 1);
+}
+
+fn split_cubic(pieces: &mut Vec<Piece>, a: Point, c1: Point, c2: Point, b: Point, tolerance: f32, depth: u32) {
+    /* Approximates a cubic with quads, splitting it in half until a single
+    quad (with its control point at (3 (c1 + c2) - a - b) / 4) is close enough. */
+    // Distance between the cubic and that quad is at most sqrt(3) / 36 * |b - 3 c2 + 3 c1 - a|
+    let error = length((b.0 - 3f32 * c2.0 + 3f32 * c1.0 - a.0, b.1 - 3f32 * c2.1 + 3f32 * c1.1 - a.1)) * 3f32.sqrt() / 36f32;
+    if error <= tolerance || depth >= MAX_SUBDIVISIONS {
+        let control = ((3f32 * (c1.0 + c2.0) - a.0 - b.0) / 4f32, (3f32 * (c1.1 + c2.1) - a.1 - b.1) / 4f32);
+        split_quad(pieces, a, control, b, depth);
+        return;
+    }
+    let ab = midpoint(a, c1);
+    let bc = midpoint(c1, c2);
+    let cd = midpoint(c2, b);
+    let abc = midpoint(ab, bc);
+    let bcd = midpoint(bc, cd);
+    let middle = midpoint(abc, bcd);
+    split_cubic(pieces, a, ab, abc, middle, tolerance, depth + 1);
+    split_cubic(pieces, middle, bcd, cd, b, tolerance, depth + 1);
+}
+
+fn offset_side(pieces: &[Piece], distance: f32, closed: bool, style: &StrokeStyle) -> Vec<Piece> {
+    // One side of the stroke in the direction of the contour, with joins between the pieces
+    let mut path = Vec::<Piece>::new();
+    for i in 0..pieces.len() {
+        path.push(pieces[i].offset(distance));
+        if i + 1 < pieces.len() || closed {
+            let next = pieces[(i + 1) % pieces.len()];
+            add_join(&mut path, pieces[i].end(), pieces[i].end_tangent(), next.start_tangent(), distance, style);
+        }
+    }
+    return path;
+}
+
+fn offset_closed(pieces: &[Piece], distance: f32, tolerance: f32, style: &StrokeStyle) -> Vec<Piece> {
+    /* One side of a closed contour, with the offset pieces on the inside of
+    corners cut back to where they cross. A piece that doesn't reach its
+    neighbour (a short piece between two corners, or a bend tighter than the
+    distance) is left out and the pieces on either side are made to meet
+    instead. */
+    let offsets: Vec<Piece> = pieces.iter().map(|piece| piece.offset(distance)).collect();
+    let mut kept: Vec<usize> = (0..pieces.len()).collect();
+    loop {
+        let n = kept.len();
+        let mut trimmed: Vec<Piece> = kept.iter().map(|&i| offsets[i]).collect();
+        let mut joins = vec![Vec::<Piece>::new(); n];
+        for k in 0..n {
+            let (i, j) = (kept[k], kept[(k + 1) % n]);
+            let next = (k + 1) % n;
+            let (incoming, outgoing) = (pieces[i].end_tangent(), pieces[j].start_tangent());
+            // Joins on the outside of corners; neighbours of a left out piece are cut back like the inside of one
+            if cross(incoming, outgoing) * distance <= 0f32 && j == (i + 1) % pieces.len() {
+                add_join(&mut joins[k], pieces[i].end(), incoming, outgoing, distance, style);
+                continue;
+            }
+            let (from, to) = (trimmed[k].end(), trimmed[next].start());
+            if let Some((t, u, meet)) = intersect_pieces(&trimmed[k], &trimmed[next]) {
+                trimmed[k] = trimmed[k].split(0f32, t).with_end(meet);
+                trimmed[next] = trimmed[next].split(u, 1f32).with_start(meet);
+                continue;
+            }
+            if length(sub(to, from)) <= tolerance {
+                // Between the pieces of a split curve, where the gap is tiny
+                joins[k].push(Piece::Line(from, to));
+                continue;
+            }
+            match intersect_lines(from, incoming, to, outgoing) {
+                /* Pieces cut short of each other (e.g. either side of a short one
+                that was left out) still cross behind the end of the first and ahead
+                of the start of the second, not further away than a miter would go */
+                Some(meet) if dot(sub(meet, from), incoming) <= 0f32 && dot(sub(meet, to), outgoing) >= 0f32
+                    && length(sub(meet, from)).max(length(sub(meet, to))) <= style.miter_limit * distance.abs() => {
+                    trimmed[k] = trimmed[k].with_end(meet);
+                    trimmed[next] = trimmed[next].with_start(meet);
+                },
+                // Neighbours of a left out piece that don't meet are bridged
+                _ => joins[k].push(Piece::Line(from, to)),
+            }
+        }
+        let mut dropped = vec![false; n];
+        for k in 0..n {
+            // Cut back past its other end, or turned round by a bend tighter than the distance
+            let original = pieces[kept[k]];
+            let backwards = dot(sub(trimmed[k].end(), trimmed[k].start()), sub(original.end(), original.start())) <= 0f32
+                || dot(trimmed[k].start_tangent(), original.start_tangent()) <= 0f32
+                || dot(trimmed[k].end_tangent(), original.end_tangent()) <= 0f32;
+            // All of it inside a part of the contour narrower than twice the distance
+            let swallowed = [0.25f32, 0.5f32, 0.75f32].iter().all(|t| distance_to_pieces(pieces, trimmed[k].point(*t)) < distance.abs() * 0.9f32);
+            if backwards || swallowed {
+                dropped[k] = true;
+            }
+        }
+        if dropped.iter().any(|d| *d) && n <= 2 {
+            // The contour has closed up (a counter thinner than twice the distance)
+            return Vec::new();
+        }
+        if !dropped.iter().any(|d| *d) {
+            let mut path = Vec::<Piece>::new();
+            for (piece, join) in trimmed.iter().zip(joins) {
+                path.push(*piece);
+                path.extend(join);
+            }
+            return path;
+        }
+        kept = (0..n).filter(|k| !dropped[*k]).map(|k| kept[k]).collect();
+    }
+}
+
+fn add_join(path: &mut Vec<Piece>, corner: Point, incoming: Point, outgoing: Point, distance: f32, style: &StrokeStyle) {
+    let from = add(corner, scale(normal(incoming), distance));
+    let to = add(corner, scale(normal(outgoing), distance));
+    // Smooth joins (e.g. between the pieces of a split curve) just need the gap closed
+    if length((to.0 - from.0, to.1 - from.1)) <= distance.abs() * 1e-3f32 {
+        if from != to {
+            path.push(Piece::Line(from, to));
+        }
+        return;
+    }
+    // Positive when the contour turns left, which puts the left side (positive distances) on the inside
+    let turn = cross(incoming, outgoing);
+    if turn * distance > 0f32 {
+        // Through the corner so the overlapping offsets wind the right way
+        path.push(Piece::Line(from, corner));
+        path.push(Piece::Line(corner, to));
+        return;
+    }
+    let radius = distance.abs();
+    let a = direction(corner, from);
+    let b = direction(corner, to);
+    let sweep = cross(a, b).atan2(dot(a, b));
+    match style.join {
+        LineJoin::Bevel => path.push(Piece::Line(from, to)),
+        LineJoin::Round => add_arc(path, corner, from, sweep),
+        LineJoin::Miter => {
+            let half_angle = (sweep / 2f32).abs();
+            let ratio = 1f32 / half_angle.cos();
+            if ratio <= style.miter_limit && half_angle < PI / 2f32 {
+                let tip = add(corner, scale(normalize(add(a, b)), radius * ratio));
+                path.push(Piece::Line(from, tip));
+                path.push(Piece::Line(tip, to));
+            } else {
+                path.push(Piece::Line(from, to));
+            }
+        },
+    }
+}
+
+fn add_cap(path: &mut Vec<Piece>, end: Point, tangent: Point, half: f32, cap: LineCap) {
+    // From the left side of the end of a contour heading in the tangent's direction to the right side
+    let n = normal(tangent);
+    let left = add(end, scale(n, half));
+    let right = add(end, scale(n, -half));
+    match cap {
+        LineCap::Butt => path.push(Piece::Line(left, right)),
+        LineCap::Square => {
+            let out = scale(tangent, half);
+            path.push(Piece::Line(left, add(left, out)));
+            path.push(Piece::Line(add(left, out), add(right, out)));
+            path.push(Piece::Line(add(right, out), right));
+        },
+        LineCap::Round => add_arc(path, end, left, -PI),
+    }
+}
+
+fn add_arc(path: &mut Vec<Piece>, center: Point, from: Point, sweep: f32) {
+    // Circular arc around center starting at from, as quads of at most 45 degrees
+    let radius = length((from.0 - center.0, from.1 - center.1));
+    let start_angle = (from.1 - center.1).atan2(from.0 - center.0);
+    let count = (sweep.abs() / (PI / 4f32)).ceil().max(1f32) as i32;
+    let step = sweep / count as f32;
+    let point_at = |angle: f32, r: f32| (center.0 + r * angle.cos(), center.1 + r * angle.sin());
+    let mut previous = from;
+    for i in 0..count {
+        let angle = start_angle + step * i as f32;
+        let control = point_at(angle + step / 2f32, radius / (step / 2f32).cos());
+        let end = point_at(angle + step, radius);
+        path.push(Piece::Quad(previous, control, end));
+        previous = end;
+    }
+}
+
+fn emit(builder: &mut Builder, path: &[Piece]) {
+    let first = match path.first() {
+        Some(p) => p,
+        None => return,
+    };
+    builder.move_to(first.start().0, first.start().1);
+    for piece in path.iter() {
+        match *piece {
+            Piece::Line(_, b) => builder.line_to(b.0, b.1),
+            Piece::Quad(_, c, b) => builder.quad_to(c.0, c.1, b.0, b.1),
+        }
+    }
+    // Rounding can leave the end a hair away from the start
+    let (start, end) = (first.start(), path[path.len() - 1].end());
+    if start != end {
+        builder.line_to(start.0, start.1);
+    }
+    builder.close();
+}
+
+fn intersect_lines(p: Point, d: Point, q: Point, e: Point) -> Option<Point> {
+    // Where the line through p along d meets the line through q along e
+    let denominator = cross(d, e);
+    if denominator.abs() < 1e-4f32 {
+        return None;
+    }
+    let t = cross((q.0 - p.0, q.1 - p.1), e) / denominator;
+    return Some(add(p, scale(d, t)));
+}
+
+fn intersect_pieces(a: &Piece, b: &Piece) -> Option<(f32, f32, Point)> {
+    /* Where two pieces cross, as the parameter along each and the point,
+    taking the crossing nearest the end of the first. The pieces turn by
+    less than MAX_TURN, so a few chords follow them closely enough. */
+    let steps = 16;
+    let mut best: Option<(f32, f32, Point)> = None;
+    for s in 0..steps {
+        let (s0, s1) = (s as f32 / steps as f32, (s + 1) as f32 / steps as f32);
+        let (p, r) = (a.point(s0), sub(a.point(s1), a.point(s0)));
+        for v in 0..steps {
+            let (v0, v1) = (v as f32 / steps as f32, (v + 1) as f32 / steps as f32);
+            let (q, e) = (b.point(v0), sub(b.point(v1), b.point(v0)));
+            let denominator = cross(r, e);
+            if denominator == 0f32 {
+                continue;
+            }
+            let t = cross(sub(q, p), e) / denominator;
+            let u = cross(sub(q, p), r) / denominator;
+            if !(0f32..=1f32).contains(&t) || !(0f32..=1f32).contains(&u) {
+                continue;
+            }
+            let (t, u) = (s0 + t * (s1 - s0), v0 + u * (v1 - v0));
+            if best.is_none_or(|(nearest, _, _)| t > nearest) {
+                best = Some((t, u, add(p, scale(r, (t - s0) * steps as f32))));
+            }
+        }
+    }
+    return best;
+}
+
+fn distance_to_pieces(pieces: &[Piece], point: Point) -> f32 {
+    // Distance from a point to the nearest of a few chords along each piece
+    let steps = 4;
+    let mut nearest = f32::MAX;
+    for piece in pieces.iter() {
+        for s in 0..steps {
+            let a = piece.point(s as f32 / steps as f32);
+            let ab = sub(piece.point((s + 1) as f32 / steps as f32), a);
+            let t = if dot(ab, ab) == 0f32 { 0f32 } else { (dot(sub(point, a), ab) / dot(ab, ab)).clamp(0f32, 1f32) };
+            nearest = nearest.min(length(sub(point, add(a, scale(ab, t)))));
+        }
+    }
+    return nearest;
+}
+
+fn angle_between(a: Point, b: Point) -> f32 {
+    return cross(a, b).atan2(dot(a, b)).abs();
+}
+
+fn direction(from: Point, to: Point) -> Point {
+    return normalize((to.0 - from.0, to.1 - from.1));
+}
+
+fn normal(tangent: Point) -> Point {
+    // Left-hand normal (rotated a quarter turn counter-clockwise)
+    return (-tangent.1, tangent.0);
+}
+
+fn normalize(v: Point) -> Point {
+    let l = length(v);
+    if l == 0f32 {
+        return (0f32, 0f32);
+    }
+    return (v.0 / l, v.1 / l);
+}
+
+fn length(v: Point) -> f32 {
+    return (v.0 * v.0 + v.1 * v.1).sqrt();
+}
+
+fn add(a: Point, b: Point) -> Point {
+    return (a.0 + b.0, a.1 + b.1);
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    return (a.0 - b.0, a.1 - b.1);
+}
+
+fn scale(v: Point, s: f32) -> Point {
+    return (v.0 * s, v.1 * s);
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    return ((a.0 + b.0) / 2f32, (a.1 + b.1) / 2f32);
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    return a.0 * b.0 + a.1 * b.1;
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    return a.0 * b.1 - a.1 * b.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::OutlineBuilder;
+
+    use crate::curve::{winding_number, Contour, Curve, Element};
+    use crate::float::Precision;
+    use crate::flatten::flatten;
+    use crate::outline::Builder;
+    use crate::scanline::GLYPH_FILL;
+    use super::{parse_stroke_style, stroke_outline, LineCap, LineJoin, StrokeStyle};
+
+    // Control point distance for a quarter circle as a cubic
+    const KAPPA: f32 = 0.552_284_8;
+
+    fn square() -> Vec<Contour> {
+        // 0 to 100, counter-clockwise
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.line_to(100f32, 0f32);
+        builder.line_to(100f32, 100f32);
+        builder.line_to(0f32, 100f32);
+        builder.close();
+        return builder.contours;
+    }
+
+    fn circle(radius: f32) -> Vec<Contour> {
+        let (r, k) = (radius, radius * KAPPA);
+        let mut builder = Builder::default();
+        builder.move_to(r, 0f32);
+        builder.curve_to(r, k, k, r, 0f32, r);
+        builder.curve_to(-k, r, -r, k, -r, 0f32);
+        builder.curve_to(-r, -k, -k, -r, 0f32, -r);
+        builder.curve_to(k, -r, r, -k, r, 0f32);
+        builder.close();
+        return builder.contours;
+    }
+
+    fn line() -> Vec<Contour> {
+        // Open, from (0, 0) to (100, 0)
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.line_to(100f32, 0f32);
+        return builder.contours;
+    }
+
+    fn covers(contours: &[Contour], x: f32, y: f32) -> bool {
+        return GLYPH_FILL.contains(winding_number(contours, x, y, 1f32, 0f32, Precision::Double));
+    }
+
+    fn stroke(contours: &[Contour], join: LineJoin, miter_limit: f32, cap: LineCap) -> Vec<Contour> {
+        return stroke_outline(contours, &StrokeStyle { width: 20f32, join, miter_limit, cap }).0;
+    }
+
+    #[test]
+    fn square_joins() {
+        let miter = stroke(&square(), LineJoin::Miter, 4f32, LineCap::Butt);
+        let limited = stroke(&square(), LineJoin::Miter, 1.2f32, LineCap::Butt);
+        let bevel = stroke(&square(), LineJoin::Bevel, 4f32, LineCap::Butt);
+        let round = stroke(&square(), LineJoin::Round, 4f32, LineCap::Butt);
+        for (name, outline) in [("miter", &miter), ("limited", &limited), ("bevel", &bevel), ("round", &round)] {
+            // Half the width on both sides of every edge, and nothing in the middle
+            for (x, y) in [(50f32, -9f32), (50f32, 9f32), (109f32, 50f32), (91f32, 50f32), (50f32, 109f32), (-9f32, 50f32), (5f32, 5f32), (95f32, 95f32)] {
+                assert!(covers(outline, x, y), "{} leaves out ({}, {})", name, x, y);
+            }
+            for (x, y) in [(50f32, -11f32), (50f32, 11f32), (111f32, 50f32), (50f32, 50f32), (11f32, 11f32), (89f32, 89f32)] {
+                assert!(!covers(outline, x, y), "{} covers ({}, {})", name, x, y);
+            }
+        }
+        // The corners: a miter goes all the way to (-10, -10), a bevel cuts across from (-10, 0) to (0, -10)
+        assert!(covers(&miter, -9f32, -9f32) && !covers(&miter, -11f32, -11f32));
+        assert!(covers(&bevel, -4f32, -4f32) && !covers(&bevel, -6f32, -6f32));
+        // A miter limit below sqrt(2) turns right angles into bevels
+        assert!(covers(&limited, -4f32, -4f32) && !covers(&limited, -6f32, -6f32));
+        // A round join is 10 from the corner
+        assert!(covers(&round, -6.7f32, -6.7f32) && !covers(&round, -7.5f32, -7.5f32));
+        // The same at the other corners
+        for (x, y) in [(109f32, -9f32), (109f32, 109f32), (-9f32, 109f32)] {
+            assert!(covers(&miter, x, y) && !covers(&bevel, x, y), "({}, {})", x, y);
+        }
+
+        let bbox = stroke_outline(&square(), &StrokeStyle { width: 20f32, join: LineJoin::Miter, miter_limit: 4f32, cap: LineCap::Butt }).1.unwrap();
+        assert_eq!((bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max), (-10, -10, 110, 110));
+    }
+
+    #[test]
+    fn circle_offsets() {
+        // Both sides of the stroke stay 10 from the circle all the way round
+        for join in [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel] {
+            let ring = stroke(&circle(50f32), join, 4f32, LineCap::Butt);
+            for i in 0..64 {
+                let angle = i as f32 / 64f32 * std::f32::consts::TAU;
+                let at = |r: f32| (r * angle.cos(), r * angle.sin());
+                for (r, expected) in [(39f32, false), (41f32, true), (50f32, true), (59f32, true), (61f32, false)] {
+                    let (x, y) = at(r);
+                    assert_eq!(covers(&ring, x, y), expected, "{:?} at radius {}, angle {}", join, r, angle);
+                }
+            }
+            assert!(!covers(&ring, 0f32, 0f32));
+        }
+    }
+
+    #[test]
+    fn line_caps() {
+        let butt = stroke(&line(), LineJoin::Miter, 4f32, LineCap::Butt);
+        let square = stroke(&line(), LineJoin::Miter, 4f32, LineCap::Square);
+        let round = stroke(&line(), LineJoin::Miter, 4f32, LineCap::Round);
+        for (name, outline) in [("butt", &butt), ("square", &square), ("round", &round)] {
+            assert!(covers(outline, 50f32, 9f32) && covers(outline, 50f32, -9f32), "{}", name);
+            assert!(!covers(outline, 50f32, 11f32) && !covers(outline, 50f32, -11f32), "{}", name);
+            assert_eq!(outline.len(), 1, "{}: open contours give one contour around both sides", name);
+        }
+        for (x, outside) in [(0f32, -1f32), (100f32, 1f32)] {
+            // Butt caps end at the end points
+            assert!(covers(&butt, x - outside, 0f32) && !covers(&butt, x + outside, 0f32));
+            // Square caps go on by half the width, corners included
+            assert!(covers(&square, x + 9f32 * outside, 9f32) && covers(&square, x + 9f32 * outside, -9f32));
+            assert!(!covers(&square, x + 11f32 * outside, 0f32));
+            // Round caps are half circles
+            assert!(covers(&round, x + 9f32 * outside, 0f32) && covers(&round, x + 6f32 * outside, 6f32));
+            assert!(!covers(&round, x + 8f32 * outside, 8f32) && !covers(&round, x + 11f32 * outside, 0f32));
+        }
+    }
+
+    #[test]
+    fn cubic_offset_distance() {
+        // Every point of the sides of a stroked S curve is half the width from the curve
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.curve_to(100f32, 200f32, 200f32, -100f32, 300f32, 100f32);
+        let original = builder.contours;
+        let reference = flatten(&original, 0.001f32, 1f32);
+        let distance = |(x, y): (f32, f32)| -> f32 {
+            let mut nearest = f32::MAX;
+            for pair in reference[0].points.windows(2) {
+                let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+                let (dx, dy) = (bx - ax, by - ay);
+                let t = (((x - ax) * dx + (y - ay) * dy) / (dx * dx + dy * dy)).clamp(0f32, 1f32);
+                nearest = nearest.min((x - ax - t * dx).hypot(y - ay - t * dy));
+            }
+            return nearest;
+        };
+        let stroked = stroke(&original, LineJoin::Round, 4f32, LineCap::Butt);
+        let mut checked = 0;
+        for element in stroked[0].curves.iter() {
+            // The butt caps and the short lines closing gaps between split pieces are the only lines
+            if let Element::Quad(quad) = element {
+                for i in 0..=8 {
+                    let d = distance(quad.evaluate(i as f32 / 8f32));
+                    assert!((d - 10f32).abs() < 0.2f32, "{:?} is {} from the curve", quad, d);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 100);
+        assert!(stroked[0].curves.iter().all(|c| !matches!(c, Element::Cubic(_))), "cubics are offset as quads");
+    }
+
+    #[test]
+    fn style_settings() {
+        let style = parse_stroke_style("width=3, join=round, miter-limit=2, cap=square");
+        assert_eq!(style, StrokeStyle { width: 3f32, join: LineJoin::Round, miter_limit: 2f32, cap: LineCap::Square });
+        // Unknown and malformed settings keep the defaults
+        assert_eq!(parse_stroke_style("width=x, join=sharp, colour=red, cap"), StrokeStyle::default());
+    }
+}
@@ -0,0 +1,1368 @@
+/* Color glyphs from the OpenType SVG table.
+https://learn.microsoft.com/en-us/typography/opentype/spec/svg
+
+Only the subset of SVG that fonts actually use is supported: <g>, <path>,
+the basic shapes, <use>, transforms, solid fills, linear and radial gradients
+and group opacity. Strokes, clip paths, masks, filters, text and CSS style
+sheets are ignored. Documents are read with a small XML parser that keeps
+elements and attributes only.
+
+SVG glyphs are drawn in a y-down coordinate system with the origin on the
+baseline and one user unit per font unit, so the transform from the document
+to font units is the root viewBox, if any, followed by a flip of the y axis.
+Paths are flattened to line segments and filled with a winding number test,
+since SVG fills default to the nonzero rule which a parity test on the
+crossings can't give. */
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::bitmap::{composite_pixel, BitmapBounds, CompositeMode, RgbaBitmap};
+use crate::colr::{fill_color, premultiply, ColorLine, Extend, Paint};
+use crate::curve::Contour;
+use crate::outline::Builder;
+use crate::scanline::FillRule;
+use crate::transform::Transform;
+
+// Guards against <use> cycles and absurdly deep documents
+const MAX_DEPTH: u32 = 64;
+// Line segments per curve when flattening
+const CURVE_STEPS: usize = 16;
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+#[derive(Debug, Clone)]
+enum Fill {
+    None,
+    Color([f32; 4]),
+    CurrentColor,
+    Url(String),
+}
+
+// Inherited presentation properties
+#[derive(Debug, Clone)]
+struct Style {
+    fill: Fill,
+    fill_opacity: f32,
+    fill_rule: FillRule,
+    color: [f32; 4],
+}
+
+struct PathBuilder {
+    // Maps user space to font units
+    transform: Transform,
+    // Flattened edges (x0, y0, x1, y1) in font units
+    edges: Vec<(f32, f32, f32, f32)>,
+    // Subpath start and current point, in user space
+    start: (f32, f32),
+    current: (f32, f32),
+    open: bool,
+    // Bounding box of the path in user space (for objectBoundingBox gradients)
+    bounds: Option<BitmapBounds>,
+    // The path as contours in user space, before flattening
+    outline: Builder,
+}
+
+// Arguments of an elliptical arc command, in user space
+struct EllipticalArc {
+    rx: f32,
+    ry: f32,
+    angle: f32,
+    large_arc: bool,
+    sweep: bool,
+    to: (f32, f32),
+}
+
+struct Renderer<'a> {
+    root: &'a Element,
+    ratio: f32,
+    samples: i32,
+    bounds: BitmapBounds,
+    units_per_em: f32,
+}
+
+pub fn render_svg_glyph(face: &Face, glyph_id: GlyphId, foreground: [f32; 4], ratio: f32, samples: i32) -> Option<(RgbaBitmap, BitmapBounds)> {
+    /* Renders the SVG version of a glyph at the given scale (pixels per font
+    unit) with samples x samples supersampling. The bitmap covers the em box,
+    from the origin to units_per_em horizontally and from the descender up by
+    units_per_em vertically. foreground is the unpremultiplied text color used
+    for currentColor. Returns None if the glyph has no SVG document or the
+    document can't be read. */
+    let data = face.glyph_svg_image(glyph_id)?;
+    let text = decode_document(data)?;
+    let root = parse_xml(&text)?;
+    return Some(render_document(&root, glyph_id, face.units_per_em() as f32, face.descender() as f32, foreground, ratio, samples));
+}
+
+fn render_document(root: &Element, glyph_id: GlyphId, units_per_em: f32, descender: f32, foreground: [f32; 4], ratio: f32, samples: i32) -> (RgbaBitmap, BitmapBounds) {
+    let bounds = BitmapBounds { x_min: 0f32, y_min: descender, x_max: units_per_em, y_max: descender + units_per_em };
+    let size = (units_per_em * ratio).ceil().max(0f32) as usize;
+    let mut bitmap = RgbaBitmap::new(size, size);
+    let renderer = Renderer { root, ratio, samples: samples.max(1), bounds, units_per_em };
+
+    // A document can hold several glyphs, each one in an element with id="glyph<id>"
+    let id = format!("glyph{}", glyph_id.0);
+    let mut ancestors = Vec::<&Element>::new();
+    let mut style = Style { fill: Fill::Color([0f32, 0f32, 0f32, 1f32]), fill_opacity: 1f32, fill_rule: FillRule::NonZero, color: foreground };
+    let mut transform = Transform::scale(1f32, -1f32).then(&view_box_transform(root, units_per_em));
+    if find_ancestors(root, &id, &mut ancestors) {
+        if let Some(glyph) = ancestors.pop() {
+            for ancestor in ancestors {
+                style = inherit_style(&style, ancestor);
+                transform = transform.then(&parse_transform(ancestor.attribute("transform").unwrap_or("")));
+            }
+            renderer.render_element(glyph, &transform, &style, &mut bitmap, 0);
+        }
+    } else {
+        renderer.render_element(root, &transform, &style, &mut bitmap, 0);
+    }
+    return (bitmap, bounds);
+}
+
+fn view_box_transform(root: &Element, units_per_em: f32) -> Transform {
+    /* Maps the root viewBox onto the viewport, which starts at the glyph origin
+    and is one em square unless the root sets width and height. The default
+    preserveAspectRatio scales uniformly and centers the view box. */
+    let view_box = parse_numbers(root.attribute("viewBox").unwrap_or(""));
+    if root.name != "svg" || view_box.len() != 4 || view_box[2] <= 0f32 || view_box[3] <= 0f32 {
+        return Transform::IDENTITY;
+    }
+    let (min_x, min_y, width, height) = (view_box[0], view_box[1], view_box[2], view_box[3]);
+    let viewport_width = parse_length(root.attribute("width"), units_per_em, units_per_em);
+    let viewport_height = parse_length(root.attribute("height"), units_per_em, units_per_em);
+    let (mut sx, mut sy) = (viewport_width / width, viewport_height / height);
+    let (mut dx, mut dy) = (0f32, 0f32);
+    if root.attribute("preserveAspectRatio").map(str::trim) != Some("none") {
+        let s = sx.min(sy);
+        dx = (viewport_width - width * s) / 2f32;
+        dy = (viewport_height - height * s) / 2f32;
+        sx = s;
+        sy = s;
+    }
+    return Transform::translate(dx, dy).then(&Transform::scale(sx, sy)).then(&Transform::translate(-min_x, -min_y));
+}
+
+fn decode_document(data: &[u8]) -> Option<String> {
+    // Documents may be gzip-compressed (SVGZ)
+    if data.starts_with(&[0x1F, 0x8B]) {
+        let mut decompressed = Vec::<u8>::new();
+        GzDecoder::new(data).read_to_end(&mut decompressed).ok()?;
+        return Some(String::from_utf8_lossy(&decompressed).into_owned());
+    }
+    return Some(String::from_utf8_lossy(data).into_owned());
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        return self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        // Declarations in the style attribute win over presentation attributes
+        if let Some(style) = self.attribute("style") {
+            for declaration in style.split(';') {
+                if let Some((key, value)) = declaration.split_once(':') {
+                    if key.trim() == name {
+                        return Some(value.trim().to_string());
+                    }
+                }
+            }
+        }
+        return self.attribute(name).map(|v| v.trim().to_string());
+    }
+
+    fn href(&self) -> Option<&str> {
+        let href = self.attribute("href").or_else(|| self.attribute("xlink:href"))?;
+        return href.trim().strip_prefix('#');
+    }
+}
+
+fn parse_xml(text: &str) -> Option<Element> {
+    /* Minimal XML reader: returns the root element with its attributes and
+    child elements. Text content, comments, processing instructions, CDATA
+    and the doctype are skipped. */
+    let mut stack = Vec::<Element>::new();
+    let mut root: Option<Element> = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let skip_to = |rest: &str, end: &str| -> Option<usize> { return Some(rest.find(end)? + end.len()); };
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to(rest, "-->")?..];
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            rest = &rest[skip_to(rest, "]]>")?..];
+            continue;
+        }
+        if rest.starts_with("<?") {
+            rest = &rest[skip_to(rest, "?>")?..];
+            continue;
+        }
+        if rest.starts_with("<!") {
+            // The doctype can have an internal subset in brackets
+            let close = rest.find('>')?;
+            let end = match rest.find('[') {
+                Some(bracket) if bracket < close => skip_to(rest, "]>")?,
+                _ => close + 1,
+            };
+            rest = &rest[end..];
+            continue;
+        }
+        let end = find_tag_end(rest)?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('/') {
+            let element = stack.pop()?;
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => root = root.or(Some(element)),
+            }
+            continue;
+        }
+        let (tag, self_closing) = match tag.strip_suffix('/') {
+            Some(t) => (t, true),
+            None => (tag, false),
+        };
+        let element = parse_tag(tag);
+        if self_closing {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => root = root.or(Some(element)),
+            }
+        } else {
+            stack.push(element);
+        }
+    }
+    return root;
+}
+
+fn find_tag_end(text: &str) -> Option<usize> {
+    // Position of the '>' closing the tag at the start of text, skipping quoted attribute values
+    let mut quote: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return Some(i),
+            None => {},
+        }
+    }
+    return None;
+}
+
+fn parse_tag(tag: &str) -> Element {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    // Drop namespace prefixes like svg:path
+    let name = tag[..name_end].rsplit(':').next().unwrap_or("").to_string();
+    let mut attributes = Vec::<(String, String)>::new();
+    let mut rest = &tag[name_end..];
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim().to_string();
+        let value_part = rest[equals + 1..].trim_start();
+        let quote = match value_part.chars().next() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => break,
+        };
+        let value_end = match value_part[1..].find(quote) {
+            Some(e) => e + 1,
+            None => break,
+        };
+        attributes.push((key, decode_entities(&value_part[1..value_end])));
+        rest = &value_part[value_end + 1..];
+    }
+    return Element { name, attributes, children: Vec::<Element>::new() };
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let semicolon = match rest.find(';') {
+            Some(s) => s,
+            None => break,
+        };
+        let entity = &rest[1..semicolon];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse::<u32>().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+            },
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[semicolon + 1..];
+            },
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+    return result;
+}
+
+fn find_ancestors<'a>(element: &'a Element, id: &str, path: &mut Vec<&'a Element>) -> bool {
+    // Fills path with the chain of elements from element down to the one with the given id
+    path.push(element);
+    if element.attribute("id") == Some(id) {
+        return true;
+    }
+    for child in element.children.iter() {
+        if find_ancestors(child, id, path) {
+            return true;
+        }
+    }
+    path.pop();
+    return false;
+}
+
+fn find_by_id<'a>(element: &'a Element, id: &str) -> Option<&'a Element> {
+    if element.attribute("id") == Some(id) {
+        return Some(element);
+    }
+    return element.children.iter().find_map(|child| find_by_id(child, id));
+}
+
+fn inherit_style(parent: &Style, element: &Element) -> Style {
+    let mut style = parent.clone();
+    if let Some(color) = element.property("color").and_then(|c| parse_color(&c)) {
+        style.color = color;
+    }
+    if let Some(fill) = element.property("fill").and_then(|f| parse_fill(&f)) {
+        style.fill = fill;
+    }
+    if let Some(opacity) = element.property("fill-opacity").and_then(|o| parse_opacity(&o)) {
+        style.fill_opacity = opacity;
+    }
+    match element.property("fill-rule").as_deref() {
+        Some("evenodd") => style.fill_rule = FillRule::EvenOdd,
+        Some("nonzero") => style.fill_rule = FillRule::NonZero,
+        _ => {}
+    }
+    return style;
+}
+
+fn parse_fill(value: &str) -> Option<Fill> {
+    if value == "none" {
+        return Some(Fill::None);
+    }
+    if value == "currentColor" {
+        return Some(Fill::CurrentColor);
+    }
+    if let Some(url) = value.strip_prefix("url(") {
+        // Fallback colors after the url are ignored
+        let end = url.find(')')?;
+        let id = url[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        return Some(Fill::Url(id.strip_prefix('#').unwrap_or(id).to_string()));
+    }
+    return parse_color(value).map(Fill::Color);
+}
+
+fn parse_opacity(value: &str) -> Option<f32> {
+    let opacity = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok()? / 100f32,
+        None => value.parse::<f32>().ok()?,
+    };
+    return Some(opacity.clamp(0f32, 1f32));
+}
+
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    // Unpremultiplied RGBA from a CSS color: #rgb, #rrggbb, #rrggbbaa, rgb(), rgba() or a basic color name
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digit = |i: usize| -> Option<f32> { return Some(u8::from_str_radix(hex.get(i..i + 1)?, 16).ok()? as f32 * 17f32 / 255f32); };
+        let byte = |i: usize| -> Option<f32> { return Some(u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()? as f32 / 255f32); };
+        return match hex.len() {
+            3 => Some([digit(0)?, digit(1)?, digit(2)?, 1f32]),
+            4 => Some([digit(0)?, digit(1)?, digit(2)?, digit(3)?]),
+            6 => Some([byte(0)?, byte(2)?, byte(4)?, 1f32]),
+            8 => Some([byte(0)?, byte(2)?, byte(4)?, byte(6)?]),
+            _ => None,
+        };
+    }
+    if let Some(arguments) = value.strip_prefix("rgba(").or_else(|| value.strip_prefix("rgb(")) {
+        let arguments = arguments.strip_suffix(')')?;
+        let parts: Vec<&str> = arguments.split(|c: char| c == ',' || c == '/' || c.is_whitespace()).filter(|p| !p.is_empty()).collect();
+        if parts.len() < 3 {
+            return None;
+        }
+        let channel = |part: &str| -> Option<f32> {
+            return match part.strip_suffix('%') {
+                Some(percent) => Some((percent.parse::<f32>().ok()? / 100f32).clamp(0f32, 1f32)),
+                None => Some((part.parse::<f32>().ok()? / 255f32).clamp(0f32, 1f32)),
+            };
+        };
+        let alpha = match parts.get(3) {
+            Some(a) => parse_opacity(a)?,
+            None => 1f32,
+        };
+        return Some([channel(parts[0])?, channel(parts[1])?, channel(parts[2])?, alpha]);
+    }
+    let rgb: [u8; 3] = match value.to_ascii_lowercase().as_str() {
+        "transparent" => return Some([0f32; 4]),
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "red" => [255, 0, 0],
+        "lime" => [0, 255, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" | "aqua" => [0, 255, 255],
+        "magenta" | "fuchsia" => [255, 0, 255],
+        "gray" | "grey" => [128, 128, 128],
+        "silver" => [192, 192, 192],
+        "maroon" => [128, 0, 0],
+        "olive" => [128, 128, 0],
+        "navy" => [0, 0, 128],
+        "purple" => [128, 0, 128],
+        "teal" => [0, 128, 128],
+        "orange" => [255, 165, 0],
+        "brown" => [165, 42, 42],
+        "pink" => [255, 192, 203],
+        "gold" => [255, 215, 0],
+        _ => return None,
+    };
+    return Some([rgb[0] as f32 / 255f32, rgb[1] as f32 / 255f32, rgb[2] as f32 / 255f32, 1f32]);
+}
+
+fn parse_length(value: Option<&str>, reference: f32, default: f32) -> f32 {
+    // Plain numbers and px are user units, percentages are relative to reference
+    let value = match value {
+        Some(v) => v.trim(),
+        None => return default,
+    };
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent.trim().parse::<f32>().map(|p| p / 100f32 * reference).unwrap_or(default);
+    }
+    return value.trim_end_matches("px").parse::<f32>().unwrap_or(default);
+}
+
+fn parse_numbers(text: &str) -> Vec<f32> {
+    let mut lexer = Lexer { data: text.as_bytes(), pos: 0 };
+    let mut numbers = Vec::<f32>::new();
+    while let Some(n) = lexer.number() {
+        numbers.push(n);
+    }
+    return numbers;
+}
+
+fn parse_transform(text: &str) -> Transform {
+    // Transform lists like "translate(10 20) rotate(45)", applied right to left
+    let mut result = Transform::IDENTITY;
+    let mut rest = text;
+    while let Some(open) = rest.find('(') {
+        let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+        let close = match rest[open..].find(')') {
+            Some(c) => open + c,
+            None => break,
+        };
+        let v = parse_numbers(&rest[open + 1..close]);
+        let transform = match (name, v.len()) {
+            ("matrix", 6) => Transform { xx: v[0], yx: v[1], xy: v[2], yy: v[3], dx: v[4], dy: v[5] },
+            ("translate", 1) => Transform::translate(v[0], 0f32),
+            ("translate", 2) => Transform::translate(v[0], v[1]),
+            ("scale", 1) => Transform::scale(v[0], v[0]),
+            ("scale", 2) => Transform::scale(v[0], v[1]),
+            ("rotate", 1) | ("rotate", 3) => {
+                let angle = v[0].to_radians();
+                let rotation = Transform::rotate(angle);
+                if v.len() == 3 { rotation.around(v[1], v[2]) } else { rotation }
+            },
+            ("skewX", 1) => Transform::skew(v[0].to_radians().tan(), 0f32),
+            ("skewY", 1) => Transform::skew(0f32, v[0].to_radians().tan()),
+            _ => Transform::IDENTITY,
+        };
+        result = result.then(&transform);
+        rest = &rest[close + 1..];
+    }
+    return result;
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn skip_separators(&mut self) {
+        while self.pos < self.data.len() && (self.data[self.pos].is_ascii_whitespace() || self.data[self.pos] == b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let c = *self.data.get(self.pos)?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.pos += 1;
+            return Some(c);
+        }
+        return None;
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+        return matches!(self.data.get(self.pos), Some(c) if c.is_ascii_digit() || *c == b'.' || *c == b'-' || *c == b'+');
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        // Numbers can run into each other, e.g. "1.5.5" is 1.5 then .5 and "1-2" is 1 then -2
+        if !self.at_number() {
+            return None;
+        }
+        let start = self.pos;
+        let digits = |lexer: &mut Lexer| {
+            while lexer.pos < lexer.data.len() && lexer.data[lexer.pos].is_ascii_digit() {
+                lexer.pos += 1;
+            }
+        };
+        if self.data[self.pos] == b'-' || self.data[self.pos] == b'+' {
+            self.pos += 1;
+        }
+        digits(self);
+        if self.data.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            digits(self);
+        }
+        if matches!(self.data.get(self.pos), Some(b'e') | Some(b'E')) {
+            let exponent = self.data.get(self.pos + 1);
+            let exponent_sign = exponent == Some(&b'-') || exponent == Some(&b'+');
+            let digit_at = if exponent_sign { self.pos + 2 } else { self.pos + 1 };
+            if matches!(self.data.get(digit_at), Some(c) if c.is_ascii_digit()) {
+                self.pos = digit_at;
+                digits(self);
+            }
+        }
+        return std::str::from_utf8(&self.data[start..self.pos]).ok()?.parse::<f32>().ok();
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        // Arc flags are single digits that don't need separators ("a1 1 0 0110 10")
+        self.skip_separators();
+        let c = *self.data.get(self.pos)?;
+        self.pos += 1;
+        return match c {
+            b'0' => Some(false),
+            b'1' => Some(true),
+            _ => None,
+        };
+    }
+}
+
+impl PathBuilder {
+    fn new(transform: Transform) -> PathBuilder {
+        return PathBuilder { transform, edges: Vec::new(), start: (0f32, 0f32), current: (0f32, 0f32), open: false, bounds: None, outline: Builder::default() };
+    }
+
+    fn include(&mut self, x: f32, y: f32) {
+        self.bounds = Some(match self.bounds {
+            Some(b) => BitmapBounds { x_min: b.x_min.min(x), y_min: b.y_min.min(y), x_max: b.x_max.max(x), y_max: b.y_max.max(y) },
+            None => BitmapBounds { x_min: x, y_min: y, x_max: x, y_max: y },
+        });
+    }
+
+    fn edge_to(&mut self, x: f32, y: f32) {
+        let (x0, y0) = self.transform.apply(self.current.0, self.current.1);
+        let (x1, y1) = self.transform.apply(x, y);
+        self.edges.push((x0, y0, x1, y1));
+        self.current = (x, y);
+    }
+
+    fn ensure_open(&mut self) {
+        // Drawing after a close starts a new subpath at the current point
+        if !self.open {
+            let (x, y) = self.current;
+            self.move_to(x, y);
+        }
+    }
+
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.start = (x, y);
+        self.current = (x, y);
+        self.open = true;
+        self.include(x, y);
+        self.outline.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.ensure_open();
+        self.include(x, y);
+        self.outline.line_to(x, y);
+        self.edge_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.ensure_open();
+        let (x0, y0) = self.current;
+        self.include(x1, y1);
+        self.include(x, y);
+        self.outline.quad_to(x1, y1, x, y);
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let u = 1f32 - t;
+            self.edge_to(u * u * x0 + 2f32 * u * t * x1 + t * t * x, u * u * y0 + 2f32 * u * t * y1 + t * t * y);
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.ensure_open();
+        let (x0, y0) = self.current;
+        self.include(x1, y1);
+        self.include(x2, y2);
+        self.include(x, y);
+        self.outline.curve_to(x1, y1, x2, y2, x, y);
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let u = 1f32 - t;
+            let (a, b, c, d) = (u * u * u, 3f32 * u * u * t, 3f32 * u * t * t, t * t * t);
+            self.edge_to(a * x0 + b * x1 + c * x2 + d * x, a * y0 + b * y1 + c * y2 + d * y);
+        }
+    }
+
+    fn arc_to(&mut self, arc: &EllipticalArc) {
+        /* Elliptical arc from the current point, converted to cubics of at most
+        90 degrees each. See the endpoint to center conversion in
+        https://www.w3.org/TR/SVG11/implnote.html#ArcImplementationNotes */
+        self.ensure_open();
+        let (x0, y0) = self.current;
+        let (x, y) = arc.to;
+        let (large_arc, sweep) = (arc.large_arc, arc.sweep);
+        if x0 == x && y0 == y {
+            return;
+        }
+        let (mut rx, mut ry) = (arc.rx.abs(), arc.ry.abs());
+        if rx == 0f32 || ry == 0f32 {
+            self.line_to(x, y);
+            return;
+        }
+        let phi = arc.angle.to_radians();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let hx = (x0 - x) / 2f32;
+        let hy = (y0 - y) / 2f32;
+        let x1p = cos_phi * hx + sin_phi * hy;
+        let y1p = -sin_phi * hx + cos_phi * hy;
+        // Scale up radii that are too small to reach the end point
+        let lambda = (x1p * x1p) / (rx * rx) + (y1p * y1p) / (ry * ry);
+        if lambda > 1f32 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = rx * rx * ry * ry - rx * rx * y1p * y1p - ry * ry * x1p * x1p;
+        let denominator = rx * rx * y1p * y1p + ry * ry * x1p * x1p;
+        let mut coefficient = (numerator / denominator).max(0f32).sqrt();
+        if large_arc == sweep {
+            coefficient = -coefficient;
+        }
+        let cxp = coefficient * rx * y1p / ry;
+        let cyp = -coefficient * ry * x1p / rx;
+        let cx = cos_phi * cxp - sin_phi * cyp + (x0 + x) / 2f32;
+        let cy = sin_phi * cxp + cos_phi * cyp + (y0 + y) / 2f32;
+
+        let vector_angle = |ux: f32, uy: f32| uy.atan2(ux);
+        let theta1 = vector_angle((x1p - cxp) / rx, (y1p - cyp) / ry);
+        let mut delta = vector_angle((-x1p - cxp) / rx, (-y1p - cyp) / ry) - theta1;
+        if sweep && delta < 0f32 {
+            delta += 2f32 * std::f32::consts::PI;
+        } else if !sweep && delta > 0f32 {
+            delta -= 2f32 * std::f32::consts::PI;
+        }
+
+        let pieces = (delta.abs() / std::f32::consts::FRAC_PI_2).ceil().max(1f32) as usize;
+        let step = delta / pieces as f32;
+        let k = 4f32 / 3f32 * (step / 4f32).tan();
+        let point = |t: f32| -> (f32, f32) {
+            let (sin_t, cos_t) = t.sin_cos();
+            return (cx + rx * cos_t * cos_phi - ry * sin_t * sin_phi, cy + rx * cos_t * sin_phi + ry * sin_t * cos_phi);
+        };
+        let derivative = |t: f32| -> (f32, f32) {
+            let (sin_t, cos_t) = t.sin_cos();
+            return (-rx * sin_t * cos_phi - ry * cos_t * sin_phi, -rx * sin_t * sin_phi + ry * cos_t * cos_phi);
+        };
+        for i in 0..pieces {
+            let t0 = theta1 + step * i as f32;
+            let t1 = t0 + step;
+            let (p0x, p0y) = point(t0);
+            let (d0x, d0y) = derivative(t0);
+            let (p1x, p1y) = if i == pieces - 1 { (x, y) } else { point(t1) };
+            let (d1x, d1y) = derivative(t1);
+            self.curve_to(p0x + k * d0x, p0y + k * d0y, p1x - k * d1x, p1y - k * d1y, p1x, p1y);
+        }
+    }
+
+    fn close(&mut self) {
+        // Fills always close their subpaths
+        if self.open && self.current != self.start {
+            let (x, y) = self.start;
+            self.edge_to(x, y);
+        }
+        if self.open {
+            self.outline.close();
+        }
+        self.current = self.start;
+        self.open = false;
+    }
+}
+
+pub fn parse_path(data: &str) -> Vec<Contour> {
+    /* Reads SVG path data (the d attribute, e.g. "M 100 500 Q 650 100 1200 500")
+    into contours in the path's own coordinates, with y going down. Arcs become
+    cubics, and anything after an error is dropped. Subpaths are only closed
+    where the data says so. */
+    let mut path = PathBuilder::new(Transform::IDENTITY);
+    let _ = build_path(data, &mut path);
+    // Ends the last subpath without closing it
+    path.open = false;
+    return path.outline.contours;
+}
+
+fn build_path(data: &str, path: &mut PathBuilder) -> Option<()> {
+    // Reads SVG path data into the builder, stopping at the first error like browsers do
+    let mut lexer = Lexer { data: data.as_bytes(), pos: 0 };
+    let mut command = 0u8;
+    // Control points for the S and T shorthands
+    let mut cubic_control: Option<(f32, f32)> = None;
+    let mut quad_control: Option<(f32, f32)> = None;
+    loop {
+        match lexer.command() {
+            Some(c) => command = c,
+            None => {
+                if command == 0 || !lexer.at_number() {
+                    return Some(());
+                }
+            },
+        }
+        let (ox, oy) = if command.is_ascii_lowercase() { path.current } else { (0f32, 0f32) };
+        let (cx, cy) = path.current;
+        let mut next_cubic_control: Option<(f32, f32)> = None;
+        let mut next_quad_control: Option<(f32, f32)> = None;
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.move_to(x, y);
+                // Coordinates following a moveto are linetos
+                command = if command == b'm' { b'l' } else { b'L' };
+            },
+            b'L' => {
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.line_to(x, y);
+            },
+            b'H' => {
+                let x = ox + lexer.number()?;
+                path.line_to(x, cy);
+            },
+            b'V' => {
+                let y = oy + lexer.number()?;
+                path.line_to(cx, y);
+            },
+            b'C' => {
+                let (x1, y1) = (ox + lexer.number()?, oy + lexer.number()?);
+                let (x2, y2) = (ox + lexer.number()?, oy + lexer.number()?);
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.curve_to(x1, y1, x2, y2, x, y);
+                next_cubic_control = Some((x2, y2));
+            },
+            b'S' => {
+                let (x1, y1) = match cubic_control {
+                    Some((px, py)) => (2f32 * cx - px, 2f32 * cy - py),
+                    None => (cx, cy),
+                };
+                let (x2, y2) = (ox + lexer.number()?, oy + lexer.number()?);
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.curve_to(x1, y1, x2, y2, x, y);
+                next_cubic_control = Some((x2, y2));
+            },
+            b'Q' => {
+                let (x1, y1) = (ox + lexer.number()?, oy + lexer.number()?);
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.quad_to(x1, y1, x, y);
+                next_quad_control = Some((x1, y1));
+            },
+            b'T' => {
+                let (x1, y1) = match quad_control {
+                    Some((px, py)) => (2f32 * cx - px, 2f32 * cy - py),
+                    None => (cx, cy),
+                };
+                let (x, y) = (ox + lexer.number()?, oy + lexer.number()?);
+                path.quad_to(x1, y1, x, y);
+                next_quad_control = Some((x1, y1));
+            },
+            b'A' => {
+                let rx = lexer.number()?;
+                let ry = lexer.number()?;
+                let angle = lexer.number()?;
+                let large_arc = lexer.flag()?;
+                let sweep = lexer.flag()?;
+                let to = (ox + lexer.number()?, oy + lexer.number()?);
+                path.arc_to(&EllipticalArc { rx, ry, angle, large_arc, sweep, to });
+            },
+            b'Z' => {
+                path.close();
+                // Z takes no arguments, so numbers right after it are an error
+                command = 0;
+            },
+            _ => return None,
+        }
+        cubic_control = next_cubic_control;
+        quad_control = next_quad_control;
+    }
+}
+
+fn build_shape(element: &Element, path: &mut PathBuilder, units_per_em: f32) -> Option<()> {
+    // Basic shapes as paths. Percentages are taken relative to the em
+    let length = |name: &str, default: f32| parse_length(element.attribute(name), units_per_em, default);
+    match element.name.as_str() {
+        "path" => return build_path(element.attribute("d")?, path),
+        "rect" => {
+            let (x, y, width, height) = (length("x", 0f32), length("y", 0f32), length("width", 0f32), length("height", 0f32));
+            if width <= 0f32 || height <= 0f32 {
+                return None;
+            }
+            let rx_attribute = element.attribute("rx").map(|_| length("rx", 0f32));
+            let ry_attribute = element.attribute("ry").map(|_| length("ry", 0f32));
+            let rx = rx_attribute.or(ry_attribute).unwrap_or(0f32).clamp(0f32, width / 2f32);
+            let ry = ry_attribute.or(rx_attribute).unwrap_or(0f32).clamp(0f32, height / 2f32);
+            let quarter = |x: f32, y: f32| EllipticalArc { rx, ry, angle: 0f32, large_arc: false, sweep: true, to: (x, y) };
+            path.move_to(x + rx, y);
+            path.line_to(x + width - rx, y);
+            path.arc_to(&quarter(x + width, y + ry));
+            path.line_to(x + width, y + height - ry);
+            path.arc_to(&quarter(x + width - rx, y + height));
+            path.line_to(x + rx, y + height);
+            path.arc_to(&quarter(x, y + height - ry));
+            path.line_to(x, y + ry);
+            path.arc_to(&quarter(x + rx, y));
+            path.close();
+        },
+        "circle" | "ellipse" => {
+            let (cx, cy) = (length("cx", 0f32), length("cy", 0f32));
+            let (rx, ry) = if element.name == "circle" {
+                (length("r", 0f32), length("r", 0f32))
+            } else {
+                (length("rx", 0f32), length("ry", 0f32))
+            };
+            if rx <= 0f32 || ry <= 0f32 {
+                return None;
+            }
+            let quarter = |x: f32, y: f32| EllipticalArc { rx, ry, angle: 0f32, large_arc: false, sweep: true, to: (x, y) };
+            path.move_to(cx + rx, cy);
+            path.arc_to(&quarter(cx, cy + ry));
+            path.arc_to(&quarter(cx - rx, cy));
+            path.arc_to(&quarter(cx, cy - ry));
+            path.arc_to(&quarter(cx + rx, cy));
+            path.close();
+        },
+        "polygon" | "polyline" => {
+            let points = parse_numbers(element.attribute("points")?);
+            for (i, pair) in points.chunks_exact(2).enumerate() {
+                if i == 0 {
+                    path.move_to(pair[0], pair[1]);
+                } else {
+                    path.line_to(pair[0], pair[1]);
+                }
+            }
+            path.close();
+        },
+        _ => return None,
+    }
+    return Some(());
+}
+
+impl<'a> Renderer<'a> {
+    fn render_element(&self, element: &Element, transform: &Transform, parent_style: &Style, bitmap: &mut RgbaBitmap, depth: u32) {
+        // Draws element and its children onto bitmap. transform maps the parent's user space to font units
+        if depth > MAX_DEPTH {
+            return;
+        }
+        if element.property("display").as_deref() == Some("none") {
+            return;
+        }
+        let style = inherit_style(parent_style, element);
+        let transform = transform.then(&parse_transform(element.attribute("transform").unwrap_or("")));
+
+        // Group opacity applies to the element as a whole, so it gets its own layer
+        let opacity = element.property("opacity").and_then(|o| parse_opacity(&o)).unwrap_or(1f32);
+        if opacity <= 0f32 {
+            return;
+        }
+        let mut layer = if opacity < 1f32 { Some(RgbaBitmap::new(bitmap.width, bitmap.height)) } else { None };
+        let target = match layer.as_mut() {
+            Some(l) => l,
+            None => &mut *bitmap,
+        };
+
+        match element.name.as_str() {
+            "svg" | "g" | "a" | "switch" => {
+                for child in element.children.iter() {
+                    self.render_element(child, &transform, &style, target, depth + 1);
+                }
+            },
+            "use" => {
+                if let Some(referenced) = element.href().and_then(|id| find_by_id(self.root, id)) {
+                    let offset = Transform::translate(
+                        parse_length(element.attribute("x"), self.units_per_em, 0f32),
+                        parse_length(element.attribute("y"), self.units_per_em, 0f32),
+                    );
+                    let transform = transform.then(&offset);
+                    if referenced.name == "symbol" {
+                        for child in referenced.children.iter() {
+                            self.render_element(child, &transform, &style, target, depth + 1);
+                        }
+                    } else {
+                        self.render_element(referenced, &transform, &style, target, depth + 1);
+                    }
+                }
+            },
+            "path" | "rect" | "circle" | "ellipse" | "polygon" | "polyline" => {
+                let mut path = PathBuilder::new(transform);
+                // A path with an error is still drawn up to the error
+                let _ = build_shape(element, &mut path, self.units_per_em);
+                path.close();
+                self.fill_path(&path, &style, target);
+            },
+            // defs, gradients, symbols, clip paths and so on aren't drawn directly
+            _ => {}
+        }
+
+        if let Some(mut layer) = layer {
+            for pixel in layer.data.iter_mut() {
+                *pixel = [pixel[0] * opacity, pixel[1] * opacity, pixel[2] * opacity, pixel[3] * opacity];
+            }
+            bitmap.composite(&layer, CompositeMode::SourceOver);
+        }
+    }
+
+    fn fill_path(&self, path: &PathBuilder, style: &Style, bitmap: &mut RgbaBitmap) {
+        if path.edges.is_empty() {
+            return;
+        }
+        let (paint, paint_transform) = match &style.fill {
+            Fill::None => return,
+            Fill::Color(color) => (Paint::Solid(*color), Transform::IDENTITY),
+            Fill::CurrentColor => (Paint::Solid(style.color), Transform::IDENTITY),
+            Fill::Url(id) => match self.resolve_gradient(id, path, style.color) {
+                Some(gradient) => gradient,
+                None => return,
+            },
+        };
+        // Maps font units back to the paint's own coordinates
+        let inverse = match paint_transform.invert() {
+            Some(i) => i,
+            None => return,
+        };
+
+        // Only visit the pixels the path can touch
+        let mut x_min = f32::MAX;
+        let mut x_max = f32::MIN;
+        let mut y_min = f32::MAX;
+        let mut y_max = f32::MIN;
+        for &(x0, y0, x1, y1) in path.edges.iter() {
+            x_min = x_min.min(x0.min(x1));
+            x_max = x_max.max(x0.max(x1));
+            y_min = y_min.min(y0.min(y1));
+            y_max = y_max.max(y0.max(y1));
+        }
+        let column = |x: f32| ((x - self.bounds.x_min) * self.ratio).floor().clamp(0f32, bitmap.width as f32) as usize;
+        let row = |y: f32| ((self.bounds.y_max - y) * self.ratio).floor().clamp(0f32, bitmap.height as f32) as usize;
+        let (first_column, last_column) = (column(x_min), (column(x_max) + 1).min(bitmap.width));
+        let (first_row, last_row) = (row(y_max), (row(y_min) + 1).min(bitmap.height));
+
+        let n = self.samples;
+        for y in first_row..last_row {
+            for x in first_column..last_column {
+                let mut inside = 0;
+                for x_subsample in 0..n {
+                    for y_subsample in 0..n {
+                        let (fx, fy) = self.pixel_to_font_units(
+                            x as f32 + (x_subsample as f32 + 0.5f32) / n as f32,
+                            y as f32 + (y_subsample as f32 + 0.5f32) / n as f32,
+                        );
+                        if style.fill_rule.contains(winding_number(&path.edges, fx, fy)) {
+                            inside += 1;
+                        }
+                    }
+                }
+                if inside == 0 {
+                    continue;
+                }
+                let coverage = inside as f32 / (n * n) as f32;
+                let (fx, fy) = self.pixel_to_font_units(x as f32 + 0.5f32, y as f32 + 0.5f32);
+                let (px, py) = inverse.apply(fx, fy);
+                let mut color = fill_color(&paint, px, py);
+                color[3] *= style.fill_opacity * coverage;
+                bitmap.set(x, y, composite_pixel(premultiply(color), bitmap.get(x, y), CompositeMode::SourceOver));
+            }
+        }
+    }
+
+    fn resolve_gradient(&self, id: &str, path: &PathBuilder, current_color: [f32; 4]) -> Option<(Paint, Transform)> {
+        /* Builds the paint for a gradient fill, along with the transform from
+        gradient space to font units. Attributes and stops missing from a
+        gradient are taken from the one it references with href. */
+        let element = find_by_id(self.root, id)?;
+        let linear = match element.name.as_str() {
+            "linearGradient" => true,
+            "radialGradient" => false,
+            _ => return None,
+        };
+        let attribute = |name: &str| -> Option<&str> {
+            let mut current = element;
+            for _ in 0..MAX_DEPTH {
+                if let Some(value) = current.attribute(name) {
+                    return Some(value);
+                }
+                current = find_by_id(self.root, current.href()?)?;
+            }
+            return None;
+        };
+        let mut stop_source = element;
+        for _ in 0..MAX_DEPTH {
+            if stop_source.children.iter().any(|c| c.name == "stop") {
+                break;
+            }
+            match stop_source.href().and_then(|href| find_by_id(self.root, href)) {
+                Some(referenced) => stop_source = referenced,
+                None => break,
+            }
+        }
+
+        let mut stops = Vec::<(f32, [f32; 4])>::new();
+        for stop in stop_source.children.iter().filter(|c| c.name == "stop") {
+            let offset = match stop.attribute("offset") {
+                Some(o) => parse_length(Some(o), 1f32, 0f32).clamp(0f32, 1f32),
+                None => 0f32,
+            };
+            // Offsets can't go backwards
+            let offset = stops.last().map_or(offset, |last| offset.max(last.0));
+            let mut color = match stop.property("stop-color").as_deref() {
+                Some("currentColor") => current_color,
+                Some(c) => parse_color(c).unwrap_or([0f32, 0f32, 0f32, 1f32]),
+                None => [0f32, 0f32, 0f32, 1f32],
+            };
+            color[3] *= stop.property("stop-opacity").and_then(|o| parse_opacity(&o)).unwrap_or(1f32);
+            stops.push((offset, color));
+        }
+        if stops.is_empty() {
+            return None;
+        }
+        if stops.len() == 1 {
+            return Some((Paint::Solid(stops[0].1), Transform::IDENTITY));
+        }
+        let extend = match attribute("spreadMethod") {
+            Some("reflect") => Extend::Reflect,
+            Some("repeat") => Extend::Repeat,
+            _ => Extend::Pad,
+        };
+        let line = ColorLine { extend, stops };
+
+        // objectBoundingBox (the default) puts the gradient in the unit square of the shape's bounding box
+        let user_space = attribute("gradientUnits") == Some("userSpaceOnUse");
+        let (reference, units) = if user_space {
+            (self.units_per_em, Transform::IDENTITY)
+        } else {
+            let b = path.bounds?;
+            if b.x_max <= b.x_min || b.y_max <= b.y_min {
+                return None;
+            }
+            (1f32, Transform { xx: b.x_max - b.x_min, yy: b.y_max - b.y_min, dx: b.x_min, dy: b.y_min, ..Transform::IDENTITY })
+        };
+        let length = |name: &str, default: f32| parse_length(attribute(name), reference, default);
+        let gradient_transform = parse_transform(attribute("gradientTransform").unwrap_or(""));
+        let paint_transform = path.transform.then(&units).then(&gradient_transform);
+
+        let paint = if linear {
+            let p0 = (length("x1", 0f32), length("y1", 0f32));
+            let p1 = (length("x2", reference), length("y2", 0f32));
+            // Color bands are perpendicular to p0 -> p1
+            let p2 = (p0.0 - (p1.1 - p0.1), p0.1 + (p1.0 - p0.0));
+            Paint::LinearGradient { line, p0, p1, p2 }
+        } else {
+            let center = (length("cx", reference * 0.5f32), length("cy", reference * 0.5f32));
+            let focus = (length("fx", center.0), length("fy", center.1));
+            Paint::RadialGradient { line, c0: focus, r0: length("fr", 0f32), c1: center, r1: length("r", reference * 0.5f32) }
+        };
+        return Some((paint, paint_transform));
+    }
+
+    fn pixel_to_font_units(&self, x: f32, y: f32) -> (f32, f32) {
+        return (self.bounds.x_min + x / self.ratio, self.bounds.y_max - y / self.ratio);
+    }
+}
+
+fn winding_number(edges: &[(f32, f32, f32, f32)], x: f32, y: f32) -> i32 {
+    // Nonzero winding of the edges around (x, y), counting crossings of a ray towards +x
+    let mut winding = 0;
+    for &(x0, y0, x1, y1) in edges.iter() {
+        if (y0 <= y) == (y1 <= y) {
+            continue;
+        }
+        let crossing = x0 + (y - y0) * (x1 - x0) / (y1 - y0);
+        if crossing > x {
+            winding += if y1 > y0 { 1 } else { -1 };
+        }
+    }
+    return winding;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::GlyphId;
+
+    use crate::bitmap::RgbaBitmap;
+    use crate::transform::Transform;
+    use super::{build_path, parse_transform, parse_xml, render_document, PathBuilder};
+
+    const RED: [f32; 4] = [1f32, 0f32, 0f32, 1f32];
+    const FOREGROUND: [f32; 4] = [0f32, 0f32, 1f32, 1f32];
+
+    fn path(data: &str) -> PathBuilder {
+        let mut path = PathBuilder::new(Transform::IDENTITY);
+        let _ = build_path(data, &mut path);
+        return path;
+    }
+
+    fn render(text: &str, glyph_id: u16) -> RgbaBitmap {
+        // A 100 unit em below the baseline, at 10 x 10 pixels
+        let root = parse_xml(text).unwrap();
+        return render_document(&root, GlyphId(glyph_id), 100f32, -100f32, FOREGROUND, 0.1f32, 4).0;
+    }
+
+    fn assert_color(actual: [f32; 4], expected: [f32; 4], tolerance: f32) {
+        for channel in 0..4 {
+            assert!((actual[channel] - expected[channel]).abs() <= tolerance, "{:?} is not {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn xml_parser() {
+        let text = "<?xml version=\"1.0\"?>\n\
+            <!DOCTYPE svg [<!ENTITY e \"x\">]>\n\
+            <!-- <not-an-element/> -->\n\
+            <svg:svg xmlns:svg=\"http://www.w3.org/2000/svg\">\
+              <g id='a&amp;b' data-x=\"1 &gt; 0\">text<![CDATA[<rect/>]]><path d=\"M0 0\"/></g>\
+              <rect title='&#65;&#x42;&unknown;'/>\
+            </svg:svg>";
+        let root = parse_xml(text).unwrap();
+        assert_eq!(root.name, "svg");
+        assert_eq!(root.attribute("xmlns:svg"), Some("http://www.w3.org/2000/svg"));
+        assert_eq!(root.children.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["g", "rect"]);
+        let group = &root.children[0];
+        assert_eq!(group.attribute("id"), Some("a&b"));
+        assert_eq!(group.attribute("data-x"), Some("1 > 0"));
+        assert_eq!(group.children.len(), 1);
+        assert_eq!(group.children[0].attribute("d"), Some("M0 0"));
+        assert_eq!(root.children[1].attribute("title"), Some("AB&unknown;"));
+
+        // Unclosed documents have no root
+        assert!(parse_xml("<svg><g>").is_none());
+    }
+
+    #[test]
+    fn implicit_path_commands() {
+        // Pairs after a moveto are linetos, and other commands repeat while numbers follow
+        let lines = path("M 0 0 10 0 20 0 H 30 V 10");
+        assert_eq!(lines.current, (30f32, 10f32));
+        assert_eq!(lines.edges.len(), 4);
+        let relative = path("m 10 10 20 0 0 20 l -5 0 -5 0");
+        assert_eq!(relative.current, (20f32, 30f32));
+
+        // Numbers can run into each other
+        let packed = path("M1-2.5.5.5L1e1,2E-1");
+        assert_eq!(packed.edges, [(1f32, -2.5f32, 0.5f32, 0.5f32), (0.5f32, 0.5f32, 10f32, 0.2f32)]);
+
+        // Z takes no numbers, so the path stops there
+        let closed = path("M 0 0 L 10 0 L 10 10 Z 5 5");
+        assert_eq!(closed.edges.len(), 3);
+        assert_eq!(closed.edges[2], (10f32, 10f32, 0f32, 0f32));
+
+        // Smooth curves reflect the previous control point
+        let smooth = path("M 0 0 Q 10 10 20 0 T 40 0");
+        let (first, second): (Vec<&(f32, f32, f32, f32)>, Vec<_>) = smooth.edges.iter().partition(|e| e.2 <= 20f32);
+        assert!(first.iter().all(|e| e.3 >= 0f32) && first.iter().any(|e| e.3 > 4f32));
+        assert!(second.iter().all(|e| e.3 <= 0f32) && second.iter().any(|e| e.3 < -4f32));
+    }
+
+    #[test]
+    fn path_arcs() {
+        let radius_from = |path: &PathBuilder, cx: f32, cy: f32| -> (f32, f32) {
+            let distances = path.edges.iter().map(|&(_, _, x, y)| (x - cx).hypot(y - cy));
+            return distances.fold((f32::MAX, f32::MIN), |(min, max), d| (min.min(d), max.max(d)));
+        };
+        let extent = |path: &PathBuilder| -> (f32, f32, f32, f32) {
+            let points = path.edges.iter().flat_map(|&(x0, y0, x1, y1)| [(x0, y0), (x1, y1)]);
+            return points.fold((f32::MAX, f32::MAX, f32::MIN, f32::MIN), |(x0, y0, x1, y1), (x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)));
+        };
+
+        // A half circle, with flags packed against the numbers and radii scaled up to reach the end
+        for data in ["M 0 0 A 10 10 0 0 1 20 0", "M0 0a10 10 0 0120 0", "M 0 0 A 1 1 0 0 1 20 0"] {
+            let arc = path(data);
+            let (min, max) = radius_from(&arc, 10f32, 0f32);
+            assert!(min > 9.99f32 && max < 10.01f32, "{}: radius {}..{}", data, min, max);
+            assert_eq!(arc.current, (20f32, 0f32));
+            let (_, y_min, _, y_max) = extent(&arc);
+            assert!(y_min < -9.99f32 && y_max <= 0f32, "{}: sweep goes towards -y", data);
+        }
+
+        // The flags pick one of the four arcs between (0, 0) and (10, 10)
+        let cases = [
+            ("0 0", (10f32, 0f32), 10f32),
+            ("0 1", (0f32, 10f32), 10f32),
+            ("1 0", (0f32, 10f32), 20f32),
+            ("1 1", (10f32, 0f32), 20f32),
+        ];
+        for (flags, center, width) in cases {
+            let arc = path(&format!("M 0 0 A 10 10 0 {} 10 10", flags));
+            let (min, max) = radius_from(&arc, center.0, center.1);
+            assert!(min > 9.99f32 && max < 10.01f32, "flags {}: radius {}..{}", flags, min, max);
+            let (x_min, _, x_max, _) = extent(&arc);
+            assert!((x_max - x_min - width).abs() < 0.05f32, "flags {}: width {}", flags, x_max - x_min);
+        }
+
+        // Rotated ellipse: the major axis lies along the diagonal
+        let ellipse = path("M 0 0 A 20 10 45 1 1 0 0.001");
+        let (x_min, y_min, x_max, y_max) = extent(&ellipse);
+        assert!((x_max - x_min - (y_max - y_min)).abs() < 0.1f32);
+
+        // Zero radii are straight lines
+        let flat = path("M 0 0 A 0 10 0 0 1 20 0");
+        assert_eq!(flat.edges, [(0f32, 0f32, 20f32, 0f32)]);
+    }
+
+    #[test]
+    fn transform_lists() {
+        let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-4f32 && (a.1 - b.1).abs() < 1e-4f32;
+        // Applied right to left
+        assert_eq!(parse_transform("translate(10 20) scale(2)").apply(1f32, 1f32), (12f32, 22f32));
+        assert_eq!(parse_transform("scale(2) translate(10 20)").apply(1f32, 1f32), (22f32, 42f32));
+        assert_eq!(parse_transform("translate(1,2),scale(2,3)").apply(1f32, 1f32), (3f32, 5f32));
+        assert_eq!(parse_transform("translate(5)").apply(0f32, 0f32), (5f32, 0f32));
+        assert_eq!(parse_transform("matrix(1 2 3 4 5 6)").apply(1f32, 1f32), (9f32, 12f32));
+        assert!(close(parse_transform("rotate(90)").apply(10f32, 0f32), (0f32, 10f32)));
+        assert!(close(parse_transform("rotate(90 10 0)").apply(20f32, 0f32), (10f32, 10f32)));
+        assert!(close(parse_transform("skewX(45)").apply(0f32, 10f32), (10f32, 10f32)));
+        assert!(close(parse_transform("skewY(45)").apply(10f32, 0f32), (10f32, 10f32)));
+        // Unknown or malformed entries are ignored
+        assert!(parse_transform("").is_identity());
+        assert!(parse_transform("perspective(2) scale(1 2 3)").is_identity());
+        assert_eq!(parse_transform("bogus(1) translate(3 4)").apply(0f32, 0f32), (3f32, 4f32));
+    }
+
+    #[test]
+    fn gradients() {
+        let text = "<svg><defs>\
+              <linearGradient id='base'><stop offset='0' stop-color='red'/><stop offset='100%' stop-color='currentColor'/></linearGradient>\
+              <linearGradient id='reversed' href='#base' x1='1' x2='0'/>\
+              <radialGradient id='radial' gradientUnits='userSpaceOnUse' cx='50' cy='50' r='50'>\
+                <stop offset='0' style='stop-color: red'/><stop offset='1' stop-color='#0000ff' stop-opacity='0.5'/>\
+              </radialGradient>\
+            </defs>\
+            <g id='glyph1'><rect width='100' height='100' fill='url(#base)'/></g>\
+            <g id='glyph2'><rect width='100' height='100' fill='url(#reversed)'/></g>\
+            <g id='glyph3'><rect width='100' height='100' fill='url(#radial)'/></g>\
+            <g id='glyph4'><rect width='100' height='100' fill='url(#missing)'/></g>\
+            </svg>";
+        // objectBoundingBox by default, so the gradient spans the rectangle, and currentColor is the foreground
+        let forward = render(text, 1);
+        assert_color(forward.get(0, 5), RED, 0.1f32);
+        assert_color(forward.get(9, 5), FOREGROUND, 0.1f32);
+        assert_color(forward.get(4, 0), forward.get(4, 9), 1e-6f32);
+        // Stops come from the referenced gradient, the end points don't
+        let reversed = render(text, 2);
+        assert_color(reversed.get(0, 5), FOREGROUND, 0.1f32);
+        assert_color(reversed.get(9, 5), RED, 0.1f32);
+        // Radial, in user space, with the stop opacity applied (premultiplied)
+        let radial = render(text, 3);
+        assert_color(radial.get(5, 5), RED, 0.15f32);
+        assert_color(radial.get(5, 0), [0f32, 0f32, 0.5f32, 0.5f32], 0.1f32);
+        assert!(radial.get(0, 0)[3] > 0f32, "padded past the radius");
+        // A fill that points nowhere draws nothing
+        assert!(render(text, 4).data.iter().all(|p| p[3] == 0f32));
+    }
+
+    #[test]
+    fn use_and_glyph_lookup() {
+        let text = "<svg>\
+            <defs><rect id='half' width='50' height='100' fill='red'/><symbol id='top'><rect width='100' height='50'/></symbol></defs>\
+            <g id='glyph1'><use href='#half' x='50'/></g>\
+            <g id='glyph2' fill='currentColor'><use xlink:href='#top'/></g>\
+            <g id='loop'><use href='#loop'/><rect width='10' height='10'/></g>\
+            </svg>";
+        // Only the right half is covered, and only by glyph 1's content
+        let used = render(text, 1);
+        assert_color(used.get(2, 5), [0f32; 4], 0f32);
+        assert_color(used.get(7, 5), RED, 1e-6f32);
+        // Symbols are drawn through use, inheriting the use's style
+        let symbol = render(text, 2);
+        assert_color(symbol.get(5, 2), FOREGROUND, 1e-6f32);
+        assert_color(symbol.get(5, 7), [0f32; 4], 0f32);
+        // Without a glyph element the whole document is drawn, and cycles stop at the depth limit
+        let whole = render(text, 9);
+        assert_color(whole.get(0, 0), [0f32, 0f32, 0f32, 1f32], 1e-6f32);
+        assert_color(whole.get(5, 2), FOREGROUND, 1e-6f32);
+        assert_color(whole.get(7, 7), RED, 1e-6f32);
+        assert_color(whole.get(2, 7), [0f32; 4], 0f32);
+    }
+
+    #[test]
+    fn fill_rules() {
+        // Two squares wound the same way: the inner one is a hole only with evenodd
+        let square = "M 0 0 H 100 V 100 H 0 Z M 30 30 H 70 V 70 H 30 Z";
+        let nonzero = render(&format!("<svg><path d='{}'/></svg>", square), 1);
+        let evenodd = render(&format!("<svg><g fill-rule='evenodd'><path d='{}'/></g></svg>", square), 1);
+        assert_eq!((nonzero.get(1, 1)[3], nonzero.get(5, 5)[3]), (1f32, 1f32));
+        assert_eq!((evenodd.get(1, 1)[3], evenodd.get(5, 5)[3]), (1f32, 0f32));
+    }
+
+    #[test]
+    fn root_view_box() {
+        // A 10 unit view box fills the 100 unit em
+        let scaled = render("<svg viewBox='0 0 10 10'><rect width='5' height='5'/></svg>", 1);
+        assert_eq!(scaled.get(4, 4)[3], 1f32);
+        assert_eq!(scaled.get(5, 4)[3], 0f32);
+        assert_eq!(scaled.get(4, 5)[3], 0f32);
+        // The minimum moves the origin
+        let moved = render("<svg viewBox='5 5 10 10'><rect x='5' y='5' width='5' height='5'/></svg>", 1);
+        assert_eq!(moved.get(0, 0)[3], 1f32);
+        assert_eq!(moved.get(5, 5)[3], 0f32);
+        // A wide view box keeps its aspect ratio and is centered vertically
+        let wide = render("<svg viewBox='0 0 20 10'><rect width='20' height='10'/></svg>", 1);
+        assert_eq!((0..10).map(|y| wide.get(5, y)[3]).collect::<Vec<_>>(), [0f32, 0f32, 0.5f32, 1f32, 1f32, 1f32, 1f32, 0.5f32, 0f32, 0f32]);
+        // Unless preserveAspectRatio is none
+        let stretched = render("<svg viewBox='0 0 20 10' preserveAspectRatio='none'><rect width='10' height='10'/></svg>", 1);
+        assert_eq!(stretched.get(4, 9)[3], 1f32);
+        assert_eq!(stretched.get(5, 0)[3], 0f32);
+        // width and height set the viewport
+        let small = render("<svg viewBox='0 0 10 10' width='50' height='50'><rect width='10' height='10'/></svg>", 1);
+        assert_eq!(small.get(4, 4)[3], 1f32);
+        assert_eq!(small.get(5, 5)[3], 0f32);
+    }
+}
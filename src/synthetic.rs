@@ -0,0 +1,143 @@
+/* Synthetic bold and oblique for families that don't come with those styles.
+Bold moves every contour outwards by half the strength with the stroker's
+offsetting (see offset_outline) and then up and right by as much, so stems
+get thicker by the strength and the glyph grows to the right and up from
+where it was, like FreeType's FT_GlyphSlot_Embolden. Unlike pushing each
+point along the bisector at it, this keeps the whole outline (curves and
+rounded outer corners) the same distance out. Oblique shears the outline to the right, like FT_GlyphSlot_Oblique. */
+
+use ttf_parser::{Rect, Style};
+
+use crate::curve::Contour;
+use crate::fontdb::{FontEntry, FontQuery};
+use crate::stroke::{offset_outline, LineCap, LineJoin, StrokeStyle};
+use crate::transform::{transform_outline, Transform};
+
+// Horizontal shift per unit of height, tan(12 degrees) as in FreeType
+const OBLIQUE_SHEAR: f32 = 0.2126f32;
+// How much thicker stems get, as a fraction of the em
+const BOLD_STRENGTH: f32 = 1f32 / 24f32;
+// Rounded outer corners, so every point of the bold outline is the same distance out (width is unused)
+const BOLD_CORNERS: StrokeStyle = StrokeStyle { width: 0f32, join: LineJoin::Round, miter_limit: 4f32, cap: LineCap::Butt };
+// Weights from which a query asks for bold (CSS font-weight: bold is 700)
+const BOLD_WEIGHT: u16 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SyntheticStyle {
+    pub bold: bool,
+    pub oblique: bool,
+}
+
+impl SyntheticStyle {
+    pub fn for_query(query: &FontQuery, entry: &FontEntry) -> SyntheticStyle {
+        // Styles the query asks for that the matched face doesn't have
+        return SyntheticStyle {
+            bold: query.weight >= BOLD_WEIGHT && entry.weight < BOLD_WEIGHT,
+            oblique: query.style != Style::Normal && entry.style == Style::Normal,
+        };
+    }
+}
+
+pub fn apply_synthetic_style(contours: &mut Vec<Contour>, bbox: Rect, units_per_em: f32, style: SyntheticStyle) -> Rect {
+    // Emboldens and/or shears an outline (in font units) and returns its new bounding box
+    let mut bbox = bbox;
+    if style.bold {
+        let half = units_per_em * BOLD_STRENGTH / 2f32;
+        // TrueType outlines run clockwise with the ink on their right, CFF ones the other way round
+        let clockwise = contours.iter().map(|contour| contour.signed_area()).sum::<f32>() <= 0f32;
+        *contours = offset_outline(contours, if clockwise { half } else { -half }, &BOLD_CORNERS);
+        bbox = transform_outline(contours, &Transform::translate(half, half)).unwrap_or(bbox);
+    }
+    if style.oblique {
+        // Slants the outline by moving each point right in proportion to its height above the baseline
+        bbox = transform_outline(contours, &Transform::skew(OBLIQUE_SHEAR, 0f32)).unwrap_or(bbox);
+    }
+    return bbox;
+}
+
+pub fn synthetic_advance(advance: f32, units_per_em: f32, style: SyntheticStyle) -> f32 {
+    // Emboldening makes glyphs wider, so the advance grows by the same amount (shearing leaves it alone)
+    if style.bold {
+        return advance + units_per_em * BOLD_STRENGTH;
+    }
+    return advance;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::curve::{winding_number, Contour};
+    use crate::fixtures::bundled_fonts;
+    use crate::float::Precision;
+    use crate::flatten::{flatten, Polyline};
+    use crate::scanline::GLYPH_FILL;
+    use crate::stroke::offset_outline;
+    use crate::transform::{transform_outline, Transform};
+    use super::{apply_synthetic_style, SyntheticStyle, BOLD_CORNERS, BOLD_STRENGTH};
+
+    const GLYPHS_PER_FONT: u16 = 60;
+    // Points checked across each glyph in each direction
+    const GRID: usize = 32;
+    // How far off half the strength the edge of the bold outline may be, as a fraction of it
+    const SLACK: f32 = 0.1f32;
+    // Points per thousand that may still be wrong, next to counters that would have to split in two
+    const MISSES_PER_THOUSAND: usize = 1;
+
+    fn distance_to_polylines(polylines: &[Polyline], (x, y): (f32, f32)) -> f32 {
+        let mut nearest = f32::MAX;
+        for polyline in polylines.iter() {
+            for pair in polyline.points.windows(2) {
+                let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+                let (dx, dy) = (bx - ax, by - ay);
+                let length = dx * dx + dy * dy;
+                let t = if length == 0f32 { 0f32 } else { (((x - ax) * dx + (y - ay) * dy) / length).clamp(0f32, 1f32) };
+                nearest = nearest.min(((x - ax - t * dx).powi(2) + (y - ay - t * dy).powi(2)).sqrt());
+            }
+        }
+        return nearest;
+    }
+
+    fn inside(contours: &[Contour], x: f32, y: f32) -> bool {
+        // The same rule the renderer fills glyphs with
+        return GLYPH_FILL.contains(winding_number(contours, x, y, 1f32, 0f32, Precision::Double));
+    }
+
+    #[test]
+    fn bold_outline_keeps_its_distance() {
+        /* Before it's moved up and right, the bold outline covers everything
+        within half the strength of the glyph and nothing much further out
+        (but for a few points where a contour is bridged instead of split) */
+        for font in bundled_fonts(GLYPHS_PER_FONT).iter() {
+            let units_per_em = font.units_per_em;
+            let half = units_per_em * BOLD_STRENGTH / 2f32;
+            let (mut checked, mut misses) = (0usize, Vec::<String>::new());
+            for glyph in font.glyphs.iter() {
+                let (id, original, bbox) = (glyph.id, &glyph.contours, glyph.bbox);
+                let mut bold = original.clone();
+                apply_synthetic_style(&mut bold, bbox, units_per_em, SyntheticStyle { bold: true, oblique: false });
+                let clockwise = original.iter().map(|contour| contour.signed_area()).sum::<f32>() <= 0f32;
+                let offset = offset_outline(original, if clockwise { half } else { -half }, &BOLD_CORNERS);
+                let mut moved = offset.clone();
+                transform_outline(&mut moved, &Transform::translate(half, half));
+                assert_eq!(bold, moved, "{} glyph {}", font.path.display(), id);
+
+                let reference = flatten(original, 0.01f32, 1f32);
+                let (left, bottom) = (bbox.x_min as f32 - 2f32 * half, bbox.y_min as f32 - 2f32 * half);
+                let (width, height) = (bbox.width() as f32 + 4f32 * half, bbox.height() as f32 + 4f32 * half);
+                for i in 0..GRID {
+                    for j in 0..GRID {
+                        let (x, y) = (left + width * (i as f32 + 0.5f32) / GRID as f32, bottom + height * (j as f32 + 0.5f32) / GRID as f32);
+                        let distance = distance_to_polylines(&reference, (x, y));
+                        let (ink, covered) = (inside(original, x, y), inside(&offset, x, y));
+                        if (ink || distance < half * (1f32 - SLACK)) && !covered {
+                            misses.push(format!("glyph {} leaves out ({}, {}), {} from the outline", id, x, y, distance));
+                        } else if !ink && distance > half * (1f32 + SLACK) && covered {
+                            misses.push(format!("glyph {} covers ({}, {}), {} from the outline", id, x, y, distance));
+                        }
+                        checked += 1;
+                    }
+                }
+            }
+            assert!(misses.len() * 1000 <= checked * MISSES_PER_THOUSAND, "{}: {:?}", font.path.display(), misses);
+        }
+    }
+}
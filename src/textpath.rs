@@ -0,0 +1,292 @@
+/* Text laid out along a path, like SVG's <textPath>. Glyphs are placed by
+arc length: each glyph's midpoint goes on the path and the glyph is turned
+to the path's direction there, so letters fan out around curves. Glyphs
+whose midpoint falls off either end of the path aren't drawn.
+
+Instead of turning each glyph as a whole, an outline can also be warped
+onto the path, bending the glyphs themselves: every point at x along the
+baseline and y above it moves to the point x along the path, y out along
+its normal. Curves are split into short pieces first so they follow the
+bend.
+
+Distances along Bezier curves have no closed form, so each curve is
+sampled into a table of (t, distance) pairs that is searched and
+interpolated, scaled to the curve's length. */
+
+use crate::curve::{Contour, Curve, Element, Point};
+
+// Samples per curve for the arc length table
+const LENGTH_SAMPLES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathAlign {
+    // start_offset is where the text starts, is centred or ends (SVG's text-anchor)
+    Start,
+    Middle,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathRendering {
+    // Each glyph rotated to the path as a whole
+    PerGlyph,
+    // Glyph outlines bent to follow the path
+    Warped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathLayout {
+    pub align: PathAlign,
+    // Distance along the path the text is aligned to
+    pub start_offset: f32,
+    // Distance of the baseline from the path, to the left of its direction (above a path going right)
+    pub offset: f32,
+    pub rendering: PathRendering,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphPlacement {
+    // Where the glyph's origin goes
+    pub x: f32,
+    pub y: f32,
+    // Direction of the baseline in radians, counter-clockwise from +x
+    pub angle: f32,
+    // Distance of the glyph's origin along the path (for warping)
+    pub distance: f32,
+}
+
+pub struct TextPath {
+    // Every subpath's curves, with closes turned into lines back to the start
+    pieces: Vec<Element>,
+    // (t, distance from the start of the path) samples for each piece
+    table: Vec<Vec<(f32, f32)>>,
+    length: f32,
+}
+
+impl TextPath {
+    pub fn new(contours: &[Contour]) -> TextPath {
+        let pieces: Vec<Element> = contours.iter().flat_map(|contour| contour.curves.iter().copied()).collect();
+
+        let mut table = Vec::<Vec<(f32, f32)>>::new();
+        let mut length = 0f32;
+        for piece in pieces.iter() {
+            let steps = if matches!(piece, Element::Line(_)) { 1 } else { LENGTH_SAMPLES };
+            // Chords of the samples, stretched a little so they add up to the length of the curve
+            let mut chords = vec![0f32];
+            let mut previous = piece.evaluate(0f32);
+            for step in 1..=steps {
+                let point = piece.evaluate(step as f32 / steps as f32);
+                chords.push(chords[step - 1] + ((point.0 - previous.0).powi(2) + (point.1 - previous.1).powi(2)).sqrt());
+                previous = point;
+            }
+            let total = chords[steps];
+            let scale = if total > 0f32 { piece.length() / total } else { 1f32 };
+            table.push(chords.iter().enumerate().map(|(step, d)| (step as f32 / steps as f32, length + d * scale)).collect());
+            length += total * scale;
+        }
+        return TextPath { pieces, table, length };
+    }
+
+    pub fn length(&self) -> f32 {
+        return self.length;
+    }
+
+    pub fn point_at(&self, distance: f32) -> Option<((f32, f32), (f32, f32))> {
+        // Point that far along the path and the unit tangent there, None off either end
+        if self.pieces.is_empty() || distance < 0f32 || distance > self.length {
+            return None;
+        }
+        return Some(self.locate(distance));
+    }
+
+    pub fn place_glyphs(&self, advances: &[f32], layout: &PathLayout) -> Vec<Option<GlyphPlacement>> {
+        // Where each glyph with the given advance goes (None for glyphs that fall off the path)
+        let total: f32 = advances.iter().sum();
+        let mut pen = match layout.align {
+            PathAlign::Start => layout.start_offset,
+            PathAlign::Middle => layout.start_offset - total / 2f32,
+            PathAlign::End => layout.start_offset - total,
+        };
+        let mut placements = Vec::<Option<GlyphPlacement>>::new();
+        for advance in advances.iter() {
+            let middle = pen + advance / 2f32;
+            placements.push(self.point_at(middle).map(|((x, y), (tx, ty))| {
+                // Back from the midpoint along the tangent, and out along the normal
+                GlyphPlacement {
+                    x: x - tx * advance / 2f32 - ty * layout.offset,
+                    y: y - ty * advance / 2f32 + tx * layout.offset,
+                    angle: ty.atan2(tx),
+                    distance: pen,
+                }
+            }));
+            pen += advance;
+        }
+        return placements;
+    }
+
+    pub fn warp_outline(&self, contours: &[Contour], distance: f32, offset: f32, step: f32) -> Vec<Contour> {
+        /* Bends an outline with its origin at distance along the path (and its
+        baseline offset from it) onto the path. Lines and curves are split into
+        pieces no longer than step along the baseline first. Parts that hang
+        off the ends of the path carry on along the tangent there. */
+        let map = |(x, y): Point| {
+            let ((px, py), (tx, ty)) = self.locate(distance + x);
+            return (px - ty * (y + offset), py + tx * (y + offset));
+        };
+        if self.pieces.is_empty() {
+            return Vec::new();
+        }
+        return contours.iter().map(|contour| {
+            let mut curves = Vec::<Element>::new();
+            for curve in contour.curves.iter() {
+                let (x_min, _, x_max, _) = curve.bounds();
+                let count = ((x_max - x_min) / step).ceil().clamp(1f32, 64f32) as usize;
+                let mut rest = *curve;
+                for i in 0..count - 1 {
+                    // Split off an equal share of what's left, so the pieces are even in t
+                    let (piece, remainder) = rest.split(1f32 / (count - i) as f32);
+                    rest = remainder;
+                    curves.push(piece.map_points(map));
+                }
+                // The last piece keeps the curve's own end, where the next curve starts
+                curves.push(rest.map_points(map));
+            }
+            return Contour { curves: curves, closed: contour.closed };
+        }).collect();
+    }
+
+    fn locate(&self, distance: f32) -> ((f32, f32), (f32, f32)) {
+        // Like point_at, but carries on in a straight line past the ends
+        let last = self.pieces.len() - 1;
+        let index = self.table.partition_point(|samples| samples[samples.len() - 1].1 < distance).min(last);
+        let samples = &self.table[index];
+        let piece = &self.pieces[index];
+        let tangent = |t: f32| {
+            let (dx, dy) = piece.derivative(t);
+            let length = (dx * dx + dy * dy).sqrt();
+            if length == 0f32 {
+                // Control point on the end, fall back to the chord
+                let (start, end) = (piece.evaluate(0f32), piece.evaluate(1f32));
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let length = (dx * dx + dy * dy).sqrt().max(f32::MIN_POSITIVE);
+                return (dx / length, dy / length);
+            }
+            return (dx / length, dy / length);
+        };
+        if distance <= 0f32 || distance >= self.length {
+            let t = if distance <= 0f32 { 0f32 } else { 1f32 };
+            let (x, y) = piece.evaluate(t);
+            let (tx, ty) = tangent(t);
+            let beyond = if distance <= 0f32 { distance } else { distance - self.length };
+            return ((x + tx * beyond, y + ty * beyond), (tx, ty));
+        }
+        let i = samples.partition_point(|(_, d)| *d < distance).clamp(1, samples.len() - 1);
+        let ((t0, d0), (t1, d1)) = (samples[i - 1], samples[i]);
+        let t = if d1 > d0 { t0 + (t1 - t0) * (distance - d0) / (d1 - d0) } else { t0 };
+        return (piece.evaluate(t), tangent(t));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use ttf_parser::OutlineBuilder;
+
+    use crate::curve::Contour;
+    use crate::outline::Builder;
+    use super::{GlyphPlacement, PathAlign, PathLayout, PathRendering, TextPath};
+
+    fn polyline(points: &[(f32, f32)], close: bool) -> Vec<Contour> {
+        let mut builder = Builder::default();
+        builder.move_to(points[0].0, points[0].1);
+        for &(x, y) in points[1..].iter() {
+            builder.line_to(x, y);
+        }
+        if close {
+            builder.close();
+        }
+        return builder.contours;
+    }
+
+    fn layout(align: PathAlign, start_offset: f32, offset: f32) -> PathLayout {
+        return PathLayout { align, start_offset, offset, rendering: PathRendering::PerGlyph };
+    }
+
+    fn assert_origins(placements: &[Option<GlyphPlacement>], expected: &[Option<(f32, f32)>]) {
+        let origins: Vec<Option<(f32, f32)>> = placements.iter().map(|p| p.map(|p| (p.x, p.y))).collect();
+        let matches = origins.len() == expected.len() && origins.iter().zip(expected).all(|pair| match pair {
+            (Some(a), Some(b)) => (a.0 - b.0).abs() < 1e-4f32 && (a.1 - b.1).abs() < 1e-4f32,
+            (a, b) => a == b,
+        });
+        assert!(matches, "{:?} is not {:?}", origins, expected);
+    }
+
+    #[test]
+    fn arc_lengths() {
+        assert_eq!(TextPath::new(&polyline(&[(0f32, 0f32), (300f32, 0f32)], false)).length(), 300f32);
+        assert_eq!(TextPath::new(&polyline(&[(0f32, 0f32), (30f32, 40f32), (30f32, 140f32)], false)).length(), 150f32);
+        // Closing adds the way back to the start
+        assert_eq!(TextPath::new(&polyline(&[(0f32, 0f32), (100f32, 0f32), (100f32, 100f32), (0f32, 100f32)], true)).length(), 400f32);
+        // Subpaths run on from each other
+        let mut two = polyline(&[(0f32, 0f32), (10f32, 0f32)], false);
+        two.extend(polyline(&[(0f32, 50f32), (0f32, 70f32)], false));
+        assert_eq!(TextPath::new(&two).length(), 30f32);
+
+        // A quarter circle of radius 100, as a cubic
+        let mut builder = Builder::default();
+        builder.move_to(100f32, 0f32);
+        builder.curve_to(100f32, 55.228_47f32, 55.228_47f32, 100f32, 0f32, 100f32);
+        let arc = TextPath::new(&builder.contours);
+        assert!((arc.length() - 50f32 * PI).abs() < 0.05f32, "{}", arc.length());
+        // Halfway along by length is halfway round, heading up and to the left
+        let ((x, y), (tx, ty)) = arc.point_at(arc.length() / 2f32).unwrap();
+        let diagonal = 100f32 / 2f32.sqrt();
+        assert!((x - diagonal).abs() < 0.1f32 && (y - diagonal).abs() < 0.1f32, "({}, {})", x, y);
+        assert!((tx + 0.5f32.sqrt()).abs() < 1e-3f32 && (ty - 0.5f32.sqrt()).abs() < 1e-3f32, "({}, {})", tx, ty);
+    }
+
+    #[test]
+    fn straight_path_alignment() {
+        let path = TextPath::new(&polyline(&[(0f32, 0f32), (300f32, 0f32)], false));
+        let advances = [10f32, 20f32, 30f32];
+        let start = path.place_glyphs(&advances, &layout(PathAlign::Start, 10f32, 0f32));
+        assert_origins(&start, &[Some((10f32, 0f32)), Some((20f32, 0f32)), Some((40f32, 0f32))]);
+        assert!(start.iter().all(|p| p.unwrap().angle == 0f32));
+        assert_eq!(start.iter().map(|p| p.unwrap().distance).collect::<Vec<_>>(), [10f32, 20f32, 40f32]);
+        let middle = path.place_glyphs(&advances, &layout(PathAlign::Middle, 150f32, 0f32));
+        assert_origins(&middle, &[Some((120f32, 0f32)), Some((130f32, 0f32)), Some((150f32, 0f32))]);
+        let end = path.place_glyphs(&advances, &layout(PathAlign::End, 300f32, 0f32));
+        assert_origins(&end, &[Some((240f32, 0f32)), Some((250f32, 0f32)), Some((270f32, 0f32))]);
+
+        // The offset is to the left of the path's direction
+        let raised = path.place_glyphs(&advances, &layout(PathAlign::Start, 0f32, 5f32));
+        assert_origins(&raised, &[Some((0f32, 5f32)), Some((10f32, 5f32)), Some((30f32, 5f32))]);
+        let backwards = TextPath::new(&polyline(&[(300f32, 0f32), (0f32, 0f32)], false));
+        let upside_down = backwards.place_glyphs(&advances, &layout(PathAlign::Start, 0f32, 5f32));
+        for (placement, expected) in upside_down.iter().zip([300f32, 290f32, 270f32]) {
+            let placement = placement.unwrap();
+            assert!((placement.x - expected).abs() < 1e-4f32 && (placement.y + 5f32).abs() < 1e-4f32, "{:?}", placement);
+            assert!((placement.angle.abs() - PI).abs() < 1e-6f32);
+        }
+    }
+
+    #[test]
+    fn glyphs_off_the_path() {
+        let path = TextPath::new(&polyline(&[(0f32, 0f32), (100f32, 0f32)], false));
+        let advances = [10f32; 4];
+        // Placed by their midpoints, so half a glyph can hang off an end
+        let before = path.place_glyphs(&advances, &layout(PathAlign::Start, -10f32, 0f32));
+        assert_origins(&before, &[None, Some((0f32, 0f32)), Some((10f32, 0f32)), Some((20f32, 0f32))]);
+        let after = path.place_glyphs(&advances, &layout(PathAlign::Start, 85f32, 0f32));
+        assert_origins(&after, &[Some((85f32, 0f32)), Some((95f32, 0f32)), None, None]);
+        let centred = path.place_glyphs(&[300f32], &layout(PathAlign::Middle, 50f32, 0f32));
+        assert_eq!(centred, [Some(GlyphPlacement { x: -100f32, y: 0f32, angle: 0f32, distance: -100f32 })]);
+        assert_eq!(path.point_at(-0.1f32), None);
+        assert_eq!(path.point_at(100.1f32), None);
+        // An empty path has nowhere to put anything
+        let empty = TextPath::new(&[]);
+        assert_eq!(empty.length(), 0f32);
+        assert_eq!(empty.place_glyphs(&advances, &layout(PathAlign::Start, 0f32, 0f32)), [None; 4]);
+    }
+}
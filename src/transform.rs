@@ -0,0 +1,204 @@
+/* 2x3 affine transforms, for rotating, skewing and scaling outlines before
+they're rasterized (and for the transforms in COLRv1 paints and SVG glyphs).
+Affine maps take lines to lines and Bezier curves to Bezier curves of the
+same degree, so an outline is transformed by moving its end and control
+points. The control points of a transformed curve can stick out well past
+the curve itself, so bounding boxes are worked out from the curves'
+extremes. */
+
+use ttf_parser::Rect;
+
+use crate::curve::{Contour, Curve};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    // x' = xx * x + xy * y + dx
+    // y' = yx * x + yy * y + dy
+    pub xx: f32,
+    pub yx: f32,
+    pub xy: f32,
+    pub yy: f32,
+    pub dx: f32,
+    pub dy: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { xx: 1f32, yx: 0f32, xy: 0f32, yy: 1f32, dx: 0f32, dy: 0f32 };
+
+    pub fn translate(dx: f32, dy: f32) -> Transform {
+        return Transform { dx, dy, ..Transform::IDENTITY };
+    }
+
+    pub fn scale(sx: f32, sy: f32) -> Transform {
+        return Transform { xx: sx, yy: sy, ..Transform::IDENTITY };
+    }
+
+    pub fn rotate(angle: f32) -> Transform {
+        // Counter-clockwise by angle radians (with y going up)
+        return Transform { xx: angle.cos(), yx: angle.sin(), xy: -angle.sin(), yy: angle.cos(), dx: 0f32, dy: 0f32 };
+    }
+
+    pub fn skew(x_skew: f32, y_skew: f32) -> Transform {
+        // x moves by x_skew per unit of y and y by y_skew per unit of x (tangents of the skew angles)
+        return Transform { xy: x_skew, yx: y_skew, ..Transform::IDENTITY };
+    }
+
+    pub fn then(&self, inner: &Transform) -> Transform {
+        // Returns self * inner, i.e. inner is applied first
+        return Transform {
+            xx: self.xx * inner.xx + self.xy * inner.yx,
+            yx: self.yx * inner.xx + self.yy * inner.yx,
+            xy: self.xx * inner.xy + self.xy * inner.yy,
+            yy: self.yx * inner.xy + self.yy * inner.yy,
+            dx: self.xx * inner.dx + self.xy * inner.dy + self.dx,
+            dy: self.yx * inner.dx + self.yy * inner.dy + self.dy,
+        };
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        return (self.xx * x + self.xy * y + self.dx, self.yx * x + self.yy * y + self.dy);
+    }
+
+    pub fn apply_vector(&self, x: f32, y: f32) -> (f32, f32) {
+        // Without the translation, for directions and advances
+        return (self.xx * x + self.xy * y, self.yx * x + self.yy * y);
+    }
+
+    pub fn invert(&self) -> Option<Transform> {
+        let det = self.xx * self.yy - self.xy * self.yx;
+        if det == 0f32 || !det.is_finite() {
+            return None;
+        }
+        let xx = self.yy / det;
+        let xy = -self.xy / det;
+        let yx = -self.yx / det;
+        let yy = self.xx / det;
+        return Some(Transform {
+            xx, yx, xy, yy,
+            dx: -(xx * self.dx + xy * self.dy),
+            dy: -(yx * self.dx + yy * self.dy),
+        });
+    }
+
+    pub fn around(&self, cx: f32, cy: f32) -> Transform {
+        // Same transform, but about the point (cx, cy) instead of the origin
+        return Transform::translate(cx, cy).then(self).then(&Transform::translate(-cx, -cy));
+    }
+
+    pub fn is_identity(&self) -> bool {
+        return *self == Transform::IDENTITY;
+    }
+}
+
+pub fn transform_outline(contours: &mut [Contour], transform: &Transform) -> Option<Rect> {
+    // Transforms an outline in place and returns its new bounding box (None if it's empty)
+    for contour in contours.iter_mut() {
+        contour.map_points(|(x, y)| transform.apply(x, y));
+    }
+    return outline_bounds(contours);
+}
+
+pub fn outline_bounds(contours: &[Contour]) -> Option<Rect> {
+    /* Smallest box of whole font units around the outline: the end points,
+    plus the points where curves turn around in x or y. */
+    let mut bounds: Option<(f32, f32, f32, f32)> = None;
+    for curve in contours.iter().flat_map(|contour| contour.curves.iter()) {
+        let (x_min, y_min, x_max, y_max) = curve.bounds();
+        bounds = Some(match bounds {
+            Some(b) => (b.0.min(x_min), b.1.min(y_min), b.2.max(x_max), b.3.max(y_max)),
+            None => (x_min, y_min, x_max, y_max),
+        });
+    }
+    return bounds.map(|(x_min, y_min, x_max, y_max)| Rect {
+        x_min: x_min.floor() as i16,
+        y_min: y_min.floor() as i16,
+        x_max: x_max.ceil() as i16,
+        y_max: y_max.ceil() as i16,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use ttf_parser::OutlineBuilder;
+
+    use crate::outline::Builder;
+    use super::{outline_bounds, transform_outline, Transform};
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        return (a.0 - b.0).abs() < 1e-4f32 && (a.1 - b.1).abs() < 1e-4f32;
+    }
+
+    #[test]
+    fn then_applies_the_inner_transform_first() {
+        let (translate, scale) = (Transform::translate(10f32, 0f32), Transform::scale(2f32, 3f32));
+        assert_eq!(translate.then(&scale).apply(1f32, 1f32), (12f32, 3f32));
+        assert_eq!(scale.then(&translate).apply(1f32, 1f32), (22f32, 3f32));
+        let rotate = Transform::rotate(FRAC_PI_2);
+        assert!(close(rotate.then(&translate).apply(0f32, 0f32), (0f32, 10f32)));
+        assert!(close(translate.then(&rotate).apply(0f32, 0f32), (10f32, 0f32)));
+        // Vectors skip the translation
+        assert!(close(translate.then(&rotate).apply_vector(1f32, 0f32), (0f32, 1f32)));
+        assert!(Transform::IDENTITY.then(&Transform::IDENTITY).is_identity());
+    }
+
+    #[test]
+    fn invert() {
+        let transform = Transform::translate(5f32, -7f32).then(&Transform::rotate(0.3f32)).then(&Transform::skew(0.5f32, 0f32)).then(&Transform::scale(2f32, 0.5f32));
+        let inverse = transform.invert().unwrap();
+        for (x, y) in [(0f32, 0f32), (1f32, 0f32), (-3f32, 8f32), (100f32, 250f32)] {
+            let (tx, ty) = transform.apply(x, y);
+            assert!(close(inverse.apply(tx, ty), (x, y)), "({}, {})", x, y);
+        }
+        let product = inverse.then(&transform);
+        assert!(close((product.xx, product.yx), (1f32, 0f32)) && close((product.xy, product.yy), (0f32, 1f32)));
+        assert!(close((product.dx, product.dy), (0f32, 0f32)));
+        // Flattening everything onto a line can't be undone
+        assert!(Transform::scale(0f32, 1f32).invert().is_none());
+        assert!(Transform { xx: 1f32, yx: 2f32, xy: 2f32, yy: 4f32, dx: 0f32, dy: 0f32 }.invert().is_none());
+        assert!(Transform::scale(f32::INFINITY, 1f32).invert().is_none());
+    }
+
+    #[test]
+    fn around_keeps_its_center() {
+        let rotation = Transform::rotate(FRAC_PI_2).around(10f32, 0f32);
+        assert!(close(rotation.apply(10f32, 0f32), (10f32, 0f32)));
+        assert!(close(rotation.apply(20f32, 0f32), (10f32, 10f32)));
+        let scale = Transform::scale(2f32, 2f32).around(5f32, 5f32);
+        assert_eq!(scale.apply(5f32, 5f32), (5f32, 5f32));
+        assert_eq!(scale.apply(6f32, 4f32), (7f32, 3f32));
+    }
+
+    #[test]
+    fn bounds_come_from_the_curves() {
+        // Control points at y = 100, but the curves only reach 50 and 75
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.quad_to(50f32, 100f32, 100f32, 0f32);
+        builder.close();
+        let mut quad = builder.contours;
+        let bbox = outline_bounds(&quad).unwrap();
+        assert_eq!((bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max), (0, 0, 100, 50));
+
+        let mut builder = Builder::default();
+        builder.move_to(0f32, 0f32);
+        builder.curve_to(-20f32, 100f32, 120f32, 100f32, 100f32, 0f32);
+        builder.close();
+        let bbox = outline_bounds(&builder.contours).unwrap();
+        assert_eq!((bbox.y_min, bbox.y_max), (0, 75));
+        // Bulging out sideways past the ends by just under 2, not the 20 of the control points
+        assert_eq!((bbox.x_min, bbox.x_max), (-2, 102));
+
+        // Turned on its side the peak becomes the left edge
+        let bbox = transform_outline(&mut quad, &Transform::rotate(FRAC_PI_2)).unwrap();
+        assert_eq!((bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max), (-50, 0, 0, 100));
+
+        // Rounded outwards to whole units
+        let mut builder = Builder::default();
+        builder.move_to(0.2f32, -0.3f32);
+        builder.line_to(10.5f32, 3.1f32);
+        assert_eq!(outline_bounds(&builder.contours).map(|b| (b.x_min, b.y_min, b.x_max, b.y_max)), Some((0, -1, 11, 4)));
+        assert!(outline_bounds(&[]).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+/* Variable font support. Axis settings (wght, wdth, opsz, slnt, or any custom
+axis in the fvar table) are applied to a Face through ttf_parser, which then
+takes care of gvar outline deltas, HVAR advance deltas and avar mapping. */
+
+use ttf_parser::{Face, Tag};
+
+use crate::read::{read_f2dot14, read_u16};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variation {
+    pub axis: Tag,
+    // Value in user space, e.g. 700 for wght or -12 for slnt
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Axis {
+    pub tag: Tag,
+    pub min_value: f32,
+    pub default_value: f32,
+    pub max_value: f32,
+}
+
+pub fn parse_variations(text: &str) -> Vec<Variation> {
+    /* Parses settings written like CSS font-variation-settings without the
+    quotes, e.g. "wght=700, wdth=87.5". Malformed entries are ignored. */
+    let mut variations = Vec::<Variation>::new();
+    for setting in text.split(',') {
+        let (tag, value) = match setting.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let tag = tag.trim();
+        if tag.len() != 4 {
+            continue;
+        }
+        if let Ok(value) = value.trim().parse::<f32>() {
+            variations.push(Variation { axis: Tag::from_bytes_lossy(tag.as_bytes()), value });
+        }
+    }
+    return variations;
+}
+
+pub fn get_axes(face: &Face) -> Vec<Axis> {
+    // Lists the axes declared in fvar (empty for non-variable fonts)
+    return face.variation_axes().into_iter()
+        .map(|a| Axis { tag: a.tag, min_value: a.min_value, default_value: a.def_value, max_value: a.max_value })
+        .collect();
+}
+
+pub fn apply_variations(face: &mut Face, variations: &[Variation]) {
+    /* Sets the variation coordinates on the face. Settings for axes the face
+    doesn't have are ignored, and values are clamped to the axis range.
+
+    Face::set_variation re-runs the avar mapping over *every* coordinate each
+    time it is called, so an axis set before another one would be mapped more
+    than once. To compensate, each value is passed through the inverse avar
+    mapping once for every call that comes after it. */
+    if !face.is_variable() {
+        return;
+    }
+    let axes = get_axes(face);
+    let settings: Vec<(usize, f32)> = variations.iter()
+        .filter_map(|v| axes.iter().position(|a| a.tag == v.axis).map(|i| (i, v.value)))
+        .collect();
+    let segment_maps = face.raw_face().table(Tag::from_bytes(b"avar")).and_then(parse_avar);
+
+    for (n, &(axis_index, value)) in settings.iter().enumerate() {
+        let axis = axes[axis_index];
+        let mut value = value;
+        if let Some(maps) = segment_maps.as_ref().and_then(|m| m.get(axis_index)) {
+            let mut normalized = normalize(&axis, value);
+            for _ in 0..(settings.len() - n - 1) {
+                normalized = unmap_value(maps, normalized);
+            }
+            value = denormalize(&axis, normalized);
+        }
+        face.set_variation(axis.tag, value);
+    }
+}
+
+fn normalize(axis: &Axis, value: f32) -> f32 {
+    // fvar default normalization to [-1, 1]
+    let value = value.clamp(axis.min_value, axis.max_value);
+    if value < axis.default_value {
+        return (value - axis.default_value) / (axis.default_value - axis.min_value);
+    } else if value > axis.default_value {
+        return (value - axis.default_value) / (axis.max_value - axis.default_value);
+    }
+    return 0f32;
+}
+
+fn denormalize(axis: &Axis, normalized: f32) -> f32 {
+    // Undoes normalize()
+    if normalized < 0f32 {
+        return axis.default_value + normalized * (axis.default_value - axis.min_value);
+    } else if normalized > 0f32 {
+        return axis.default_value + normalized * (axis.max_value - axis.default_value);
+    }
+    return axis.default_value;
+}
+
+fn parse_avar(data: &[u8]) -> Option<Vec<Vec<(f32, f32)>>> {
+    /* Reads the segment maps of an avar v1 table as (from, to) pairs of
+    normalized coordinates, one list per axis.
+    https://learn.microsoft.com/en-us/typography/opentype/spec/avar */
+    let axis_count = read_u16(data, 6)? as usize;
+    let mut offset = 8;
+    let mut maps = Vec::<Vec<(f32, f32)>>::new();
+    for _ in 0..axis_count {
+        let count = read_u16(data, offset)? as usize;
+        offset += 2;
+        let mut map = Vec::<(f32, f32)>::new();
+        for _ in 0..count {
+            map.push((read_f2dot14(data, offset)?, read_f2dot14(data, offset + 2)?));
+            offset += 4;
+        }
+        maps.push(map);
+    }
+    return Some(maps);
+}
+
+fn unmap_value(map: &[(f32, f32)], value: f32) -> f32 {
+    // Inverse of the piecewise-linear avar mapping (the "to" values are required to be ascending)
+    if map.len() < 2 {
+        return value;
+    }
+    for pair in map.windows(2) {
+        let (from0, to0) = pair[0];
+        let (from1, to1) = pair[1];
+        if value >= to0 && value <= to1 {
+            if to1 == to0 {
+                return from0;
+            }
+            return from0 + (value - to0) * (from1 - from0) / (to1 - to0);
+        }
+    }
+    return value;
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{Face, Tag};
+
+    use crate::read::{read_u16, read_u32};
+    use super::{apply_variations, Variation};
+
+    // (tag, min, default, max) and the avar segment map of each axis
+    const AXES: [(&[u8; 4], f32, f32, f32); 2] = [(b"wght", 100f32, 400f32, 900f32), (b"wdth", 50f32, 100f32, 200f32)];
+    const SEGMENT_MAPS: [[(f32, f32); 4]; 2] = [
+        [(-1f32, -1f32), (0f32, 0f32), (0.5f32, 0.8f32), (1f32, 1f32)],
+        [(-1f32, -1f32), (0f32, 0f32), (0.5f32, 0.2f32), (1f32, 1f32)],
+    ];
+
+    fn variable_font() -> Vec<u8> {
+        /* The CFF fixture with fvar and avar tables added, which is all
+        ttf_parser needs to treat it as a variable font */
+        let font = std::fs::read("fonts/CubicTest-Regular.otf").unwrap();
+        let mut tables: Vec<([u8; 4], Vec<u8>)> = (0..read_u16(&font, 4).unwrap() as usize).map(|i| {
+            let record = 12 + i * 16;
+            let (offset, length) = (read_u32(&font, record + 8).unwrap() as usize, read_u32(&font, record + 12).unwrap() as usize);
+            return (font[record..record + 4].try_into().unwrap(), font[offset..offset + length].to_vec());
+        }).collect();
+        let fixed = |value: f32| ((value * 65536f32) as i32).to_be_bytes();
+        let f2dot14 = |value: f32| ((value * 16384f32).round() as i16).to_be_bytes();
+        let mut fvar = [0x00010000u32.to_be_bytes().as_slice(), &16u16.to_be_bytes(), &2u16.to_be_bytes(), &(AXES.len() as u16).to_be_bytes(), &20u16.to_be_bytes(), &0u16.to_be_bytes(), &0u16.to_be_bytes()].concat();
+        for (tag, min, default, max) in AXES {
+            fvar.extend([tag.as_slice(), &fixed(min), &fixed(default), &fixed(max), &0u16.to_be_bytes(), &256u16.to_be_bytes()].concat());
+        }
+        let mut avar = [0x00010000u32.to_be_bytes().as_slice(), &0u16.to_be_bytes(), &(SEGMENT_MAPS.len() as u16).to_be_bytes()].concat();
+        for map in SEGMENT_MAPS {
+            avar.extend((map.len() as u16).to_be_bytes());
+            for (from, to) in map {
+                avar.extend([f2dot14(from), f2dot14(to)].concat());
+            }
+        }
+        tables.push((*b"fvar", fvar));
+        tables.push((*b"avar", avar));
+        tables.sort();
+
+        // Checksums are left at zero, ttf_parser doesn't check them
+        let mut directory = [0x4F54544Fu32.to_be_bytes().as_slice(), &(tables.len() as u16).to_be_bytes(), &[0u8; 6]].concat();
+        let mut data = Vec::<u8>::new();
+        let start = 12 + tables.len() * 16;
+        for (tag, table) in tables.iter() {
+            directory.extend([tag.as_slice(), &0u32.to_be_bytes(), &((start + data.len()) as u32).to_be_bytes(), &(table.len() as u32).to_be_bytes()].concat());
+            data.extend(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        directory.extend(data);
+        return directory;
+    }
+
+    fn mapped(axis: usize, normalized: f32) -> f32 {
+        // One pass through the avar segment map
+        let map = SEGMENT_MAPS[axis];
+        for pair in map.windows(2) {
+            let ((from0, to0), (from1, to1)) = (pair[0], pair[1]);
+            if normalized >= from0 && normalized <= from1 {
+                return to0 + (normalized - from0) * (to1 - to0) / (from1 - from0);
+            }
+        }
+        return normalized;
+    }
+
+    #[test]
+    fn avar_is_applied_once_per_axis() {
+        /* set_variation in ttf_parser 0.18 maps every coordinate through avar
+        again on each call, which apply_variations undoes. If that's fixed
+        upstream the compensation maps axes set early too little, and this
+        fails: each axis has to come out mapped exactly once. */
+        let data = variable_font();
+        let wght = Variation { axis: Tag::from_bytes(b"wght"), value: 650f32 };
+        let wdth = Variation { axis: Tag::from_bytes(b"wdth"), value: 150f32 };
+        // Both normalize to 0.5, which the maps move to 0.8 and 0.2
+        let expected = [mapped(0, 0.5f32), mapped(1, 0.5f32)];
+        for variations in [[wght, wdth], [wdth, wght]] {
+            let mut face = Face::parse(&data, 0).unwrap();
+            assert!(face.is_variable());
+            apply_variations(&mut face, &variations);
+            let coordinates: Vec<f32> = face.variation_coordinates().iter().map(|c| c.get() as f32 / 16384f32).collect();
+            for (axis, (coordinate, expected)) in coordinates.iter().zip(expected).enumerate() {
+                assert!((coordinate - expected).abs() < 2f32 / 16384f32, "axis {} is at {}, not {} after {:?}", axis, coordinate, expected, variations);
+            }
+        }
+    }
+}
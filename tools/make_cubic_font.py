@@ -0,0 +1,257 @@
+#!/usr/bin/env python3
+"""Writes fonts/CubicTest-Regular.otf, a small OpenType font with CFF outlines.
+
+Every other font in fonts/ is TrueType, whose quadratic outlines never use the
+cubic term. The glyphs here are drawn with cubics the way a CFF font is: bowls
+and counters, S bends with inflections, arches meeting stems at corners, long
+flat curves and contours that overlap, so the tests that run over fonts/ get
+real cubics to check.
+
+Run from the repository root: python3 tools/make_cubic_font.py
+"""
+
+import struct
+
+UNITS_PER_EM = 1000
+ASCENDER = 800
+DESCENDER = -200
+# Control point distance for a quarter circle of radius 1
+KAPPA = 0.5522847498
+
+
+def ellipse(cx, cy, rx, ry, clockwise=False):
+    # Four cubics starting at the bottom, counter-clockwise unless asked otherwise
+    kx, ky = rx * KAPPA, ry * KAPPA
+    quarters = [
+        ((cx + kx, cy - ry), (cx + rx, cy - ky), (cx + rx, cy)),
+        ((cx + rx, cy + ky), (cx + kx, cy + ry), (cx, cy + ry)),
+        ((cx - kx, cy + ry), (cx - rx, cy + ky), (cx - rx, cy)),
+        ((cx - rx, cy - ky), (cx - kx, cy - ry), (cx, cy - ry)),
+    ]
+    contour = [("M", (cx, cy - ry))] + [("C",) + quarter for quarter in quarters]
+    return reverse(contour) if clockwise else contour
+
+
+def reverse(contour):
+    # The same closed contour the other way round
+    points = [contour[0][1]]
+    for command in contour[1:]:
+        points.append(command[-1])
+    reversed_commands = [("M", points[-1])]
+    for i in range(len(contour) - 1, 0, -1):
+        command = contour[i]
+        start = points[i - 1]
+        if command[0] == "L":
+            reversed_commands.append(("L", start))
+        else:
+            reversed_commands.append(("C", command[2], command[1], start))
+    return reversed_commands
+
+
+def stroked(spine, width):
+    """An outline of a pen of the given width run along a smooth spine of
+    (point, direction) pairs, one cubic per pair of them on each side. Each
+    side's control points are the spine's moved along the normal at their end,
+    which stays close to the true offset while each piece turns by less than
+    a right angle."""
+    sides = []
+    for side in (1, -1):
+        commands = []
+        for i, ((x, y), (dx, dy)) in enumerate(spine):
+            length = (dx * dx + dy * dy) ** 0.5
+            dx, dy = dx / length, dy / length
+            nx, ny = -dy * width / 2 * side, dx * width / 2 * side
+            if i == 0:
+                commands.append(("M", (x + nx, y + ny)))
+            else:
+                (px, py), (pdx, pdy), pn = previous
+                chord = ((x - px) ** 2 + (y - py) ** 2) ** 0.5 / 3
+                commands.append(("C", (px + pdx * chord + pn[0], py + pdy * chord + pn[1]), (x - dx * chord + nx, y - dy * chord + ny), (x + nx, y + ny)))
+            previous = ((x, y), (dx, dy), (nx, ny))
+        sides.append(commands)
+    left, right = sides
+    back = reverse(right)
+    return left + [("L", back[0][1])] + back[1:]
+
+
+GLYPHS = [
+    # (name, character, advance, contours)
+    (".notdef", None, 500, [
+        [("M", (50, 0)), ("L", (450, 0)), ("L", (450, 700)), ("L", (50, 700))],
+        reverse([("M", (100, 50)), ("L", (400, 50)), ("L", (400, 650)), ("L", (100, 650))]),
+    ]),
+    # A ring: the counter goes the other way
+    ("O", "O", 760, [ellipse(380, 350, 330, 360), ellipse(380, 350, 240, 280, clockwise=True)]),
+    # Straight stem and a bowl, with a counter
+    ("D", "D", 700, [
+        [("M", (80, 0)), ("L", (320, 0)), ("C", (540, 0), (640, 150), (640, 350)), ("C", (640, 550), (540, 700), (320, 700)), ("L", (80, 700))],
+        reverse([("M", (170, 80)), ("L", (310, 80)), ("C", (470, 80), (545, 190), (545, 350)), ("C", (545, 510), (470, 620), (310, 620)), ("L", (170, 620))]),
+    ]),
+    # A pen stroke with a bend either way, so the sides have inflections
+    ("S", "S", 600, [stroked([((500, 600), (-0.6, 0.8)), ((300, 680), (-1, 0)), ((110, 520), (0, -1)), ((300, 360), (1, -0.3)),
+                              ((490, 180), (0, -1)), ((300, 20), (-1, 0)), ((90, 110), (-0.6, 0.8))], 90)]),
+    # Straight stems with an arch between them, meeting the left stem at a corner
+    ("n", "n", 580, [[("M", (60, 0)), ("L", (160, 0)), ("L", (160, 380)), ("C", (200, 440), (260, 470), (320, 470)), ("C", (380, 470), (420, 430), (420, 350)),
+                      ("L", (420, 0)), ("L", (520, 0)), ("L", (520, 360)), ("C", (520, 490), (450, 560), (340, 560)), ("C", (260, 560), (200, 520), (160, 460)),
+                      ("L", (160, 540)), ("L", (60, 540))]]),
+    # Two overlapping discs going the same way, which non-zero fills where they overlap
+    ("overlap", "8", 620, [ellipse(310, 200, 220, 200), ellipse(310, 500, 180, 200)]),
+    # A curved tail with a long, almost flat curve (like the ends of strokes in a brush script)
+    ("tail", "j", 500, [[("M", (260, 700)), ("L", (360, 700)), ("L", (360, 0)), ("C", (360, -150), (250, -220), (20, -190)), ("L", (30, -120)), ("C", (200, -130), (270, -90), (270, 0))]]),
+    # An e: a bar and a bowl opening to the right
+    ("e", "e", 560, [
+        [("M", (510, 240)), ("L", (130, 240)), ("C", (140, 100), (230, 60), (320, 60)), ("C", (400, 60), (450, 90), (490, 130)),
+         ("L", (520, 80)), ("C", (470, 20), (400, -10), (310, -10)), ("C", (140, -10), (40, 100), (40, 260)),
+         ("C", (40, 420), (150, 530), (290, 530)), ("C", (430, 530), (515, 430), (515, 280))],
+        reverse([("M", (135, 310)), ("L", (420, 310)), ("C", (410, 410), (360, 460), (285, 460)), ("C", (210, 460), (150, 410), (135, 310))]),
+    ]),
+]
+
+
+def number(value):
+    # A charstring operand
+    value = int(round(value))
+    if -107 <= value <= 107:
+        return bytes([value + 139])
+    return bytes([28]) + struct.pack(">h", value)
+
+
+def charstring(contours):
+    data = bytearray()
+    current = (0, 0)
+
+    def relative(point):
+        nonlocal current
+        delta = number(point[0] - current[0]) + number(point[1] - current[1])
+        current = (round(point[0]), round(point[1]))
+        return delta
+
+    for contour in contours:
+        for command in contour:
+            if command[0] == "M":
+                data += relative(command[1]) + bytes([21])
+            elif command[0] == "L":
+                data += relative(command[1]) + bytes([5])
+            else:
+                data += relative(command[1]) + relative(command[2]) + relative(command[3]) + bytes([8])
+    data += bytes([14])
+    return bytes(data)
+
+
+def index(items):
+    # A CFF INDEX with 4 byte offsets
+    if not items:
+        return struct.pack(">H", 0)
+    offsets = [1]
+    for item in items:
+        offsets.append(offsets[-1] + len(item))
+    return struct.pack(">HB", len(items), 4) + b"".join(struct.pack(">I", offset) for offset in offsets) + b"".join(items)
+
+
+def dict_int(value):
+    # Always five bytes, so offsets can be filled in without changing the size
+    return bytes([29]) + struct.pack(">i", value)
+
+
+def cff(charstrings):
+    header = bytes([1, 0, 4, 4])
+    names = index([b"CubicTest-Regular"])
+    strings = index([])
+    global_subrs = index([])
+    private = dict_int(0) + bytes([20])
+
+    def top(charstrings_offset, private_offset):
+        return dict_int(charstrings_offset) + bytes([17]) + dict_int(len(private)) + dict_int(private_offset) + bytes([18])
+
+    before = len(header) + len(names) + len(index([top(0, 0)])) + len(strings) + len(global_subrs)
+    charstrings_index = index(charstrings)
+    top_dict = index([top(before, before + len(charstrings_index))])
+    return header + names + top_dict + strings + global_subrs + charstrings_index + private
+
+
+def bounds(contours):
+    points = [point for contour in contours for command in contour for point in command[1:]]
+    if not points:
+        return (0, 0, 0, 0)
+    xs = [p[0] for p in points]
+    ys = [p[1] for p in points]
+    return (int(min(xs)), int(min(ys)), int(max(xs)), int(max(ys)))
+
+
+def cmap(mapping):
+    # Format 4 with a segment per character, on the Windows Unicode BMP encoding
+    codes = sorted(mapping)
+    ends = codes + [0xFFFF]
+    starts = codes + [0xFFFF]
+    deltas = [(mapping[code] - code) & 0xFFFF for code in codes] + [1]
+    segments = len(ends)
+    search = 2 ** (segments.bit_length() - 1)
+    subtable = struct.pack(">HHHHHHH", 4, 0, 0, segments * 2, search * 2, search.bit_length() - 1, segments * 2 - search * 2)
+    subtable += b"".join(struct.pack(">H", end) for end in ends) + struct.pack(">H", 0)
+    subtable += b"".join(struct.pack(">H", start) for start in starts)
+    subtable += b"".join(struct.pack(">H", delta) for delta in deltas)
+    subtable += b"".join(struct.pack(">H", 0) for _ in range(segments))
+    subtable = subtable[:2] + struct.pack(">H", len(subtable)) + subtable[4:]
+    return struct.pack(">HHHHI", 0, 1, 3, 1, 12) + subtable
+
+
+def name_table():
+    records = [(1, "Cubic Test"), (2, "Regular"), (4, "Cubic Test Regular"), (6, "CubicTest-Regular")]
+    strings = b""
+    entries = b""
+    for name_id, text in records:
+        encoded = text.encode("utf-16-be")
+        entries += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(encoded), len(strings))
+        strings += encoded
+    return struct.pack(">HHH", 0, len(records), 6 + len(entries)) + entries + strings
+
+
+def checksum(data):
+    data = data + b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+
+def main():
+    all_bounds = [bounds(contours) for _, _, _, contours in GLYPHS]
+    x_min = min(b[0] for b in all_bounds)
+    y_min = min(b[1] for b in all_bounds)
+    x_max = max(b[2] for b in all_bounds)
+    y_max = max(b[3] for b in all_bounds)
+    count = len(GLYPHS)
+    advance_max = max(advance for _, _, advance, _ in GLYPHS)
+
+    tables = {}
+    tables[b"CFF "] = cff([charstring(contours) for _, _, _, contours in GLYPHS])
+    tables[b"head"] = struct.pack(">IIIIHHqqhhhhHHhhh", 0x00010000, 0x00010000, 0, 0x5F0F3CF5, 0b1011, UNITS_PER_EM, 0, 0,
+                                  x_min, y_min, x_max, y_max, 0, 8, 2, 0, 0)
+    tables[b"hhea"] = struct.pack(">IhhhHhhhhhhhhhhhH", 0x00010000, ASCENDER, DESCENDER, 0, advance_max,
+                                  min(b[0] for b in all_bounds), min(advance - b[2] for (_, _, advance, _), b in zip(GLYPHS, all_bounds)),
+                                  x_max, 1, 0, 0, 0, 0, 0, 0, 0, count)
+    tables[b"maxp"] = struct.pack(">IH", 0x00005000, count)
+    tables[b"hmtx"] = b"".join(struct.pack(">Hh", advance, b[0]) for (_, _, advance, _), b in zip(GLYPHS, all_bounds))
+    tables[b"cmap"] = cmap({ord(character): gid for gid, (_, character, _, _) in enumerate(GLYPHS) if character})
+    tables[b"OS/2"] = struct.pack(">HhHHHhhhhhhhhhhh10sIIIIIHHHhhhHHIIhhHHH", 4, 560, 400, 5, 0,
+                                  650, 600, 0, 75, 650, 600, 0, 350, 50, 250, 0, bytes(10), 0, 0, 0, 0, 0x20202020,
+                                  0x40, 0x20, 0x7A, ASCENDER, DESCENDER, 0, ASCENDER, -DESCENDER, 1, 0, 500, 700, 0, 32, 0)
+    tables[b"name"] = name_table()
+    tables[b"post"] = struct.pack(">IIhhIIIII", 0x00030000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+    tags = sorted(tables)
+    search = 2 ** (len(tags).bit_length() - 1)
+    directory = struct.pack(">IHHHH", 0x4F54544F, len(tags), search * 16, search.bit_length() - 1, len(tags) * 16 - search * 16)
+    offset = 12 + 16 * len(tags)
+    body = b""
+    for tag in tags:
+        data = tables[tag]
+        directory += struct.pack(">4sIII", tag, checksum(data), offset + len(body), len(data))
+        body += data + b"\0" * (-len(data) % 4)
+    font = bytearray(directory + body)
+    # checkSumAdjustment in head, so the whole font sums to 0xB1B0AFBA
+    head_offset = offset + sum(len(tables[tag]) + (-len(tables[tag]) % 4) for tag in tags[:tags.index(b"head")])
+    struct.pack_into(">I", font, head_offset + 8, (0xB1B0AFBA - checksum(bytes(font))) & 0xFFFFFFFF)
+    with open("fonts/CubicTest-Regular.otf", "wb") as file:
+        file.write(font)
+
+
+if __name__ == "__main__":
+    main()